@@ -0,0 +1,31 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{thread, time::Instant};
+use sync::channel::chan::channel;
+
+// Run with and without the `std-impl` feature to compare the channel
+// built on the crate's Mutex and Condvar with the one built on std's.
+fn bench_chan(c: &mut Criterion) {
+    c.bench_function("4 senders 1 receiver chan", |b| {
+        b.iter_custom(|iters| {
+            let (tx, mut rx) = channel();
+            let start = Instant::now();
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    let tx = tx.clone();
+                    s.spawn(move || {
+                        for i in 0..iters {
+                            tx.send(i);
+                        }
+                    });
+                }
+                for _ in 0..iters * 4 {
+                    rx.recv();
+                }
+            });
+            start.elapsed()
+        })
+    });
+}
+
+criterion_group!(benches, bench_chan);
+criterion_main!(benches);
@@ -1,6 +1,13 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use std::thread;
-use sync::mutex::Mutex;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+use sync::{
+    combiner::FlatCombiner,
+    lock::Lock,
+    mutex::{FairMutex, MicroMutex, Mutex},
+};
 
 const LOOP_COUNTS: usize = 10;
 
@@ -33,5 +40,199 @@ fn bench_multi_thread_mutex(c: &mut Criterion) {
     });
 }
 
-criterion_group!(mutex, bench_single_thread_mutex, bench_multi_thread_mutex);
+fn bench_flat_combiner(c: &mut Criterion) {
+    let m = Mutex::new(0u64);
+    c.bench_function("4 threads mutex increment", |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..iters {
+                            *m.lock() += 1;
+                        }
+                    });
+                }
+            });
+            start.elapsed()
+        })
+    });
+
+    let fc = FlatCombiner::new(0u64);
+    c.bench_function("4 threads flat combiner increment", |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..iters {
+                            fc.apply(|v| *v += 1);
+                        }
+                    });
+                }
+            });
+            start.elapsed()
+        })
+    });
+}
+
+// Increment random locks of an array from 4 threads, `hot` locks contend
+// heavily while many locks are mostly uncontended.
+fn bench_lock_array<L: Lock<u64> + Sync>(c: &mut Criterion, name: &str, len: usize) {
+    let locks: Vec<L> = (0..len).map(|_| L::new(0)).collect();
+    let id = format!(
+        "4 threads {} array of {} ({} bytes) increment",
+        name,
+        len,
+        len * std::mem::size_of::<L>()
+    );
+    c.bench_function(&id, |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            thread::scope(|s| {
+                for t in 0..4u64 {
+                    let locks = &locks;
+                    s.spawn(move || {
+                        let mut x = t + 1;
+                        for _ in 0..iters {
+                            // xorshift
+                            x ^= x << 13;
+                            x ^= x >> 7;
+                            x ^= x << 17;
+                            *locks[x as usize % len].lock() += 1;
+                        }
+                    });
+                }
+            });
+            start.elapsed()
+        })
+    });
+}
+
+fn bench_micro_mutex(c: &mut Criterion) {
+    for len in [1, 1 << 20] {
+        bench_lock_array::<Mutex<u64>>(c, "mutex", len);
+        bench_lock_array::<MicroMutex<u64>>(c, "micro mutex", len);
+    }
+}
+
+// 4 threads increment through critical sections of `work` steps, with
+// the spin budget before parking from none to long. Short sections favor
+// spinning, the crossover comes as sections grow past the budget.
+fn bench_spin_budget(c: &mut Criterion) {
+    for work in [1, 100, 10_000] {
+        for spin_iters in [0, 100, 1_000, 10_000] {
+            let m = Mutex::new(0u64);
+            let id = format!("4 threads mutex work {} spin budget {}", work, spin_iters);
+            c.bench_function(&id, |b| {
+                b.iter_custom(|iters| {
+                    let start = Instant::now();
+                    thread::scope(|s| {
+                        for _ in 0..4 {
+                            s.spawn(|| {
+                                for _ in 0..iters {
+                                    let mut g = m.lock_spin_then_park(spin_iters);
+                                    for _ in 0..work {
+                                        *g = black_box(*g + 1);
+                                    }
+                                }
+                            });
+                        }
+                    });
+                    start.elapsed()
+                })
+            });
+            // Parks per budget, run with `--features metrics`.
+            #[cfg(feature = "metrics")]
+            {
+                for (primitive, stats) in sync::futex::futex_stats() {
+                    println!("{}: {:?}", primitive, stats);
+                }
+                sync::futex::reset_futex_stats();
+            }
+        }
+    }
+}
+
+// A single hot lock, where FairMutex trades throughput for FIFO order.
+fn bench_fair_mutex(c: &mut Criterion) {
+    bench_lock_array::<Mutex<u64>>(c, "mutex", 1);
+    bench_lock_array::<FairMutex<u64>>(c, "fair mutex", 1);
+}
+
+// 4 threads increment a hot lock, barging vs handing off on unlock. The
+// time is the throughput, the longest single wait printed is the latency
+// bound: handoff is slower in total but no waiter loses race after race.
+fn bench_handoff(c: &mut Criterion) {
+    for (mode, m) in [
+        ("barging", Mutex::new(0u64)),
+        ("handoff", Mutex::with_handoff(0u64)),
+    ] {
+        let max_wait = std::sync::Mutex::new(Duration::ZERO);
+        let id = format!("4 threads {} mutex increment", mode);
+        c.bench_function(&id, |b| {
+            b.iter_custom(|iters| {
+                let start = Instant::now();
+                thread::scope(|s| {
+                    for _ in 0..4 {
+                        s.spawn(|| {
+                            let mut longest = Duration::ZERO;
+                            for _ in 0..iters {
+                                let asked = Instant::now();
+                                let mut g = m.lock();
+                                longest = longest.max(asked.elapsed());
+                                *g += 1;
+                            }
+                            let mut max_wait = max_wait.lock().unwrap();
+                            *max_wait = (*max_wait).max(longest);
+                        });
+                    }
+                });
+                start.elapsed()
+            })
+        });
+        println!(
+            "{} mutex longest wait: {:?}",
+            mode,
+            max_wait.into_inner().unwrap()
+        );
+    }
+}
+
+#[cfg(all(feature = "linux-pi", target_os = "linux"))]
+fn bench_pi_futex_mutex(c: &mut Criterion) {
+    use sync::mutex::PiFutexMutex;
+
+    let m = PiFutexMutex::new(0u64);
+    c.bench_function("4 threads pi futex mutex increment", |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..iters {
+                            *m.lock() += 1;
+                        }
+                    });
+                }
+            });
+            start.elapsed()
+        })
+    });
+}
+
+#[cfg(not(all(feature = "linux-pi", target_os = "linux")))]
+fn bench_pi_futex_mutex(_: &mut Criterion) {}
+
+criterion_group!(
+    mutex,
+    bench_single_thread_mutex,
+    bench_multi_thread_mutex,
+    bench_flat_combiner,
+    bench_micro_mutex,
+    bench_fair_mutex,
+    bench_handoff,
+    bench_spin_budget,
+    bench_pi_futex_mutex
+);
 criterion_main!(mutex);
@@ -0,0 +1,91 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::{thread, time::Instant};
+use sync::lock::RwLockLike;
+use sync::rwlock::bench_harness::{run, HarnessLock, Workload};
+use sync::rwlock::{RwLock, WideRwLock};
+
+const WRITE_RATIOS: [f64; 3] = [0.01, 0.1, 0.5];
+
+fn bench_lock<L: HarnessLock>(c: &mut Criterion, name: &str, lock: &L) {
+    for write_ratio in WRITE_RATIOS {
+        let id = format!("{} rwlock {}% write", name, write_ratio * 100.0);
+        c.bench_function(&id, |b| {
+            b.iter_custom(|iters| {
+                let workload = Workload {
+                    ops_per_thread: iters as usize,
+                    write_ratio,
+                    ..Default::default()
+                };
+                run(lock, &workload).elapsed
+            })
+        });
+    }
+}
+
+fn bench_rwlock(c: &mut Criterion) {
+    bench_lock(c, "sync", &RwLock::new(0));
+    #[cfg(feature = "metrics")]
+    print_futex_stats();
+    bench_lock(c, "wide", &WideRwLock::new(0));
+    #[cfg(feature = "metrics")]
+    print_futex_stats();
+    bench_lock(c, "std", &std::sync::RwLock::new(0));
+}
+
+// Futex syscalls of the lock benched last, run with `--features metrics`.
+#[cfg(feature = "metrics")]
+fn print_futex_stats() {
+    for (primitive, stats) in sync::futex::futex_stats() {
+        println!("{}: {:?}", primitive, stats);
+    }
+    sync::futex::reset_futex_stats();
+}
+
+// Read or, one time in ten, increment random locks of an array from 4
+// threads, one hot lock contends heavily while many locks are mostly
+// uncontended. The size of each array is in the bench name.
+fn bench_lock_array<L: RwLockLike<u64> + Sync>(c: &mut Criterion, name: &str, len: usize) {
+    let locks: Vec<L> = (0..len).map(|_| L::new(0)).collect();
+    let id = format!(
+        "4 threads {} array of {} ({} bytes) 10% write",
+        name,
+        len,
+        len * std::mem::size_of::<L>()
+    );
+    c.bench_function(&id, |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            thread::scope(|s| {
+                for t in 0..4u64 {
+                    let locks = &locks;
+                    s.spawn(move || {
+                        let mut x = t + 1;
+                        for _ in 0..iters {
+                            // xorshift
+                            x ^= x << 13;
+                            x ^= x >> 7;
+                            x ^= x << 17;
+                            let lock = &locks[x as usize % len];
+                            if x % 10 == 0 {
+                                *lock.write() += 1;
+                            } else {
+                                black_box(*lock.read());
+                            }
+                        }
+                    });
+                }
+            });
+            start.elapsed()
+        })
+    });
+}
+
+fn bench_rwlock_array(c: &mut Criterion) {
+    for len in [1, 1 << 20] {
+        bench_lock_array::<RwLock<u64>>(c, "rwlock", len);
+        bench_lock_array::<WideRwLock<u64>>(c, "wide rwlock", len);
+    }
+}
+
+criterion_group!(rwlock, bench_rwlock, bench_rwlock_array);
+criterion_main!(rwlock);
@@ -0,0 +1,136 @@
+use std::{
+    mem::ManuallyDrop,
+    ptr::NonNull,
+    sync::atomic::{
+        AtomicPtr, AtomicUsize,
+        Ordering::{Release, SeqCst},
+    },
+};
+
+use super::{Arc, ArcInner};
+use crate::{atomic_ext::Backoff, mutex::Mutex};
+
+/// An Arc which can be loaded and replaced atomically.
+///
+/// Loading never blocks: a reader registers itself in the current epoch
+/// before taking a reference. Replacing flips the epoch and waits until the
+/// readers of the previous epoch are gone before releasing the old Arc,
+/// so new readers never delay a writer.
+pub struct AtomicArc<T> {
+    ptr: AtomicPtr<ArcInner<T>>,
+    epoch: AtomicUsize,
+    // Count of readers registered in even and odd epoch.
+    readers: [AtomicUsize; 2],
+    // Writers are serialized, so at most two epochs are in use.
+    writer: Mutex<()>,
+}
+
+unsafe impl<T: Sync + Send> Send for AtomicArc<T> {}
+unsafe impl<T: Sync + Send> Sync for AtomicArc<T> {}
+
+impl<T> AtomicArc<T> {
+    pub fn new(value: Arc<T>) -> Self {
+        Self {
+            ptr: AtomicPtr::new(ManuallyDrop::new(value).inner.as_ptr()),
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Get a clone of current Arc.
+    pub fn load(&self) -> Arc<T> {
+        let readers = loop {
+            let epoch = self.epoch.load(SeqCst);
+            let readers = &self.readers[epoch % 2];
+            readers.fetch_add(1, SeqCst);
+            // The writer may flip the epoch before registered,
+            // register again in the new epoch to be waited.
+            if self.epoch.load(SeqCst) == epoch {
+                break readers;
+            }
+            readers.fetch_sub(1, Release);
+        };
+
+        // Safety: the Arc is not released while a reader of its epoch is registered.
+        let inner = unsafe { NonNull::new_unchecked(self.ptr.load(SeqCst)) };
+        let arc = ManuallyDrop::new(Arc { inner });
+        let value = Arc::clone(&arc);
+        readers.fetch_sub(1, Release);
+        value
+    }
+
+    /// Replace current Arc with given one.
+    pub fn store(&self, value: Arc<T>) {
+        drop(self.swap(value));
+    }
+
+    /// Replace current Arc with given one, return the previous Arc.
+    pub fn swap(&self, value: Arc<T>) -> Arc<T> {
+        let _writer = self.writer.lock();
+        let new = ManuallyDrop::new(value).inner.as_ptr();
+        let old = self.ptr.swap(new, SeqCst);
+
+        // Wait for readers which may have seen the old pointer.
+        let epoch = self.epoch.fetch_add(1, SeqCst);
+        let mut backoff = Backoff::new();
+        while self.readers[epoch % 2].load(SeqCst) != 0 {
+            backoff.snooze();
+        }
+
+        // Safety: old is taken from an Arc and no reader is going to access it.
+        Arc {
+            inner: unsafe { NonNull::new_unchecked(old) },
+        }
+    }
+}
+
+impl<T> Drop for AtomicArc<T> {
+    fn drop(&mut self) {
+        drop(Arc {
+            inner: NonNull::new(*self.ptr.get_mut()).unwrap(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::thread;
+
+    use super::{Arc, AtomicArc};
+
+    #[test]
+    fn test_atomic_arc() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(usize);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Relaxed);
+            }
+        }
+
+        let cell = AtomicArc::new(Arc::new(DetectDrop(0)));
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1..=1000 {
+                    cell.store(Arc::new(DetectDrop(i)));
+                }
+            });
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..1000 {
+                        let v = cell.load();
+                        assert!(v.0 >= last);
+                        last = v.0;
+                    }
+                });
+            }
+        });
+        assert_eq!(cell.load().0, 1000);
+        assert_eq!(NUM_DROPS.load(Relaxed), 1000);
+        drop(cell);
+        assert_eq!(NUM_DROPS.load(Relaxed), 1001);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{fmt, marker::PhantomData, mem::ManuallyDrop, ops::Deref, ptr::NonNull};
+
+use super::{Arc, ArcInner};
+
+/// A borrowed reference known to be backed by an Arc, by `Arc::borrow_arc`.
+/// It's Copy and passed down call stacks for free, and turned into an Arc
+/// by `clone_arc` only where one must be kept, so calls that merely read
+/// skip the atomic increment and decrement of an Arc clone.
+pub struct ArcBorrow<'a, T> {
+    inner: NonNull<ArcInner<T>>,
+    _arc: PhantomData<&'a Arc<T>>,
+}
+
+// ArcBorrow shares like a &Arc<T>.
+unsafe impl<T: Sync + Send> Send for ArcBorrow<'_, T> {}
+unsafe impl<T: Sync + Send> Sync for ArcBorrow<'_, T> {}
+
+impl<T> Arc<T> {
+    /// Borrow as an ArcBorrow, which may be cloned into an Arc later.
+    pub fn borrow_arc(&self) -> ArcBorrow<'_, T> {
+        ArcBorrow {
+            inner: self.inner,
+            _arc: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> ArcBorrow<'a, T> {
+    /// Clone the backing Arc.
+    pub fn clone_arc(this: Self) -> Arc<T> {
+        // Safety: the borrowed Arc keeps the allocation alive, the clone
+        // takes a count of its own.
+        let arc = ManuallyDrop::new(Arc { inner: this.inner });
+        Arc::clone(&arc)
+    }
+
+    /// The value with the lifetime of the borrow rather than of this.
+    pub fn get(this: Self) -> &'a T {
+        // Safety: the value lives as long as the borrowed Arc.
+        unsafe { &*this.inner.as_ref().data.get() }
+    }
+
+    /// Whether both borrow the same allocation.
+    pub fn ptr_eq(this: Self, other: Self) -> bool {
+        this.inner == other.inner
+    }
+}
+
+impl<T> Clone for ArcBorrow<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ArcBorrow<'_, T> {}
+
+impl<'a, T> From<&'a Arc<T>> for ArcBorrow<'a, T> {
+    fn from(arc: &'a Arc<T>) -> Self {
+        arc.borrow_arc()
+    }
+}
+
+impl<T> Deref for ArcBorrow<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        ArcBorrow::get(*self)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ArcBorrow<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arc, ArcBorrow};
+
+    #[test]
+    fn test_arc_borrow() {
+        struct Registry {
+            kept: Vec<Arc<String>>,
+        }
+
+        // Only the callee that keeps the value pays for a clone.
+        fn visit(name: ArcBorrow<'_, String>, depth: usize, registry: &mut Registry) {
+            if depth == 0 {
+                registry.kept.push(ArcBorrow::clone_arc(name));
+                return;
+            }
+            assert_eq!(name.len(), 4);
+            visit(name, depth - 1, registry);
+        }
+
+        let name = Arc::new(String::from("node"));
+        let mut registry = Registry { kept: Vec::new() };
+        visit(name.borrow_arc(), 10, &mut registry);
+        assert_eq!(Arc::downgrade(&name).strong_count(), 2);
+
+        let borrowed = ArcBorrow::from(&name);
+        let value: &String = ArcBorrow::get(borrowed);
+        assert!(ArcBorrow::ptr_eq(borrowed, registry.kept[0].borrow_arc()));
+        assert_eq!(format!("{:?}", borrowed), "\"node\"");
+        drop(registry);
+        assert_eq!(value, "node");
+        assert_eq!(Arc::downgrade(&name).strong_count(), 1);
+    }
+}
@@ -0,0 +1,75 @@
+use std::{error::Error, fmt};
+
+use super::Arc;
+
+/// A cheaply clonable error, all clones share the same underlying error.
+/// Useful to fan out one failure to many consumers, e.g. by broadcast channel.
+///
+/// Arc only holds sized values, so the error is boxed inside the Arc.
+#[derive(Clone)]
+pub struct SharedError(Arc<Box<dyn Error + Send + Sync>>);
+
+impl SharedError {
+    /// Create a shared error from any error.
+    pub fn new<E>(error: E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        Self(Arc::new(error.into()))
+    }
+
+    /// Get reference of the underlying error.
+    pub fn get_ref(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &**self.0
+    }
+
+    /// Downcast the underlying error to a concrete type.
+    pub fn downcast_ref<E: Error + 'static>(&self) -> Option<&E> {
+        self.get_ref().downcast_ref()
+    }
+}
+
+impl fmt::Debug for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.get_ref(), f)
+    }
+}
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.get_ref(), f)
+    }
+}
+
+impl Error for SharedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.get_ref().source()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, thread};
+
+    use super::SharedError;
+    use crate::channel::broadcast::{self, Policy};
+
+    #[test]
+    fn test_shared_error() {
+        let tx = broadcast::channel(1);
+        let receivers: Vec<_> = (0..2).map(|_| tx.subscribe(Policy::Block)).collect();
+        tx.send(SharedError::new(io::Error::other("disk failure")))
+            .unwrap();
+        drop(tx);
+
+        thread::scope(|s| {
+            for mut rx in receivers {
+                s.spawn(move || {
+                    let err = rx.recv().unwrap();
+                    assert_eq!(err.to_string(), "disk failure");
+                    assert!(err.downcast_ref::<io::Error>().is_some());
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,173 @@
+use std::cell::UnsafeCell;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{Relaxed, Release};
+
+use crate::atomic_ext::atomic_update;
+use crate::tsan::acquire_fence;
+
+struct Inner<T, M> {
+    strong_ref_count: AtomicUsize,
+    weak_ref_count: AtomicUsize,
+    // Lives as long as the allocation, unlike data.
+    meta: M,
+    data: UnsafeCell<ManuallyDrop<T>>,
+}
+
+/// An Arc whose control block carries metadata `M`, which is accessible
+/// from both Arc and Weak even after the data is dropped,
+/// e.g. an ID or a tombstone flag of a cache entry.
+pub struct ArcWithMeta<T, M> {
+    inner: NonNull<Inner<T, M>>,
+}
+
+unsafe impl<T: Sync + Send, M: Sync + Send> Send for ArcWithMeta<T, M> {}
+unsafe impl<T: Sync + Send, M: Sync + Send> Sync for ArcWithMeta<T, M> {}
+
+impl<T, M> ArcWithMeta<T, M> {
+    pub fn new(data: T, meta: M) -> Self {
+        let inner = Box::new(Inner {
+            strong_ref_count: AtomicUsize::new(1),
+            weak_ref_count: AtomicUsize::new(1),
+            meta,
+            data: UnsafeCell::new(ManuallyDrop::new(data)),
+        });
+        Self {
+            inner: NonNull::from(Box::leak(inner)),
+        }
+    }
+
+    /// Get the metadata.
+    pub fn meta(arc: &Self) -> &M {
+        &arc.inner().meta
+    }
+
+    pub fn downgrade(arc: &Self) -> WeakWithMeta<T, M> {
+        if arc.inner().weak_ref_count.fetch_add(1, Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        WeakWithMeta { inner: arc.inner }
+    }
+
+    fn inner(&self) -> &Inner<T, M> {
+        // Safety: the allocation is alive while an Arc exists.
+        unsafe { self.inner.as_ref() }
+    }
+}
+
+impl<T, M> Deref for ArcWithMeta<T, M> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.inner().data.get() }
+    }
+}
+
+impl<T, M> Clone for ArcWithMeta<T, M> {
+    fn clone(&self) -> Self {
+        if self.inner().strong_ref_count.fetch_add(1, Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        Self { inner: self.inner }
+    }
+}
+
+impl<T, M> Drop for ArcWithMeta<T, M> {
+    fn drop(&mut self) {
+        if self.inner().strong_ref_count.fetch_sub(1, Release) != 1 {
+            return;
+        }
+        acquire_fence!(self.inner().strong_ref_count);
+
+        unsafe { ManuallyDrop::drop(&mut *self.inner().data.get()) }
+
+        drop(WeakWithMeta { inner: self.inner });
+    }
+}
+
+/// The Weak of ArcWithMeta, the metadata is accessible even if the data
+/// is dropped.
+pub struct WeakWithMeta<T, M> {
+    inner: NonNull<Inner<T, M>>,
+}
+
+unsafe impl<T: Sync + Send, M: Sync + Send> Send for WeakWithMeta<T, M> {}
+unsafe impl<T: Sync + Send, M: Sync + Send> Sync for WeakWithMeta<T, M> {}
+
+impl<T, M> WeakWithMeta<T, M> {
+    /// Get the metadata, alive or not.
+    pub fn meta(&self) -> &M {
+        &self.inner().meta
+    }
+
+    /// Whether the data is dropped.
+    pub fn is_dead(&self) -> bool {
+        self.inner().strong_ref_count.load(Relaxed) == 0
+    }
+
+    pub fn upgrade(&self) -> Option<ArcWithMeta<T, M>> {
+        atomic_update(&self.inner().strong_ref_count, Relaxed, Relaxed, |n| {
+            (n != 0).then_some(n + 1)
+        })
+        .ok()
+        .map(|_| ArcWithMeta { inner: self.inner })
+    }
+
+    fn inner(&self) -> &Inner<T, M> {
+        // Safety: the allocation is alive while a Weak exists.
+        unsafe { self.inner.as_ref() }
+    }
+}
+
+impl<T, M> Clone for WeakWithMeta<T, M> {
+    fn clone(&self) -> Self {
+        if self.inner().weak_ref_count.fetch_add(1, Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        Self { inner: self.inner }
+    }
+}
+
+impl<T, M> Drop for WeakWithMeta<T, M> {
+    fn drop(&mut self) {
+        if self.inner().weak_ref_count.fetch_sub(1, Release) == 1 {
+            acquire_fence!(self.inner().weak_ref_count);
+            unsafe { drop(Box::from_raw(self.inner.as_ptr())) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+
+    use super::ArcWithMeta;
+
+    #[test]
+    fn test_arc_with_meta() {
+        struct Meta {
+            id: u64,
+            evicted: AtomicBool,
+        }
+        let meta = Meta {
+            id: 7,
+            evicted: AtomicBool::new(false),
+        };
+        let x = ArcWithMeta::new(String::from("entry"), meta);
+        let weak = ArcWithMeta::downgrade(&x);
+        let t = std::thread::spawn(move || {
+            assert_eq!(weak.upgrade().unwrap().as_str(), "entry");
+            weak
+        });
+        let weak = t.join().unwrap();
+        assert_eq!(ArcWithMeta::meta(&x).id, 7);
+        ArcWithMeta::meta(&x).evicted.store(true, Relaxed);
+        assert!(!weak.is_dead());
+        drop(x);
+        // The metadata outlives the data.
+        assert!(weak.is_dead() && weak.upgrade().is_none());
+        assert_eq!(weak.meta().id, 7);
+        assert!(weak.meta().evicted.load(Relaxed));
+    }
+}
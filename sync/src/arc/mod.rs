@@ -1,10 +1,37 @@
 use std::cell::UnsafeCell;
+use std::error::Error;
+use std::fmt;
 use std::mem::ManuallyDrop;
 use std::ops::Deref;
-use std::sync::atomic::fence;
-use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
+use std::sync::atomic::{fence, AtomicU32};
 use std::{ptr::NonNull, sync::atomic::AtomicUsize};
 
+use crate::atomic_ext::{atomic_update, Backoff};
+use crate::futex::{wait, wake_all};
+use crate::tsan::acquire_fence;
+
+mod atomic;
+mod borrow;
+mod error;
+mod meta;
+mod once;
+mod projection;
+mod string;
+pub use atomic::AtomicArc;
+pub use borrow::ArcBorrow;
+pub use error::SharedError;
+pub use meta::{ArcWithMeta, WeakWithMeta};
+pub use once::OnceArc;
+pub use projection::ArcRef;
+pub use string::ArcStr;
+
+// Threads parked by downgrade on a weak count locked by get_mut, and the
+// word they park on. Shared by all Arcs, since get_mut locks briefly and
+// parking is rare.
+static DOWNGRADE_WAITERS: AtomicU32 = AtomicU32::new(0);
+static DOWNGRADE_EPOCH: AtomicU32 = AtomicU32::new(0);
+
 struct ArcInner<T> {
     strong_ref_count: AtomicUsize,
     weak_ref_count: AtomicUsize,
@@ -44,32 +71,43 @@ impl<T> Arc<T> {
         }
         let is_unique = arc.data().strong_ref_count.load(Relaxed) == 1;
         arc.data().weak_ref_count.store(1, Release);
+        // Pairs with the check of downgrade, so either it sees the unlock
+        // or we see it parking.
+        fence(SeqCst);
+        if DOWNGRADE_WAITERS.load(Relaxed) > 0 {
+            DOWNGRADE_EPOCH.fetch_add(1, Release);
+            wake_all(&DOWNGRADE_EPOCH);
+        }
         if !is_unique {
             return None;
         }
 
-        fence(Acquire);
+        acquire_fence!(arc.data().strong_ref_count);
         Some(unsafe { &mut **arc.inner.as_mut().data.get_mut() })
     }
 
     pub fn downgrade(arc: &Self) -> Weak<T> {
-        let mut n = arc.data().weak_ref_count.load(Relaxed);
-        loop {
-            if n == usize::MAX {
-                std::hint::spin_loop();
-                n = arc.data().weak_ref_count.load(Relaxed);
+        let mut backoff = Backoff::new();
+        // Weak count is locked as usize::MAX by get_mut, wait until it's released,
+        // backing off a while then parking.
+        while atomic_update(&arc.data().weak_ref_count, Acquire, Relaxed, |n| {
+            // Lazily, n + 1 overflows on the locked count.
+            (n != usize::MAX).then(|| n + 1)
+        })
+        .is_err()
+        {
+            if !backoff.is_completed() {
+                backoff.snooze();
                 continue;
             }
-            if let Err(e) =
-                arc.data()
-                    .weak_ref_count
-                    .compare_exchange_weak(n, n + 1, Acquire, Relaxed)
-            {
-                n = e;
-                continue;
+            DOWNGRADE_WAITERS.fetch_add(1, SeqCst);
+            let epoch = DOWNGRADE_EPOCH.load(SeqCst);
+            if arc.data().weak_ref_count.load(SeqCst) == usize::MAX {
+                wait(&DOWNGRADE_EPOCH, epoch);
             }
-            return Weak { inner: arc.inner };
+            DOWNGRADE_WAITERS.fetch_sub(1, Relaxed);
         }
+        Weak { inner: arc.inner }
     }
 
     fn data(&self) -> &ArcInner<T> {
@@ -85,6 +123,24 @@ impl<T> Deref for Arc<T> {
     }
 }
 
+impl<T: fmt::Debug> fmt::Debug for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: Error> Error for Arc<T> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        (**self).source()
+    }
+}
+
 impl<T> Clone for Arc<T> {
     fn clone(&self) -> Self {
         if self.data().strong_ref_count.fetch_add(1, Relaxed) > usize::MAX / 2 {
@@ -99,7 +155,7 @@ impl<T> Drop for Arc<T> {
         if self.data().strong_ref_count.fetch_sub(1, Release) != 1 {
             return;
         }
-        fence(Acquire);
+        acquire_fence!(self.data().strong_ref_count);
 
         unsafe { ManuallyDrop::drop(&mut *self.data().data.get()) }
 
@@ -115,33 +171,69 @@ unsafe impl<T: Sync + Send> Send for Weak<T> {}
 unsafe impl<T: Sync + Send> Sync for Weak<T> {}
 
 impl<T> Weak<T> {
-    fn data(&self) -> &ArcInner<T> {
-        unsafe { self.inner.as_ref() }
+    /// Create a dangling Weak without allocation, it never upgrades.
+    pub const fn new() -> Self {
+        // Safety: the sentinel is not null, and never dereferenced.
+        Weak {
+            inner: unsafe { NonNull::new_unchecked(std::ptr::without_provenance_mut(usize::MAX)) },
+        }
     }
 
-    pub fn upgrade(&self) -> Option<Arc<T>> {
-        let mut n = self.data().strong_ref_count.load(Relaxed);
-        loop {
-            if n == 0 {
-                return None;
-            }
-            if let Err(e) =
-                self.data()
-                    .strong_ref_count
-                    .compare_exchange_weak(n, n + 1, Relaxed, Relaxed)
-            {
-                n = e;
-                continue;
-            }
-            return Some(Arc { inner: self.inner });
+    /// None if the Weak is created by `Weak::new`.
+    fn data(&self) -> Option<&ArcInner<T>> {
+        if self.inner.as_ptr() as usize == usize::MAX {
+            return None;
         }
+        Some(unsafe { self.inner.as_ref() })
+    }
+
+    /// Count of Arc pointing to the same allocation.
+    pub fn strong_count(&self) -> usize {
+        self.data()
+            .map_or(0, |data| data.strong_ref_count.load(Relaxed))
+    }
+
+    /// Count of Weak pointing to the same allocation,
+    /// 0 if there's no Arc left.
+    pub fn weak_count(&self) -> usize {
+        let Some(data) = self.data() else {
+            return 0;
+        };
+        let weak = data.weak_ref_count.load(Relaxed);
+        if data.strong_ref_count.load(Relaxed) == 0 {
+            return 0;
+        }
+        // All Arc together hold one weak count.
+        weak - 1
+    }
+
+    /// Whether the data is dropped or the Weak is created by `Weak::new`,
+    /// checked without upgrading.
+    pub fn is_dangling(&self) -> bool {
+        self.strong_count() == 0
+    }
+
+    pub fn upgrade(&self) -> Option<Arc<T>> {
+        atomic_update(&self.data()?.strong_ref_count, Relaxed, Relaxed, |n| {
+            (n != 0).then_some(n + 1)
+        })
+        .ok()
+        .map(|_| Arc { inner: self.inner })
+    }
+}
+
+impl<T> Default for Weak<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl<T> Clone for Weak<T> {
     fn clone(&self) -> Self {
-        if self.data().weak_ref_count.fetch_add(1, Relaxed) > usize::MAX / 2 {
-            std::process::abort();
+        if let Some(data) = self.data() {
+            if data.weak_ref_count.fetch_add(1, Relaxed) > usize::MAX / 2 {
+                std::process::abort();
+            }
         }
         Weak { inner: self.inner }
     }
@@ -149,8 +241,11 @@ impl<T> Clone for Weak<T> {
 
 impl<T> Drop for Weak<T> {
     fn drop(&mut self) {
-        if self.data().weak_ref_count.fetch_sub(1, Release) == 1 {
-            fence(Acquire);
+        let Some(data) = self.data() else {
+            return;
+        };
+        if data.weak_ref_count.fetch_sub(1, Release) == 1 {
+            acquire_fence!(data.weak_ref_count);
             unsafe { drop(Box::from_raw(self.inner.as_ptr())) }
         }
     }
@@ -195,4 +290,46 @@ mod tests {
         assert_eq!(NUM_DROPS.load(Relaxed), 1);
         assert!(z.upgrade().is_none());
     }
+
+    #[test]
+    fn test_weak_counts() {
+        #[derive(Default)]
+        struct Node {
+            parent: Weak<i32>,
+        }
+        let node = Node::default();
+        assert!(node.parent.is_dangling() && node.parent.upgrade().is_none());
+        assert_eq!(node.parent.clone().weak_count(), 0);
+
+        let x = Arc::new(1);
+        let y = x.clone();
+        let w = Arc::downgrade(&x);
+        let w2 = w.clone();
+        assert_eq!((w.strong_count(), w.weak_count()), (2, 2));
+        assert!(!w.is_dangling());
+        drop(w2);
+        drop((x, y));
+        assert_eq!((w.strong_count(), w.weak_count()), (0, 0));
+        assert!(w.is_dangling());
+    }
+
+    #[test]
+    fn test_downgrade_racing_get_mut() {
+        let mut x = Arc::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                let mut x = x.clone();
+                s.spawn(move || {
+                    for _ in 0..10000 {
+                        // Locks the weak count briefly, and fails as x is shared.
+                        assert!(Arc::get_mut(&mut x).is_none());
+                        let w = Arc::downgrade(&x);
+                        assert_eq!(w.upgrade().as_deref(), Some(&0));
+                    }
+                });
+            }
+        });
+        *Arc::get_mut(&mut x).unwrap() += 1;
+        assert_eq!(Arc::downgrade(&x).weak_count(), 1);
+    }
 }
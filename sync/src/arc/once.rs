@@ -0,0 +1,73 @@
+use super::Arc;
+use crate::rwlock::RwLock;
+
+/// An Arc initialized once on first access, every caller gets a clone of
+/// the same Arc until it's reset, e.g. for a lazy global shared object.
+pub struct OnceArc<T> {
+    slot: RwLock<Option<Arc<T>>>,
+}
+
+impl<T> Default for OnceArc<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> OnceArc<T> {
+    pub const fn new() -> Self {
+        Self {
+            slot: RwLock::new(None),
+        }
+    }
+
+    /// Get the Arc if it's initialized.
+    pub fn get(&self) -> Option<Arc<T>> {
+        self.slot.read().clone()
+    }
+
+    /// Get the Arc, initialize it with f if it's not initialized.
+    /// Only one caller runs f, the others block until it's done.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> Arc<T> {
+        if let Some(arc) = self.get() {
+            return arc;
+        }
+        let mut slot = self.slot.write();
+        slot.get_or_insert_with(|| Arc::new(f())).clone()
+    }
+
+    /// Drop the reference of the Arc and return it,
+    /// the next access initializes a new one.
+    pub fn reset(&self) -> Option<Arc<T>> {
+        self.slot.write().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::thread;
+
+    use super::OnceArc;
+
+    #[test]
+    fn test_once_arc() {
+        static POOL: OnceArc<String> = OnceArc::new();
+        static INITS: AtomicUsize = AtomicUsize::new(0);
+        let init = || {
+            INITS.fetch_add(1, Relaxed);
+            String::from("pool")
+        };
+        assert!(POOL.get().is_none());
+        let arcs: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = (0..8).map(|_| s.spawn(|| POOL.get_or_init(init))).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(INITS.load(Relaxed), 1);
+        assert!(arcs.iter().all(|a| a.as_str() == "pool"));
+        let old = POOL.reset().unwrap();
+        assert_eq!(old.as_str(), "pool");
+        assert!(POOL.get().is_none());
+        POOL.get_or_init(init);
+        assert_eq!(INITS.load(Relaxed), 2);
+    }
+}
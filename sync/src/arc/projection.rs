@@ -0,0 +1,91 @@
+use std::{ops::Deref, ptr::NonNull};
+
+use super::Arc;
+
+/// An owning reference into a part of the value of an Arc.
+/// The whole allocation is kept alive while dereferencing to the part.
+pub struct ArcRef<T, U: ?Sized> {
+    owner: Arc<T>,
+    value: NonNull<U>,
+}
+
+// ArcRef shares U between threads, and may drop T on any thread.
+unsafe impl<T: Sync + Send, U: ?Sized + Sync> Send for ArcRef<T, U> {}
+unsafe impl<T: Sync + Send, U: ?Sized + Sync> Sync for ArcRef<T, U> {}
+
+impl<T, U: ?Sized> ArcRef<T, U> {
+    /// Project an Arc into a part of its value.
+    pub fn map<F>(owner: Arc<T>, f: F) -> Self
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        let value = NonNull::from(f(&owner));
+        Self { owner, value }
+    }
+
+    /// Project further into a part of the referenced value.
+    pub fn project<V: ?Sized, F>(this: Self, f: F) -> ArcRef<T, V>
+    where
+        F: FnOnce(&U) -> &V,
+    {
+        let value = NonNull::from(f(&this));
+        ArcRef {
+            owner: this.owner,
+            value,
+        }
+    }
+
+    /// Get the Arc owning the referenced value.
+    pub fn owner(this: &Self) -> &Arc<T> {
+        &this.owner
+    }
+}
+
+impl<T> From<Arc<T>> for ArcRef<T, T> {
+    fn from(owner: Arc<T>) -> Self {
+        Self::map(owner, |t| t)
+    }
+}
+
+impl<T, U: ?Sized> Deref for ArcRef<T, U> {
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        // Safety: the value is borrowed from the data of owner,
+        // which is never moved or dropped while owner is alive.
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<T, U: ?Sized> Clone for ArcRef<T, U> {
+    fn clone(&self) -> Self {
+        Self {
+            owner: self.owner.clone(),
+            value: self.value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arc, ArcRef};
+
+    #[test]
+    fn test_arc_ref() {
+        struct Config {
+            name: String,
+            ports: Vec<u16>,
+        }
+        let config = Arc::new(Config {
+            name: String::from("server"),
+            ports: vec![80, 443],
+        });
+        let name: ArcRef<Config, str> = ArcRef::map(config.clone(), |c| c.name.as_str());
+        let port = ArcRef::project(ArcRef::map(config, |c| &c.ports), |p| &p[1]);
+        let t = std::thread::spawn(move || {
+            assert_eq!(&*name, "server");
+            assert_eq!(ArcRef::owner(&name).ports.len(), 2);
+        });
+        t.join().unwrap();
+        assert_eq!(*port, 443);
+    }
+}
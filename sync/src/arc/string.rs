@@ -0,0 +1,209 @@
+use std::alloc::{self, Layout};
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{Relaxed, Release};
+
+use crate::tsan::acquire_fence;
+
+// The header of the allocation, followed by len bytes of the string.
+#[repr(C)]
+struct Header {
+    ref_count: AtomicUsize,
+    len: usize,
+}
+
+/// An immutable string shared by atomic reference counting, with the count,
+/// length and bytes in one allocation, unlike `Arc<String>` which points to
+/// the String then to the bytes.
+pub struct ArcStr {
+    inner: NonNull<Header>,
+}
+
+unsafe impl Send for ArcStr {}
+unsafe impl Sync for ArcStr {}
+
+impl ArcStr {
+    pub fn new(s: &str) -> Self {
+        let layout = Self::layout(s.len());
+        // Safety: the layout is never zero-sized for the header,
+        // and the bytes are written right after the header.
+        unsafe {
+            let header = alloc::alloc(layout) as *mut Header;
+            let Some(inner) = NonNull::new(header) else {
+                alloc::handle_alloc_error(layout)
+            };
+            header.write(Header {
+                ref_count: AtomicUsize::new(1),
+                len: s.len(),
+            });
+            ptr::copy_nonoverlapping(s.as_ptr(), header.add(1) as *mut u8, s.len());
+            Self { inner }
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Safety: the bytes are copied from a str and never mutated.
+        unsafe {
+            let bytes = self.inner.as_ptr().add(1) as *const u8;
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(bytes, self.header().len))
+        }
+    }
+
+    /// Count of ArcStr sharing the string.
+    pub fn strong_count(this: &Self) -> usize {
+        this.header().ref_count.load(Relaxed)
+    }
+
+    /// Whether both share the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.inner == other.inner
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::new::<Header>()
+            .extend(Layout::array::<u8>(len).unwrap())
+            .unwrap()
+            .0
+    }
+
+    fn header(&self) -> &Header {
+        // Safety: the allocation is alive while an ArcStr exists.
+        unsafe { self.inner.as_ref() }
+    }
+}
+
+impl Deref for ArcStr {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Clone for ArcStr {
+    fn clone(&self) -> Self {
+        if self.header().ref_count.fetch_add(1, Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        Self { inner: self.inner }
+    }
+}
+
+impl Drop for ArcStr {
+    fn drop(&mut self) {
+        if self.header().ref_count.fetch_sub(1, Release) != 1 {
+            return;
+        }
+        acquire_fence!(self.header().ref_count);
+        let layout = Self::layout(self.header().len);
+        // Safety: the last ArcStr frees the allocation by its own layout.
+        unsafe { alloc::dealloc(self.inner.as_ptr() as *mut u8, layout) }
+    }
+}
+
+impl From<&str> for ArcStr {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl From<String> for ArcStr {
+    fn from(s: String) -> Self {
+        Self::new(&s)
+    }
+}
+
+impl Default for ArcStr {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl AsRef<str> for ArcStr {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl Borrow<str> for ArcStr {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl PartialEq for ArcStr {
+    fn eq(&self, other: &Self) -> bool {
+        Self::ptr_eq(self, other) || self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for ArcStr {}
+
+impl PartialEq<str> for ArcStr {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ArcStr {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialOrd for ArcStr {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ArcStr {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for ArcStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for ArcStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for ArcStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::ArcStr;
+
+    #[test]
+    fn test_arc_str() {
+        let x = ArcStr::from("hello");
+        let y = x.clone();
+        assert_eq!(ArcStr::strong_count(&x), 2);
+        let t = std::thread::spawn(move || y.to_uppercase());
+        assert_eq!(t.join().unwrap(), "HELLO");
+        assert_eq!(ArcStr::strong_count(&x), 1);
+        assert_eq!(x, "hello");
+        assert_eq!(x, ArcStr::from(String::from("hello")));
+        assert_eq!(&*ArcStr::default(), "");
+        assert_eq!(format!("{:?}", x), "\"hello\"");
+
+        let set: HashSet<ArcStr> = ["a", "b", "a"].into_iter().map(ArcStr::from).collect();
+        assert!(set.len() == 2 && set.contains("a"));
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use super::{atomic_update, load_order};
+
+macro_rules! atomic_float {
+    ($(#[$doc:meta])* $name:ident, $float:ty, $atomic:ty) => {
+        $(#[$doc])*
+        #[derive(Default)]
+        pub struct $name {
+            bits: $atomic,
+        }
+
+        impl $name {
+            pub const fn new(value: $float) -> Self {
+                Self {
+                    bits: <$atomic>::new(value.to_bits()),
+                }
+            }
+
+            pub fn load(&self, order: Ordering) -> $float {
+                <$float>::from_bits(self.bits.load(order))
+            }
+
+            pub fn store(&self, value: $float, order: Ordering) {
+                self.bits.store(value.to_bits(), order)
+            }
+
+            pub fn swap(&self, value: $float, order: Ordering) -> $float {
+                <$float>::from_bits(self.bits.swap(value.to_bits(), order))
+            }
+
+            pub fn into_inner(self) -> $float {
+                <$float>::from_bits(self.bits.into_inner())
+            }
+
+            /// Update the value with f in a CAS loop, see `atomic_update`.
+            pub fn fetch_update<F>(
+                &self,
+                set_order: Ordering,
+                fetch_order: Ordering,
+                mut f: F,
+            ) -> Result<$float, $float>
+            where
+                F: FnMut($float) -> Option<$float>,
+            {
+                atomic_update(&self.bits, set_order, fetch_order, |bits| {
+                    f(<$float>::from_bits(bits)).map(<$float>::to_bits)
+                })
+                .map(<$float>::from_bits)
+                .map_err(<$float>::from_bits)
+            }
+
+            /// Add to the current value, return the previous value.
+            pub fn fetch_add(&self, value: $float, order: Ordering) -> $float {
+                self.fetch(order, |v| Some(v + value))
+            }
+
+            /// Subtract from the current value, return the previous value.
+            pub fn fetch_sub(&self, value: $float, order: Ordering) -> $float {
+                self.fetch(order, |v| Some(v - value))
+            }
+
+            /// Store the maximum of current and given value, return the previous value.
+            /// NaN is ignored like `max` does.
+            pub fn fetch_max(&self, value: $float, order: Ordering) -> $float {
+                self.fetch(order, |v| (v.max(value) != v).then_some(value))
+            }
+
+            /// Store the minimum of current and given value, return the previous value.
+            /// NaN is ignored like `min` does.
+            pub fn fetch_min(&self, value: $float, order: Ordering) -> $float {
+                self.fetch(order, |v| (v.min(value) != v).then_some(value))
+            }
+
+            fn fetch<F>(&self, order: Ordering, f: F) -> $float
+            where
+                F: FnMut($float) -> Option<$float>,
+            {
+                self.fetch_update(order, load_order(order), f)
+                    .unwrap_or_else(|v| v)
+            }
+        }
+
+        impl From<$float> for $name {
+            fn from(value: $float) -> Self {
+                Self::new(value)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.load(Ordering::Relaxed), f)
+            }
+        }
+    };
+}
+
+atomic_float!(
+    /// A f64 atomic, bit-casted onto `AtomicU64`.
+    AtomicF64,
+    f64,
+    AtomicU64
+);
+
+atomic_float!(
+    /// A f32 atomic, bit-casted onto `AtomicU32`.
+    AtomicF32,
+    f32,
+    AtomicU32
+);
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::thread;
+
+    use super::{AtomicF32, AtomicF64};
+
+    #[test]
+    fn test_atomic_float() {
+        let sum = AtomicF64::new(0.0);
+        let max = AtomicF32::new(f32::MIN);
+        let min = AtomicF32::new(f32::MAX);
+        thread::scope(|s| {
+            for t in 0..4 {
+                let (sum, max, min) = (&sum, &max, &min);
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        sum.fetch_add(0.5, Relaxed);
+                        max.fetch_max((t * 1000 + i) as f32, Relaxed);
+                        min.fetch_min((t * 1000 + i) as f32, Relaxed);
+                    }
+                });
+            }
+        });
+        assert_eq!(sum.load(Relaxed), 2000.0);
+        assert_eq!(max.load(Relaxed), 3999.0);
+        assert_eq!(min.load(Relaxed), 0.0);
+        assert_eq!(max.fetch_max(f32::NAN, Relaxed), 3999.0);
+        assert_eq!(sum.fetch_sub(2000.0, Relaxed), 2000.0);
+        assert_eq!(sum.into_inner(), 0.0);
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    hint,
+    sync::atomic::{
+        AtomicI32, AtomicI64, AtomicIsize, AtomicU32, AtomicU64, AtomicUsize,
+        Ordering::{self, AcqRel, Acquire, Relaxed, Release, SeqCst},
+    },
+    thread,
+};
+
+use crate::config;
+
+mod float;
+mod pair;
+
+pub use float::{AtomicF32, AtomicF64};
+pub use pair::AtomicPair;
+
+/// Exponential backoff for retry loops on atomics, capped by the limits
+/// of `config::SyncConfig`.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    pub const fn new() -> Self {
+        Self { step: 0 }
+    }
+
+    /// Back off after a failed CAS, other threads are making progress.
+    pub fn spin(&mut self) {
+        crate::futex::yield_point();
+        let spin_limit = config::get().backoff_spin_limit;
+        for _ in 0..1u32 << self.step.min(spin_limit) {
+            hint::spin_loop();
+        }
+        if self.step <= spin_limit {
+            self.step += 1;
+        }
+    }
+
+    /// Back off while waiting another thread to release something,
+    /// yield the thread once spinning is exhausted.
+    pub fn snooze(&mut self) {
+        crate::futex::yield_point();
+        let config = config::get();
+        if self.step <= config.backoff_spin_limit {
+            for _ in 0..1 << self.step {
+                hint::spin_loop();
+            }
+        } else {
+            thread::yield_now();
+        }
+        if self.step <= config.backoff_yield_limit {
+            self.step += 1;
+        }
+    }
+
+    /// Return true if backing off is no longer cheaper than blocking.
+    pub fn is_completed(&self) -> bool {
+        self.step > config::get().backoff_yield_limit
+    }
+
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+}
+
+/// Integer atomics supported by the update helpers.
+pub trait AtomicInteger {
+    type Value: Copy + Ord;
+
+    fn load(&self, order: Ordering) -> Self::Value;
+
+    fn compare_exchange_weak(
+        &self,
+        current: Self::Value,
+        new: Self::Value,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Self::Value, Self::Value>;
+}
+
+macro_rules! impl_atomic_integer {
+    ($($atomic:ty => $value:ty),*) => {
+        $(
+            impl AtomicInteger for $atomic {
+                type Value = $value;
+
+                fn load(&self, order: Ordering) -> $value {
+                    <$atomic>::load(self, order)
+                }
+
+                fn compare_exchange_weak(
+                    &self,
+                    current: $value,
+                    new: $value,
+                    success: Ordering,
+                    failure: Ordering,
+                ) -> Result<$value, $value> {
+                    <$atomic>::compare_exchange_weak(self, current, new, success, failure)
+                }
+            }
+        )*
+    };
+}
+
+impl_atomic_integer!(
+    AtomicU32 => u32,
+    AtomicU64 => u64,
+    AtomicUsize => usize,
+    AtomicI32 => i32,
+    AtomicI64 => i64,
+    AtomicIsize => isize
+);
+
+/// Update the atomic with f in a weak CAS loop, backing off on contention.
+/// Return `Ok(previous)` if updated, or `Err(current)` once f returns None.
+pub fn atomic_update<A, F>(
+    atomic: &A,
+    set_order: Ordering,
+    fetch_order: Ordering,
+    mut f: F,
+) -> Result<A::Value, A::Value>
+where
+    A: AtomicInteger,
+    F: FnMut(A::Value) -> Option<A::Value>,
+{
+    let mut backoff = Backoff::new();
+    let mut prev = atomic.load(fetch_order);
+    while let Some(next) = f(prev) {
+        match atomic.compare_exchange_weak(prev, next, set_order, fetch_order) {
+            Ok(v) => return Ok(v),
+            Err(v) => {
+                prev = v;
+                backoff.spin();
+            }
+        }
+    }
+    Err(prev)
+}
+
+/// Store the maximum of current and given value, return the previous value.
+pub fn fetch_max<A: AtomicInteger>(atomic: &A, value: A::Value, order: Ordering) -> A::Value {
+    atomic_update(atomic, order, load_order(order), |v| {
+        (value > v).then_some(value)
+    })
+    .unwrap_or_else(|v| v)
+}
+
+/// Store the minimum of current and given value, return the previous value.
+pub fn fetch_min<A: AtomicInteger>(atomic: &A, value: A::Value, order: Ordering) -> A::Value {
+    atomic_update(atomic, order, load_order(order), |v| {
+        (value < v).then_some(value)
+    })
+    .unwrap_or_else(|v| v)
+}
+
+/// The strongest ordering allowed for the load part of an update.
+fn load_order(order: Ordering) -> Ordering {
+    match order {
+        Release | Relaxed => Relaxed,
+        AcqRel | Acquire => Acquire,
+        _ => SeqCst,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+    use std::thread;
+
+    use super::{atomic_update, fetch_max, fetch_min};
+
+    #[test]
+    fn test_atomic_update() {
+        let counter = AtomicUsize::new(0);
+        let max = AtomicU64::new(0);
+        let min = AtomicU64::new(u64::MAX);
+        thread::scope(|s| {
+            for t in 0..4 {
+                let (counter, max, min) = (&counter, &max, &min);
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        atomic_update(counter, Relaxed, Relaxed, |n| Some(n + 1)).unwrap();
+                        fetch_max(max, t * 1000 + i, Relaxed);
+                        fetch_min(min, t * 1000 + i, Relaxed);
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.load(Relaxed), 4000);
+        assert_eq!(max.load(Relaxed), 3999);
+        assert_eq!(min.load(Relaxed), 0);
+        assert_eq!(
+            atomic_update(&counter, Relaxed, Relaxed, |_| None),
+            Err(4000)
+        );
+    }
+}
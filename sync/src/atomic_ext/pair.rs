@@ -0,0 +1,235 @@
+use std::{
+    fmt,
+    sync::atomic::{
+        fence, AtomicU64,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use super::Backoff;
+
+/// A double-width atomic on a `(u64, u64)` pair, e.g. a pointer with
+/// a full-width tag or version counter.
+///
+/// It's lock-free with `cmpxchg16b` on x86_64, otherwise both words are
+/// guarded by a sequence lock: readers never write, writers serialize.
+/// All operations are at least acquire-release.
+#[repr(C, align(16))]
+pub struct AtomicPair {
+    lo: AtomicU64,
+    hi: AtomicU64,
+    // Sequence of the fallback lock, odd while writing.
+    seq: AtomicU64,
+}
+
+impl AtomicPair {
+    pub const fn new(value: (u64, u64)) -> Self {
+        Self {
+            lo: AtomicU64::new(value.0),
+            hi: AtomicU64::new(value.1),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Return true if the pair is lock-free on this cpu.
+    pub fn is_lock_free() -> bool {
+        cas16::is_supported()
+    }
+
+    pub fn load(&self) -> (u64, u64) {
+        if cas16::is_supported() {
+            // A CAS never succeeding unless the pair is zero, which writes zero back.
+            return self.compare_exchange((0, 0), (0, 0)).unwrap_or_else(|v| v);
+        }
+        let mut backoff = Backoff::new();
+        loop {
+            let seq = self.seq.load(Acquire);
+            if seq.is_multiple_of(2) {
+                let value = (self.lo.load(Relaxed), self.hi.load(Relaxed));
+                fence(Acquire);
+                if self.seq.load(Relaxed) == seq {
+                    return value;
+                }
+            }
+            backoff.snooze();
+        }
+    }
+
+    pub fn store(&self, value: (u64, u64)) {
+        self.swap(value);
+    }
+
+    /// Store the value, return the previous value.
+    pub fn swap(&self, value: (u64, u64)) -> (u64, u64) {
+        if cas16::is_supported() {
+            let mut current = self.load();
+            loop {
+                match self.compare_exchange(current, value) {
+                    Ok(prev) => return prev,
+                    Err(prev) => current = prev,
+                }
+            }
+        }
+        self.write_locked(|_| Some(value))
+    }
+
+    /// Store new if the pair equals current.
+    /// Return `Ok(previous)` if stored, `Err(actual)` otherwise.
+    pub fn compare_exchange(
+        &self,
+        current: (u64, u64),
+        new: (u64, u64),
+    ) -> Result<(u64, u64), (u64, u64)> {
+        if cas16::is_supported() {
+            // The assembly is invisible to TSan, annotate it as acquire-release.
+            crate::tsan::happens_before(self);
+            // Safety: cmpxchg16b is supported and the pair is 16-byte aligned.
+            let result = unsafe { cas16::compare_exchange(self.lo.as_ptr().cast(), current, new) };
+            crate::tsan::happens_after(self);
+            return result;
+        }
+        let prev = self.write_locked(|v| (v == current).then_some(new));
+        if prev == current {
+            Ok(prev)
+        } else {
+            Err(prev)
+        }
+    }
+
+    pub fn into_inner(self) -> (u64, u64) {
+        (self.lo.into_inner(), self.hi.into_inner())
+    }
+
+    /// Lock the fallback sequence lock, store the value f returns if any.
+    /// Return the previous value.
+    fn write_locked(&self, f: impl FnOnce((u64, u64)) -> Option<(u64, u64)>) -> (u64, u64) {
+        let mut backoff = Backoff::new();
+        let seq = loop {
+            let seq = self.seq.load(Relaxed);
+            if seq.is_multiple_of(2)
+                && self
+                    .seq
+                    .compare_exchange_weak(seq, seq + 1, Acquire, Relaxed)
+                    .is_ok()
+            {
+                break seq;
+            }
+            backoff.snooze();
+        };
+        // Readers must not see the new value with the old sequence.
+        fence(Release);
+        let prev = (self.lo.load(Relaxed), self.hi.load(Relaxed));
+        if let Some(value) = f(prev) {
+            self.lo.store(value.0, Relaxed);
+            self.hi.store(value.1, Relaxed);
+        }
+        self.seq.store(seq + 2, Release);
+        prev
+    }
+}
+
+impl Default for AtomicPair {
+    fn default() -> Self {
+        Self::new((0, 0))
+    }
+}
+
+impl fmt::Debug for AtomicPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.load(), f)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod cas16 {
+    use std::{arch::asm, sync::OnceLock};
+
+    pub(super) fn is_supported() -> bool {
+        static SUPPORTED: OnceLock<bool> = OnceLock::new();
+        *SUPPORTED.get_or_init(|| std::is_x86_feature_detected!("cmpxchg16b"))
+    }
+
+    /// Safety: cmpxchg16b must be supported, ptr must be valid and 16-byte aligned.
+    pub(super) unsafe fn compare_exchange(
+        ptr: *mut u128,
+        current: (u64, u64),
+        new: (u64, u64),
+    ) -> Result<(u64, u64), (u64, u64)> {
+        let (lo, hi): (u64, u64);
+        let ok: u8;
+        // rbx is reserved by llvm, swap the low word of new in and out.
+        asm!(
+            "xchg {new_lo}, rbx",
+            "lock cmpxchg16b xmmword ptr [{ptr}]",
+            "sete {ok}",
+            "mov rbx, {new_lo}",
+            ptr = in(reg) ptr,
+            new_lo = inout(reg) new.0 => _,
+            ok = out(reg_byte) ok,
+            in("rcx") new.1,
+            inout("rax") current.0 => lo,
+            inout("rdx") current.1 => hi,
+            options(nostack),
+        );
+        if ok != 0 {
+            Ok((lo, hi))
+        } else {
+            Err((lo, hi))
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod cas16 {
+    pub(super) fn is_supported() -> bool {
+        false
+    }
+
+    pub(super) unsafe fn compare_exchange(
+        _: *mut u128,
+        _: (u64, u64),
+        _: (u64, u64),
+    ) -> Result<(u64, u64), (u64, u64)> {
+        unreachable!("no cmpxchg16b")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::AtomicPair;
+
+    #[test]
+    fn test_atomic_pair() {
+        let pair = AtomicPair::new((0, 0));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        let mut current = pair.load();
+                        // Both words always move together.
+                        assert_eq!(current.0, current.1);
+                        while let Err(v) =
+                            pair.compare_exchange(current, (current.0 + 1, current.1 + 1))
+                        {
+                            current = v;
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(pair.load(), (40_000, 40_000));
+        assert_eq!(pair.swap((1, u64::MAX)), (40_000, 40_000));
+        assert_eq!(pair.compare_exchange((0, 0), (2, 2)), Err((1, u64::MAX)));
+        assert_eq!(pair.into_inner(), (1, u64::MAX));
+    }
+
+    #[test]
+    fn test_atomic_pair_fallback() {
+        let pair = AtomicPair::new((1, 2));
+        assert_eq!(pair.write_locked(|_| Some((3, 4))), (1, 2));
+        assert_eq!(pair.write_locked(|_| None), (3, 4));
+        assert_eq!(pair.seq.into_inner(), 4);
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use crate::{condvar::Condvar, mutex::Mutex};
+
+/// Result of a barrier wait, exactly one waiter of a phase is the leader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    is_leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Return true for the last arrival of the phase.
+    pub fn is_leader(&self) -> bool {
+        self.is_leader
+    }
+}
+
+/// Generation logic shared by the blocking and async barrier.
+struct Generation {
+    num_threads: usize,
+    arrived: usize,
+    id: u64,
+}
+
+impl Generation {
+    fn new(num_threads: usize) -> Self {
+        Self {
+            num_threads: num_threads.max(1),
+            arrived: 0,
+            id: 0,
+        }
+    }
+
+    /// Register an arrival, return the generation arrived at
+    /// and whether it's the leader which completes the generation.
+    fn arrive(&mut self) -> (u64, bool) {
+        let id = self.id;
+        self.arrived += 1;
+        if self.arrived < self.num_threads {
+            return (id, false);
+        }
+        self.arrived = 0;
+        self.id += 1;
+        (id, true)
+    }
+
+    fn is_completed(&self, id: u64) -> bool {
+        self.id != id
+    }
+}
+
+/// A barrier blocking threads until all of them arrive.
+pub struct Barrier {
+    generation: Mutex<Generation>,
+    completed: Condvar,
+}
+
+impl Barrier {
+    /// Create a barrier for given count of threads.
+    pub fn new(num_threads: usize) -> Self {
+        Self {
+            generation: Mutex::new(Generation::new(num_threads)),
+            completed: Condvar::new(),
+        }
+    }
+
+    /// Block until all threads arrive at the barrier.
+    pub fn wait(&self) -> BarrierWaitResult {
+        let mut generation = self.generation.lock();
+        let (id, is_leader) = generation.arrive();
+        if is_leader {
+            drop(generation);
+            self.completed.notify_all();
+        } else {
+            while !generation.is_completed(id) {
+                generation = self.completed.wait(generation);
+            }
+        }
+        BarrierWaitResult { is_leader }
+    }
+}
+
+/// A barrier for async tasks, waiting tasks are woken by their wakers.
+pub struct AsyncBarrier {
+    state: Mutex<(Generation, Vec<Waker>)>,
+}
+
+impl AsyncBarrier {
+    /// Create a barrier for given count of tasks.
+    pub fn new(num_tasks: usize) -> Self {
+        Self {
+            state: Mutex::new((Generation::new(num_tasks), Vec::new())),
+        }
+    }
+
+    /// Wait until all tasks arrive at the barrier.
+    /// A task arrives at the first poll, the arrival is not revoked
+    /// even if the future is dropped before completion.
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            arrival: None,
+        }
+    }
+}
+
+/// Future returned by `AsyncBarrier::wait`.
+pub struct BarrierWait<'a> {
+    barrier: &'a AsyncBarrier,
+    // The generation arrived at.
+    arrival: Option<u64>,
+}
+
+impl Future for BarrierWait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.barrier.state.lock();
+        let (generation, wakers) = &mut *state;
+        let id = match self.arrival {
+            Some(id) => id,
+            None => {
+                let (id, is_leader) = generation.arrive();
+                if is_leader {
+                    wakers.drain(..).for_each(Waker::wake);
+                    return Poll::Ready(BarrierWaitResult { is_leader });
+                }
+                id
+            }
+        };
+        if generation.is_completed(id) {
+            return Poll::Ready(BarrierWaitResult { is_leader: false });
+        }
+        if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        drop(state);
+        self.arrival = Some(id);
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::thread;
+
+    use super::{AsyncBarrier, Barrier};
+    use crate::task::block_on;
+
+    #[test]
+    fn test_barrier() {
+        let barrier = Barrier::new(4);
+        let async_barrier = AsyncBarrier::new(4);
+        let leaders = AtomicUsize::new(0);
+        let phase = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for i in 0..10 {
+                        assert_eq!(phase.load(Relaxed) / 4, i);
+                        if barrier.wait().is_leader() {
+                            leaders.fetch_add(1, Relaxed);
+                        }
+                        phase.fetch_add(1, Relaxed);
+                        if block_on(async_barrier.wait()).is_leader() {
+                            leaders.fetch_add(1, Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(leaders.load(Relaxed), 20);
+    }
+}
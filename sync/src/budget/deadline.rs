@@ -0,0 +1,226 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    sync::{
+        atomic::{
+            AtomicU32,
+            Ordering::{Acquire, Release},
+        },
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    futex::{wait, wait_timeout, wake_one},
+    mutex::Mutex,
+    time,
+};
+
+/// How long an `acquire` without deadline waits before it's served as if
+/// its deadline passed, so deadline-tagged waits never starve it.
+pub const DEFAULT_AGING: Duration = Duration::from_secs(1);
+
+const WAITING: u32 = 0;
+const GRANTED: u32 = 1;
+
+/// A counting semaphore serving waiters by the earliest deadline first,
+/// e.g. for admission control in services with latency objectives: a
+/// request close to its deadline jumps ahead of those with time to spare.
+///
+/// A released permit is handed to the first waiter directly, so new
+/// acquirers never barge ahead of waiters.
+pub struct DeadlineSemaphore {
+    state: Mutex<State>,
+    aging: Duration,
+}
+
+struct State {
+    permits: usize,
+    // Waiters by deadline, then arrival. Each parks on its own word.
+    waiters: BTreeMap<(Instant, u64), Arc<AtomicU32>>,
+    next_seq: u64,
+}
+
+/// Error returned by `DeadlineSemaphore::try_acquire_until` if the deadline
+/// passes before a permit is granted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcquireTimeoutError;
+
+impl fmt::Display for AcquireTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadline passed before a permit is granted")
+    }
+}
+
+impl Error for AcquireTimeoutError {}
+
+/// A permit of a DeadlineSemaphore, released on drop.
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a DeadlineSemaphore,
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
+
+impl DeadlineSemaphore {
+    /// Create a semaphore of given permits, aging by `DEFAULT_AGING`.
+    pub fn new(permits: usize) -> Self {
+        Self::with_aging(permits, DEFAULT_AGING)
+    }
+
+    /// Create a semaphore whose waits without deadline are served as if
+    /// their deadline is aging after they start waiting.
+    pub fn with_aging(permits: usize, aging: Duration) -> Self {
+        Self {
+            state: Mutex::new(State {
+                permits,
+                waiters: BTreeMap::new(),
+                next_seq: 0,
+            }),
+            aging,
+        }
+    }
+
+    /// Take a permit if one is free and nobody waits.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state.lock();
+        if state.permits == 0 || !state.waiters.is_empty() {
+            return None;
+        }
+        state.permits -= 1;
+        Some(SemaphorePermit { semaphore: self })
+    }
+
+    /// Block until a permit is granted, ordered among deadline-tagged
+    /// waiters as if its deadline is the aging from now.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        let priority = time::now() + self.aging;
+        match self.acquire_with(priority, None) {
+            Ok(permit) => permit,
+            Err(_) => unreachable!("acquire without deadline never times out"),
+        }
+    }
+
+    /// Block until a permit is granted or the deadline passes,
+    /// served before the waiters with later deadlines.
+    pub fn try_acquire_until(
+        &self,
+        deadline: Instant,
+    ) -> Result<SemaphorePermit<'_>, AcquireTimeoutError> {
+        self.acquire_with(deadline, Some(deadline))
+    }
+
+    fn acquire_with(
+        &self,
+        priority: Instant,
+        deadline: Option<Instant>,
+    ) -> Result<SemaphorePermit<'_>, AcquireTimeoutError> {
+        let mut state = self.state.lock();
+        if state.permits > 0 && state.waiters.is_empty() {
+            state.permits -= 1;
+            return Ok(SemaphorePermit { semaphore: self });
+        }
+        if deadline.is_some_and(|at| at <= time::now()) {
+            return Err(AcquireTimeoutError);
+        }
+        let key = (priority, state.next_seq);
+        state.next_seq += 1;
+        let word = Arc::new(AtomicU32::new(WAITING));
+        state.waiters.insert(key, Arc::clone(&word));
+        drop(state);
+
+        while word.load(Acquire) == WAITING {
+            let Some(at) = deadline else {
+                wait(&word, WAITING);
+                continue;
+            };
+            let remaining = at.saturating_duration_since(time::now());
+            if !remaining.is_zero() {
+                wait_timeout(&word, WAITING, remaining);
+                continue;
+            }
+            // Leave the queue, unless a permit is granted meanwhile.
+            if self.state.lock().waiters.remove(&key).is_some() {
+                return Err(AcquireTimeoutError);
+            }
+        }
+        Ok(SemaphorePermit { semaphore: self })
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock();
+        let Some((_, word)) = state.waiters.pop_first() else {
+            state.permits += 1;
+            return;
+        };
+        drop(state);
+        word.store(GRANTED, Release);
+        wake_one(&*word);
+    }
+
+    /// Count of free permits now.
+    pub fn available(&self) -> usize {
+        self.state.lock().permits
+    }
+
+    /// Count of threads waiting for a permit now.
+    pub fn waiting(&self) -> usize {
+        self.state.lock().waiters.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{AcquireTimeoutError, DeadlineSemaphore};
+    use crate::mutex::Mutex;
+
+    #[test]
+    fn test_deadline_semaphore() {
+        let semaphore = DeadlineSemaphore::with_aging(1, Duration::from_secs(60));
+        let order = Mutex::new(Vec::new());
+        let held = semaphore.acquire();
+        assert!(semaphore.try_acquire().is_none());
+        let soon = Instant::now() + Duration::from_millis(10);
+        assert!(matches!(
+            semaphore.try_acquire_until(soon),
+            Err(AcquireTimeoutError)
+        ));
+
+        thread::scope(|s| {
+            let far = Instant::now() + Duration::from_secs(30);
+            let near = Instant::now() + Duration::from_secs(10);
+            let waiters = [
+                ("no deadline", None),
+                ("far", Some(far)),
+                ("near", Some(near)),
+            ];
+            for (i, (name, deadline)) in waiters.into_iter().enumerate() {
+                let (semaphore, order) = (&semaphore, &order);
+                s.spawn(move || {
+                    let _permit = match deadline {
+                        Some(at) => semaphore.try_acquire_until(at).unwrap(),
+                        None => semaphore.acquire(),
+                    };
+                    order.lock().push(name);
+                });
+                while semaphore.waiting() <= i {
+                    thread::yield_now();
+                }
+            }
+            drop(held);
+        });
+        // Served by deadline, the aged wait last.
+        assert_eq!(*order.lock(), ["near", "far", "no deadline"]);
+        assert_eq!(semaphore.available(), 1);
+    }
+}
@@ -0,0 +1,159 @@
+use std::{error::Error, fmt};
+
+use crate::{
+    condvar::Condvar,
+    mutex::{Mutex, MutexGuard},
+};
+
+mod deadline;
+pub use deadline::{AcquireTimeoutError, DeadlineSemaphore, SemaphorePermit, DEFAULT_AGING};
+
+/// Runs closures with at most `limit` of them in flight at a time,
+/// blocking or refusing the rest, e.g. around calls to a backend.
+pub struct ConcurrencyLimiter {
+    state: Mutex<State>,
+    limit: usize,
+    released: Condvar,
+}
+
+struct State {
+    in_flight: usize,
+    waiting: usize,
+    peak: usize,
+}
+
+/// Error returned by `ConcurrencyLimiter::try_run` if the limit is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WouldBlock;
+
+impl fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "concurrency limit reached")
+    }
+}
+
+impl Error for WouldBlock {}
+
+// Gives the slot back when the closure returns or unwinds.
+struct Slot<'a>(&'a ConcurrencyLimiter);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock();
+        state.in_flight -= 1;
+        let waiting = state.waiting > 0;
+        drop(state);
+        if waiting {
+            self.0.released.notify_one();
+        }
+    }
+}
+
+impl ConcurrencyLimiter {
+    /// Create a limiter running at most limit closures at once, limit > 0.
+    pub fn new(limit: usize) -> Self {
+        assert!(limit > 0, "limit must be positive");
+        Self {
+            state: Mutex::new(State {
+                in_flight: 0,
+                waiting: 0,
+                peak: 0,
+            }),
+            limit,
+            released: Condvar::new(),
+        }
+    }
+
+    /// Run f once less than `limit` closures are in flight,
+    /// block until then.
+    pub fn run<R>(&self, f: impl FnOnce() -> R) -> R {
+        let mut state = self.state.lock();
+        if state.in_flight >= self.limit {
+            state.waiting += 1;
+            while state.in_flight >= self.limit {
+                state = self.released.wait(state);
+            }
+            state.waiting -= 1;
+        }
+        let _slot = self.acquire(state);
+        f()
+    }
+
+    /// Run f if less than `limit` closures are in flight.
+    pub fn try_run<R>(&self, f: impl FnOnce() -> R) -> Result<R, WouldBlock> {
+        let state = self.state.lock();
+        if state.in_flight >= self.limit {
+            return Err(WouldBlock);
+        }
+        let _slot = self.acquire(state);
+        Ok(f())
+    }
+
+    fn acquire(&self, mut state: MutexGuard<'_, State>) -> Slot<'_> {
+        state.in_flight += 1;
+        state.peak = state.peak.max(state.in_flight);
+        Slot(self)
+    }
+
+    /// Most closures in flight at once.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Count of closures running now.
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().in_flight
+    }
+
+    /// Count of `run` calls blocked by the limit now.
+    pub fn waiting(&self) -> usize {
+        self.state.lock().waiting
+    }
+
+    /// Most closures in flight at a time since the limiter is created.
+    pub fn peak(&self) -> usize {
+        self.state.lock().peak
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering::Relaxed},
+        thread,
+    };
+
+    use super::{ConcurrencyLimiter, WouldBlock};
+
+    #[test]
+    fn test_concurrency_limiter() {
+        let limiter = ConcurrencyLimiter::new(2);
+        let running = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..50 {
+                        limiter.run(|| {
+                            assert!(running.fetch_add(1, Relaxed) < 2);
+                            thread::yield_now();
+                            running.fetch_sub(1, Relaxed);
+                        });
+                    }
+                });
+            }
+        });
+        assert!(limiter.peak() <= 2);
+        assert_eq!((limiter.in_flight(), limiter.waiting()), (0, 0));
+
+        // Refused at the limit, and a panicking closure gives its slot back.
+        limiter.run(|| {
+            limiter.run(|| assert_eq!(limiter.try_run(|| ()), Err(WouldBlock)));
+            assert_eq!(limiter.in_flight(), 1);
+        });
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            limiter.run(|| panic!("failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(limiter.try_run(|| limiter.in_flight()), Ok(1));
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{
+            AtomicU32,
+            Ordering::{Acquire, Release},
+        },
+        Arc,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    futex::{wait, wake_all},
+    mutex::Mutex,
+};
+
+/// Error returned by an operation stopped by cancellation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// A token shared by clones, signalling cancellation to threads and tasks.
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    // 1 if cancelled, threads wait on it.
+    cancelled: AtomicU32,
+    wakers: Mutex<Wakers>,
+}
+
+#[derive(Default)]
+struct Wakers {
+    next_key: u64,
+    wakers: HashMap<u64, Waker>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                cancelled: AtomicU32::new(0),
+                wakers: Mutex::new(Wakers::default()),
+            }),
+        }
+    }
+
+    /// Cancel the token, wake all threads and tasks waiting for it.
+    pub fn cancel(&self) {
+        if self.inner.cancelled.swap(1, Release) == 1 {
+            return;
+        }
+        wake_all(&self.inner.cancelled);
+        let wakers = std::mem::take(&mut self.inner.wakers.lock().wakers);
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Acquire) == 1
+    }
+
+    /// Block until the token is cancelled.
+    pub fn wait(&self) {
+        while !self.is_cancelled() {
+            wait(&self.inner.cancelled, 0);
+        }
+    }
+
+    /// Wait until the token is cancelled.
+    pub fn cancelled(&self) -> WaitCancelled<'_> {
+        WaitCancelled {
+            token: self,
+            key: None,
+        }
+    }
+
+    /// Poll for cancellation, register the waker under key if not cancelled.
+    fn poll_cancelled(&self, key: &mut Option<u64>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_cancelled() {
+            return Poll::Ready(());
+        }
+        let mut wakers = self.inner.wakers.lock();
+        // Check again under the lock, cancel takes the wakers after marked.
+        if self.is_cancelled() {
+            return Poll::Ready(());
+        }
+        let k = *key.get_or_insert_with(|| {
+            wakers.next_key += 1;
+            wakers.next_key
+        });
+        wakers.wakers.insert(k, cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn deregister(&self, key: Option<u64>) {
+        if let Some(key) = key {
+            self.inner.wakers.lock().wakers.remove(&key);
+        }
+    }
+}
+
+/// Future returned by `CancellationToken::cancelled`.
+pub struct WaitCancelled<'a> {
+    token: &'a CancellationToken,
+    key: Option<u64>,
+}
+
+impl Future for WaitCancelled<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        this.token.poll_cancelled(&mut this.key, cx)
+    }
+}
+
+impl Drop for WaitCancelled<'_> {
+    fn drop(&mut self) {
+        self.token.deregister(self.key);
+    }
+}
+
+/// Run the future until it completes or the token is cancelled.
+pub fn with_cancel<F: Future>(token: &CancellationToken, future: F) -> WithCancel<'_, F> {
+    WithCancel {
+        token,
+        key: None,
+        future,
+    }
+}
+
+/// Future returned by `with_cancel`.
+pub struct WithCancel<'a, F> {
+    token: &'a CancellationToken,
+    key: Option<u64>,
+    future: F,
+}
+
+impl<F: Future> Future for WithCancel<'_, F> {
+    type Output = Result<F::Output, Cancelled>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: future is pinned structurally, it's never moved out.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.token.poll_cancelled(&mut this.key, cx).is_ready() {
+            return Poll::Ready(Err(Cancelled));
+        }
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        future.poll(cx).map(Ok)
+    }
+}
+
+impl<F> Drop for WithCancel<'_, F> {
+    fn drop(&mut self) {
+        self.token.deregister(self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::{with_cancel, CancellationToken, Cancelled};
+    use crate::task::block_on;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        assert_eq!(block_on(with_cancel(&token, async { 1 })), Ok(1));
+        thread::scope(|s| {
+            s.spawn(|| token.wait());
+            s.spawn(|| block_on(token.cancelled()));
+            s.spawn(|| {
+                let pending = std::future::pending::<()>();
+                assert_eq!(block_on(with_cancel(&token, pending)), Err(Cancelled));
+            });
+            thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        });
+        assert!(token.is_cancelled());
+        assert!(token.inner.wakers.lock().wakers.is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+//! Receivers transforming messages on receiving, so a simple pipeline
+//! stage doesn't need a thread of its own.
+//!
+//! ```
+//! use sync::channel::{adapter::Recv, bounded};
+//!
+//! let (tx, rx) = bounded::channel(4);
+//! let rx = rx.filter(|n: &i32| n % 2 == 0).map(|n| n * 10);
+//! for n in 1..=4 {
+//!     tx.send(n).unwrap();
+//! }
+//! drop(tx);
+//! assert_eq!(rx.recv(), Ok(20));
+//! assert_eq!(rx.recv(), Ok(40));
+//! assert!(rx.recv().is_err());
+//! ```
+
+use std::time::Duration;
+
+use super::bounded::{self, RecvError, RecvTimeoutError, TryRecvError};
+use crate::time;
+
+/// The receiving end of a bounded channel, or an adapter of it.
+pub trait Recv {
+    type Item;
+
+    /// Receive the next message, block until there's one.
+    fn recv(&self) -> Result<Self::Item, RecvError>;
+
+    /// Receive the next message if there's one.
+    fn try_recv(&self) -> Result<Self::Item, TryRecvError>;
+
+    /// Receive the next message, block for at most timeout until there's one.
+    fn recv_timeout(&self, timeout: Duration) -> Result<Self::Item, RecvTimeoutError>;
+
+    /// Transform every message by f.
+    fn map<U, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Self::Item) -> U,
+    {
+        Map { inner: self, f }
+    }
+
+    /// Drop messages which p returns false for.
+    fn filter<P>(self, p: P) -> Filter<Self, P>
+    where
+        Self: Sized,
+        P: Fn(&Self::Item) -> bool,
+    {
+        Filter { inner: self, p }
+    }
+}
+
+impl<T> Recv for bounded::Receiver<T> {
+    type Item = T;
+
+    fn recv(&self) -> Result<T, RecvError> {
+        bounded::Receiver::recv(self)
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        bounded::Receiver::try_recv(self)
+    }
+
+    fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        bounded::Receiver::recv_timeout(self, timeout)
+    }
+}
+
+/// A receiver transforming messages, created by `Recv::map`.
+pub struct Map<R, F> {
+    inner: R,
+    f: F,
+}
+
+impl<R: Recv, U, F: Fn(R::Item) -> U> Recv for Map<R, F> {
+    type Item = U;
+
+    fn recv(&self) -> Result<U, RecvError> {
+        self.inner.recv().map(&self.f)
+    }
+
+    fn try_recv(&self) -> Result<U, TryRecvError> {
+        self.inner.try_recv().map(&self.f)
+    }
+
+    fn recv_timeout(&self, timeout: Duration) -> Result<U, RecvTimeoutError> {
+        self.inner.recv_timeout(timeout).map(&self.f)
+    }
+}
+
+/// A receiver dropping messages, created by `Recv::filter`.
+pub struct Filter<R, P> {
+    inner: R,
+    p: P,
+}
+
+impl<R: Recv, P: Fn(&R::Item) -> bool> Recv for Filter<R, P> {
+    type Item = R::Item;
+
+    fn recv(&self) -> Result<R::Item, RecvError> {
+        loop {
+            let item = self.inner.recv()?;
+            if (self.p)(&item) {
+                return Ok(item);
+            }
+        }
+    }
+
+    fn try_recv(&self) -> Result<R::Item, TryRecvError> {
+        loop {
+            let item = self.inner.try_recv()?;
+            if (self.p)(&item) {
+                return Ok(item);
+            }
+        }
+    }
+
+    /// Dropped messages count against the timeout.
+    fn recv_timeout(&self, timeout: Duration) -> Result<R::Item, RecvTimeoutError> {
+        let deadline = time::now() + timeout;
+        loop {
+            let timeout = deadline.saturating_duration_since(time::now());
+            let item = self.inner.recv_timeout(timeout)?;
+            if (self.p)(&item) {
+                return Ok(item);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Recv;
+    use crate::channel::bounded::{self, RecvTimeoutError, TryRecvError};
+
+    #[test]
+    fn test_adapters() {
+        let (tx, rx) = bounded::channel(8);
+        let rx = rx.map(|n: u32| n + 1).filter(|n| n % 3 == 0);
+        for n in 0..6 {
+            tx.send(n).unwrap();
+        }
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Ok(6));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        tx.send(7).unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        std::thread::scope(|s| {
+            s.spawn(|| tx.send(8).unwrap());
+            assert_eq!(rx.recv(), Ok(9));
+        });
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    }
+}
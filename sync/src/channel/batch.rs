@@ -0,0 +1,102 @@
+use std::mem;
+
+use crate::{condvar::Condvar, mutex::Mutex};
+
+/// A channel transferring whole batches of messages.
+/// Buffers are swapped instead of copied whenever possible,
+/// so allocations are recycled between producer and consumer.
+pub struct VecChannel<T> {
+    queue: Mutex<Vec<T>>,
+    item_ready: Condvar,
+}
+
+impl<T> Default for VecChannel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> VecChannel<T> {
+    pub fn new() -> Self {
+        Self {
+            queue: Mutex::new(Vec::new()),
+            item_ready: Condvar::new(),
+        }
+    }
+
+    /// Send a single message.
+    pub fn send(&self, value: T) {
+        let mut queue = self.queue.lock();
+        queue.push(value);
+        if queue.len() == 1 {
+            drop(queue);
+            self.item_ready.notify_one();
+        }
+    }
+
+    /// Send all messages in batch, leaving batch empty.
+    /// If the channel is drained, batch is swapped in and the caller
+    /// gets back the spare buffer of the consumer.
+    pub fn send_vec(&self, batch: &mut Vec<T>) {
+        if batch.is_empty() {
+            return;
+        }
+        let mut queue = self.queue.lock();
+        let was_empty = queue.is_empty();
+        if was_empty {
+            mem::swap(&mut *queue, batch);
+        } else {
+            queue.append(batch);
+        }
+        drop(queue);
+        if was_empty {
+            self.item_ready.notify_one();
+        }
+    }
+
+    /// Receive all pending messages into batch, block until there's one.
+    /// An empty batch is swapped with the channel buffer,
+    /// otherwise messages are appended to it.
+    pub fn recv_vec(&self, batch: &mut Vec<T>) {
+        let mut queue = self.queue.lock();
+        while queue.is_empty() {
+            queue = self.item_ready.wait(queue);
+        }
+        if batch.is_empty() {
+            mem::swap(&mut *queue, batch);
+        } else {
+            batch.append(&mut queue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::VecChannel;
+
+    #[test]
+    fn test_vec_channel() {
+        let channel = VecChannel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                let mut batch = Vec::new();
+                for i in 0..100 {
+                    batch.extend(i * 10..(i + 1) * 10);
+                    channel.send_vec(&mut batch);
+                    assert!(batch.is_empty());
+                }
+            });
+            let mut batch = Vec::new();
+            let mut expected = 0;
+            while expected < 1000 {
+                channel.recv_vec(&mut batch);
+                for i in batch.drain(..) {
+                    assert_eq!(i, expected);
+                    expected += 1;
+                }
+            }
+        });
+    }
+}
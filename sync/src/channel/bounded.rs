@@ -0,0 +1,701 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    condvar::Condvar,
+    mutex::{Mutex, MutexGuard},
+    time,
+};
+
+/// Create a channel buffering at most `capacity` messages,
+/// split into the cloneable sending half and the receiving half.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "capacity must be positive");
+    build(capacity, None)
+}
+
+/// Create a channel whose capacity adapts between min and max by load,
+/// e.g. for pipelines whose best buffering varies: it starts at min,
+/// doubles while senders block on a full channel more than the receiver
+/// waits on an empty one, and halves while it's the other way around.
+pub fn adaptive_channel<T>(min: usize, max: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(
+        0 < min && min <= max,
+        "capacity range must be positive and ordered"
+    );
+    build(
+        min,
+        Some(Tuner {
+            min,
+            max,
+            received: 0,
+            blocked: Duration::ZERO,
+            idle: Duration::ZERO,
+            #[cfg(feature = "metrics")]
+            resizes: 0,
+        }),
+    )
+}
+
+fn build<T>(capacity: usize, tuner: Option<Tuner>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            tuner,
+            senders: 1,
+            receiver_alive: true,
+        }),
+        item_ready: Condvar::new(),
+        space_ready: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    item_ready: Condvar,
+    space_ready: Condvar,
+}
+
+struct State<T> {
+    buffer: VecDeque<T>,
+    capacity: usize,
+    // Some if the capacity adapts.
+    tuner: Option<Tuner>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+// Messages received between capacity adjustments.
+const TUNE_WINDOW: u32 = 64;
+
+struct Tuner {
+    min: usize,
+    max: usize,
+    received: u32,
+    // Time senders waited for space and the receiver waited for messages
+    // in the window.
+    blocked: Duration,
+    idle: Duration,
+    #[cfg(feature = "metrics")]
+    resizes: u64,
+}
+
+impl<T> State<T> {
+    // Count a received message, adjust the capacity at the end of a window,
+    // true if it's grown.
+    fn tune(&mut self) -> bool {
+        let Some(tuner) = &mut self.tuner else {
+            return false;
+        };
+        tuner.received += 1;
+        if tuner.received < TUNE_WINDOW {
+            return false;
+        }
+        let old = self.capacity;
+        if tuner.blocked > tuner.idle * 2 {
+            self.capacity = (old * 2).min(tuner.max);
+        } else if tuner.idle > tuner.blocked * 2 {
+            self.capacity = (old / 2).max(tuner.min);
+        }
+        tuner.received = 0;
+        tuner.blocked = Duration::ZERO;
+        tuner.idle = Duration::ZERO;
+        #[cfg(feature = "metrics")]
+        if self.capacity != old {
+            tuner.resizes += 1;
+        }
+        self.capacity > old
+    }
+}
+
+/// A snapshot of the capacity of a channel by `adaptive_channel`.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityStats {
+    /// Capacity now.
+    pub capacity: usize,
+    /// Count of capacity changes.
+    pub resizes: u64,
+}
+
+/// Error returned by `Sender::send` if the receiver is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "receiver dropped")
+    }
+}
+
+impl<T: fmt::Debug> Error for SendError<T> {}
+
+/// Error returned by `Receiver::recv` if all senders are dropped
+/// and all messages are received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "channel closed")
+    }
+}
+
+impl Error for RecvError {}
+
+/// Error returned by `Receiver::try_peek`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// There's no message now.
+    Empty,
+    /// All senders are dropped and all messages are received.
+    Closed,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "channel empty"),
+            TryRecvError::Closed => write!(f, "channel closed"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+/// Error returned by `Receiver::peek_timeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    /// There's no message before the timeout.
+    Timeout,
+    /// All senders are dropped and all messages are received.
+    Closed,
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => write!(f, "timed out"),
+            RecvTimeoutError::Closed => write!(f, "channel closed"),
+        }
+    }
+}
+
+impl Error for RecvTimeoutError {}
+
+/// The sending half of a bounded channel.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Send a message, block while the channel is full.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if !state.receiver_alive {
+                return Err(SendError(value));
+            }
+            if state.buffer.len() < state.capacity {
+                break;
+            }
+            if state.tuner.is_none() {
+                state = self.shared.space_ready.wait(state);
+                continue;
+            }
+            let start = time::now();
+            state = self.shared.space_ready.wait(state);
+            if let Some(tuner) = &mut state.tuner {
+                tuner.blocked += time::now() - start;
+            }
+        }
+        state.buffer.push_back(value);
+        drop(state);
+        self.shared.item_ready.notify_one();
+        Ok(())
+    }
+
+    /// Capacity of the channel now, it changes if the channel is adaptive.
+    pub fn capacity(&self) -> usize {
+        self.shared.state.lock().capacity
+    }
+
+    /// Whether both senders send to the same channel.
+    pub(crate) fn same_channel(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl<T: Clone> Sender<T> {
+    /// Combine with a sender of another channel into a Tee, which sends
+    /// a clone of every message to the mirror channel as well.
+    pub fn tee(self, mirror: Sender<T>) -> Tee<T> {
+        Tee {
+            primary: self,
+            mirror,
+            order: Arc::new(Mutex::new(())),
+        }
+    }
+}
+
+/// A sender duplicating every message into a primary and a mirror channel,
+/// e.g. mirroring production traffic into a debug consumer.
+///
+/// Clones of a Tee send one at a time, so both channels get messages in
+/// the same order. A full mirror channel blocks the senders like a full
+/// primary one, and a dropped mirror receiver is ignored.
+pub struct Tee<T> {
+    primary: Sender<T>,
+    mirror: Sender<T>,
+    order: Arc<Mutex<()>>,
+}
+
+impl<T: Clone> Tee<T> {
+    /// Send a message to both channels, fail if the primary receiver is dropped.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let _order = self.order.lock();
+        // Drop the message if the mirror is closed.
+        let _ = self.mirror.send(value.clone());
+        self.primary.send(value)
+    }
+}
+
+impl<T> Clone for Tee<T> {
+    fn clone(&self) -> Self {
+        Self {
+            primary: self.primary.clone(),
+            mirror: self.mirror.clone(),
+            order: Arc::clone(&self.order),
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().senders += 1;
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.item_ready.notify_all();
+        }
+    }
+}
+
+/// The receiving half of a bounded channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next message, block until there's one.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if let Some(value) = state.buffer.pop_front() {
+                let grown = state.tune();
+                drop(state);
+                self.notify_space(grown);
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            if state.tuner.is_none() {
+                state = self.shared.item_ready.wait(state);
+                continue;
+            }
+            let start = time::now();
+            state = self.shared.item_ready.wait(state);
+            if let Some(tuner) = &mut state.tuner {
+                tuner.idle += time::now() - start;
+            }
+        }
+    }
+
+    // A grown channel has room for more than one blocked sender.
+    fn notify_space(&self, grown: bool) {
+        if grown {
+            self.shared.space_ready.notify_all();
+        } else {
+            self.shared.space_ready.notify_one();
+        }
+    }
+
+    /// Capacity of the channel now, it changes if the channel is adaptive.
+    pub fn capacity(&self) -> usize {
+        self.shared.state.lock().capacity
+    }
+
+    /// Capacity statistics, None if the channel is not adaptive.
+    #[cfg(feature = "metrics")]
+    pub fn capacity_stats(&self) -> Option<CapacityStats> {
+        let state = self.shared.state.lock();
+        let tuner = state.tuner.as_ref()?;
+        Some(CapacityStats {
+            capacity: state.capacity,
+            resizes: tuner.resizes,
+        })
+    }
+
+    /// Receive the next message if there's one.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.try_peek().map(Peek::take)
+    }
+
+    /// Receive the next message, block for at most timeout until there's one.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.peek_timeout(timeout).map(Peek::take)
+    }
+
+    /// Receive up to n messages, block until there're n or the deadline
+    /// passes, and return the messages received, which may be none.
+    /// Fail only if the channel is closed before any message is received.
+    pub fn recv_deadline_batch(&self, n: usize, deadline: Instant) -> Result<Vec<T>, RecvError> {
+        let mut state = self.shared.state.lock();
+        let mut batch = Vec::with_capacity(n.min(state.capacity));
+        loop {
+            // Take messages as they come, the batch may be larger than the channel.
+            let k = (n - batch.len()).min(state.buffer.len());
+            if k > 0 {
+                batch.extend(state.buffer.drain(..k));
+                self.shared.space_ready.notify_all();
+            }
+            if batch.len() == n {
+                break;
+            }
+            if state.senders == 0 {
+                if batch.is_empty() {
+                    return Err(RecvError);
+                }
+                break;
+            }
+            let now = time::now();
+            if now >= deadline {
+                break;
+            }
+            state = self.shared.item_ready.wait_timeout(state, deadline - now).0;
+        }
+        Ok(batch)
+    }
+
+    /// Borrow the next message without receiving it, block until there's one.
+    ///
+    /// The guard holds the channel lock, so senders block until it's dropped.
+    pub fn peek(&self) -> Result<Peek<'_, T>, RecvError> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if !state.buffer.is_empty() {
+                return Ok(Peek {
+                    receiver: self,
+                    state,
+                });
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self.shared.item_ready.wait(state);
+        }
+    }
+
+    /// Borrow the next message without receiving it if there's one.
+    pub fn try_peek(&self) -> Result<Peek<'_, T>, TryRecvError> {
+        let state = self.shared.state.lock();
+        if !state.buffer.is_empty() {
+            Ok(Peek {
+                receiver: self,
+                state,
+            })
+        } else if state.senders == 0 {
+            Err(TryRecvError::Closed)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+
+    /// Borrow the next message without receiving it,
+    /// block for at most timeout until there's one.
+    pub fn peek_timeout(&self, timeout: Duration) -> Result<Peek<'_, T>, RecvTimeoutError> {
+        let deadline = time::now() + timeout;
+        let mut state = self.shared.state.lock();
+        loop {
+            if !state.buffer.is_empty() {
+                return Ok(Peek {
+                    receiver: self,
+                    state,
+                });
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Closed);
+            }
+            let now = time::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self.shared.item_ready.wait_timeout(state, deadline - now).0;
+        }
+    }
+
+    /// Iterate over received messages until the channel is closed.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.receiver_alive = false;
+        // Messages nobody receives are dropped now rather than with the last
+        // sender, e.g. a reply sender inside must not keep its caller waiting.
+        let buffer = std::mem::take(&mut state.buffer);
+        drop(state);
+        drop(buffer);
+        // Producers may be blocked by a full channel.
+        self.shared.space_ready.notify_all();
+    }
+}
+
+/// The next message borrowed by `Receiver::peek`, holding the channel lock.
+pub struct Peek<'a, T> {
+    receiver: &'a Receiver<T>,
+    state: MutexGuard<'a, State<T>>,
+}
+
+impl<T> Peek<'_, T> {
+    /// Receive the borrowed message.
+    pub fn take(mut self) -> T {
+        // A Peek is only created with a message buffered.
+        let value = self.state.buffer.pop_front().unwrap();
+        let grown = self.state.tune();
+        let receiver = self.receiver;
+        drop(self);
+        receiver.notify_space(grown);
+        value
+    }
+}
+
+impl<T> Deref for Peek<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // A Peek is only created with a message buffered.
+        self.state.buffer.front().unwrap()
+    }
+}
+
+/// Iterator returned by `Receiver::iter`.
+pub struct Iter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use std::time::{Duration, Instant};
+
+    use super::{
+        adaptive_channel, channel, RecvError, RecvTimeoutError, SendError, TryRecvError,
+        TUNE_WINDOW,
+    };
+
+    #[test]
+    fn test_bounded() {
+        let (tx, rx) = channel(4);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                let tx = tx.clone();
+                s.spawn(move || {
+                    for i in 0..100 {
+                        tx.send(i).unwrap();
+                    }
+                });
+            }
+            drop(tx);
+            assert_eq!(rx.iter().sum::<i32>(), 9900);
+        });
+        assert_eq!(rx.recv(), Err(RecvError));
+
+        let (tx, rx) = channel(1);
+        tx.send(1).unwrap();
+        drop(rx);
+        assert_eq!(tx.send(2), Err(SendError(2)));
+    }
+
+    #[test]
+    fn test_tee() {
+        let (tx, rx) = channel(4);
+        let (mirror, mirror_rx) = channel(4);
+        let tee = tx.tee(mirror);
+        thread::scope(|s| {
+            for t in 0..4 {
+                let tee = tee.clone();
+                s.spawn(move || {
+                    for i in 0..100 {
+                        tee.send(t * 100 + i).unwrap();
+                    }
+                });
+            }
+            drop(tee);
+            let mirrored = s.spawn(|| mirror_rx.iter().collect::<Vec<_>>());
+            let received = rx.iter().collect::<Vec<_>>();
+            assert_eq!(received.len(), 400);
+            assert_eq!(mirrored.join().unwrap(), received);
+        });
+
+        // A dropped mirror doesn't stop the primary.
+        let (tx, rx) = channel(1);
+        let (mirror, mirror_rx) = channel(1);
+        let tee = tx.tee(mirror);
+        drop(mirror_rx);
+        tee.send(1).unwrap();
+        assert_eq!(rx.recv(), Ok(1));
+        drop(rx);
+        assert_eq!(tee.send(2), Err(SendError(2)));
+    }
+
+    #[test]
+    fn test_recv_deadline_batch() {
+        let (tx, rx) = channel(2);
+        let soon = || Instant::now() + Duration::from_millis(10);
+        assert_eq!(rx.recv_deadline_batch(4, soon()), Ok(vec![]));
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..5 {
+                    tx.send(i).unwrap();
+                }
+            });
+            // The batch is larger than the channel.
+            let far = Instant::now() + Duration::from_secs(10);
+            assert_eq!(rx.recv_deadline_batch(4, far), Ok(vec![0, 1, 2, 3]));
+        });
+        assert_eq!(rx.recv_deadline_batch(4, soon()), Ok(vec![4]));
+        drop(tx);
+        assert_eq!(rx.recv_deadline_batch(4, soon()), Err(RecvError));
+    }
+
+    #[test]
+    fn test_peek() {
+        let (tx, rx) = channel(4);
+        assert_eq!(rx.try_peek().err(), Some(TryRecvError::Empty));
+        assert_eq!(
+            rx.peek_timeout(Duration::from_millis(10)).err(),
+            Some(RecvTimeoutError::Timeout)
+        );
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                tx.send(1).unwrap();
+                tx.send(2).unwrap();
+            });
+            assert_eq!(*rx.peek().unwrap(), 1);
+            assert_eq!(*rx.peek_timeout(Duration::from_secs(10)).unwrap(), 1);
+            assert_eq!(rx.peek().unwrap().take(), 1);
+        });
+        drop(tx);
+        assert_eq!(*rx.try_peek().unwrap(), 2);
+        assert_eq!(rx.recv(), Ok(2));
+        assert_eq!(rx.try_peek().err(), Some(TryRecvError::Closed));
+        assert_eq!(rx.peek().err(), Some(RecvError));
+    }
+
+    #[test]
+    fn test_adaptive_channel() {
+        let (tx, rx) = adaptive_channel::<u32>(2, 16);
+        assert_eq!(tx.capacity(), 2);
+        // Run a window in which the senders were blocked and the receiver
+        // idle for the given times, return whether the channel grew.
+        let window = |blocked: u64, idle: u64| {
+            let mut state = rx.shared.state.lock();
+            let tuner = state.tuner.as_mut().unwrap();
+            tuner.blocked = Duration::from_millis(blocked);
+            tuner.idle = Duration::from_millis(idle);
+            let grown = (0..TUNE_WINDOW).map(|_| state.tune()).collect::<Vec<_>>();
+            assert!(grown[..grown.len() - 1].iter().all(|grown| !grown));
+            grown[grown.len() - 1]
+        };
+        // A slow receiver blocks the senders, the channel grows up to max.
+        for capacity in [4, 8, 16, 16] {
+            let old = rx.capacity();
+            assert_eq!(window(30, 10), capacity > old);
+            assert_eq!(rx.capacity(), capacity);
+        }
+        // Comparable waits leave it alone.
+        assert!(!window(10, 10));
+        assert_eq!(rx.capacity(), 16);
+        // A slow sender leaves the receiver idle, it shrinks down to min.
+        for capacity in [8, 4, 2, 2] {
+            assert!(!window(10, 30));
+            assert_eq!(rx.capacity(), capacity);
+        }
+        #[cfg(feature = "metrics")]
+        assert_eq!(rx.capacity_stats().unwrap().resizes, 6);
+        assert_eq!(channel::<()>(4).1.capacity(), 4);
+    }
+
+    #[cfg(feature = "testutil")]
+    #[test]
+    fn test_bounded_linearizable() {
+        use crate::testutil::linearizability::{check, History, QueueModel, QueueOp};
+
+        for _ in 0..20 {
+            let (tx, rx) = channel(2);
+            let history = History::new();
+            thread::scope(|s| {
+                for t in 0..2 {
+                    let (tx, history) = (tx.clone(), &history);
+                    s.spawn(move || {
+                        for v in t * 10..t * 10 + 4 {
+                            history.record(QueueOp::Push(v), || {
+                                tx.send(v).unwrap();
+                                None
+                            });
+                        }
+                    });
+                }
+                for _ in 0..8 {
+                    history.record(QueueOp::Pop, || rx.recv().ok());
+                }
+            });
+            check(QueueModel::default(), &history).unwrap();
+        }
+    }
+}
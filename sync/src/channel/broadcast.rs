@@ -0,0 +1,255 @@
+use std::{collections::VecDeque, error::Error, fmt, sync::Arc};
+
+use crate::{condvar::Condvar, mutex::Mutex};
+
+/// Create a broadcast channel sharing a ring of `capacity` messages
+/// between all receivers. Receivers are created by `Sender::subscribe`.
+pub fn channel<T: Clone>(capacity: usize) -> Sender<T> {
+    assert!(capacity > 0, "capacity must be positive");
+    Sender {
+        shared: Arc::new(Shared {
+            state: Mutex::new(State {
+                buffer: VecDeque::with_capacity(capacity),
+                head: 0,
+                receivers: Vec::new(),
+                senders: 1,
+            }),
+            capacity,
+            item_ready: Condvar::new(),
+            space_ready: Condvar::new(),
+        }),
+    }
+}
+
+/// What a receiver wants to happen when it falls behind a full ring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Block the producers until the receiver catches up.
+    Block,
+    /// Silently skip the overwritten messages.
+    DropOldest,
+    /// Skip the overwritten messages and report `RecvError::Lagged` once.
+    Lagged,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    item_ready: Condvar,
+    space_ready: Condvar,
+}
+
+struct State<T> {
+    buffer: VecDeque<T>,
+    // Sequence number of the front message of buffer.
+    head: u64,
+    // Cursors of receivers, indexed by receiver id.
+    receivers: Vec<Option<Cursor>>,
+    senders: usize,
+}
+
+struct Cursor {
+    // Sequence number of the next message to receive.
+    next: u64,
+    policy: Policy,
+}
+
+impl<T> State<T> {
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+
+    /// Return true if a blocking receiver haven't received the front message.
+    fn is_front_pinned(&self) -> bool {
+        self.receivers
+            .iter()
+            .flatten()
+            .any(|c| c.policy == Policy::Block && c.next == self.head)
+    }
+}
+
+/// Error returned by `Sender::send` if there's no receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no broadcast receiver")
+    }
+}
+
+impl<T: fmt::Debug> Error for SendError<T> {}
+
+/// Error returned by `Receiver::recv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// All senders are dropped and all messages are received.
+    Closed,
+    /// The receiver fell behind and skipped given count of messages.
+    Lagged(u64),
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Closed => write!(f, "broadcast channel closed"),
+            RecvError::Lagged(n) => write!(f, "broadcast receiver lagged by {} messages", n),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+/// The sending half of a broadcast channel.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Clone> Sender<T> {
+    /// Send a message to all receivers subscribed.
+    /// Block while the ring is full and a `Policy::Block` receiver is behind.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if state.receivers.iter().all(Option::is_none) {
+                return Err(SendError(value));
+            }
+            if state.buffer.len() < self.shared.capacity {
+                break;
+            }
+            if !state.is_front_pinned() {
+                // Overwrite the oldest message.
+                state.buffer.pop_front();
+                state.head += 1;
+                break;
+            }
+            state = self.shared.space_ready.wait(state);
+        }
+        state.buffer.push_back(value);
+        drop(state);
+        self.shared.item_ready.notify_all();
+        Ok(())
+    }
+
+    /// Subscribe a new receiver with given overflow policy.
+    /// The receiver only observes messages sent after subscribing.
+    pub fn subscribe(&self, policy: Policy) -> Receiver<T> {
+        let mut state = self.shared.state.lock();
+        let cursor = Cursor {
+            next: state.tail(),
+            policy,
+        };
+        let id = match state.receivers.iter().position(Option::is_none) {
+            Some(id) => {
+                state.receivers[id] = Some(cursor);
+                id
+            }
+            None => {
+                state.receivers.push(Some(cursor));
+                state.receivers.len() - 1
+            }
+        };
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            id,
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().senders += 1;
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.item_ready.notify_all();
+        }
+    }
+}
+
+/// The receiving half of a broadcast channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    id: usize,
+}
+
+impl<T: Clone> Receiver<T> {
+    /// Receive the next message, block until there's one.
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        let mut state = self.shared.state.lock();
+        loop {
+            let head = state.head;
+            let tail = state.tail();
+            let cursor = state.receivers[self.id].as_mut().unwrap();
+            if cursor.next < head {
+                // Messages were overwritten, only non-blocking receivers fall behind.
+                let skipped = head - cursor.next;
+                cursor.next = head;
+                if cursor.policy == Policy::Lagged {
+                    return Err(RecvError::Lagged(skipped));
+                }
+            }
+            if cursor.next < tail {
+                let seq = cursor.next;
+                cursor.next += 1;
+                let unpin = cursor.policy == Policy::Block && seq == head;
+                let value = state.buffer[(seq - head) as usize].clone();
+                drop(state);
+                if unpin {
+                    self.shared.space_ready.notify_all();
+                }
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvError::Closed);
+            }
+            state = self.shared.item_ready.wait(state);
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().receivers[self.id] = None;
+        // Producers may be blocked by this receiver.
+        self.shared.space_ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{channel, Policy, RecvError};
+
+    #[test]
+    fn test_broadcast() {
+        let tx = channel(4);
+        let mut blocking = tx.subscribe(Policy::Block);
+        let mut lagged = tx.subscribe(Policy::Lagged);
+        let mut dropping = tx.subscribe(Policy::DropOldest);
+        thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..100 {
+                    tx.send(i).unwrap();
+                }
+            });
+            for i in 0..100 {
+                assert_eq!(blocking.recv(), Ok(i));
+            }
+        });
+        assert_eq!(blocking.recv(), Err(RecvError::Closed));
+        assert_eq!(lagged.recv(), Err(RecvError::Lagged(96)));
+        assert_eq!(lagged.recv(), Ok(96));
+        assert_eq!(dropping.recv(), Ok(96));
+    }
+}
@@ -1,45 +1,298 @@
 use std::{
     collections::VecDeque,
-    sync::{Condvar, Mutex},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
 };
 
+#[cfg(not(feature = "std-impl"))]
+use crate::{
+    condvar::Condvar,
+    mutex::{Mutex, MutexGuard},
+};
+#[cfg(feature = "std-impl")]
+use std::sync::{Condvar, Mutex, MutexGuard};
+
 pub struct Channel<T> {
-    queue: Mutex<VecDeque<T>>,
+    queue: Mutex<Queue<T>>,
     item_ready: Condvar,
 }
 
+struct Queue<T> {
+    items: VecDeque<T>,
+    // Wakers of async receivers waiting for an item.
+    wakers: Vec<Waker>,
+}
+
 impl<T> Default for Channel<T> {
     fn default() -> Self {
-        Self {
-            queue: Mutex::new(VecDeque::new()),
-            item_ready: Condvar::new(),
-        }
+        Self::new()
     }
 }
 
 impl<T> Channel<T> {
     pub fn new() -> Self {
         Self {
-            queue: Mutex::new(VecDeque::new()),
+            queue: Mutex::new(Queue {
+                items: VecDeque::new(),
+                wakers: Vec::new(),
+            }),
             item_ready: Condvar::new(),
         }
     }
 
     pub fn send(&self, value: T) {
-        let mut queue = self.queue.lock().unwrap();
-        queue.push_back(value);
-        if queue.len() == 1 {
-            self.item_ready.notify_one();
+        let mut queue = self.lock();
+        queue.items.push_back(value);
+        let wakers = std::mem::take(&mut queue.wakers);
+        drop(queue);
+        self.item_ready.notify_one();
+        // The woken tasks race for the item, the losers register again.
+        for waker in wakers {
+            waker.wake();
         }
     }
 
     pub fn recv(&self) -> T {
-        let mut queue = self
-            .item_ready
-            .wait_while(self.queue.lock().unwrap(), |q| q.is_empty())
-            .unwrap();
-        assert!(!queue.is_empty());
-        queue.pop_front().unwrap()
+        let mut queue = self.wait_items();
+        assert!(!queue.items.is_empty());
+        queue.items.pop_front().unwrap()
+    }
+
+    /// Block until there're items, move at most max of them into buffer.
+    fn recv_batch(&self, max: usize, buffer: &mut VecDeque<T>) {
+        let mut queue = self.wait_items();
+        take_batch(&mut queue.items, max, buffer);
+    }
+
+    /// Move at most max of the items there're now into buffer.
+    fn take_ready(&self, max: usize, buffer: &mut VecDeque<T>) {
+        let mut queue = self.lock();
+        let n = queue.items.len().min(max);
+        buffer.extend(queue.items.drain(..n));
+    }
+
+    #[cfg(not(feature = "std-impl"))]
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.queue.lock()
+    }
+
+    #[cfg(feature = "std-impl")]
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.queue.lock().unwrap()
+    }
+
+    /// Lock the queue, block until there're items.
+    #[cfg(not(feature = "std-impl"))]
+    fn wait_items(&self) -> MutexGuard<'_, Queue<T>> {
+        let mut queue = self.lock();
+        while queue.items.is_empty() {
+            queue = self.item_ready.wait(queue);
+        }
+        queue
+    }
+
+    /// Lock the queue, block until there're items.
+    #[cfg(feature = "std-impl")]
+    fn wait_items(&self) -> MutexGuard<'_, Queue<T>> {
+        self.item_ready
+            .wait_while(self.lock(), |q| q.items.is_empty())
+            .unwrap()
+    }
+
+    fn poll_recv_batch(
+        &self,
+        cx: &mut Context<'_>,
+        max: usize,
+        buffer: &mut VecDeque<T>,
+    ) -> Poll<()> {
+        let mut queue = self.lock();
+        if !queue.items.is_empty() {
+            take_batch(&mut queue.items, max, buffer);
+            return Poll::Ready(());
+        }
+        if !queue.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            queue.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Move at most max items into the empty buffer,
+/// swap the whole queue in if it's not more than max.
+fn take_batch<T>(items: &mut VecDeque<T>, max: usize, buffer: &mut VecDeque<T>) {
+    if items.len() <= max {
+        std::mem::swap(items, buffer);
+    } else {
+        buffer.extend(items.drain(..max));
+    }
+}
+
+/// Count of messages a receiver takes from the channel at a time by default.
+const DEFAULT_BATCH: usize = 32;
+
+/// Create a channel, split into the sending and the blocking receiving half.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Channel::new());
+    (
+        Sender {
+            channel: Arc::clone(&channel),
+        },
+        Receiver {
+            channel,
+            buffer: VecDeque::new(),
+            batch: DEFAULT_BATCH,
+        },
+    )
+}
+
+/// The sending half of a channel, sending never blocks.
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    pub fn send(&self, value: T) {
+        self.channel.send(value)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            channel: Arc::clone(&self.channel),
+        }
+    }
+}
+
+/// The receiving half of a channel for threads.
+///
+/// Messages are taken from the channel in batches into a local buffer,
+/// so the channel lock is taken once per batch instead of per message.
+/// The buffer is owned by the receiver, so receiving takes `&mut self`,
+/// and the receiver and its futures are Send if the messages are.
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+    buffer: VecDeque<T>,
+    batch: usize,
+}
+
+impl<T> Receiver<T> {
+    /// Block until a message is received.
+    pub fn recv(&mut self) -> T {
+        if self.buffer.is_empty() {
+            self.channel.recv_batch(self.batch, &mut self.buffer);
+        }
+        self.buffer.pop_front().unwrap()
+    }
+
+    /// Set the count of messages taken from the channel at a time, at least 1.
+    /// Larger batches cut the synchronization per message,
+    /// smaller ones leave more messages to the other receiving half.
+    pub fn recv_batch_hint(&mut self, n: usize) {
+        self.batch = n.max(1);
+    }
+
+    /// Convert into a receiver for async tasks, messages buffered are kept.
+    pub fn into_async(self) -> AsyncReceiver<T> {
+        AsyncReceiver {
+            channel: self.channel,
+            buffer: self.buffer,
+            batch: self.batch,
+        }
+    }
+}
+
+/// The receiving half of a channel for async tasks, buffering like `Receiver`.
+pub struct AsyncReceiver<T> {
+    channel: Arc<Channel<T>>,
+    buffer: VecDeque<T>,
+    batch: usize,
+}
+
+impl<T> AsyncReceiver<T> {
+    /// Wait until a message is received.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+
+    /// See `Receiver::recv_batch_hint`.
+    pub fn recv_batch_hint(&mut self, n: usize) {
+        self.batch = n.max(1);
+    }
+
+    /// Wait until messages are received, and take at most max (at least 1)
+    /// of those ready without waiting for more. Await it in a loop to
+    /// amortize the handling downstream without adding latency.
+    pub fn ready_chunks(&mut self, max: usize) -> ReadyChunks<'_, T> {
+        ReadyChunks {
+            receiver: self,
+            max: max.max(1),
+        }
+    }
+
+    /// Convert into a receiver for threads, messages buffered are kept.
+    pub fn into_blocking(self) -> Receiver<T> {
+        Receiver {
+            channel: self.channel,
+            buffer: self.buffer,
+            batch: self.batch,
+        }
+    }
+}
+
+/// Future returned by `AsyncReceiver::recv`.
+pub struct Recv<'a, T> {
+    receiver: &'a mut AsyncReceiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let receiver = &mut *self.get_mut().receiver;
+        let buffer = &mut receiver.buffer;
+        if buffer.is_empty()
+            && receiver
+                .channel
+                .poll_recv_batch(cx, receiver.batch, buffer)
+                .is_pending()
+        {
+            return Poll::Pending;
+        }
+        Poll::Ready(buffer.pop_front().unwrap())
+    }
+}
+
+/// Future returned by `AsyncReceiver::ready_chunks`.
+pub struct ReadyChunks<'a, T> {
+    receiver: &'a mut AsyncReceiver<T>,
+    max: usize,
+}
+
+impl<T> Future for ReadyChunks<'_, T> {
+    type Output = Vec<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Vec<T>> {
+        let this = self.get_mut();
+        let (receiver, max) = (&mut *this.receiver, this.max);
+        let buffer = &mut receiver.buffer;
+        if buffer.is_empty() {
+            if receiver
+                .channel
+                .poll_recv_batch(cx, max, buffer)
+                .is_pending()
+            {
+                return Poll::Pending;
+            }
+        } else if buffer.len() < max {
+            // Top up the buffered with those sent meanwhile.
+            let len = buffer.len();
+            receiver.channel.take_ready(max - len, buffer);
+        }
+        let n = buffer.len().min(max);
+        Poll::Ready(buffer.drain(..n).collect())
     }
 }
 
@@ -50,6 +303,20 @@ mod tests {
     #[allow(unused_imports)]
     use std::{sync::Arc, thread};
 
+    use super::{channel, AsyncReceiver, ReadyChunks, Receiver, Recv};
+    use crate::task::block_on;
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_send() {
+        // Receivers and their futures move across threads with the messages.
+        assert_send::<Receiver<Vec<u8>>>();
+        assert_send::<AsyncReceiver<Vec<u8>>>();
+        assert_send::<Recv<'_, Vec<u8>>>();
+        assert_send::<ReadyChunks<'_, Vec<u8>>>();
+    }
+
     #[test]
     fn test_channel() {
         let sender = Arc::new(Channel::new());
@@ -67,4 +334,80 @@ mod tests {
             });
         });
     }
+
+    #[test]
+    fn test_async_bridge() {
+        let (tx, rx) = channel();
+        let mut rx = rx.into_async();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    tx.send(i);
+                }
+            });
+            rx.recv_batch_hint(3);
+            for i in 0..50 {
+                assert_eq!(block_on(rx.recv()), i);
+            }
+            let mut rx = rx.into_blocking();
+            for i in 50..100 {
+                assert_eq!(rx.recv(), i);
+            }
+        });
+    }
+
+    #[test]
+    fn test_ready_chunks() {
+        let (tx, rx) = channel();
+        let mut rx = rx.into_async();
+        (0..5).for_each(|i| tx.send(i));
+        rx.recv_batch_hint(2);
+        assert_eq!(block_on(rx.recv()), 0);
+        // The buffered one first, topped up with those in the channel.
+        assert_eq!(block_on(rx.ready_chunks(3)), [1, 2, 3]);
+        assert_eq!(block_on(rx.ready_chunks(3)), [4]);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    tx.send(i);
+                }
+            });
+            let mut received = Vec::new();
+            while received.len() < 100 {
+                let chunk = block_on(rx.ready_chunks(8));
+                assert!((1..=8).contains(&chunk.len()));
+                received.extend(chunk);
+            }
+            assert_eq!(received, (0..100).collect::<Vec<_>>());
+        });
+    }
+
+    #[cfg(feature = "testutil")]
+    #[test]
+    fn test_channel_linearizable() {
+        use crate::testutil::linearizability::{check, History, QueueModel, QueueOp};
+
+        for _ in 0..20 {
+            let (tx, mut rx) = channel();
+            rx.recv_batch_hint(2);
+            let history = History::new();
+            thread::scope(|s| {
+                for t in 0..2 {
+                    let (tx, history) = (tx.clone(), &history);
+                    s.spawn(move || {
+                        for v in t * 10..t * 10 + 4 {
+                            history.record(QueueOp::Push(v), || {
+                                tx.send(v);
+                                None
+                            });
+                        }
+                    });
+                }
+                for _ in 0..8 {
+                    history.record(QueueOp::Pop, || Some(rx.recv()));
+                }
+            });
+            check(QueueModel::default(), &history).unwrap();
+        }
+    }
 }
@@ -0,0 +1,145 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use super::bounded::RecvError;
+use crate::{condvar::Condvar, mutex::Mutex};
+
+/// Create a channel with an urgent and a normal lane,
+/// split into the cloneable sending half and the receiving half.
+///
+/// The receiver prefers the urgent lane, but takes a normal message after
+/// every `ratio` urgent messages in a row, so the normal lane never starves.
+pub fn channel<T>(ratio: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(ratio > 0, "ratio must be positive");
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            urgent: VecDeque::new(),
+            normal: VecDeque::new(),
+            urgent_in_row: 0,
+            senders: 1,
+        }),
+        ratio,
+        item_ready: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    ratio: usize,
+    item_ready: Condvar,
+}
+
+struct State<T> {
+    urgent: VecDeque<T>,
+    normal: VecDeque<T>,
+    // Count of urgent messages received in a row while normal ones wait.
+    urgent_in_row: usize,
+    senders: usize,
+}
+
+/// The sending half of a two-lane channel, sending never blocks.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Send a message on the normal lane.
+    pub fn send(&self, value: T) {
+        self.shared.state.lock().normal.push_back(value);
+        self.shared.item_ready.notify_one();
+    }
+
+    /// Send a message on the urgent lane.
+    pub fn send_urgent(&self, value: T) {
+        self.shared.state.lock().urgent.push_back(value);
+        self.shared.item_ready.notify_one();
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().senders += 1;
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.item_ready.notify_all();
+        }
+    }
+}
+
+/// The receiving half of a two-lane channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next message, block until there's one.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if let Some(value) = self.pop(&mut state) {
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self.shared.item_ready.wait(state);
+        }
+    }
+
+    fn pop(&self, state: &mut State<T>) -> Option<T> {
+        if state.normal.is_empty() {
+            state.urgent_in_row = 0;
+            return state.urgent.pop_front();
+        }
+        if state.urgent_in_row < self.shared.ratio {
+            if let Some(value) = state.urgent.pop_front() {
+                state.urgent_in_row += 1;
+                return Some(value);
+            }
+        }
+        state.urgent_in_row = 0;
+        state.normal.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::channel;
+    use crate::channel::bounded::RecvError;
+
+    #[test]
+    fn test_lanes() {
+        let (tx, rx) = channel(2);
+        for i in 0..3 {
+            tx.send(i);
+        }
+        for i in 10..16 {
+            tx.send_urgent(i);
+        }
+        let received: Vec<_> = (0..9).map(|_| rx.recv().unwrap()).collect();
+        assert_eq!(received, [10, 11, 0, 12, 13, 1, 14, 15, 2]);
+
+        std::thread::scope(|s| {
+            let tx = tx.clone();
+            s.spawn(move || tx.send(1));
+            assert_eq!(rx.recv(), Ok(1));
+        });
+        drop(tx);
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+}
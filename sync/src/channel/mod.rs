@@ -1,2 +1,13 @@
+pub mod adapter;
+pub mod batch;
+pub mod bounded;
+pub mod broadcast;
 pub mod chan;
+pub mod lanes;
 pub mod oneshot;
+pub mod router;
+pub mod rpc;
+pub mod scoped;
+pub mod slot;
+pub mod watch;
+pub mod wfq;
@@ -1,56 +1,380 @@
-use std::sync::atomic::Ordering::{Relaxed, Release};
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release, SeqCst};
+use std::sync::atomic::{fence, AtomicPtr};
 use std::sync::Arc;
-use std::{cell::UnsafeCell, mem::MaybeUninit, sync::atomic::AtomicBool};
+use std::time::Instant;
+use std::{cell::UnsafeCell, mem::MaybeUninit, ptr, sync::atomic::AtomicU32};
+
+use crate::futex::{wait, wait_timeout, wake_all, wake_one};
+use crate::mutex::Mutex;
+use crate::time;
+
+const ONESHOT_EMPTY: u32 = 0; // no message
+const ONESHOT_READY: u32 = 1; // message sent
+const ONESHOT_WAITING: u32 = 2; // no message, receiver waiting
+const ONESHOT_CLOSED: u32 = 3; // sender dropped without sending
 
 pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
-    let channel = Arc::new(Channel {
-        message: UnsafeCell::new(MaybeUninit::uninit()),
-        ready: AtomicBool::new(false),
-    });
+    split(Arc::new(Channel::new()), None)
+}
+
+fn split<T>(channel: Arc<Channel<T>>, pool: Option<&Pool<T>>) -> (Sender<T>, Receiver<T>) {
     (
         Sender {
             channel: Arc::clone(&channel),
+            pool: pool.cloned(),
+            sent: false,
+        },
+        Receiver {
+            channel,
+            pool: pool.cloned(),
         },
-        Receiver { channel },
     )
 }
 
 struct Channel<T> {
     message: UnsafeCell<MaybeUninit<T>>,
-    ready: AtomicBool,
+    ready: AtomicU32,
+    // Count of pooled halves alive, the last one recycles the channel.
+    halves: AtomicU32,
+    // Word of the Gather waiting on the channel, bumped on send, null if none.
+    notify: AtomicPtr<AtomicU32>,
+}
+
+impl<T> Channel<T> {
+    fn new() -> Self {
+        Self {
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+            ready: AtomicU32::new(ONESHOT_EMPTY),
+            halves: AtomicU32::new(2),
+            notify: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Drop the word of the Gather waiting on the channel if there's one.
+    fn clear_notify(&self) {
+        let notify = self.notify.swap(ptr::null_mut(), Acquire);
+        if !notify.is_null() {
+            drop(unsafe { Arc::from_raw(notify) });
+        }
+    }
+
+    /// Drop the half of a pooled channel, recycle it if it's the last half.
+    fn release(self: &Arc<Self>, pool: &Pool<T>) {
+        if self.halves.fetch_sub(1, AcqRel) != 1 {
+            return;
+        }
+        // Both halves are done, the channel is accessed exclusively.
+        if self.ready.load(Acquire) == ONESHOT_READY {
+            unsafe { (*self.message.get()).assume_init_drop() }
+        }
+        self.ready.store(ONESHOT_EMPTY, Relaxed);
+        self.halves.store(2, Relaxed);
+        self.clear_notify();
+        let mut free = pool.shared.free.lock();
+        if free.len() < pool.shared.max_idle {
+            free.push(Arc::clone(self));
+        }
+    }
 }
 
 unsafe impl<T> Sync for Channel<T> where T: Send {}
 
 pub struct Sender<T> {
     channel: Arc<Channel<T>>,
+    pool: Option<Pool<T>>,
+    // Whether the message is sent, the channel closes if dropped unsent.
+    sent: bool,
 }
 
 pub struct Receiver<T> {
     channel: Arc<Channel<T>>,
+    pool: Option<Pool<T>>,
 }
 
 impl<T> Sender<T> {
-    pub fn send(self, message: T) {
+    pub fn send(mut self, message: T) {
+        self.sent = true;
         unsafe { (*self.channel.message.get()).write(message) };
-        self.channel.ready.store(true, Release);
+        if self.channel.ready.swap(ONESHOT_READY, Release) == ONESHOT_WAITING {
+            wake_one(&self.channel.ready);
+        }
+        // Pairs with the fence of gather, so either the Gather sees the
+        // message or we see its word.
+        fence(SeqCst);
+        let notify = self.channel.notify.load(Acquire);
+        if !notify.is_null() {
+            // Safety: the word is kept until the channel is dropped or recycled,
+            // which waits for this sender.
+            let notify = unsafe { &*notify };
+            notify.fetch_add(1, Release);
+            wake_all(notify);
+        }
     }
 }
 
 impl<T> Receiver<T> {
     pub fn is_ready(&self) -> bool {
-        self.channel.ready.load(Relaxed)
+        self.channel.ready.load(Relaxed) == ONESHOT_READY
     }
 
+    /// Take the message, panic if the message is not ready.
     pub fn receive(self) -> T {
+        // Reset the state, so the message is not dropped twice.
+        if self.channel.ready.swap(ONESHOT_EMPTY, Acquire) != ONESHOT_READY {
+            panic!("no message available!");
+        }
         unsafe { (*self.channel.message.get()).assume_init_read() }
     }
+
+    /// Block until the message is sent, then take it.
+    /// Panic if the sender is dropped without sending.
+    pub fn recv(self) -> T {
+        self.recv_or_closed()
+            .expect("oneshot sender dropped without sending")
+    }
+
+    /// Block until the message is sent, then take it.
+    /// None if the sender is dropped without sending.
+    pub fn recv_or_closed(self) -> Option<T> {
+        // Mark the receiver waiting, the sender wakes it up only if marked.
+        let ready = &self.channel.ready;
+        let _ = ready.compare_exchange(ONESHOT_EMPTY, ONESHOT_WAITING, Relaxed, Relaxed);
+        while ready.load(Relaxed) == ONESHOT_WAITING {
+            wait(ready, ONESHOT_WAITING);
+        }
+        if ready.load(Acquire) == ONESHOT_CLOSED {
+            return None;
+        }
+        Some(self.receive())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if !self.sent && self.channel.ready.swap(ONESHOT_CLOSED, Release) == ONESHOT_WAITING {
+            wake_one(&self.channel.ready);
+        }
+        if let Some(pool) = &self.pool {
+            self.channel.release(pool);
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            self.channel.release(pool);
+        }
+    }
+}
+
+/// A pool of oneshot channels, whose allocations are reused after both
+/// halves of a channel are dropped, e.g. for a oneshot per request.
+pub struct Pool<T> {
+    shared: Arc<PoolShared<T>>,
+}
+
+struct PoolShared<T> {
+    free: Mutex<Vec<Arc<Channel<T>>>>,
+    max_idle: usize,
+}
+
+impl<T> Pool<T> {
+    /// Create a pool keeping at most max_idle channels for reuse.
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            shared: Arc::new(PoolShared {
+                free: Mutex::new(Vec::new()),
+                max_idle,
+            }),
+        }
+    }
+
+    /// Create a channel, reusing an idle one if there's any.
+    pub fn channel(&self) -> (Sender<T>, Receiver<T>) {
+        let channel = self.shared.free.lock().pop();
+        split(
+            channel.unwrap_or_else(|| Arc::new(Channel::new())),
+            Some(self),
+        )
+    }
+
+    /// Count of idle channels kept for reuse.
+    pub fn idle(&self) -> usize {
+        self.shared.free.lock().len()
+    }
+}
+
+impl<T> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
 }
 
 impl<T> Drop for Channel<T> {
     fn drop(&mut self) {
-        if *self.ready.get_mut() {
+        if *self.ready.get_mut() == ONESHOT_READY {
             unsafe { self.message.get_mut().assume_init_drop() }
         }
+        self.clear_notify();
+    }
+}
+
+/// Wait on many receivers at once, e.g. replies of sub-requests fanned out,
+/// and iterate the messages as they arrive. Of those ready at once, the
+/// one of the lowest receiver index comes first.
+pub fn gather<T>(receivers: Vec<Receiver<T>>) -> Gather<T> {
+    let notify = Arc::new(AtomicU32::new(0));
+    for rx in &receivers {
+        let old = rx
+            .channel
+            .notify
+            .swap(Arc::into_raw(Arc::clone(&notify)).cast_mut(), Release);
+        debug_assert!(old.is_null(), "receiver gathered twice");
+    }
+    // Pairs with the fence of send.
+    fence(SeqCst);
+    Gather {
+        pending: receivers.len(),
+        receivers: receivers.into_iter().map(Some).collect(),
+        notify,
+        deadline: None,
+    }
+}
+
+/// Iterator over messages of gathered receivers with the index of their
+/// receiver, blocking until the next message is sent.
+pub struct Gather<T> {
+    receivers: Vec<Option<Receiver<T>>>,
+    pending: usize,
+    // Bumped by every send to the gathered channels.
+    notify: Arc<AtomicU32>,
+    deadline: Option<Instant>,
+}
+
+impl<T> Gather<T> {
+    /// Stop iterating once the deadline passes, leaving the late receivers pending.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Count of receivers whose message is not taken yet.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    fn take_ready(&mut self) -> Option<(usize, T)> {
+        let i = self
+            .receivers
+            .iter()
+            .position(|rx| rx.as_ref().is_some_and(Receiver::is_ready))?;
+        self.pending -= 1;
+        Some((i, self.receivers[i].take()?.receive()))
+    }
+}
+
+impl<T> Iterator for Gather<T> {
+    type Item = (usize, T);
+
+    /// Block until a gathered message is sent, None if all are taken
+    /// or the deadline passes.
+    fn next(&mut self) -> Option<(usize, T)> {
+        loop {
+            // Load the word before checking, so a send in between is not missed.
+            let sends = self.notify.load(Acquire);
+            if let Some(item) = self.take_ready() {
+                return Some(item);
+            }
+            if self.pending == 0 {
+                return None;
+            }
+            match self.deadline {
+                None => wait(&self.notify, sends),
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(time::now());
+                    if remaining.is_zero() {
+                        return None;
+                    }
+                    wait_timeout(&self.notify, sends, remaining);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{channel, gather, Pool};
+
+    #[test]
+    fn test_oneshot() {
+        let (tx, rx) = channel();
+        thread::scope(|s| {
+            s.spawn(|| tx.send(String::from("hello")));
+            assert_eq!(rx.recv(), "hello");
+        });
+        // A sender dropped unsent wakes the receiver.
+        let (tx, rx) = channel::<u32>();
+        thread::scope(|s| {
+            s.spawn(|| drop(tx));
+            assert_eq!(rx.recv_or_closed(), None);
+        });
+    }
+
+    #[test]
+    fn test_oneshot_pool() {
+        let pool = Pool::new(2);
+        for i in 0..100 {
+            let (tx, rx) = pool.channel();
+            thread::scope(|s| {
+                s.spawn(|| tx.send(i.to_string()));
+                assert_eq!(rx.recv(), i.to_string());
+            });
+            assert_eq!(pool.idle(), 1);
+        }
+        // Unreceived messages are dropped on recycling.
+        let (tx, rx) = pool.channel();
+        tx.send(String::from("dropped"));
+        drop(rx);
+        let (tx, rx) = pool.channel();
+        assert!(!rx.is_ready());
+        drop(tx);
+        let channels: Vec<_> = (0..4).map(|_| pool.channel()).collect();
+        drop(channels);
+        assert_eq!(pool.idle(), 2);
+    }
+
+    #[test]
+    fn test_gather() {
+        let pool = Pool::new(4);
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| pool.channel()).unzip();
+        let mut replies = gather(receivers).deadline(Instant::now() + Duration::from_secs(3600));
+        let mut senders = senders.into_iter().enumerate();
+        let (_, never) = senders.next().unwrap();
+        // Replies come by the order they're sent, the first one never does.
+        thread::scope(|s| {
+            for (i, tx) in senders.rev() {
+                s.spawn(move || tx.send(i * 10));
+                assert_eq!(replies.next(), Some((i, i * 10)));
+            }
+        });
+        // The late receiver is left pending once the deadline passes.
+        let mut replies = replies.deadline(Instant::now());
+        assert_eq!(replies.next(), None);
+        assert_eq!(replies.pending(), 1);
+        drop(never);
+        drop(replies);
+        // Recycled channels are not notifying the dropped Gather anymore.
+        let (tx, rx) = pool.channel();
+        tx.send(0);
+        assert_eq!(rx.recv(), 0);
     }
 }
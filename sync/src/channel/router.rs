@@ -0,0 +1,92 @@
+use std::{collections::HashMap, hash::Hash};
+
+use super::bounded::{channel, Receiver, Sender};
+use crate::mutex::Mutex;
+
+/// A demultiplexer owning an inbound receiver, routing every message to the
+/// outbound channel subscribed with the key of the message, so sharded
+/// consumers don't all contend one queue.
+pub struct Router<K, T> {
+    inbound: Receiver<T>,
+    routes: Mutex<HashMap<K, Sender<T>>>,
+}
+
+impl<K: Eq + Hash + Clone, T> Router<K, T> {
+    pub fn new(inbound: Receiver<T>) -> Self {
+        Self {
+            inbound,
+            routes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Subscribe the messages of the key with a channel of given capacity,
+    /// replacing the former subscriber of the key.
+    pub fn subscribe(&self, key: K, capacity: usize) -> Receiver<T> {
+        let (tx, rx) = channel(capacity);
+        self.routes.lock().insert(key, tx);
+        rx
+    }
+
+    /// Unsubscribe the key, return false if it's not subscribed.
+    pub fn unsubscribe(&self, key: &K) -> bool {
+        self.routes.lock().remove(key).is_some()
+    }
+
+    /// Route the inbound messages by the key given by f, until all inbound
+    /// senders are dropped, then close all outbound channels.
+    ///
+    /// A message without a subscriber of its key is dropped, so is the
+    /// subscriber dropping its receiver. Sending blocks while the
+    /// subscribed channel is full. Return the count of messages dropped.
+    pub fn route(&self, mut f: impl FnMut(&T) -> K) -> usize {
+        let mut dropped = 0;
+        for msg in &self.inbound {
+            let key = f(&msg);
+            // Don't block subscribing while sending.
+            let Some(tx) = self.routes.lock().get(&key).cloned() else {
+                dropped += 1;
+                continue;
+            };
+            if tx.send(msg).is_err() {
+                dropped += 1;
+                let mut routes = self.routes.lock();
+                if routes.get(&key).is_some_and(|t| t.same_channel(&tx)) {
+                    routes.remove(&key);
+                }
+            }
+        }
+        self.routes.lock().clear();
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::Router;
+    use crate::channel::bounded::channel;
+
+    #[test]
+    fn test_router() {
+        let (tx, rx) = channel(4);
+        let router = Router::new(rx);
+        let zero = router.subscribe(0, 2);
+        drop(router.subscribe(1, 2));
+        router.subscribe(3, 1);
+        assert!(router.unsubscribe(&3));
+        assert!(!router.unsubscribe(&3));
+        thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..30 {
+                    tx.send(i).unwrap();
+                }
+            });
+            let zero = s.spawn(move || zero.iter().collect::<Vec<_>>());
+            // The receiver of 1 is dropped, 2 is never subscribed.
+            assert_eq!(router.route(|&i| i % 3), 20);
+            let zero = zero.join().unwrap();
+            assert_eq!(zero, (0..30).step_by(3).collect::<Vec<_>>());
+        });
+    }
+}
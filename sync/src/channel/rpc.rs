@@ -0,0 +1,123 @@
+use std::{error::Error, fmt};
+
+use super::{
+    bounded::{self, RecvError},
+    oneshot,
+};
+
+/// Create a request/response channel buffering at most `capacity` requests,
+/// split into the cloneable client and the server.
+///
+/// Each call sends the request with a oneshot channel for the response,
+/// reused from a pool, and blocks until the server responds.
+pub fn channel<Req, Resp>(capacity: usize) -> (Client<Req, Resp>, Server<Req, Resp>) {
+    let (tx, rx) = bounded::channel(capacity);
+    let client = Client {
+        requests: tx,
+        responses: oneshot::Pool::new(capacity),
+    };
+    (client, Server { requests: rx })
+}
+
+/// Error returned by `Client::call` if the server is dropped, or drops the
+/// request without responding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallError;
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request dropped without response")
+    }
+}
+
+impl Error for CallError {}
+
+/// The calling half of a request/response channel.
+pub struct Client<Req, Resp> {
+    requests: bounded::Sender<(Req, Responder<Resp>)>,
+    responses: oneshot::Pool<Resp>,
+}
+
+impl<Req, Resp> Client<Req, Resp> {
+    /// Send the request and block until it's responded.
+    pub fn call(&self, request: Req) -> Result<Resp, CallError> {
+        let (tx, rx) = self.responses.channel();
+        self.requests
+            .send((request, Responder { response: tx }))
+            .map_err(|_| CallError)?;
+        rx.recv_or_closed().ok_or(CallError)
+    }
+}
+
+impl<Req, Resp> Clone for Client<Req, Resp> {
+    fn clone(&self) -> Self {
+        Self {
+            requests: self.requests.clone(),
+            responses: self.responses.clone(),
+        }
+    }
+}
+
+/// The serving half of a request/response channel.
+pub struct Server<Req, Resp> {
+    requests: bounded::Receiver<(Req, Responder<Resp>)>,
+}
+
+impl<Req, Resp> Server<Req, Resp> {
+    /// Receive the next request with its responder, block until there's one.
+    pub fn recv(&self) -> Result<(Req, Responder<Resp>), RecvError> {
+        self.requests.recv()
+    }
+
+    /// Respond to requests by f until all clients are dropped.
+    pub fn serve(&self, mut f: impl FnMut(Req) -> Resp) {
+        while let Ok((request, responder)) = self.recv() {
+            responder.respond(f(request));
+        }
+    }
+}
+
+/// Responds to a request, the call fails if it's dropped without responding.
+pub struct Responder<Resp> {
+    response: oneshot::Sender<Resp>,
+}
+
+impl<Resp> Responder<Resp> {
+    /// Respond to the call, the response is dropped if the caller is gone.
+    pub fn respond(self, response: Resp) {
+        self.response.send(response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{channel, CallError};
+
+    #[test]
+    fn test_rpc() {
+        let (client, server) = channel(4);
+        thread::scope(|s| {
+            for t in 0..4 {
+                let client = client.clone();
+                s.spawn(move || {
+                    for i in 0..100 {
+                        assert_eq!(client.call(t * 1000 + i), Ok((t * 1000 + i) * 2));
+                    }
+                });
+            }
+            drop(client);
+            server.serve(|x| x * 2);
+        });
+
+        // A request dropped by the server fails its call.
+        let (client, server) = channel::<u32, u32>(4);
+        thread::scope(|s| {
+            s.spawn(|| drop(server.recv().unwrap()));
+            assert_eq!(client.call(1), Err(CallError));
+        });
+        drop(server);
+        assert_eq!(client.call(2), Err(CallError));
+    }
+}
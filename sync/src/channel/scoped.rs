@@ -0,0 +1,227 @@
+use std::mem::MaybeUninit;
+
+use super::bounded::{RecvError, SendError};
+use crate::{condvar::Condvar, mutex::Mutex};
+
+/// A bounded channel buffering messages in borrowed storage, e.g. an array
+/// on the stack, so it never allocates. Its handles borrow the channel,
+/// for threads of `thread::scope`.
+///
+/// ```
+/// use std::{mem::MaybeUninit, thread};
+/// use sync::channel::scoped::ScopedChannel;
+///
+/// let mut storage = [const { MaybeUninit::uninit() }; 4];
+/// let mut channel = ScopedChannel::with_capacity_in(&mut storage);
+/// let (tx, rx) = channel.split();
+/// thread::scope(|s| {
+///     s.spawn(move || (0..10).for_each(|i| tx.send(i).unwrap()));
+///     assert_eq!(rx.iter().sum::<i32>(), 45);
+/// });
+/// ```
+pub struct ScopedChannel<'a, T> {
+    state: Mutex<State<'a, T>>,
+    item_ready: Condvar,
+    space_ready: Condvar,
+}
+
+struct State<'a, T> {
+    // A ring of len messages starting at head.
+    buffer: &'a mut [MaybeUninit<T>],
+    head: usize,
+    len: usize,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+impl<T> State<'_, T> {
+    fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        // Safety: the len slots from head are initialized.
+        let value = unsafe { self.buffer[self.head].assume_init_read() };
+        self.head = (self.head + 1) % self.buffer.len();
+        self.len -= 1;
+        Some(value)
+    }
+}
+
+impl<'a, T> ScopedChannel<'a, T> {
+    /// Create a channel buffering at most `storage.len()` messages in storage.
+    pub fn with_capacity_in(storage: &'a mut [MaybeUninit<T>]) -> Self {
+        assert!(!storage.is_empty(), "capacity must be positive");
+        Self {
+            state: Mutex::new(State {
+                buffer: storage,
+                head: 0,
+                len: 0,
+                senders: 0,
+                receiver_alive: false,
+            }),
+            item_ready: Condvar::new(),
+            space_ready: Condvar::new(),
+        }
+    }
+
+    /// Split into the cloneable sending half and the receiving half,
+    /// the channel may be split again once both are dropped.
+    pub fn split(&mut self) -> (Sender<'_, 'a, T>, Receiver<'_, 'a, T>) {
+        let mut state = self.state.lock();
+        state.senders = 1;
+        state.receiver_alive = true;
+        drop(state);
+        (Sender { channel: self }, Receiver { channel: self })
+    }
+}
+
+impl<T> Drop for ScopedChannel<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        while state.pop().is_some() {}
+    }
+}
+
+/// The sending half of a scoped channel.
+pub struct Sender<'c, 'a, T> {
+    channel: &'c ScopedChannel<'a, T>,
+}
+
+impl<T> Sender<'_, '_, T> {
+    /// Send a message, block while the channel is full.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.channel.state.lock();
+        loop {
+            if !state.receiver_alive {
+                return Err(SendError(value));
+            }
+            if state.len < state.buffer.len() {
+                break;
+            }
+            state = self.channel.space_ready.wait(state);
+        }
+        let tail = (state.head + state.len) % state.buffer.len();
+        state.buffer[tail].write(value);
+        state.len += 1;
+        drop(state);
+        self.channel.item_ready.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<'_, '_, T> {
+    fn clone(&self) -> Self {
+        self.channel.state.lock().senders += 1;
+        Self {
+            channel: self.channel,
+        }
+    }
+}
+
+impl<T> Drop for Sender<'_, '_, T> {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.channel.item_ready.notify_all();
+        }
+    }
+}
+
+/// The receiving half of a scoped channel.
+pub struct Receiver<'c, 'a, T> {
+    channel: &'c ScopedChannel<'a, T>,
+}
+
+impl<'c, 'a, T> Receiver<'c, 'a, T> {
+    /// Receive the next message, block until there's one.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.channel.state.lock();
+        loop {
+            if let Some(value) = state.pop() {
+                drop(state);
+                self.channel.space_ready.notify_one();
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self.channel.item_ready.wait(state);
+        }
+    }
+
+    /// Iterate messages until all senders are dropped.
+    pub fn iter(&self) -> Iter<'_, 'c, 'a, T> {
+        Iter { receiver: self }
+    }
+}
+
+impl<T> Drop for Receiver<'_, '_, T> {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock();
+        state.receiver_alive = false;
+        // Messages nobody receives are dropped now, each outside the lock.
+        while let Some(value) = state.pop() {
+            drop(state);
+            drop(value);
+            state = self.channel.state.lock();
+        }
+        drop(state);
+        // Producers may be blocked by a full channel.
+        self.channel.space_ready.notify_all();
+    }
+}
+
+/// Iterator returned by `Receiver::iter`.
+pub struct Iter<'r, 'c, 'a, T> {
+    receiver: &'r Receiver<'c, 'a, T>,
+}
+
+impl<T> Iterator for Iter<'_, '_, '_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem::MaybeUninit, rc::Rc, thread};
+
+    use super::ScopedChannel;
+    use crate::channel::bounded::{RecvError, SendError};
+
+    #[test]
+    fn test_scoped_channel() {
+        let mut storage = [const { MaybeUninit::uninit() }; 2];
+        let mut channel = ScopedChannel::with_capacity_in(&mut storage);
+        let (tx, rx) = channel.split();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let tx = tx.clone();
+                s.spawn(move || (0..100).for_each(|i| tx.send(t * 100 + i).unwrap()));
+            }
+            drop(tx);
+            let mut received: Vec<_> = rx.iter().collect();
+            received.sort();
+            assert_eq!(received, (0..400).collect::<Vec<_>>());
+        });
+        assert_eq!(rx.recv(), Err(RecvError));
+        drop(rx);
+
+        // Split again, messages left are dropped with the receiver.
+        let (tx, rx) = channel.split();
+        let value = Rc::new(());
+        let mut storage = [const { MaybeUninit::uninit() }; 2];
+        let mut rc_channel = ScopedChannel::with_capacity_in(&mut storage);
+        let (rc_tx, rc_rx) = rc_channel.split();
+        rc_tx.send(Rc::clone(&value)).unwrap();
+        drop(rc_rx);
+        assert_eq!(Rc::strong_count(&value), 1);
+        assert_eq!(rc_tx.send(value), Err(SendError(Rc::new(()))));
+        tx.send(1).unwrap();
+        assert_eq!(rx.recv(), Ok(1));
+    }
+}
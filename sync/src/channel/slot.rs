@@ -0,0 +1,108 @@
+use std::ptr;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicPtr, AtomicU32};
+
+use crate::futex::{wait, wake_all};
+
+/// A single-slot channel keeping only the most recent value:
+/// `send` replaces any unread value, `recv` always takes the latest one.
+pub struct SlotCell<T> {
+    // Boxed unread value, null if empty.
+    slot: AtomicPtr<T>,
+    // Bumped by every send, receivers wait on it.
+    sends: AtomicU32,
+}
+
+unsafe impl<T> Sync for SlotCell<T> where T: Send {}
+unsafe impl<T> Send for SlotCell<T> where T: Send {}
+
+impl<T> Default for SlotCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SlotCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            slot: AtomicPtr::new(ptr::null_mut()),
+            sends: AtomicU32::new(0),
+        }
+    }
+
+    /// Put the value into the slot, return the unread value replaced.
+    pub fn send(&self, value: T) -> Option<T> {
+        let new = Box::into_raw(Box::new(value));
+        let old = self.slot.swap(new, AcqRel);
+        self.sends.fetch_add(1, Release);
+        wake_all(&self.sends);
+        // Safety: the pointer swapped out is owned by us now.
+        (!old.is_null()).then(|| *unsafe { Box::from_raw(old) })
+    }
+
+    /// Take the latest value if there's an unread one.
+    pub fn try_recv(&self) -> Option<T> {
+        let old = self.slot.swap(ptr::null_mut(), Acquire);
+        // Safety: the pointer swapped out is owned by us now.
+        (!old.is_null()).then(|| *unsafe { Box::from_raw(old) })
+    }
+
+    /// Block until there's an unread value, then take the latest one.
+    pub fn recv(&self) -> T {
+        loop {
+            // Load the counter before checking, so a send in between is not missed.
+            let sends = self.sends.load(Acquire);
+            if let Some(value) = self.try_recv() {
+                return value;
+            }
+            wait(&self.sends, sends);
+        }
+    }
+
+    pub fn has_value(&self) -> bool {
+        !self.slot.load(Relaxed).is_null()
+    }
+}
+
+impl<T> Drop for SlotCell<T> {
+    fn drop(&mut self) {
+        let value = *self.slot.get_mut();
+        if !value.is_null() {
+            drop(unsafe { Box::from_raw(value) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::SlotCell;
+
+    #[test]
+    fn test_slot_cell() {
+        let cell = SlotCell::new();
+        assert_eq!(cell.send(1), None);
+        assert_eq!(cell.send(2), Some(1));
+        assert_eq!(cell.recv(), 2);
+        assert_eq!(cell.try_recv(), None);
+
+        let cell = SlotCell::new();
+        cell.send(String::from("stale"));
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..1000 {
+                    cell.send(i.to_string());
+                }
+            });
+            // Values may be skipped but never go backwards.
+            let mut last = -1;
+            while last != 999 {
+                let value = cell.recv().parse().unwrap_or(-1);
+                assert!(value > last || value == -1);
+                last = last.max(value);
+            }
+        });
+        assert!(!cell.has_value());
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{
+            AtomicBool, AtomicUsize,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        Arc,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    cancel::{with_cancel, CancellationToken},
+    mutex::Mutex,
+    rwlock::{ReadGuard, RwLock},
+};
+
+/// Create a watch channel holding the initial value.
+/// Receivers observe only the latest value sent.
+pub fn channel<T>(init: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        value: RwLock::new(init),
+        version: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+        wakers: Mutex::new(Vec::new()),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared, seen: 0 },
+    )
+}
+
+struct Shared<T> {
+    value: RwLock<T>,
+    // Bumped on every send while holding the write lock of value.
+    version: AtomicUsize,
+    // Set when the sender is dropped.
+    closed: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl<T> Shared<T> {
+    fn wake_all(&self) {
+        for waker in self.wakers.lock().drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// Error returned by `Receiver::changed` once the sender is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "watch sender dropped")
+    }
+}
+
+impl Error for RecvError {}
+
+/// Error returned by `Receiver::changed_cancellable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangedError {
+    /// The sender is dropped.
+    Closed,
+    /// The token is cancelled before a new value is published.
+    Cancelled,
+}
+
+impl fmt::Display for ChangedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangedError::Closed => write!(f, "watch sender dropped"),
+            ChangedError::Cancelled => write!(f, "waiting for change cancelled"),
+        }
+    }
+}
+
+impl Error for ChangedError {}
+
+/// The publishing half of a watch channel.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Publish a new value and notify all receivers.
+    pub fn send(&self, value: T) {
+        {
+            let mut current = self.shared.value.write();
+            *current = value;
+            self.shared.version.fetch_add(1, Release);
+        }
+        self.shared.wake_all();
+    }
+
+    /// Borrow the latest value.
+    pub fn borrow(&self) -> ReadGuard<'_, T> {
+        self.shared.value.read()
+    }
+
+    /// Create a new receiver which has seen the current value.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let value = self.shared.value.read();
+        let seen = self.shared.version.load(Acquire);
+        drop(value);
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            seen,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Release);
+        self.shared.wake_all();
+    }
+}
+
+/// The observing half of a watch channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // Version of the value seen last time.
+    seen: usize,
+}
+
+impl<T> Receiver<T> {
+    /// Borrow the latest value without marking it as seen.
+    pub fn borrow(&self) -> ReadGuard<'_, T> {
+        self.shared.value.read()
+    }
+
+    /// Borrow the latest value and mark it as seen.
+    pub fn borrow_and_update(&mut self) -> ReadGuard<'_, T> {
+        // Version is only bumped under the write lock,
+        // so it's consistent with the value while reading.
+        let value = self.shared.value.read();
+        self.seen = self.shared.version.load(Acquire);
+        value
+    }
+
+    /// Return true if a value is published after last seen.
+    pub fn has_changed(&self) -> bool {
+        self.shared.version.load(Acquire) != self.seen
+    }
+
+    /// Wait until a new value is published, and mark it as seen.
+    /// Resolve to `RecvError` if the sender is dropped.
+    pub fn changed(&mut self) -> Changed<'_, T> {
+        Changed { receiver: self }
+    }
+
+    /// Same as `changed`, but give up once the token is cancelled.
+    pub async fn changed_cancellable(
+        &mut self,
+        token: &CancellationToken,
+    ) -> Result<(), ChangedError> {
+        match with_cancel(token, self.changed()).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(RecvError)) => Err(ChangedError::Closed),
+            Err(_) => Err(ChangedError::Cancelled),
+        }
+    }
+
+    fn poll_changed(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), RecvError>> {
+        let shared = &*self.shared;
+        if let Some(result) = Self::try_changed(shared, &mut self.seen) {
+            return Poll::Ready(result);
+        }
+
+        let mut wakers = shared.wakers.lock();
+        // Check again under the waker lock,
+        // sender always bumps the version before draining the wakers.
+        if let Some(result) = Self::try_changed(shared, &mut self.seen) {
+            return Poll::Ready(result);
+        }
+        if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    fn try_changed(shared: &Shared<T>, seen: &mut usize) -> Option<Result<(), RecvError>> {
+        let version = shared.version.load(Acquire);
+        if version != *seen {
+            *seen = version;
+            return Some(Ok(()));
+        }
+        if shared.closed.load(Relaxed) {
+            return Some(Err(RecvError));
+        }
+        None
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            seen: self.seen,
+        }
+    }
+}
+
+/// Future returned by `Receiver::changed`.
+pub struct Changed<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for Changed<'_, T> {
+    type Output = Result<(), RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.receiver.poll_changed(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{channel, ChangedError, RecvError};
+    use crate::{cancel::CancellationToken, task::block_on};
+
+    #[test]
+    fn test_watch() {
+        let (tx, mut rx) = channel(0);
+        assert!(!rx.has_changed());
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 1..=100 {
+                    tx.send(i);
+                }
+            });
+            let mut last = 0;
+            while last < 100 {
+                block_on(rx.changed()).unwrap();
+                let v = *rx.borrow_and_update();
+                assert!(v > last);
+                last = v;
+            }
+        });
+        drop(tx);
+        assert_eq!(block_on(rx.changed()), Err(RecvError));
+    }
+
+    #[test]
+    fn test_watch_cancellable() {
+        let (tx, mut rx) = channel(0);
+        let token = CancellationToken::new();
+        thread::scope(|s| {
+            s.spawn(|| tx.send(1));
+            assert_eq!(block_on(rx.changed_cancellable(&token)), Ok(()));
+        });
+        token.cancel();
+        assert_eq!(
+            block_on(rx.changed_cancellable(&token)),
+            Err(ChangedError::Cancelled)
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use super::bounded::RecvError;
+use crate::{condvar::Condvar, mutex::Mutex};
+
+/// Create a weighted fair queuing channel, split into the sending half of
+/// the first producer with the given weight, and the receiving half.
+///
+/// Each producer has its own queue, and the receiver takes messages from
+/// the queues by deficit round robin: a queue gets as many messages as its
+/// weight per round, so a busy producer never starves the others,
+/// e.g. for tenants multiplexed onto one worker.
+pub fn channel<T>(weight: u32) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queues: HashMap::new(),
+            active: VecDeque::new(),
+            next_id: 0,
+            senders: 0,
+        }),
+        item_ready: Condvar::new(),
+    });
+    let tx = Sender::new(&shared, weight);
+    (tx, Receiver { shared })
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    item_ready: Condvar,
+}
+
+struct State<T> {
+    queues: HashMap<u64, Queue<T>>,
+    // Queues with messages by the order of their turns, the front one is served.
+    active: VecDeque<u64>,
+    next_id: u64,
+    senders: usize,
+}
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    weight: u32,
+    // Messages the queue may still take in its turn.
+    deficit: u32,
+    senders: usize,
+}
+
+/// The sending half of a producer, clones send to the same queue.
+/// Sending never blocks.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    id: u64,
+}
+
+impl<T> Sender<T> {
+    fn new(shared: &Arc<Shared<T>>, weight: u32) -> Self {
+        assert!(weight > 0, "weight must be positive");
+        let mut state = shared.state.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.senders += 1;
+        state.queues.insert(
+            id,
+            Queue {
+                items: VecDeque::new(),
+                weight,
+                deficit: 0,
+                senders: 1,
+            },
+        );
+        Self {
+            shared: Arc::clone(shared),
+            id,
+        }
+    }
+
+    /// Create the sending half of a new producer with its own queue.
+    pub fn producer(&self, weight: u32) -> Sender<T> {
+        Sender::new(&self.shared, weight)
+    }
+
+    /// Queue a message of this producer, never blocks.
+    pub fn send(&self, value: T) {
+        let mut state = self.shared.state.lock();
+        let queue = state.queues.get_mut(&self.id).expect("queue of a sender");
+        queue.items.push_back(value);
+        if queue.items.len() == 1 {
+            state.active.push_back(self.id);
+        }
+        drop(state);
+        self.shared.item_ready.notify_one();
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        let mut state = self.shared.state.lock();
+        state.senders += 1;
+        state
+            .queues
+            .get_mut(&self.id)
+            .expect("queue of a sender")
+            .senders += 1;
+        Self {
+            shared: Arc::clone(&self.shared),
+            id: self.id,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.senders -= 1;
+        let queue = state.queues.get_mut(&self.id).expect("queue of a sender");
+        queue.senders -= 1;
+        // A queue with messages left is removed once it's drained.
+        if queue.senders == 0 && queue.items.is_empty() {
+            state.queues.remove(&self.id);
+        }
+        if state.senders == 0 {
+            drop(state);
+            self.shared.item_ready.notify_all();
+        }
+    }
+}
+
+/// The receiving half of a weighted fair queuing channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next message, block until there's one.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if let Some(value) = Self::pop(&mut state) {
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self.shared.item_ready.wait(state);
+        }
+    }
+
+    fn pop(state: &mut State<T>) -> Option<T> {
+        let id = *state.active.front()?;
+        let queue = state.queues.get_mut(&id).expect("queue of an active id");
+        // A turn starts with the deficit of the weight.
+        if queue.deficit == 0 {
+            queue.deficit = queue.weight;
+        }
+        let value = queue.items.pop_front();
+        queue.deficit -= 1;
+        if queue.items.is_empty() {
+            // Credit is not saved by an idle queue.
+            queue.deficit = 0;
+            state.active.pop_front();
+            if queue.senders == 0 {
+                state.queues.remove(&id);
+            }
+        } else if queue.deficit == 0 {
+            state.active.rotate_left(1);
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::channel;
+    use crate::channel::bounded::RecvError;
+
+    #[test]
+    fn test_wfq() {
+        let (heavy, rx) = channel(3);
+        let light = heavy.producer(1);
+        for i in 0..9 {
+            heavy.send(i);
+        }
+        for i in 100..103 {
+            light.send(i);
+        }
+        let received: Vec<_> = (0..12).map(|_| rx.recv().unwrap()).collect();
+        assert_eq!(received, [0, 1, 2, 100, 3, 4, 5, 101, 6, 7, 8, 102]);
+
+        // A dropped producer's messages are still received.
+        let late = light.producer(2);
+        late.send(200);
+        drop(late);
+        light.send(103);
+        assert_eq!(rx.recv(), Ok(200));
+        assert_eq!(rx.recv(), Ok(103));
+
+        std::thread::scope(|s| {
+            let heavy = heavy.clone();
+            s.spawn(move || heavy.send(9));
+            assert_eq!(rx.recv(), Ok(9));
+        });
+        drop((heavy, light));
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+}
@@ -0,0 +1,97 @@
+//! Random yields, delays and spurious wakeups injected into the futex layer,
+//! shaking out ordering assumptions of code built on the primitives.
+//!
+//! Every thread draws its decisions from a generator seeded by the global
+//! seed and the order the thread first hits a primitive, so a failure is
+//! reproduced by running again with `SYNC_CHAOS_SEED` set to the seed
+//! printed to stderr.
+
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::xorshift::XorShift;
+
+static SEED: OnceLock<u64> = OnceLock::new();
+static THREADS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static RNG: Cell<XorShift> = Cell::new(XorShift::new(seed() ^ THREADS.fetch_add(1, Relaxed)));
+}
+
+/// The seed in effect, read from `SYNC_CHAOS_SEED` or the clock.
+pub fn seed() -> u64 {
+    *SEED.get_or_init(|| {
+        let seed = std::env::var("SYNC_CHAOS_SEED")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64)
+            });
+        eprintln!("sync chaos seed: {}", seed);
+        seed
+    })
+}
+
+/// Yield at 1/16 and sleep up to 100µs at 1/1024 of the calls.
+pub(crate) fn perturb() {
+    let x = next();
+    if x.is_multiple_of(1024) {
+        thread::sleep(Duration::from_micros((x >> 8) % 100));
+    } else if x.is_multiple_of(16) {
+        thread::yield_now();
+    }
+}
+
+/// Whether a futex wait returns at once, true at 1/16 of the calls.
+pub(crate) fn spurious_wakeup() -> bool {
+    next().is_multiple_of(16)
+}
+
+fn next() -> u64 {
+    // Threads exiting after their thread-local is gone go unperturbed.
+    RNG.try_with(|rng| {
+        let mut next = rng.get();
+        let x = next.next();
+        rng.set(next);
+        x
+    })
+    .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{channel::bounded, mutex::Mutex};
+
+    #[test]
+    fn test_chaos() {
+        super::seed();
+        let x = Mutex::new(0);
+        let (tx, rx) = bounded::channel(2);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let tx = tx.clone();
+                let x = &x;
+                s.spawn(move || {
+                    for i in 0..500 {
+                        *x.lock() += 1;
+                        tx.send(i).unwrap();
+                    }
+                });
+            }
+            drop(tx);
+            assert_eq!(rx.iter().count(), 2000);
+        });
+        assert_eq!(*x.lock(), 2000);
+    }
+}
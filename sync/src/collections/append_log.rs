@@ -0,0 +1,174 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{
+        AtomicBool, AtomicPtr, AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use crate::mutex::Mutex;
+
+const FIRST_SEGMENT_BITS: u32 = 5; // the first segment holds 2^5 entries.
+const SEGMENTS: usize = (usize::BITS - FIRST_SEGMENT_BITS) as usize;
+
+/// A concurrent append-only log.
+///
+/// Writers push concurrently, each push reserves an index by one `fetch_add`.
+/// Entries live in segments doubling in size which are never moved or freed
+/// until the log is dropped, so references and indices stay valid forever.
+/// Only growing to a new segment takes a lock.
+pub struct AppendLog<T> {
+    segments: [AtomicPtr<Slot<T>>; SEGMENTS],
+    // Count of indices reserved, entries may still be written.
+    len: AtomicUsize,
+    grow: Mutex<()>,
+}
+
+struct Slot<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    ready: AtomicBool,
+}
+
+unsafe impl<T: Send> Send for AppendLog<T> {}
+unsafe impl<T: Send + Sync> Sync for AppendLog<T> {}
+
+impl<T> Default for AppendLog<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> AppendLog<T> {
+    pub fn new() -> Self {
+        Self {
+            segments: [const { AtomicPtr::new(ptr::null_mut()) }; SEGMENTS],
+            len: AtomicUsize::new(0),
+            grow: Mutex::new(()),
+        }
+    }
+
+    /// Append the value, return its index.
+    pub fn push(&self, value: T) -> usize {
+        let index = self.len.fetch_add(1, Relaxed);
+        let (segment, offset) = locate(index);
+        let slot = unsafe { &*self.segment_or_grow(segment).add(offset) };
+        // Safety: the index is reserved by us only.
+        unsafe { (*slot.value.get()).write(value) };
+        slot.ready.store(true, Release);
+        index
+    }
+
+    /// Get the entry at index, None if it's not pushed or still being written.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len.load(Relaxed) {
+            return None;
+        }
+        let (segment, offset) = locate(index);
+        let segment = self.segments[segment].load(Acquire);
+        if segment.is_null() {
+            return None;
+        }
+        let slot = unsafe { &*segment.add(offset) };
+        // Safety: written once before ready, never mutated again.
+        slot.ready
+            .load(Acquire)
+            .then(|| unsafe { (*slot.value.get()).assume_init_ref() })
+    }
+
+    /// Count of indices reserved, including entries still being written.
+    pub fn len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the written entries with their indices.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        (0..self.len()).filter_map(|i| self.get(i).map(|v| (i, v)))
+    }
+
+    fn segment_or_grow(&self, segment: usize) -> *mut Slot<T> {
+        let ptr = self.segments[segment].load(Acquire);
+        if !ptr.is_null() {
+            return ptr;
+        }
+        let _guard = self.grow.lock();
+        let ptr = self.segments[segment].load(Acquire);
+        if !ptr.is_null() {
+            return ptr;
+        }
+        let slots: Box<[Slot<T>]> = (0..segment_len(segment))
+            .map(|_| Slot {
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+                ready: AtomicBool::new(false),
+            })
+            .collect();
+        let ptr = Box::into_raw(slots).cast::<Slot<T>>();
+        self.segments[segment].store(ptr, Release);
+        ptr
+    }
+}
+
+impl<T> Drop for AppendLog<T> {
+    fn drop(&mut self) {
+        for (i, segment) in self.segments.iter_mut().enumerate() {
+            let ptr = *segment.get_mut();
+            if ptr.is_null() {
+                continue;
+            }
+            let mut slots =
+                unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, segment_len(i))) };
+            for slot in slots.iter_mut() {
+                if *slot.ready.get_mut() {
+                    unsafe { slot.value.get_mut().assume_init_drop() };
+                }
+            }
+        }
+    }
+}
+
+fn segment_len(segment: usize) -> usize {
+    1 << (segment as u32 + FIRST_SEGMENT_BITS)
+}
+
+/// Return the segment and offset in segment of the index.
+fn locate(index: usize) -> (usize, usize) {
+    let pos = index + (1 << FIRST_SEGMENT_BITS);
+    let segment = (usize::BITS - 1 - pos.leading_zeros() - FIRST_SEGMENT_BITS) as usize;
+    (segment, pos - segment_len(segment))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{locate, AppendLog};
+
+    #[test]
+    fn test_append_log() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(31), (0, 31));
+        assert_eq!(locate(32), (1, 0));
+        assert_eq!(locate(96), (2, 0));
+
+        let log = AppendLog::new();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let log = &log;
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        let index = log.push(format!("{}-{}", t, i));
+                        assert_eq!(log.get(index).unwrap(), &format!("{}-{}", t, i));
+                    }
+                });
+            }
+        });
+        assert_eq!(log.len(), 4000);
+        assert_eq!(log.iter().count(), 4000);
+        assert!(log.get(4000).is_none());
+    }
+}
@@ -0,0 +1,139 @@
+use std::{borrow::Borrow, collections::HashMap, hash::Hash};
+
+use crate::{
+    arc::{Arc, AtomicArc},
+    mutex::Mutex,
+};
+
+/// A hash map read through immutable snapshots.
+///
+/// Readers take the current snapshot without locking. Writers are
+/// serialized, they copy the map, apply a batch of writes and publish the
+/// result as a new version. Suits small maps read far more often than
+/// written, e.g. registries of metric names or feature flags.
+pub struct CowMap<K, V> {
+    current: AtomicArc<HashMap<K, V>>,
+    writer: Mutex<()>,
+}
+
+impl<K, V> Default for CowMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> CowMap<K, V> {
+    pub fn new() -> Self {
+        Self::from(HashMap::new())
+    }
+
+    /// Get the current snapshot, later writes don't change it.
+    pub fn snapshot(&self) -> Arc<HashMap<K, V>> {
+        self.current.load()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshot().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Eq + Hash, V> CowMap<K, V> {
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.snapshot().contains_key(key)
+    }
+}
+
+impl<K: Eq + Hash, V: Clone> CowMap<K, V> {
+    /// Get a clone of the value in the current snapshot.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.snapshot().get(key).cloned()
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> CowMap<K, V> {
+    /// Apply a batch of writes to a copy of the map and publish it as one
+    /// version. Return what f returns.
+    pub fn update<R>(&self, f: impl FnOnce(&mut HashMap<K, V>) -> R) -> R {
+        let _writer = self.writer.lock();
+        let mut map = HashMap::clone(&self.current.load());
+        let result = f(&mut map);
+        self.current.store(Arc::new(map));
+        result
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.update(|m| m.insert(key, value))
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.update(|m| m.remove(key))
+    }
+}
+
+impl<K, V> From<HashMap<K, V>> for CowMap<K, V> {
+    fn from(map: HashMap<K, V>) -> Self {
+        Self {
+            current: AtomicArc::new(Arc::new(map)),
+            writer: Mutex::new(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::CowMap;
+
+    #[test]
+    fn test_cow_map() {
+        let map = CowMap::new();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let map = &map;
+                s.spawn(move || {
+                    for i in 0..100 {
+                        map.insert(format!("metric.{}", t * 100 + i), i);
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..100 {
+                    let snapshot = map.snapshot();
+                    let len = snapshot.len();
+                    thread::yield_now();
+                    assert_eq!(snapshot.len(), len);
+                }
+            });
+        });
+        assert_eq!(map.len(), 400);
+        assert_eq!(map.get("metric.101"), Some(1));
+
+        // A batch is published as one version.
+        let before = map.snapshot();
+        map.update(|m| {
+            m.retain(|_, v| *v < 50);
+            m.insert("flag".to_string(), 1);
+        });
+        assert_eq!(before.len(), 400);
+        assert_eq!(map.len(), 201);
+        assert_eq!(map.remove("flag"), Some(1));
+        assert!(!map.contains_key("flag"));
+    }
+}
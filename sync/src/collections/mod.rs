@@ -0,0 +1,11 @@
+mod append_log;
+mod cow_map;
+mod sharded;
+mod snapshot_vec;
+mod subscribers;
+
+pub use append_log::AppendLog;
+pub use cow_map::CowMap;
+pub use sharded::{Merge, ShardedValue};
+pub use snapshot_vec::SnapshotVec;
+pub use subscribers::{Callback, Subscribers};
@@ -0,0 +1,134 @@
+use std::{
+    collections::HashSet,
+    hash::Hash,
+    marker::PhantomData,
+    mem,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    thread,
+};
+
+use crate::{lock::Lock, spin::SpinLock};
+
+/// State combined from shards by `ShardedValue::merge`.
+pub trait Merge {
+    /// Merge other into self, the result mustn't depend on the order.
+    fn merge(&mut self, other: &Self);
+}
+
+macro_rules! merge_by_add {
+    ($($t:ty),*) => {
+        $(impl Merge for $t {
+            fn merge(&mut self, other: &Self) {
+                *self += *other;
+            }
+        })*
+    };
+}
+
+merge_by_add!(u32, u64, usize, i32, i64, isize, f64);
+
+impl<T: Clone + Eq + Hash> Merge for HashSet<T> {
+    fn merge(&mut self, other: &Self) {
+        self.extend(other.iter().cloned());
+    }
+}
+
+// Threads are spread over the shards by the order they first update.
+static NEXT_THREAD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_INDEX: usize = NEXT_THREAD.fetch_add(1, Relaxed);
+}
+
+// Each shard owns its cache line, so threads don't false-share.
+#[repr(align(128))]
+pub(super) struct Shard<L>(pub(super) L);
+
+/// A value split into shards each updated by a few threads under its own
+/// lock, then merged on read, e.g. counters, histograms or sets
+/// updated far more often than read.
+///
+/// Shards are locked by SpinLock by default, pick another Lock by `L`
+/// if updates are long, e.g. `ShardedValue<T, Mutex<T>>`.
+pub struct ShardedValue<T, L = SpinLock<T>> {
+    shards: Box<[Shard<L>]>,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T: Default + Merge, L: Lock<T>> Default for ShardedValue<T, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Default + Merge, L: Lock<T>> ShardedValue<T, L> {
+    /// Create shards by twice the available parallelism.
+    pub fn new() -> Self {
+        let n = thread::available_parallelism().map_or(8, |n| n.get() * 2);
+        Self::with_shards(n)
+    }
+
+    pub fn with_shards(n: usize) -> Self {
+        assert!(n > 0, "shards must be positive");
+        Self {
+            shards: (0..n).map(|_| Shard(L::new(T::default()))).collect(),
+            _value: PhantomData,
+        }
+    }
+
+    /// Update the shard of the current thread, return what f returns.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let i = THREAD_INDEX.with(|i| *i) % self.shards.len();
+        f(&mut self.shards[i].0.lock())
+    }
+
+    /// Merge all shards into a new value. Updates racing with it may or
+    /// may not be counted, each shard is locked at a time.
+    pub fn merge(&self) -> T {
+        let mut value = T::default();
+        for shard in self.shards.iter() {
+            value.merge(&shard.0.lock());
+        }
+        value
+    }
+
+    /// Merge all shards and reset them to the default.
+    pub fn take(&self) -> T {
+        let mut value = T::default();
+        for shard in self.shards.iter() {
+            value.merge(&mem::take(&mut *shard.0.lock()));
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, thread};
+
+    use super::ShardedValue;
+    use crate::mutex::Mutex;
+
+    #[test]
+    fn test_sharded_value() {
+        let counter = ShardedValue::<u64>::with_shards(3);
+        let set = ShardedValue::<HashSet<u32>, Mutex<_>>::new();
+        thread::scope(|s| {
+            for t in 0..8 {
+                let (counter, set) = (&counter, &set);
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        counter.update(|c| *c += 1);
+                        set.update(|s| s.insert(t * 1000 + i % 10));
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.merge(), 8000);
+        assert_eq!(counter.take(), 8000);
+        assert_eq!(counter.merge(), 0);
+        let set = set.merge();
+        assert_eq!(set.len(), 80);
+        assert!(set.contains(&7009));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    arc::{Arc, AtomicArc},
+    mutex::Mutex,
+};
+
+/// A vector read through immutable snapshots.
+///
+/// Readers take the current snapshot without locking and iterate it freely.
+/// Writers are serialized, they copy the vector, apply a batch of mutations
+/// and publish the result as the new snapshot. Suits lists read far more
+/// often than written, e.g. subscribers iterated on every event.
+///
+/// Arc only holds sized values, so a snapshot is an `Arc<Vec<T>>`.
+pub struct SnapshotVec<T> {
+    current: AtomicArc<Vec<T>>,
+    writer: Mutex<()>,
+}
+
+impl<T> Default for SnapshotVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SnapshotVec<T> {
+    pub fn new() -> Self {
+        Self::from(Vec::new())
+    }
+
+    /// Get the current snapshot, later writes don't change it.
+    pub fn snapshot(&self) -> Arc<Vec<T>> {
+        self.current.load()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshot().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Clone> SnapshotVec<T> {
+    /// Apply a batch of mutations to a copy of the vector and publish it.
+    /// Return what f returns.
+    pub fn update<R>(&self, f: impl FnOnce(&mut Vec<T>) -> R) -> R {
+        let _writer = self.writer.lock();
+        let mut vec = Vec::clone(&self.current.load());
+        let result = f(&mut vec);
+        self.current.store(Arc::new(vec));
+        result
+    }
+
+    pub fn push(&self, value: T) {
+        self.update(|v| v.push(value))
+    }
+
+    /// Keep only the elements f returns true for.
+    pub fn retain(&self, f: impl FnMut(&T) -> bool) {
+        self.update(|v| v.retain(f))
+    }
+}
+
+impl<T> From<Vec<T>> for SnapshotVec<T> {
+    fn from(vec: Vec<T>) -> Self {
+        Self {
+            current: AtomicArc::new(Arc::new(vec)),
+            writer: Mutex::new(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::SnapshotVec;
+
+    #[test]
+    fn test_snapshot_vec() {
+        let vec = SnapshotVec::new();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let vec = &vec;
+                s.spawn(move || {
+                    for i in 0..100 {
+                        vec.push(t * 100 + i);
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..100 {
+                    let snapshot = vec.snapshot();
+                    let len = snapshot.len();
+                    // A snapshot never changes under the reader.
+                    thread::yield_now();
+                    assert_eq!(snapshot.len(), len);
+                }
+            });
+        });
+        let before = vec.snapshot();
+        vec.retain(|v| v % 2 == 0);
+        assert_eq!(before.len(), 400);
+        assert_eq!(vec.len(), 200);
+        assert_eq!(
+            vec.update(|v| v.iter().sum::<i32>()),
+            (0..400).step_by(2).sum()
+        );
+    }
+
+    #[cfg(feature = "testutil")]
+    #[test]
+    fn test_snapshot_vec_linearizable() {
+        use crate::testutil::linearizability::{check, History, Model};
+
+        #[derive(Clone, Default, Hash, PartialEq, Eq)]
+        struct VecModel(Vec<u32>);
+
+        #[derive(Debug)]
+        enum Op {
+            Push(u32),
+            Snapshot,
+        }
+
+        impl Model for VecModel {
+            type Op = Op;
+            type Ret = Option<Vec<u32>>;
+            fn apply(&mut self, op: &Op) -> Option<Vec<u32>> {
+                match *op {
+                    Op::Push(v) => {
+                        self.0.push(v);
+                        None
+                    }
+                    Op::Snapshot => Some(self.0.clone()),
+                }
+            }
+        }
+
+        for _ in 0..20 {
+            let vec = SnapshotVec::new();
+            let history = History::new();
+            thread::scope(|s| {
+                for t in 0..2 {
+                    let (vec, history) = (&vec, &history);
+                    s.spawn(move || {
+                        for v in t * 10..t * 10 + 3 {
+                            history.record(Op::Push(v), || {
+                                vec.push(v);
+                                None
+                            });
+                        }
+                    });
+                }
+                for _ in 0..4 {
+                    history.record(Op::Snapshot, || Some(vec.snapshot().to_vec()));
+                }
+            });
+            check(VecModel::default(), &history).unwrap();
+        }
+    }
+}
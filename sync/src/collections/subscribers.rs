@@ -0,0 +1,148 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Arc, Weak,
+    },
+    thread,
+};
+
+use super::sharded::Shard;
+use crate::rwlock::RwLock;
+
+/// A callback subscribed to a Subscribers.
+pub type Callback<T> = dyn Fn(&T) + Send + Sync;
+
+type Entries<T> = RwLock<Vec<Weak<Callback<T>>>>;
+
+/// A list of callbacks notified of events of T, holding them weakly:
+/// a subscription lasts as long as the subscriber keeps the Arc of its
+/// callback, and dead entries are pruned by `notify`.
+///
+/// Entries are spread over shards each under its own RwLock, so
+/// concurrent notifications and subscriptions rarely contend.
+pub struct Subscribers<T> {
+    shards: Box<[Shard<Entries<T>>]>,
+    // Subscriptions are spread over the shards round robin.
+    next: AtomicUsize,
+}
+
+impl<T> Default for Subscribers<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Subscribers<T> {
+    /// Create shards by the available parallelism.
+    pub fn new() -> Self {
+        let n = thread::available_parallelism().map_or(4, |n| n.get());
+        Self::with_shards(n)
+    }
+
+    pub fn with_shards(n: usize) -> Self {
+        assert!(n > 0, "shards must be positive");
+        Self {
+            shards: (0..n).map(|_| Shard(RwLock::new(Vec::new()))).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Subscribe the callback until its last Arc is dropped.
+    pub fn subscribe<F>(&self, callback: &Arc<F>)
+    where
+        F: Fn(&T) + Send + Sync + 'static,
+    {
+        let callback: Arc<Callback<T>> = callback.clone();
+        let i = self.next.fetch_add(1, Relaxed) % self.shards.len();
+        self.shards[i].0.write().push(Arc::downgrade(&callback));
+    }
+
+    /// Call every live callback with the event, return the count of them.
+    /// Callbacks run without any lock held, so they may subscribe or notify,
+    /// callbacks subscribed meanwhile may or may not be called.
+    pub fn notify(&self, event: &T) -> usize {
+        let mut live = Vec::new();
+        let mut notified = 0;
+        for shard in self.shards.iter() {
+            let dead = {
+                let entries = shard.0.read();
+                live.extend(entries.iter().filter_map(Weak::upgrade));
+                entries.len() - live.len()
+            };
+            if dead > 0 {
+                shard.0.write().retain(|w| w.strong_count() > 0);
+            }
+            notified += live.len();
+            for callback in live.drain(..) {
+                callback(event);
+            }
+        }
+        notified
+    }
+
+    /// Count of live subscriptions now.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .0
+                    .read()
+                    .iter()
+                    .filter(|w| w.strong_count() > 0)
+                    .count()
+            })
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Arc,
+    };
+
+    use super::Subscribers;
+
+    #[test]
+    fn test_subscribers() {
+        let subscribers = Arc::new(Subscribers::with_shards(2));
+        let total = Arc::new(AtomicUsize::new(0));
+        let callbacks: Vec<_> = (0..4)
+            .map(|_| {
+                let total = Arc::clone(&total);
+                Arc::new(move |n: &usize| {
+                    total.fetch_add(*n, Relaxed);
+                })
+            })
+            .collect();
+        callbacks.iter().for_each(|c| subscribers.subscribe(c));
+        assert_eq!(subscribers.notify(&1), 4);
+        assert_eq!(total.load(Relaxed), 4);
+
+        // Dropped callbacks are skipped and pruned.
+        drop(callbacks);
+        assert_eq!(subscribers.notify(&1), 0);
+        assert!(subscribers.shards.iter().all(|s| s.0.read().is_empty()));
+
+        // A callback may subscribe another without deadlocking.
+        let subscribers = Arc::new(Subscribers::with_shards(1));
+        let inner = Arc::new(|_: &usize| {});
+        let outer = {
+            let (subscribers, inner) = (Arc::downgrade(&subscribers), Arc::clone(&inner));
+            Arc::new(move |_: &usize| {
+                if let Some(subscribers) = subscribers.upgrade() {
+                    subscribers.subscribe(&inner);
+                }
+            })
+        };
+        subscribers.subscribe(&outer);
+        assert_eq!(subscribers.notify(&0), 1);
+        assert_eq!(subscribers.len(), 2);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    any::Any,
+    cell::UnsafeCell,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{
+        AtomicBool, AtomicU32, AtomicUsize,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+    thread,
+};
+
+use crate::{atomic_ext::Backoff, channel::oneshot};
+
+const SLOT_EMPTY: u32 = 0; // no operation
+const SLOT_WRITING: u32 = 1; // claimed by a thread writing its operation
+const SLOT_PENDING: u32 = 2; // operation published, waiting for a combiner
+
+type Operation<'a, T> = Box<dyn FnOnce(&mut T) + Send + 'a>;
+
+/// A flat-combining wrapper.
+///
+/// Threads publish operations into slots instead of taking a lock,
+/// the thread winning the combiner lock executes all published operations
+/// in a batch and the results are sent back by oneshot channels.
+/// The data stays in the cache of the combiner, and the lock is taken once
+/// per batch, so it beats a Mutex for hot and short operations.
+pub struct FlatCombiner<T> {
+    value: UnsafeCell<T>,
+    combining: AtomicBool,
+    slots: Box<[Slot<T>]>,
+}
+
+struct Slot<T> {
+    state: AtomicU32,
+    operation: UnsafeCell<Option<Operation<'static, T>>>,
+}
+
+unsafe impl<T: Send> Sync for FlatCombiner<T> {}
+
+impl<T> FlatCombiner<T> {
+    /// Create a combiner with two slots per cpu.
+    pub fn new(value: T) -> Self {
+        let cpus = thread::available_parallelism().map_or(4, |n| n.get());
+        Self::with_slots(value, cpus * 2)
+    }
+
+    /// Create a combiner with given count of slots.
+    /// Threads wait for a free slot if there're more threads than slots.
+    pub fn with_slots(value: T, slots: usize) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+            combining: AtomicBool::new(false),
+            slots: (0..slots.max(1))
+                .map(|_| Slot {
+                    state: AtomicU32::new(SLOT_EMPTY),
+                    operation: UnsafeCell::new(None),
+                })
+                .collect(),
+        }
+    }
+
+    /// Run f on the value, exclusively with other operations.
+    /// A panic of f is resumed on the calling thread.
+    pub fn apply<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R + Send,
+        R: Send,
+    {
+        let (tx, rx) = oneshot::channel::<Result<R, Box<dyn Any + Send>>>();
+        let operation: Operation<'_, T> = Box::new(move |value: &mut T| {
+            tx.send(panic::catch_unwind(AssertUnwindSafe(|| f(value))));
+        });
+        // Safety: the operation is executed before apply returns,
+        // nothing it borrows outlives it.
+        let operation: Operation<'static, T> = unsafe { mem::transmute(operation) };
+        self.publish(operation);
+
+        let mut backoff = Backoff::new();
+        while !rx.is_ready() {
+            if !self.try_combine() {
+                backoff.snooze();
+            }
+        }
+        rx.receive()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+
+    /// Get the mutable reference of the value.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn publish(&self, operation: Operation<'static, T>) {
+        let start = thread_index() % self.slots.len();
+        let mut backoff = Backoff::new();
+        loop {
+            for i in 0..self.slots.len() {
+                let slot = &self.slots[(start + i) % self.slots.len()];
+                if slot
+                    .state
+                    .compare_exchange(SLOT_EMPTY, SLOT_WRITING, Acquire, Relaxed)
+                    .is_ok()
+                {
+                    // Safety: the slot is claimed by us.
+                    unsafe { *slot.operation.get() = Some(operation) };
+                    slot.state.store(SLOT_PENDING, Release);
+                    return;
+                }
+            }
+            // All slots are in use, help to drain them.
+            if !self.try_combine() {
+                backoff.snooze();
+            }
+        }
+    }
+
+    /// Execute all published operations if no other thread is combining.
+    fn try_combine(&self) -> bool {
+        if self.combining.swap(true, Acquire) {
+            return false;
+        }
+        // Safety: only the combiner accesses the value.
+        let value = unsafe { &mut *self.value.get() };
+        for slot in self.slots.iter() {
+            if slot.state.load(Acquire) == SLOT_PENDING {
+                // Safety: a pending slot is only accessed by the combiner.
+                let operation = unsafe { (*slot.operation.get()).take() };
+                slot.state.store(SLOT_EMPTY, Release);
+                if let Some(operation) = operation {
+                    operation(value);
+                }
+            }
+        }
+        self.combining.store(false, Release);
+        true
+    }
+}
+
+/// A small index of the current thread, spreading threads over the slots.
+fn thread_index() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static INDEX: usize = NEXT.fetch_add(1, Relaxed);
+    }
+    INDEX.with(|index| *index)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        thread,
+    };
+
+    use super::FlatCombiner;
+
+    #[test]
+    fn test_flat_combiner() {
+        let counter = FlatCombiner::with_slots(Vec::new(), 2);
+        thread::scope(|s| {
+            for t in 0..4 {
+                let counter = &counter;
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        let len = counter.apply(|v| {
+                            v.push(t * 1000 + i);
+                            v.len()
+                        });
+                        assert!(len > 0);
+                    }
+                });
+            }
+        });
+        let result = panic::catch_unwind(AssertUnwindSafe(|| counter.apply(|_| panic!("boom"))));
+        assert!(result.is_err());
+        let mut values = counter.into_inner();
+        values.sort();
+        assert_eq!(values, (0..4000).collect::<Vec<_>>());
+    }
+}
@@ -1,12 +1,41 @@
-use super::mutex::MutexGuard;
-use std::sync::atomic::Ordering::Relaxed;
-use std::sync::atomic::{AtomicU32, AtomicUsize};
+use super::mutex::{self, MutexGuard};
+use std::collections::VecDeque;
+use std::ptr;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicPtr, AtomicU32, AtomicUsize};
+use std::sync::Arc;
+use std::time::Duration;
 
-use atomic_wait::{wait, wake_all, wake_one};
+use crate::futex::{wait, wait_timeout, wake_one};
+use crate::parking::{self, ParkResult, DEFAULT_UNPARK_TOKEN};
+use crate::spin::SpinLock;
+use crate::time;
 
+#[cfg(feature = "metrics")]
+mod stats;
+#[cfg(feature = "metrics")]
+pub use stats::CondvarStats;
+
+/// A condition variable, its waiters park in the `parking` table keyed by
+/// the notify counter, and notify_all requeues them onto the mutex, so
+/// they wake one by one as the mutex is unlocked instead of all at once.
+///
+/// Waiting with different mutexes at the same time isn't supported.
 pub struct Condvar {
     counter: AtomicU32,
     num_waiters: AtomicUsize,
+    // State word of the mutex waited with last, notify_all requeues onto it.
+    mutex: AtomicPtr<AtomicU32>,
+    // Queue of waiters by arrival if wakeups are FIFO, each parks on its own word.
+    fifo: Option<SpinLock<VecDeque<Arc<AtomicU32>>>>,
+    #[cfg(feature = "metrics")]
+    stats: stats::Counters,
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Condvar {
@@ -15,44 +44,207 @@ impl Condvar {
         Self {
             counter: AtomicU32::new(0),
             num_waiters: AtomicUsize::new(0),
+            mutex: AtomicPtr::new(ptr::null_mut()),
+            fifo: None,
+            #[cfg(feature = "metrics")]
+            stats: stats::Counters::new(),
         }
     }
 
+    /// Create a Condvar whose notify_one wakes waiters in the order they
+    /// started waiting, and which never wakes up spuriously.
+    /// `new` wakes waiters in FIFO order too, but they may wake up
+    /// spuriously, e.g. if notified between unlocking and parking.
+    pub const fn new_fifo() -> Self {
+        Self {
+            counter: AtomicU32::new(0),
+            num_waiters: AtomicUsize::new(0),
+            mutex: AtomicPtr::new(ptr::null_mut()),
+            fifo: Some(SpinLock::new(VecDeque::new())),
+            #[cfg(feature = "metrics")]
+            stats: stats::Counters::new(),
+        }
+    }
+
+    /// Statistics collected since the Condvar is created.
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> CondvarStats {
+        self.stats.snapshot()
+    }
+
     /// Notify one thread waiting for signal.
     pub fn notify_one(&self) {
+        #[cfg(feature = "metrics")]
+        self.stats.record_notify();
+        if let Some(queue) = &self.fifo {
+            let waiter = queue.lock().pop_front();
+            if let Some(waiter) = waiter {
+                waiter.store(1, Release);
+                wake_one(&*waiter);
+            }
+            return;
+        }
         if self.num_waiters.load(Relaxed) > 0 {
             self.counter.fetch_add(1, Relaxed);
-            wake_one(&self.counter);
+            parking::unpark_one(self.key(), |_| DEFAULT_UNPARK_TOKEN);
         }
     }
 
     /// Notify one thread waiting for all signal.
     pub fn notify_all(&self) {
+        #[cfg(feature = "metrics")]
+        self.stats.record_notify();
+        if let Some(queue) = &self.fifo {
+            let waiters = std::mem::take(&mut *queue.lock());
+            for waiter in waiters {
+                waiter.store(1, Release);
+                wake_one(&*waiter);
+            }
+            return;
+        }
         if self.num_waiters.load(Relaxed) > 0 {
             self.counter.fetch_add(1, Relaxed);
-            wake_all(&self.counter);
+            // Waiters of the mutex wait on its futex word with ffi,
+            // where requeued threads would never be woken.
+            if cfg!(feature = "ffi") || !self.requeue() {
+                parking::unpark_all(self.key(), DEFAULT_UNPARK_TOKEN);
+            }
         }
     }
 
+    // Wake the first waiter and requeue the rest onto the mutex,
+    // false if nothing is done.
+    fn requeue(&self) -> bool {
+        let word = self.mutex.load(Relaxed);
+        let validate = || {
+            // The waiters parked keep the mutex alive, and the pointer is
+            // stored before parking with the bucket locked.
+            let current = self.mutex.load(Relaxed);
+            if current != word {
+                return false;
+            }
+            mutex::mark_contended(unsafe { &*current });
+            true
+        };
+        parking::unpark_requeue(self.key(), word as usize, validate).unparked
+    }
+
+    // Waiters park on the counter.
+    fn key(&self) -> usize {
+        &self.counter as *const AtomicU32 as usize
+    }
+
     /// Wait for notifying signal. May waking up spuriously.
     pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        if let Some(queue) = &self.fifo {
+            return self.wait_fifo(queue, guard, None).0;
+        }
         // Protected by Mutex, so Relaxed is enough in correct use of CondVar.
         self.num_waiters.fetch_add(1, Relaxed);
         let counter_value = self.counter.load(Relaxed);
 
         // Remember the mutex reference and release it.
         let mutex = guard.mutex;
+        self.mutex
+            .store(ptr::from_ref(mutex.word()).cast_mut(), Relaxed);
         drop(guard);
 
         // Wait for notifying.
-        wait(&self.counter, counter_value);
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let result = self.park(counter_value, None);
+        #[cfg(feature = "metrics")]
+        self.stats
+            .record_wait(start.elapsed(), self.counter.load(Relaxed) == counter_value);
 
         // No notifying is needed if spurious wake-up happens.
         // It's safe to use relaxed ordering on here.
         self.num_waiters.fetch_sub(1, Relaxed);
 
-        // Lock the mutex after notifying.
-        mutex.lock()
+        // Lock the mutex after notifying, it may be handed over already.
+        mutex.relock(result)
+    }
+
+    // Park until notified, unless notified since the counter was read.
+    fn park(&self, counter_value: u32, timeout: Option<Duration>) -> ParkResult {
+        let validate = || self.counter.load(Relaxed) == counter_value;
+        parking::park(self.key(), validate, timeout)
+    }
+
+    /// Wait for notifying signal for at most timeout, return the guard and
+    /// whether the wait timed out. May waking up spuriously.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> (MutexGuard<'a, T>, bool) {
+        if let Some(queue) = &self.fifo {
+            return self.wait_fifo(queue, guard, Some(timeout));
+        }
+        self.num_waiters.fetch_add(1, Relaxed);
+        let counter_value = self.counter.load(Relaxed);
+
+        let mutex = guard.mutex;
+        self.mutex
+            .store(ptr::from_ref(mutex.word()).cast_mut(), Relaxed);
+        drop(guard);
+
+        #[cfg(feature = "metrics")]
+        let start = time::now();
+        let result = self.park(counter_value, Some(timeout));
+        // Notified if requeued onto the mutex before timing out there.
+        let timed_out =
+            result == ParkResult::TimedOut && self.counter.load(Relaxed) == counter_value;
+        #[cfg(feature = "metrics")]
+        {
+            let notified = self.counter.load(Relaxed) != counter_value;
+            let elapsed = time::now().saturating_duration_since(start);
+            self.stats.record_wait(elapsed, !notified && !timed_out);
+        }
+
+        self.num_waiters.fetch_sub(1, Relaxed);
+        (mutex.relock(result), timed_out)
+    }
+
+    /// Wait in the queue until notified or timed out, return the guard and
+    /// whether the wait timed out.
+    fn wait_fifo<'a, T>(
+        &self,
+        queue: &SpinLock<VecDeque<Arc<AtomicU32>>>,
+        guard: MutexGuard<'a, T>,
+        timeout: Option<Duration>,
+    ) -> (MutexGuard<'a, T>, bool) {
+        // Queued before unlocking, so a notify after the unlock finds it.
+        let waiter = Arc::new(AtomicU32::new(0));
+        queue.lock().push_back(Arc::clone(&waiter));
+
+        let mutex = guard.mutex;
+        drop(guard);
+
+        let start = time::now();
+        let mut timed_out = false;
+        while waiter.load(Acquire) == 0 {
+            let Some(timeout) = timeout else {
+                wait(&waiter, 0);
+                continue;
+            };
+            match timeout.checked_sub(time::now().saturating_duration_since(start)) {
+                Some(remaining) if !remaining.is_zero() => wait_timeout(&waiter, 0, remaining),
+                _ => {
+                    // Leave the queue, unless it's notified meanwhile.
+                    let mut queue = queue.lock();
+                    if let Some(i) = queue.iter().position(|w| Arc::ptr_eq(w, &waiter)) {
+                        queue.remove(i);
+                        timed_out = true;
+                        break;
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "metrics")]
+        self.stats
+            .record_wait(time::now().saturating_duration_since(start), false);
+        (mutex.lock(), timed_out)
     }
 }
 
@@ -87,4 +279,142 @@ mod tests {
         });
         assert!(wakeups < 10);
     }
+
+    #[test]
+    fn test_condvar_notify_all() {
+        // The waiters are requeued onto the mutex and woken one by one.
+        for m in [Mutex::new(false), Mutex::with_handoff(false)] {
+            let cv = Condvar::new();
+            let woken = std::sync::atomic::AtomicU32::new(0);
+            thread::scope(|s| {
+                for _ in 0..8 {
+                    s.spawn(|| {
+                        let mut g = m.lock();
+                        while !*g {
+                            g = cv.wait(g);
+                        }
+                        woken.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    });
+                }
+                thread::sleep(Duration::from_millis(20));
+                *m.lock() = true;
+                cv.notify_all();
+            });
+            assert_eq!(woken.into_inner(), 8);
+            assert!(!m.is_locked());
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_condvar_futex_stats() {
+        let m = Mutex::new(false);
+        let cv = Condvar::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                let mut g = m.lock();
+                while !*g {
+                    g = cv.wait(g);
+                }
+            });
+            crate::parking::wait_parked(cv.key(), 1);
+            *m.lock() = true;
+            cv.notify_one();
+        });
+        // Parking counts its futex calls for the primitive calling in.
+        let stats = crate::futex::futex_stats();
+        let (_, stats) = stats.iter().find(|(p, _)| *p == "condvar").unwrap();
+        assert!(stats.waits > 0 && stats.wakes > 0);
+    }
+
+    #[test]
+    fn test_condvar_wait_timeout() {
+        let m = Mutex::new(false);
+        let cv = Condvar::new();
+
+        // Nobody notifies, so it returns by timing out or waking up spuriously.
+        let start = std::time::Instant::now();
+        let mut g = m.lock();
+        loop {
+            let (guard, timed_out) = cv.wait_timeout(g, Duration::from_millis(10));
+            g = guard;
+            if timed_out {
+                break;
+            }
+        }
+        assert!(!*g && start.elapsed() >= Duration::from_millis(10));
+        drop(g);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                *m.lock() = true;
+                cv.notify_one();
+            });
+
+            let mut m = m.lock();
+            while !*m {
+                let (g, timed_out) = cv.wait_timeout(m, Duration::from_secs(10));
+                assert!(!timed_out);
+                m = g;
+            }
+        });
+    }
+
+    #[test]
+    fn test_condvar_fifo() {
+        let m = Mutex::new(Vec::new());
+        let cv = Condvar::new_fifo();
+        let queued = |n| cv.fifo.as_ref().unwrap().lock().len() == n;
+
+        let mut g = m.lock();
+        let (g2, timed_out) = cv.wait_timeout(g, Duration::from_millis(10));
+        g = g2;
+        assert!(timed_out && queued(0));
+        drop(g);
+
+        // Waiters queue up one by one, and are woken up in that order.
+        thread::scope(|s| {
+            for i in 0..3 {
+                let (m, cv) = (&m, &cv);
+                s.spawn(move || cv.wait(m.lock()).push(i));
+                while !queued(i + 1) {
+                    thread::yield_now();
+                }
+            }
+            for n in 1..=3 {
+                cv.notify_one();
+                while m.lock().len() < n {
+                    thread::yield_now();
+                }
+            }
+        });
+        assert_eq!(*m.lock(), [0, 1, 2]);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_condvar_stats() {
+        let m = Mutex::new(false);
+        let cv = Condvar::new();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(100));
+                *m.lock() = true;
+                cv.notify_one();
+            });
+
+            let mut m = m.lock();
+            while !*m {
+                m = cv.wait(m);
+            }
+        });
+
+        let stats = cv.stats();
+        assert_eq!(stats.notifies, 1);
+        assert!(stats.waits >= 1);
+        assert!(stats.spurious_wakeups < stats.waits);
+        assert!(stats.average_wait() > Duration::ZERO);
+    }
 }
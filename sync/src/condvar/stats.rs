@@ -0,0 +1,67 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+    time::Duration,
+};
+
+/// Counters collected by a Condvar.
+pub(super) struct Counters {
+    waits: AtomicU64,
+    notifies: AtomicU64,
+    spurious_wakeups: AtomicU64,
+    wait_nanos: AtomicU64,
+}
+
+impl Counters {
+    pub(super) const fn new() -> Self {
+        Self {
+            waits: AtomicU64::new(0),
+            notifies: AtomicU64::new(0),
+            spurious_wakeups: AtomicU64::new(0),
+            wait_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub(super) fn record_notify(&self) {
+        self.notifies.fetch_add(1, Relaxed);
+    }
+
+    pub(super) fn record_wait(&self, waited: Duration, spurious: bool) {
+        self.waits.fetch_add(1, Relaxed);
+        self.wait_nanos.fetch_add(waited.as_nanos() as u64, Relaxed);
+        if spurious {
+            self.spurious_wakeups.fetch_add(1, Relaxed);
+        }
+    }
+
+    pub(super) fn snapshot(&self) -> CondvarStats {
+        CondvarStats {
+            waits: self.waits.load(Relaxed),
+            notifies: self.notifies.load(Relaxed),
+            spurious_wakeups: self.spurious_wakeups.load(Relaxed),
+            total_wait: Duration::from_nanos(self.wait_nanos.load(Relaxed)),
+        }
+    }
+}
+
+/// A snapshot of Condvar statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CondvarStats {
+    /// Count of finished waits.
+    pub waits: u64,
+    /// Count of notify_one and notify_all calls.
+    pub notifies: u64,
+    /// Count of waits woken up without any notifying.
+    pub spurious_wakeups: u64,
+    /// Total time spent in waiting.
+    pub total_wait: Duration,
+}
+
+impl CondvarStats {
+    /// Average time spent in a wait.
+    pub fn average_wait(&self) -> Duration {
+        if self.waits == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((self.total_wait.as_nanos() / self.waits as u128) as u64)
+    }
+}
@@ -0,0 +1,142 @@
+//! Crate-wide tuning of how locks wait on contention, installed once at
+//! startup, so the spin budgets are tuned per deployment without
+//! recompiling.
+//!
+//! ```
+//! use sync::config::{self, SyncConfig};
+//!
+//! // E.g. SYNC_MUTEX_SPIN_ITERS=400 on a host with many idle cores.
+//! let _ = SyncConfig::from_env().install();
+//! assert!(config::get().backoff_spin_limit <= 31);
+//! ```
+
+use std::{error::Error, fmt, sync::OnceLock};
+
+use crate::mutex::DEFAULT_SPIN_ITERS;
+
+static CONFIG: OnceLock<SyncConfig> = OnceLock::new();
+
+/// How a SpinLock waits once it has spun for a while.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YieldStrategy {
+    /// Spin until the lock is released.
+    Spin,
+    /// Yield the thread on every retry after spinning this many times,
+    /// for hosts with more threads than cores.
+    YieldAfter(u32),
+}
+
+/// The tuning of the lock acquisition paths, see `get`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncConfig {
+    /// Spins of `Mutex::lock` before parking.
+    pub mutex_spin_iters: u32,
+    /// Spins of `RwLock::read` and `RwLock::write` before parking.
+    pub rwlock_spin_iters: u32,
+    /// How `SpinLock::lock` waits.
+    pub spin_yield: YieldStrategy,
+    /// Steps of `Backoff` spinning 2^step times, at most 31.
+    pub backoff_spin_limit: u32,
+    /// Steps of `Backoff::snooze` before it's completed.
+    pub backoff_yield_limit: u32,
+}
+
+impl SyncConfig {
+    /// The tuning in effect unless another is installed.
+    pub const DEFAULT: Self = Self {
+        mutex_spin_iters: DEFAULT_SPIN_ITERS,
+        rwlock_spin_iters: 0,
+        spin_yield: YieldStrategy::Spin,
+        backoff_spin_limit: 6,
+        backoff_yield_limit: 10,
+    };
+
+    /// The default tuning overridden by the environment variables
+    /// `SYNC_MUTEX_SPIN_ITERS`, `SYNC_RWLOCK_SPIN_ITERS`,
+    /// `SYNC_SPIN_YIELD_AFTER`, `SYNC_BACKOFF_SPIN_LIMIT` and
+    /// `SYNC_BACKOFF_YIELD_LIMIT`. Unparsable values are ignored.
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let parse = |name: &str| var(name).and_then(|v| v.trim().parse::<u32>().ok());
+        let mut config = Self::DEFAULT;
+        if let Some(n) = parse("SYNC_MUTEX_SPIN_ITERS") {
+            config.mutex_spin_iters = n;
+        }
+        if let Some(n) = parse("SYNC_RWLOCK_SPIN_ITERS") {
+            config.rwlock_spin_iters = n;
+        }
+        if let Some(n) = parse("SYNC_SPIN_YIELD_AFTER") {
+            config.spin_yield = YieldStrategy::YieldAfter(n);
+        }
+        if let Some(n) = parse("SYNC_BACKOFF_SPIN_LIMIT") {
+            config.backoff_spin_limit = n.min(31);
+        }
+        if let Some(n) = parse("SYNC_BACKOFF_YIELD_LIMIT") {
+            config.backoff_yield_limit = n;
+        }
+        config
+    }
+
+    /// Make this the tuning of the whole process. It can be installed only
+    /// once, acquisitions before use the default.
+    pub fn install(self) -> Result<(), AlreadyInstalled> {
+        assert!(self.backoff_spin_limit <= 31, "backoff spin limit over 31");
+        CONFIG.set(self).map_err(|_| AlreadyInstalled)
+    }
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The tuning in effect, the installed one or the default.
+pub fn get() -> &'static SyncConfig {
+    CONFIG.get().unwrap_or(&SyncConfig::DEFAULT)
+}
+
+/// Error returned by `SyncConfig::install` if a tuning is installed already.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlreadyInstalled;
+
+impl fmt::Display for AlreadyInstalled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sync config is installed already")
+    }
+}
+
+impl Error for AlreadyInstalled {}
+
+#[cfg(test)]
+mod tests {
+    use super::{get, AlreadyInstalled, SyncConfig, YieldStrategy};
+
+    #[test]
+    fn test_sync_config() {
+        let config = SyncConfig::from_vars(|name| match name {
+            "SYNC_MUTEX_SPIN_ITERS" => Some("400".into()),
+            "SYNC_RWLOCK_SPIN_ITERS" => Some("nope".into()),
+            "SYNC_SPIN_YIELD_AFTER" => Some(" 64 ".into()),
+            "SYNC_BACKOFF_SPIN_LIMIT" => Some("99".into()),
+            _ => None,
+        });
+        assert_eq!(
+            config,
+            SyncConfig {
+                mutex_spin_iters: 400,
+                spin_yield: YieldStrategy::YieldAfter(64),
+                backoff_spin_limit: 31,
+                ..SyncConfig::DEFAULT
+            }
+        );
+
+        // Other tests run with the same tuning, installed or not.
+        let _ = SyncConfig::DEFAULT.install();
+        assert_eq!(SyncConfig::DEFAULT.install(), Err(AlreadyInstalled));
+        assert_eq!(*get(), SyncConfig::DEFAULT);
+    }
+}
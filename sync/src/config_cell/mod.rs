@@ -0,0 +1,121 @@
+use crate::{
+    arc::{Arc, AtomicArc},
+    channel::watch,
+    mutex::Mutex,
+};
+
+type Callback<T> = std::sync::Arc<dyn Fn(&Arc<T>) + Send + Sync>;
+
+/// A read-mostly shared value, e.g. a reloadable configuration.
+///
+/// Reads load the current Arc without blocking (see `AtomicArc`),
+/// updates replace it as a whole and notify the subscribers,
+/// either by watch receivers or by registered callbacks.
+pub struct ConfigCell<T> {
+    current: AtomicArc<T>,
+    // Serialize updates, so watchers observe the same order as loads.
+    update: Mutex<watch::Sender<Arc<T>>>,
+    callbacks: Mutex<Vec<Callback<T>>>,
+}
+
+impl<T> ConfigCell<T> {
+    pub fn new(value: T) -> Self {
+        let value = Arc::new(value);
+        let (sender, _) = watch::channel(value.clone());
+        Self {
+            current: AtomicArc::new(value),
+            update: Mutex::new(sender),
+            callbacks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Get the current value.
+    pub fn load(&self) -> Arc<T> {
+        self.current.load()
+    }
+
+    /// Publish a new value and notify all subscribers.
+    pub fn store(&self, value: Arc<T>) {
+        let sender = self.update.lock();
+        self.publish(&sender, value);
+    }
+
+    /// Publish a new value derived from the current one.
+    /// Updates are serialized, so no concurrent update is lost.
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&T) -> T,
+    {
+        let sender = self.update.lock();
+        let value = Arc::new(f(&self.current.load()));
+        self.publish(&sender, value);
+    }
+
+    /// Subscribe to changes by a watch receiver.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<T>> {
+        self.update.lock().subscribe()
+    }
+
+    /// Register a callback called with every new value.
+    /// Callbacks are called on the updating thread after the value is published,
+    /// other updates are blocked meanwhile, so callbacks must not update the cell.
+    pub fn on_change<F>(&self, f: F)
+    where
+        F: Fn(&Arc<T>) + Send + Sync + 'static,
+    {
+        self.callbacks.lock().push(std::sync::Arc::new(f));
+    }
+
+    fn publish(&self, sender: &watch::Sender<Arc<T>>, value: Arc<T>) {
+        self.current.store(value.clone());
+        sender.send(value.clone());
+
+        // Call outside of the lock, so callbacks may register others.
+        let callbacks = self.callbacks.lock().clone();
+        for callback in callbacks {
+            callback(&value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::ConfigCell;
+    use crate::task::block_on;
+
+    #[test]
+    fn test_config_cell() {
+        let config = ConfigCell::new(0);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        config.on_change(move |_| {
+            counter.fetch_add(1, Relaxed);
+        });
+        let mut rx = config.subscribe();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        config.update(|v| v + 1);
+                    }
+                });
+            }
+            s.spawn(|| {
+                let mut last = 0;
+                while last < 400 {
+                    block_on(rx.changed()).unwrap();
+                    let v = **rx.borrow_and_update();
+                    assert!(v > last);
+                    last = v;
+                }
+            });
+        });
+        assert_eq!(*config.load(), 400);
+        assert_eq!(calls.load(Relaxed), 400);
+    }
+}
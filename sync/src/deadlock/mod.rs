@@ -0,0 +1,218 @@
+//! Tracking of held and awaited locks, dumped to diagnose a stuck process.
+//!
+//! Every acquisition of the crate's Mutex, RwLock and SpinLock is recorded
+//! by the acquiring thread, which slows locking down, so enable it for
+//! debugging only.
+//! Locks elided by hardware transactions are not recorded.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    thread::{self, Thread},
+};
+
+// Locks of every thread alive, each thread records its own locks
+// so tracking never contends unless dumping.
+static THREADS: Mutex<Vec<Weak<Mutex<ThreadLocks>>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static LOCAL: Arc<Mutex<ThreadLocks>> = register();
+}
+
+struct ThreadLocks {
+    thread: Thread,
+    // Address and kind of locks held.
+    held: Vec<(usize, &'static str)>,
+    waiting: Option<(usize, &'static str)>,
+}
+
+fn register() -> Arc<Mutex<ThreadLocks>> {
+    let local = Arc::new(Mutex::new(ThreadLocks {
+        thread: thread::current(),
+        held: Vec::new(),
+        waiting: None,
+    }));
+    let mut threads = lock(&THREADS);
+    threads.retain(|t| t.strong_count() > 0);
+    threads.push(Arc::downgrade(&local));
+    local
+}
+
+// Keep tracking even if a thread panicked while holding the registry.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run f on the locks of the current thread, skipped if the thread is exiting.
+fn with_local(f: impl FnOnce(&mut ThreadLocks)) {
+    let _ = LOCAL.try_with(|local| f(&mut lock(local)));
+}
+
+/// Record the current thread is going to block on the lock.
+pub(crate) fn waiting<L>(lock: &L, kind: &'static str) {
+    let addr = lock as *const L as usize;
+    with_local(|local| local.waiting = Some((addr, kind)));
+}
+
+/// Record the current thread holds the lock, no longer waiting for it.
+pub(crate) fn acquired<L>(lock: &L, kind: &'static str) {
+    let addr = lock as *const L as usize;
+    with_local(|local| {
+        local.waiting = None;
+        local.held.push((addr, kind));
+    });
+}
+
+/// Record the current thread gave up waiting, e.g. as a timed lock timed out.
+pub(crate) fn gave_up() {
+    with_local(|local| local.waiting = None);
+}
+
+/// Record the lock is released, by the current thread if it's a holder.
+pub(crate) fn released<L>(lock: &L, kind: &'static str) {
+    let addr = lock as *const L as usize;
+    let mut found = false;
+    with_local(|local| found = remove(&mut local.held, (addr, kind)));
+    if found {
+        return;
+    }
+    // A guard may be sent and released by another thread.
+    for local in lock_threads() {
+        if remove(&mut self::lock(&local).held, (addr, kind)) {
+            return;
+        }
+    }
+}
+
+fn remove(held: &mut Vec<(usize, &'static str)>, lock: (usize, &'static str)) -> bool {
+    match held.iter().rposition(|l| *l == lock) {
+        Some(i) => {
+            held.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+fn lock_threads() -> Vec<Arc<Mutex<ThreadLocks>>> {
+    lock(&THREADS).iter().filter_map(Weak::upgrade).collect()
+}
+
+#[derive(Clone)]
+struct Entry {
+    kind: &'static str,
+    thread: Thread,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.thread.name() {
+            Some(name) => write!(f, "{:?} {:?} ({})", self.thread.id(), name, self.kind),
+            None => write!(f, "{:?} ({})", self.thread.id(), self.kind),
+        }
+    }
+}
+
+/// A snapshot of all locks currently held or waited.
+pub struct LockDump {
+    locks: Vec<(usize, Vec<Entry>, Vec<Entry>)>,
+}
+
+impl LockDump {
+    pub fn is_empty(&self) -> bool {
+        self.locks.is_empty()
+    }
+}
+
+impl fmt::Display for LockDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, holders, waiters) in &self.locks {
+            match crate::registry::name_of(*addr) {
+                Some(name) => writeln!(f, "lock {:#x} {:?}", addr, name)?,
+                None => writeln!(f, "lock {:#x}", addr)?,
+            }
+            for holder in holders {
+                writeln!(f, "    held by {}", holder)?;
+            }
+            for waiter in waiters {
+                writeln!(f, "    waited by {}", waiter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Take a snapshot of all locks currently held or waited.
+pub fn all_locks() -> LockDump {
+    let mut locks: BTreeMap<usize, (Vec<Entry>, Vec<Entry>)> = BTreeMap::new();
+    for local in lock_threads() {
+        let local = lock(&local);
+        for &(addr, kind) in &local.held {
+            let thread = local.thread.clone();
+            locks
+                .entry(addr)
+                .or_default()
+                .0
+                .push(Entry { kind, thread });
+        }
+        if let Some((addr, kind)) = local.waiting {
+            let thread = local.thread.clone();
+            locks
+                .entry(addr)
+                .or_default()
+                .1
+                .push(Entry { kind, thread });
+        }
+    }
+    LockDump {
+        locks: locks
+            .into_iter()
+            .map(|(addr, (holders, waiters))| (addr, holders, waiters))
+            .collect(),
+    }
+}
+
+/// Print all locks currently held or waited, with holder and waiter threads,
+/// to stderr.
+///
+/// It takes a lock and allocates, so it's not async-signal-safe: on SIGQUIT
+/// call it from a thread handling the signal, not inside the handler.
+pub fn dump_all_locks() {
+    eprint!("{}", all_locks());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::all_locks;
+    use crate::{mutex::Mutex, rwlock::RwLock};
+
+    #[test]
+    fn test_dump_all_locks() {
+        let m = Mutex::new(0);
+        let l = RwLock::named("config", 0);
+        let guard = m.lock();
+        let read = l.read();
+        thread::scope(|s| {
+            thread::Builder::new()
+                .name("stuck".to_string())
+                .spawn_scoped(s, || *m.lock() += 1)
+                .unwrap();
+            thread::sleep(Duration::from_millis(50));
+            let dump = all_locks().to_string();
+            let lock = format!("lock {:#x}\n", &m as *const _ as usize);
+            let start = dump.find(&lock).unwrap();
+            let m_dump = &dump[start..];
+            assert!(m_dump.contains("held by") && m_dump.contains("(Mutex)"));
+            assert!(m_dump.contains("waited by") && m_dump.contains("\"stuck\" (Mutex)"));
+            let l_dump = format!("lock {:#x} \"config\"\n", &l as *const _ as usize);
+            assert!(dump.contains(&l_dump) && dump.contains("(RwLock read)"));
+            drop(guard);
+        });
+        drop(read);
+        let dump = all_locks().to_string();
+        assert!(!dump.contains(&format!("lock {:#x}\n", &m as *const _ as usize)));
+    }
+}
@@ -0,0 +1,192 @@
+//! Hardware lock elision with Intel RTM, by `Mutex::elide` and
+//! `RwLock::elide_read`.
+//!
+//! A lock is elided by running the critical section in a hardware
+//! transaction which only reads the lock state: the section runs without
+//! writing the lock word, and any thread really taking the lock aborts the
+//! transaction, which rolls back and runs the section with the lock taken.
+//! The transaction never outlives the call, unlike a guard would.
+
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+static ATTEMPTS: AtomicU64 = AtomicU64::new(0);
+static COMMITS: AtomicU64 = AtomicU64::new(0);
+static ABORTS: AtomicU64 = AtomicU64::new(0);
+
+/// Statistics of lock elision since the process started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ElisionStats {
+    /// Count of transactions started for elision.
+    pub attempts: u64,
+    /// Count of elided critical sections committed.
+    pub commits: u64,
+    /// Count of transactions aborted, falling back to the atomic path.
+    pub aborts: u64,
+}
+
+impl ElisionStats {
+    /// Ratio of aborted attempts, 0 if never attempted.
+    pub fn abort_rate(&self) -> f64 {
+        if self.attempts == 0 {
+            return 0.0;
+        }
+        self.aborts as f64 / self.attempts as f64
+    }
+}
+
+/// Get the statistics of lock elision.
+pub fn stats() -> ElisionStats {
+    ElisionStats {
+        attempts: ATTEMPTS.load(Relaxed),
+        commits: COMMITS.load(Relaxed),
+        aborts: ABORTS.load(Relaxed),
+    }
+}
+
+/// Return true if the cpu supports hardware lock elision.
+pub fn is_supported() -> bool {
+    rtm::is_supported()
+}
+
+/// Run f in a hardware transaction if `is_free`, reading the lock state
+/// inside it, returns true. None if rtm isn't supported or the transaction
+/// aborted, the caller runs its critical section with the lock taken then.
+pub(crate) fn elide<R>(is_free: impl FnOnce() -> bool, f: impl FnOnce() -> R) -> Option<R> {
+    if !rtm::is_supported() {
+        return None;
+    }
+    // Nested in another elided section, the counters must not be written
+    // inside the transaction, or every concurrent elision conflicts on them.
+    let nested = rtm::in_transaction();
+    if !nested {
+        ATTEMPTS.fetch_add(1, Relaxed);
+    }
+    // Safety: rtm is supported.
+    let result = unsafe {
+        rtm::transaction(|| {
+            if !is_free() {
+                rtm::abort();
+            }
+            f()
+        })
+    };
+    match result {
+        Ok(r) => {
+            if !nested {
+                COMMITS.fetch_add(1, Relaxed);
+            }
+            Some(r)
+        }
+        Err(_) => {
+            ABORTS.fetch_add(1, Relaxed);
+            None
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod rtm {
+    use std::{arch::asm, sync::OnceLock};
+
+    const XBEGIN_STARTED: u32 = !0;
+
+    pub(super) fn is_supported() -> bool {
+        static SUPPORTED: OnceLock<bool> = OnceLock::new();
+        *SUPPORTED.get_or_init(|| std::is_x86_feature_detected!("rtm"))
+    }
+
+    /// Run f in a transaction, or return the abort status.
+    ///
+    /// xbegin, f and xend all run in this frame: an abort rolls back the
+    /// registers and memory to the xbegin, which then falls through to its
+    /// label with the status, as if f never ran, so no frame is left open
+    /// past the transaction.
+    ///
+    /// Safety: rtm must be supported.
+    #[inline(never)]
+    pub(super) unsafe fn transaction<R>(f: impl FnOnce() -> R) -> Result<R, u32> {
+        let status: u32;
+        asm!(
+            "xbegin 2f",
+            "2:",
+            inout("eax") XBEGIN_STARTED => status,
+            options(nostack),
+        );
+        if status != XBEGIN_STARTED {
+            return Err(status);
+        }
+        let r = f();
+        asm!("xend", options(nostack));
+        Ok(r)
+    }
+
+    /// Safety: must be in a transaction, inside `transaction`.
+    pub(super) unsafe fn abort() -> ! {
+        asm!("xabort 0xff", options(nostack, noreturn));
+    }
+
+    pub(super) fn in_transaction() -> bool {
+        if !is_supported() {
+            return false;
+        }
+        let nesting: u8;
+        // Safety: rtm is supported.
+        unsafe { asm!("xtest", "setnz {}", out(reg_byte) nesting, options(nostack, nomem)) };
+        nesting != 0
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod rtm {
+    pub(super) fn is_supported() -> bool {
+        false
+    }
+
+    pub(super) unsafe fn transaction<R>(_: impl FnOnce() -> R) -> Result<R, u32> {
+        Err(0)
+    }
+
+    pub(super) unsafe fn abort() -> ! {
+        unreachable!("no transaction without rtm")
+    }
+
+    pub(super) fn in_transaction() -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{is_supported, stats};
+    use crate::{mutex::Mutex, rwlock::RwLock};
+
+    #[test]
+    fn test_elision() {
+        let m = Mutex::new(0);
+        let l = RwLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        m.elide(|x| *x += 1);
+                        assert!(l.elide_read(|x| *x) <= 40_000);
+                        *l.write() += 1;
+                        // Nested elision commits with the outer one.
+                        m.elide(|x| l.elide_read(|y| assert!(*x <= 40_000 && *y <= 40_000)));
+                    }
+                });
+            }
+        });
+        assert_eq!(*m.lock(), 40_000);
+        assert_eq!(*l.read(), 40_000);
+        let stats = stats();
+        if is_supported() {
+            assert!(stats.attempts > 0);
+        } else {
+            assert_eq!(stats.attempts, 0);
+        }
+        assert!(stats.abort_rate() <= 1.0);
+    }
+}
@@ -0,0 +1,130 @@
+use std::{cell::RefCell, sync::atomic::AtomicU32};
+
+use super::{sys_wake, Site};
+
+thread_local! {
+    // Some while the thread runs coalesce_wakes.
+    static PENDING: RefCell<Option<Vec<Pending>>> = const { RefCell::new(None) };
+}
+
+struct Pending {
+    atomic: *const AtomicU32,
+    // Threads to wake, WAKE_ALL for all.
+    count: u32,
+    site: Site,
+}
+
+/// Run f with the wakes of the crate's primitives it releases deferred,
+/// and issue them once f returns, one syscall per futex word. E.g. drop
+/// a batch of guards of the same lock inside, so their wakes coalesce.
+///
+/// The deferred wakes are issued before the thread blocks on any of the
+/// crate's primitives inside f, so it never waits on a wake it holds back.
+/// Nested calls run in the outermost batch. Locks parking in `parking`
+/// wake each waiter on a word of its own, so their wakes don't merge.
+pub fn coalesce_wakes<R>(f: impl FnOnce() -> R) -> R {
+    let outermost = PENDING.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        pending.is_none() && pending.replace(Vec::new()).is_none()
+    });
+    if !outermost {
+        return f();
+    }
+
+    // Issue the wakes even if f panics.
+    struct End;
+    impl Drop for End {
+        fn drop(&mut self) {
+            let pending = PENDING.try_with(|pending| pending.borrow_mut().take());
+            issue(pending.ok().flatten());
+        }
+    }
+    let _end = End;
+    f()
+}
+
+/// Hold the wake back if the thread runs a batch, return false if not.
+pub(super) fn defer(atomic: *const AtomicU32, count: u32, site: Site) -> bool {
+    PENDING
+        .try_with(|pending| {
+            let mut pending = pending.borrow_mut();
+            let Some(pending) = pending.as_mut() else {
+                return false;
+            };
+            match pending.iter_mut().find(|w| w.atomic == atomic) {
+                Some(w) => {
+                    w.count = w.count.saturating_add(count);
+                    #[cfg(feature = "metrics")]
+                    super::stats::record_coalesced(w.site);
+                }
+                None => pending.push(Pending {
+                    atomic,
+                    count,
+                    site,
+                }),
+            }
+            true
+        })
+        .unwrap_or(false)
+}
+
+/// Issue the wakes held back so far, before the thread blocks.
+#[inline]
+pub(super) fn flush() {
+    let pending = PENDING.try_with(|pending| pending.borrow_mut().as_mut().map(std::mem::take));
+    issue(pending.ok().flatten());
+}
+
+fn issue(pending: Option<Vec<Pending>>) {
+    // A word may be freed once its waiter is gone, waking it then only
+    // wakes spuriously whoever waits on the memory reused.
+    for w in pending.into_iter().flatten() {
+        sys_wake(w.atomic, w.count, w.site);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering::SeqCst},
+        thread,
+        time::Duration,
+    };
+
+    use super::coalesce_wakes;
+    use crate::futex::{wait, wake_all, wake_one};
+
+    #[test]
+    fn test_coalesce_wakes() {
+        let word = AtomicU32::new(0);
+        let other = AtomicU32::new(0);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    while word.load(SeqCst) == 0 {
+                        wait(&word, 0);
+                    }
+                });
+            }
+            thread::sleep(Duration::from_millis(20));
+            word.store(1, SeqCst);
+            // Three wakes on the word in one syscall wake all three.
+            coalesce_wakes(|| {
+                for _ in 0..3 {
+                    wake_one(&word);
+                }
+                coalesce_wakes(|| wake_all(&other));
+                wake_one(&other);
+            });
+        });
+
+        #[cfg(feature = "metrics")]
+        {
+            let stats = crate::futex::futex_stats();
+            let (_, stats) = stats.iter().find(|(p, _)| *p == "futex/batch").unwrap();
+            assert_eq!(stats.wakes, 2);
+            assert_eq!(stats.coalesced_wakes, 3);
+            assert!(stats.waits <= 3);
+        }
+    }
+}
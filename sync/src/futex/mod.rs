@@ -0,0 +1,177 @@
+//! The futex layer all blocking primitives of the crate are built on.
+//!
+//! It forwards to `atomic_wait`, unless the thread runs under the
+//! `testutil` scheduler which takes over waiting and waking.
+//! The `chaos` feature perturbs the yield points and waits here, and a
+//! `testutil::FakeClock` shortens timed waits into polls of its time.
+//!
+//! Wakes go through here too, so they can be coalesced by
+//! `coalesce_wakes`, and counted per primitive with the `metrics` feature.
+
+use std::{sync::atomic::AtomicU32, time::Duration};
+
+mod batch;
+#[cfg(feature = "metrics")]
+mod stats;
+
+pub use batch::coalesce_wakes;
+#[cfg(feature = "metrics")]
+pub use stats::{futex_stats, reset_futex_stats, FutexStats};
+
+// The count of sys_wake waking all waiters.
+const WAKE_ALL: u32 = u32::MAX;
+
+// The primitive calling into the futex layer, counted by the metrics.
+#[cfg(feature = "metrics")]
+type Site = &'static str;
+#[cfg(not(feature = "metrics"))]
+#[derive(Clone, Copy)]
+struct Site;
+
+#[cfg(feature = "metrics")]
+#[track_caller]
+fn site() -> Site {
+    stats::primitive(std::panic::Location::caller().file())
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline]
+fn site() -> Site {
+    Site
+}
+
+/// Block while the atomic equals value, may wake up spuriously.
+#[inline]
+#[cfg_attr(feature = "metrics", track_caller)]
+pub(crate) fn wait(atomic: &AtomicU32, value: u32) {
+    batch::flush();
+    #[cfg(feature = "testutil")]
+    if crate::testutil::hook::wait(atomic, value) {
+        return;
+    }
+    #[cfg(feature = "chaos")]
+    if crate::chaos::spurious_wakeup() {
+        return;
+    }
+    #[cfg(feature = "metrics")]
+    stats::record_wait(site());
+    atomic_wait::wait(atomic, value)
+}
+
+/// Block while the atomic equals value for at most timeout,
+/// may wake up spuriously.
+#[inline]
+#[cfg_attr(feature = "metrics", track_caller)]
+pub(crate) fn wait_timeout(atomic: &AtomicU32, value: u32, timeout: Duration) {
+    batch::flush();
+    #[cfg(feature = "testutil")]
+    if crate::testutil::hook::wait_timeout() {
+        return;
+    }
+    #[cfg(feature = "chaos")]
+    if crate::chaos::spurious_wakeup() {
+        return;
+    }
+    #[cfg(feature = "testutil")]
+    let timeout = crate::testutil::clock::wait_timeout(timeout);
+    #[cfg(feature = "metrics")]
+    stats::record_wait(site());
+    sys_wait_timeout(atomic, value, timeout)
+}
+
+#[cfg(target_os = "linux")]
+fn sys_wait_timeout(atomic: &AtomicU32, value: u32, timeout: Duration) {
+    let timeout = libc::timespec {
+        tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as _,
+    };
+    // Safety: the futex word and the timespec outlive the syscall,
+    // and waking is private to the process just like atomic_wait.
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            atomic as *const AtomicU32,
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            value,
+            &timeout as *const libc::timespec,
+        );
+    }
+}
+
+// There's no portable timed futex, poll the atomic instead.
+#[cfg(not(target_os = "linux"))]
+fn sys_wait_timeout(atomic: &AtomicU32, value: u32, timeout: Duration) {
+    use std::sync::atomic::Ordering::Relaxed;
+    if atomic.load(Relaxed) == value {
+        std::thread::sleep(timeout.min(Duration::from_millis(1)));
+    }
+}
+
+/// Wake one thread waiting on the atomic.
+#[inline]
+#[cfg_attr(feature = "metrics", track_caller)]
+pub(crate) fn wake_one(atomic: *const AtomicU32) {
+    #[cfg(feature = "testutil")]
+    if crate::testutil::hook::wake(atomic, false) {
+        return;
+    }
+    if !batch::defer(atomic, 1, site()) {
+        sys_wake(atomic, 1, site());
+    }
+}
+
+/// Wake all threads waiting on the atomic.
+#[inline]
+#[cfg_attr(feature = "metrics", track_caller)]
+pub(crate) fn wake_all(atomic: *const AtomicU32) {
+    #[cfg(feature = "testutil")]
+    if crate::testutil::hook::wake(atomic, true) {
+        return;
+    }
+    if !batch::defer(atomic, WAKE_ALL, site()) {
+        sys_wake(atomic, WAKE_ALL, site());
+    }
+}
+
+/// Wake count threads waiting on the atomic in one syscall.
+#[cfg(target_os = "linux")]
+fn sys_wake(atomic: *const AtomicU32, count: u32, site: Site) {
+    #[cfg(feature = "metrics")]
+    stats::record_wake(site);
+    #[cfg(not(feature = "metrics"))]
+    let _ = site;
+    // Safety: waking only reads the address, it's private to the process
+    // just like atomic_wait.
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            atomic,
+            libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+            count.min(i32::MAX as u32) as i32,
+        );
+    }
+}
+
+// Without a counted wake, wake one by one.
+#[cfg(not(target_os = "linux"))]
+fn sys_wake(atomic: *const AtomicU32, count: u32, site: Site) {
+    #[cfg(feature = "metrics")]
+    stats::record_wake(site);
+    #[cfg(not(feature = "metrics"))]
+    let _ = site;
+    if count == WAKE_ALL {
+        atomic_wait::wake_all(atomic);
+    } else {
+        (0..count).for_each(|_| atomic_wait::wake_one(atomic));
+    }
+}
+
+/// A point where the `testutil` scheduler may switch threads,
+/// or `chaos` may yield or delay, no-op otherwise.
+#[inline]
+pub(crate) fn yield_point() {
+    #[cfg(feature = "testutil")]
+    crate::testutil::hook::yield_point();
+    #[cfg(feature = "chaos")]
+    crate::chaos::perturb();
+}
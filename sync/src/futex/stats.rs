@@ -0,0 +1,47 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+static STATS: Mutex<BTreeMap<&'static str, FutexStats>> = Mutex::new(BTreeMap::new());
+
+/// Counts of the futex syscalls of a primitive, e.g. to quantify in
+/// benches the wakes saved by `coalesce_wakes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FutexStats {
+    pub waits: u64,
+    pub wakes: u64,
+    /// Wakes merged into the syscall of another on the same word.
+    pub coalesced_wakes: u64,
+}
+
+/// The counts by primitive since the process started or the last reset,
+/// a primitive named by its module, e.g. `rwlock` or `channel/bounded`.
+pub fn futex_stats() -> Vec<(&'static str, FutexStats)> {
+    stats().iter().map(|(p, s)| (*p, *s)).collect()
+}
+
+pub fn reset_futex_stats() {
+    stats().clear();
+}
+
+/// The primitive of the source file calling into the futex layer.
+pub(super) fn primitive(file: &'static str) -> &'static str {
+    let file = file.rsplit_once("src/").map_or(file, |(_, f)| f);
+    let file = file.strip_suffix(".rs").unwrap_or(file);
+    file.strip_suffix("/mod").unwrap_or(file)
+}
+
+pub(super) fn record_wait(primitive: &'static str) {
+    stats().entry(primitive).or_default().waits += 1;
+}
+
+pub(super) fn record_wake(primitive: &'static str) {
+    stats().entry(primitive).or_default().wakes += 1;
+}
+
+pub(super) fn record_coalesced(primitive: &'static str) {
+    stats().entry(primitive).or_default().coalesced_wakes += 1;
+}
+
+// Keep counting even if a thread panicked while counting.
+fn stats() -> std::sync::MutexGuard<'static, BTreeMap<&'static str, FutexStats>> {
+    STATS.lock().unwrap_or_else(|e| e.into_inner())
+}
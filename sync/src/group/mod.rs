@@ -0,0 +1,168 @@
+use std::{panic, sync::Arc, thread};
+
+use crate::{
+    cancel::CancellationToken,
+    condvar::Condvar,
+    mutex::Mutex,
+    thread_ext::{catch, Panicked},
+};
+
+/// A group of tasks running on their own threads, cancelled together once
+/// one of them fails, and joined together with their results.
+///
+/// Tasks get the group's token, they should check it and return early
+/// once it's cancelled.
+pub struct JoinableTaskGroup<T, E> {
+    inner: Arc<Inner<T, E>>,
+}
+
+struct Inner<T, E> {
+    token: CancellationToken,
+    state: Mutex<State<T, E>>,
+    finished: Condvar,
+}
+
+struct State<T, E> {
+    running: usize,
+    // Results by the order tasks are spawned, filled as they finish.
+    results: Vec<Option<T>>,
+    // The first failure, the panic is resumed by wait.
+    error: Option<Failure<E>>,
+}
+
+enum Failure<E> {
+    Err(E),
+    Panic(Panicked),
+}
+
+impl<T, E> Default for JoinableTaskGroup<T, E>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, E> JoinableTaskGroup<T, E>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    pub fn new() -> Self {
+        Self::with_token(CancellationToken::new())
+    }
+
+    /// Create a group cancelled by the token as well, e.g. a child of
+    /// another group.
+    pub fn with_token(token: CancellationToken) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                token,
+                state: Mutex::new(State {
+                    running: 0,
+                    results: Vec::new(),
+                    error: None,
+                }),
+                finished: Condvar::new(),
+            }),
+        }
+    }
+
+    /// The token cancelled once a task fails.
+    pub fn token(&self) -> &CancellationToken {
+        &self.inner.token
+    }
+
+    /// Spawn a task on a new thread.
+    /// An error or panic of it cancels the group.
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: FnOnce(&CancellationToken) -> Result<T, E> + Send + 'static,
+    {
+        let mut state = self.inner.state.lock();
+        let index = state.results.len();
+        state.results.push(None);
+        state.running += 1;
+        drop(state);
+
+        let inner = Arc::clone(&self.inner);
+        thread::spawn(move || {
+            let result = catch(|| f(&inner.token));
+            let mut state = inner.state.lock();
+            match result {
+                Ok(Ok(value)) => state.results[index] = Some(value),
+                Ok(Err(e)) => inner.fail(&mut state, Failure::Err(e)),
+                Err(panicked) => inner.fail(&mut state, Failure::Panic(panicked)),
+            }
+            state.running -= 1;
+            if state.running == 0 {
+                inner.finished.notify_all();
+            }
+        });
+    }
+
+    /// Wait for all tasks, return their results by the order they're spawned,
+    /// or the first error. The first panic of tasks is resumed here.
+    pub fn wait(self) -> Result<Vec<T>, E> {
+        let mut state = self.inner.state.lock();
+        while state.running > 0 {
+            state = self.inner.finished.wait(state);
+        }
+        match state.error.take() {
+            Some(Failure::Err(e)) => Err(e),
+            Some(Failure::Panic(panicked)) => panic::resume_unwind(panicked.into_payload()),
+            // Every task succeeded, so every result is filled.
+            None => Ok(state.results.drain(..).map(Option::unwrap).collect()),
+        }
+    }
+}
+
+impl<T, E> Inner<T, E> {
+    fn fail(&self, state: &mut State<T, E>, failure: Failure<E>) {
+        if state.error.is_none() {
+            state.error = Some(failure);
+            self.token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{panic::AssertUnwindSafe, time::Duration};
+
+    use super::JoinableTaskGroup;
+
+    #[test]
+    fn test_joinable_task_group() {
+        let group = JoinableTaskGroup::<_, String>::new();
+        for i in 0..4 {
+            group.spawn(move |_| {
+                std::thread::sleep(Duration::from_millis(10 * (4 - i)));
+                Ok(i)
+            });
+        }
+        assert_eq!(group.wait().unwrap(), [0, 1, 2, 3]);
+
+        // A failure cancels the siblings.
+        let group = JoinableTaskGroup::new();
+        for _ in 0..4 {
+            group.spawn(|token| {
+                token.wait();
+                Ok(())
+            });
+        }
+        group.spawn(|_| Err("failed"));
+        assert_eq!(group.wait().unwrap_err(), "failed");
+
+        let group = JoinableTaskGroup::<(), ()>::new();
+        group.spawn(|token| {
+            token.wait();
+            Ok(())
+        });
+        group.spawn(|_| panic!("boom"));
+        let payload = std::panic::catch_unwind(AssertUnwindSafe(|| group.wait())).unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+    }
+}
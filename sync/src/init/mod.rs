@@ -0,0 +1,322 @@
+//! Process initialization ordered by the dependencies among subsystems,
+//! e.g. logging before config before the database pool.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt,
+    sync::OnceLock,
+    thread,
+};
+
+use crate::{condvar::Condvar, mutex::Mutex, thread_ext::catch};
+
+type Init = Box<dyn FnOnce() + Send>;
+
+/// Initialization of a process' subsystems, each registered by name with
+/// the names it depends on, and run once in dependency order by `run`.
+///
+/// Subsystems whose dependencies are done run concurrently, on at most as
+/// many scoped threads as the machine has CPUs.
+///
+/// ```
+/// use sync::init::InitGraph;
+///
+/// static GRAPH: InitGraph = InitGraph::new();
+///
+/// let graph = &GRAPH;
+/// graph.register("logging", &[], || println!("logging"));
+/// graph.register("config", &["logging"], || println!("config"));
+/// graph.register("db", &["config", "logging"], || println!("db"));
+/// graph.run().unwrap();
+/// // Once per graph, later calls return the first result.
+/// graph.run().unwrap();
+/// ```
+pub struct InitGraph {
+    // None once run took the nodes, so register can't slip in after.
+    nodes: Mutex<Option<Vec<Node>>>,
+    done: OnceLock<Result<(), InitError>>,
+}
+
+struct Node {
+    name: &'static str,
+    deps: Vec<&'static str>,
+    init: Init,
+}
+
+/// Error returned by `InitGraph::run`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitError {
+    /// The name is registered twice, nothing runs.
+    Duplicate(&'static str),
+    /// A subsystem depends on a name never registered, nothing runs.
+    UnknownDependency {
+        name: &'static str,
+        dependency: &'static str,
+    },
+    /// The subsystems in or behind a dependency cycle, nothing runs.
+    Cycle(Vec<&'static str>),
+    /// The init of the subsystem panicked, those depending on it are
+    /// skipped, the rest still run.
+    Panicked(&'static str),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::Duplicate(name) => write!(f, "{:?} registered twice", name),
+            InitError::UnknownDependency { name, dependency } => {
+                write!(f, "{:?} depends on unknown {:?}", name, dependency)
+            }
+            InitError::Cycle(names) => write!(f, "dependency cycle among {:?}", names),
+            InitError::Panicked(name) => write!(f, "init of {:?} panicked", name),
+        }
+    }
+}
+
+impl Error for InitError {}
+
+impl Default for InitGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InitGraph {
+    /// Create an empty graph, usable as a static.
+    pub const fn new() -> Self {
+        Self {
+            nodes: Mutex::new(Some(Vec::new())),
+            done: OnceLock::new(),
+        }
+    }
+
+    /// Register the init of a subsystem, run after those of deps.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the graph has run already.
+    pub fn register<F>(&self, name: &'static str, deps: &[&'static str], init: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut nodes = self.nodes.lock();
+        let Some(nodes) = nodes.as_mut() else {
+            panic!("{:?} registered after InitGraph::run", name);
+        };
+        nodes.push(Node {
+            name,
+            deps: deps.to_vec(),
+            init: Box::new(init),
+        });
+    }
+
+    /// Run the inits registered, once. Concurrent and later calls wait for
+    /// the first one and return its result.
+    ///
+    /// An init must not run the graph it's registered in, the call waits
+    /// for the run it's part of and never returns.
+    pub fn run(&self) -> Result<(), InitError> {
+        self.done
+            .get_or_init(|| execute(self.nodes.lock().take().unwrap_or_default()))
+            .clone()
+    }
+}
+
+// The inits ready to run and the progress, shared by the workers.
+struct Schedule {
+    ready: VecDeque<usize>,
+    inits: Vec<Option<Init>>,
+    // Count of dependencies not done yet, by node.
+    pending: Vec<usize>,
+    running: usize,
+    error: Option<InitError>,
+}
+
+fn execute(nodes: Vec<Node>) -> Result<(), InitError> {
+    let mut index = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if index.insert(node.name, i).is_some() {
+            return Err(InitError::Duplicate(node.name));
+        }
+    }
+    let mut dependents = vec![Vec::new(); nodes.len()];
+    let mut pending = vec![0; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        for dependency in &node.deps {
+            let Some(&d) = index.get(dependency) else {
+                return Err(InitError::UnknownDependency {
+                    name: node.name,
+                    dependency,
+                });
+            };
+            dependents[d].push(i);
+            pending[i] += 1;
+        }
+    }
+    check_acyclic(&nodes, &dependents, &pending)?;
+
+    let names: Vec<_> = nodes.iter().map(|n| n.name).collect();
+    let schedule = Mutex::new(Schedule {
+        ready: (0..nodes.len()).filter(|&i| pending[i] == 0).collect(),
+        inits: nodes.into_iter().map(|n| Some(n.init)).collect(),
+        pending,
+        running: 0,
+        error: None,
+    });
+    let changed = Condvar::new();
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|s| {
+        for _ in 0..workers.min(names.len()) {
+            s.spawn(|| work(&schedule, &changed, &dependents, &names));
+        }
+    });
+    match schedule.into_inner().error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+// Run the ready inits until there're none and none running, which may
+// make more ready.
+fn work(
+    schedule: &Mutex<Schedule>,
+    changed: &Condvar,
+    dependents: &[Vec<usize>],
+    names: &[&'static str],
+) {
+    let mut state = schedule.lock();
+    loop {
+        let Some(i) = state.ready.pop_front() else {
+            if state.running == 0 {
+                return;
+            }
+            state = changed.wait(state);
+            continue;
+        };
+        let init = state.inits[i].take().unwrap();
+        state.running += 1;
+        drop(state);
+        let result = catch(init);
+        state = schedule.lock();
+        state.running -= 1;
+        match result {
+            Ok(()) => {
+                for &d in &dependents[i] {
+                    state.pending[d] -= 1;
+                    if state.pending[d] == 0 {
+                        state.ready.push_back(d);
+                    }
+                }
+            }
+            // Those depending on it never become ready.
+            Err(_) => {
+                state.error.get_or_insert(InitError::Panicked(names[i]));
+            }
+        }
+        changed.notify_all();
+    }
+}
+
+// Check the graph is a DAG by Kahn's algorithm, before any init runs.
+fn check_acyclic(
+    nodes: &[Node],
+    dependents: &[Vec<usize>],
+    pending: &[usize],
+) -> Result<(), InitError> {
+    let mut pending = pending.to_vec();
+    let mut ready: Vec<_> = (0..nodes.len()).filter(|&i| pending[i] == 0).collect();
+    while let Some(i) = ready.pop() {
+        for &d in &dependents[i] {
+            pending[d] -= 1;
+            if pending[d] == 0 {
+                ready.push(d);
+            }
+        }
+    }
+    let stuck: Vec<_> = (0..nodes.len())
+        .filter(|&i| pending[i] > 0)
+        .map(|i| nodes[i].name)
+        .collect();
+    if stuck.is_empty() {
+        return Ok(());
+    }
+    Err(InitError::Cycle(stuck))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering::SeqCst},
+        Arc, Mutex,
+    };
+
+    use super::{InitError, InitGraph};
+
+    #[test]
+    fn test_init_graph() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let graph = InitGraph::new();
+        let runs = Arc::new(AtomicU32::new(0));
+        for (name, deps) in [
+            ("db", &["config", "logging"][..]),
+            ("config", &["logging"]),
+            ("logging", &[]),
+            ("metrics", &[]),
+            ("server", &["db", "metrics"]),
+        ] {
+            let (order, runs) = (Arc::clone(&order), Arc::clone(&runs));
+            graph.register(name, deps, move || {
+                runs.fetch_add(1, SeqCst);
+                order.lock().unwrap().push(name);
+            });
+        }
+        graph.run().unwrap();
+        graph.run().unwrap();
+        assert_eq!(runs.load(SeqCst), 5);
+        let order = order.lock().unwrap();
+        let at = |name| order.iter().position(|n| *n == name).unwrap();
+        assert!(at("logging") < at("config"));
+        assert!(at("config") < at("db"));
+        assert!(at("db") < at("server"));
+        assert!(at("metrics") < at("server"));
+
+        let graph = InitGraph::new();
+        graph.register("a", &["b"], || unreachable!());
+        graph.register("b", &["a"], || unreachable!());
+        graph.register("c", &["b"], || unreachable!());
+        graph.register("d", &[], || unreachable!());
+        assert_eq!(graph.run(), Err(InitError::Cycle(vec!["a", "b", "c"])));
+
+        let graph = InitGraph::new();
+        graph.register("a", &["missing"], || unreachable!());
+        assert_eq!(
+            graph.run(),
+            Err(InitError::UnknownDependency {
+                name: "a",
+                dependency: "missing"
+            })
+        );
+
+        // A panic skips the dependents only.
+        let graph = InitGraph::new();
+        let runs = Arc::new(AtomicU32::new(0));
+        let counted = Arc::clone(&runs);
+        graph.register("broken", &[], || panic!("broken"));
+        graph.register("after", &["broken"], || unreachable!());
+        graph.register("other", &[], move || {
+            counted.fetch_add(1, SeqCst);
+        });
+        assert_eq!(graph.run(), Err(InitError::Panicked("broken")));
+        assert_eq!(runs.load(SeqCst), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "registered after InitGraph::run")]
+    fn test_register_after_run() {
+        static GRAPH: InitGraph = InitGraph::new();
+        GRAPH.register("a", &[], || {});
+        GRAPH.run().unwrap();
+        GRAPH.register("b", &[], || {});
+    }
+}
@@ -0,0 +1,3 @@
+mod pipe;
+
+pub use pipe::{PipeBuffer, PipeReader, PipeWriter};
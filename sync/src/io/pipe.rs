@@ -0,0 +1,282 @@
+use std::{
+    cell::UnsafeCell,
+    io::{self, Read, Write},
+    ptr,
+    sync::{
+        atomic::{
+            AtomicBool, AtomicU32, AtomicUsize,
+            Ordering::{Acquire, Relaxed, Release, SeqCst},
+        },
+        Arc,
+    },
+};
+
+use crate::futex::{wait, wake_one};
+
+/// A bounded single-producer single-consumer byte ring.
+///
+/// Bytes are copied into and out of the ring without locking, a syscall
+/// only happens if a blocking half has to sleep on an empty or full ring.
+/// Split it into a `PipeWriter` and a `PipeReader` implementing `Write` and `Read`.
+pub struct PipeBuffer {
+    buffer: Box<[UnsafeCell<u8>]>,
+    // Total bytes read and written, the difference is the bytes buffered.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    // Set once any half is dropped.
+    closed: AtomicBool,
+    readable: Event,
+    writable: Event,
+}
+
+unsafe impl Sync for PipeBuffer {}
+
+impl PipeBuffer {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        Self {
+            buffer: (0..capacity).map(|_| UnsafeCell::new(0)).collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            readable: Event::new(),
+            writable: Event::new(),
+        }
+    }
+
+    /// Split into the writing and the reading half, both blocking.
+    pub fn split(self) -> (PipeWriter, PipeReader) {
+        let pipe = Arc::new(self);
+        (
+            PipeWriter {
+                pipe: Arc::clone(&pipe),
+                nonblocking: false,
+            },
+            PipeReader {
+                pipe,
+                nonblocking: false,
+            },
+        )
+    }
+
+    fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Copy bytes between the ring from pos and buf, wrapping around.
+    ///
+    /// Safety: the range must be owned by the caller.
+    unsafe fn copy(&self, pos: usize, len: usize, mut f: impl FnMut(*mut u8, usize, usize)) {
+        let start = pos % self.capacity();
+        let first = len.min(self.capacity() - start);
+        let base = UnsafeCell::raw_get(self.buffer.as_ptr());
+        f(base.add(start), 0, first);
+        f(base, first, len - first);
+    }
+
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        if self.closed.load(Acquire) {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let tail = self.tail.load(Relaxed);
+        let free = self.capacity() - (tail - self.head.load(Acquire));
+        let len = buf.len().min(free);
+        if len == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        // Safety: the free range is owned by the writer.
+        unsafe {
+            self.copy(tail, len, |ring, offset, n| {
+                ptr::copy_nonoverlapping(buf.as_ptr().add(offset), ring, n)
+            })
+        };
+        self.tail.store(tail + len, SeqCst);
+        self.readable.notify();
+        Ok(len)
+    }
+
+    fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let head = self.head.load(Relaxed);
+        let len = buf.len().min(self.tail.load(Acquire) - head);
+        if len == 0 && !buf.is_empty() {
+            if self.closed.load(Acquire) && self.tail.load(Acquire) == head {
+                // End of stream.
+                return Ok(0);
+            }
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        // Safety: the buffered range is owned by the reader.
+        unsafe {
+            self.copy(head, len, |ring, offset, n| {
+                ptr::copy_nonoverlapping(ring, buf.as_mut_ptr().add(offset), n)
+            })
+        };
+        self.head.store(head + len, SeqCst);
+        self.writable.notify();
+        Ok(len)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, SeqCst);
+        self.readable.notify();
+        self.writable.notify();
+    }
+}
+
+/// A wake-up event, notifying is a single load unless somebody is waiting.
+struct Event {
+    seq: AtomicU32,
+    waiting: AtomicBool,
+}
+
+impl Event {
+    fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+            waiting: AtomicBool::new(false),
+        }
+    }
+
+    fn notify(&self) {
+        if self.waiting.load(SeqCst) {
+            self.seq.fetch_add(1, Release);
+            wake_one(&self.seq);
+        }
+    }
+
+    /// Retry f until it doesn't block, sleep between retries.
+    fn wait_while_blocked<R>(&self, mut f: impl FnMut() -> io::Result<R>) -> io::Result<R> {
+        loop {
+            match f() {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+            let seq = self.seq.load(Acquire);
+            self.waiting.store(true, SeqCst);
+            // Check again after marked waiting, the peer notifies only if marked.
+            let result = f();
+            if !matches!(&result, Err(e) if e.kind() == io::ErrorKind::WouldBlock) {
+                self.waiting.store(false, Relaxed);
+                return result;
+            }
+            wait(&self.seq, seq);
+            self.waiting.store(false, Relaxed);
+        }
+    }
+}
+
+/// The writing half of a pipe.
+pub struct PipeWriter {
+    pipe: Arc<PipeBuffer>,
+    nonblocking: bool,
+}
+
+impl PipeWriter {
+    /// In non-blocking mode writing to a full pipe fails with `WouldBlock`.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) {
+        self.nonblocking = nonblocking;
+    }
+}
+
+impl Write for PipeWriter {
+    /// Write as many bytes as fit, block until there's space unless non-blocking.
+    /// Fail with `BrokenPipe` if the reader is dropped.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.nonblocking {
+            return self.pipe.try_write(buf);
+        }
+        self.pipe
+            .writable
+            .wait_while_blocked(|| self.pipe.try_write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.pipe.close();
+    }
+}
+
+/// The reading half of a pipe.
+pub struct PipeReader {
+    pipe: Arc<PipeBuffer>,
+    nonblocking: bool,
+}
+
+impl PipeReader {
+    /// In non-blocking mode reading an empty pipe fails with `WouldBlock`.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) {
+        self.nonblocking = nonblocking;
+    }
+}
+
+impl Read for PipeReader {
+    /// Read the bytes buffered, block until there's some unless non-blocking.
+    /// Return 0 once the writer is dropped and all bytes are read.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.nonblocking {
+            return self.pipe.try_read(buf);
+        }
+        self.pipe
+            .readable
+            .wait_while_blocked(|| self.pipe.try_read(buf))
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.pipe.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{ErrorKind, Read, Write},
+        thread,
+    };
+
+    use super::PipeBuffer;
+
+    #[test]
+    fn test_pipe_buffer() {
+        let (mut writer, mut reader) = PipeBuffer::new(7).split();
+        let data: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+        thread::scope(|s| {
+            s.spawn(move || {
+                for chunk in data.chunks(13) {
+                    writer.write_all(chunk).unwrap();
+                }
+            });
+            let mut received = Vec::new();
+            reader.read_to_end(&mut received).unwrap();
+            assert_eq!(received.len(), 10_000);
+            assert!(received.iter().enumerate().all(|(i, &b)| b == i as u8));
+        });
+
+        let (mut writer, mut reader) = PipeBuffer::new(2).split();
+        reader.set_nonblocking(true);
+        writer.set_nonblocking(true);
+        let mut buf = [0; 4];
+        assert_eq!(
+            reader.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+        assert_eq!(writer.write(b"abc").unwrap(), 2);
+        assert_eq!(
+            writer.write(b"c").unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ab");
+        drop(reader);
+        assert_eq!(
+            writer.write(b"c").unwrap_err().kind(),
+            ErrorKind::BrokenPipe
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{arc::Arc, condvar::Condvar, mutex::Mutex};
+
+/// What readers do while the value is being recomputed after a reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadPolicy {
+    /// Block until the new value is computed.
+    Block,
+    /// Return the stale value if there's one, otherwise block.
+    ServeStale,
+}
+
+/// A lazily computed value which can be invalidated by `reset`,
+/// then it's recomputed by the next access.
+pub struct ResettableLazy<T, F = fn() -> T> {
+    init: F,
+    policy: ReadPolicy,
+    slot: Mutex<Slot<T>>,
+    computed: Condvar,
+}
+
+struct Slot<T> {
+    value: Option<Arc<T>>,
+    // False if the value is never computed or reset.
+    valid: bool,
+    // Bumped by every reset.
+    generation: u64,
+    // True if a thread is computing the value.
+    computing: bool,
+}
+
+impl<T, F: Fn() -> T> ResettableLazy<T, F> {
+    /// Create a lazy value, readers block while it's recomputed.
+    pub fn new(init: F) -> Self {
+        Self::with_policy(init, ReadPolicy::Block)
+    }
+
+    pub fn with_policy(init: F, policy: ReadPolicy) -> Self {
+        Self {
+            init,
+            policy,
+            slot: Mutex::new(Slot {
+                value: None,
+                valid: false,
+                generation: 0,
+                computing: false,
+            }),
+            computed: Condvar::new(),
+        }
+    }
+
+    /// Get the value, compute it if it's not computed since last reset.
+    pub fn get(&self) -> Arc<T> {
+        let mut slot = self.slot.lock();
+        loop {
+            if slot.valid {
+                return slot.value.clone().unwrap();
+            }
+            if !slot.computing {
+                break;
+            }
+            if self.policy == ReadPolicy::ServeStale {
+                if let Some(value) = &slot.value {
+                    return value.clone();
+                }
+            }
+            slot = self.computed.wait(slot);
+        }
+        slot.computing = true;
+        let generation = slot.generation;
+        drop(slot);
+
+        // Wake up the waiters even if init panics.
+        let computing = Computing { lazy: self };
+        let value = Arc::new((self.init)());
+        let mut slot = self.slot.lock();
+        slot.value = Some(value.clone());
+        // Keep it invalid if reset during computing, it may be derived from stale inputs.
+        slot.valid = slot.generation == generation;
+        drop(slot);
+        drop(computing);
+        value
+    }
+
+    /// Invalidate the value, the next access recomputes it.
+    pub fn reset(&self) {
+        let mut slot = self.slot.lock();
+        slot.generation += 1;
+        slot.valid = false;
+    }
+
+    /// Count of resets so far.
+    pub fn generation(&self) -> u64 {
+        self.slot.lock().generation
+    }
+}
+
+struct Computing<'a, T, F> {
+    lazy: &'a ResettableLazy<T, F>,
+}
+
+impl<T, F> Drop for Computing<'_, T, F> {
+    fn drop(&mut self) {
+        self.lazy.slot.lock().computing = false;
+        self.lazy.computed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::{thread, time::Duration};
+
+    use super::{ReadPolicy, ResettableLazy};
+
+    #[test]
+    fn test_resettable_lazy() {
+        let computes = AtomicUsize::new(0);
+        let lazy = ResettableLazy::with_policy(
+            || {
+                let n = computes.fetch_add(1, Relaxed) + 1;
+                if n > 1 {
+                    thread::sleep(Duration::from_millis(200));
+                }
+                n
+            },
+            ReadPolicy::ServeStale,
+        );
+        assert_eq!(*lazy.get(), 1);
+        assert_eq!(*lazy.get(), 1);
+
+        lazy.reset();
+        assert_eq!(lazy.generation(), 1);
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(*lazy.get(), 2));
+            thread::sleep(Duration::from_millis(50));
+            // Recomputing is in progress, the stale value is served.
+            assert_eq!(*lazy.get(), 1);
+        });
+        assert_eq!(*lazy.get(), 2);
+        assert_eq!(computes.load(Relaxed), 2);
+    }
+}
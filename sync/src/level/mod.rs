@@ -0,0 +1,164 @@
+//! Opt-in lock leveling, catching lock-order inversions deterministically.
+//!
+//! Each leveled lock is assigned a numeric level, and a thread must acquire
+//! locks in strictly increasing levels. Debug builds panic on a thread
+//! acquiring a lock with a level not above the highest level it holds,
+//! release builds skip the check.
+
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    mutex::{Mutex, MutexGuard},
+    rwlock::{ReadGuard, RwLock, WriteGuard},
+};
+
+#[cfg(debug_assertions)]
+thread_local! {
+    // Levels of leveled locks held by the current thread.
+    static HELD: std::cell::RefCell<Vec<LockLevel>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// The level of a lock, locks are acquired in strictly increasing levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LockLevel(pub u32);
+
+impl LockLevel {
+    /// Panic if the current thread holds a lock with level not below this.
+    fn check(self) {
+        #[cfg(debug_assertions)]
+        HELD.with(|held| {
+            if let Some(&highest) = held.borrow().iter().max() {
+                assert!(
+                    highest < self,
+                    "lock order violation: acquiring level {} while holding level {}",
+                    self.0,
+                    highest.0
+                );
+            }
+        });
+    }
+
+    fn acquired(self) {
+        #[cfg(debug_assertions)]
+        HELD.with(|held| held.borrow_mut().push(self));
+    }
+
+    fn released(self) {
+        #[cfg(debug_assertions)]
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(i) = held.iter().rposition(|&l| l == self) {
+                held.remove(i);
+            }
+        });
+    }
+}
+
+/// A lock assigned with a level, e.g. `Leveled::new(LockLevel(1), Mutex::new(0))`.
+pub struct Leveled<L> {
+    level: LockLevel,
+    lock: L,
+}
+
+impl<L> Leveled<L> {
+    pub const fn new(level: LockLevel, lock: L) -> Self {
+        Self { level, lock }
+    }
+
+    pub fn level(&self) -> LockLevel {
+        self.level
+    }
+
+    fn guard<'a, G>(&'a self, acquire: impl FnOnce(&'a L) -> G) -> LeveledGuard<G> {
+        self.level.check();
+        let guard = acquire(&self.lock);
+        self.level.acquired();
+        LeveledGuard {
+            level: self.level,
+            guard,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<T> Leveled<Mutex<T>> {
+    /// Lock the mutex, checking the lock order.
+    pub fn lock(&self) -> LeveledGuard<MutexGuard<'_, T>> {
+        self.guard(Mutex::lock)
+    }
+}
+
+impl<T> Leveled<RwLock<T>> {
+    /// Read lock the rwlock, checking the lock order.
+    pub fn read(&self) -> LeveledGuard<ReadGuard<'_, T>> {
+        self.guard(RwLock::read)
+    }
+
+    /// Write lock the rwlock, checking the lock order.
+    pub fn write(&self) -> LeveledGuard<WriteGuard<'_, T>> {
+        self.guard(RwLock::write)
+    }
+}
+
+/// A guard of a leveled lock, releasing the level on drop.
+pub struct LeveledGuard<G> {
+    level: LockLevel,
+    guard: G,
+    // The level is tracked by the acquiring thread.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<G: Deref> Deref for LeveledGuard<G> {
+    type Target = G::Target;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut> DerefMut for LeveledGuard<G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<G> Drop for LeveledGuard<G> {
+    fn drop(&mut self) {
+        self.level.released();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use super::{Leveled, LockLevel};
+    use crate::{mutex::Mutex, rwlock::RwLock};
+
+    #[test]
+    fn test_lock_level() {
+        let low = Leveled::new(LockLevel(1), Mutex::new(0));
+        let high = Leveled::new(LockLevel(2), RwLock::new(0));
+        {
+            let mut l = low.lock();
+            let mut h = high.write();
+            *l += 1;
+            *h += 1;
+        }
+        // Released out of order, then acquired again.
+        let l = low.lock();
+        let h = high.read();
+        drop(l);
+        drop(h);
+
+        let h = high.read();
+        let violated = catch_unwind(AssertUnwindSafe(|| *low.lock() += 1)).is_err();
+        assert_eq!(violated, cfg!(debug_assertions));
+        let violated = catch_unwind(AssertUnwindSafe(|| *high.read())).is_err();
+        assert_eq!(violated, cfg!(debug_assertions));
+        drop(h);
+        assert_eq!(*low.lock(), if violated { 1 } else { 2 });
+    }
+}
@@ -1,6 +1,52 @@
 pub mod arc;
+pub mod atomic_ext;
+pub mod barrier;
+pub mod budget;
+pub mod cancel;
 pub mod channel;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod collections;
+pub mod combiner;
 pub mod condvar;
+pub mod config;
+pub mod config_cell;
+#[cfg(feature = "deadlock-detection")]
+pub mod deadlock;
+#[cfg(feature = "htm")]
+pub mod elision;
+pub mod futex;
+pub mod group;
+pub mod init;
+pub mod io;
+pub mod lazy;
+pub mod level;
+pub mod lock;
+pub mod mailbox;
+pub mod monitor;
 pub mod mutex;
+pub mod once_callback;
+pub mod oncecell;
+pub mod parking;
+pub mod pipeline;
+#[cfg(feature = "registry")]
+pub mod registry;
 pub mod rwlock;
+pub mod seqlock;
+#[cfg(all(feature = "signal", target_os = "linux"))]
+pub mod signal;
 pub mod spin;
+pub mod swap;
+#[cfg(feature = "testutil")]
+pub mod testutil;
+pub mod thread_ext;
+pub mod time;
+mod tsan;
+pub mod weak_cache;
+mod xorshift;
+
+#[cfg(feature = "deadlock-detection")]
+pub use deadlock::dump_all_locks;
+
+#[cfg(test)]
+mod task;
@@ -0,0 +1,233 @@
+//! Traits over the crate's locks, so generic code and containers can be
+//! parameterized by the locking strategy.
+//!
+//! ```
+//! use sync::{lock::Lock, mutex::Mutex, spin::SpinLock};
+//!
+//! fn bump<L: Lock<u64>>(lock: &L) -> u64 {
+//!     let mut g = lock.lock();
+//!     *g += 1;
+//!     *g
+//! }
+//!
+//! assert_eq!(bump(&Mutex::new(0)), 1);
+//! assert_eq!(bump(&SpinLock::new(1)), 2);
+//! ```
+
+use std::ops::{Deref, DerefMut};
+
+use crate::{
+    mutex::{
+        AdaptiveMutex, AdaptiveMutexGuard, FairMutex, FairMutexGuard, MicroMutex, MicroMutexGuard,
+        Mutex, MutexGuard,
+    },
+    rwlock::{ReadGuard, RwLock, WideReadGuard, WideRwLock, WideWriteGuard, WriteGuard},
+    spin::{SpinLock, SpinLockGuard},
+};
+
+/// A lock giving exclusive access to a value of T.
+pub trait Lock<T> {
+    type Guard<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    fn new(value: T) -> Self;
+
+    /// Block until the lock is acquired exclusively.
+    fn lock(&self) -> Self::Guard<'_>;
+}
+
+/// A lock giving shared access to readers or exclusive access to a writer.
+pub trait RwLockLike<T> {
+    type ReadGuard<'a>: Deref<Target = T>
+    where
+        Self: 'a;
+    type WriteGuard<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    fn new(value: T) -> Self;
+
+    fn read(&self) -> Self::ReadGuard<'_>;
+
+    fn write(&self) -> Self::WriteGuard<'_>;
+}
+
+impl<T> Lock<T> for Mutex<T> {
+    type Guard<'a>
+        = MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        Mutex::new(value)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, T> {
+        Mutex::lock(self)
+    }
+}
+
+impl<T> Lock<T> for AdaptiveMutex<T> {
+    type Guard<'a>
+        = AdaptiveMutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        AdaptiveMutex::new(value)
+    }
+
+    fn lock(&self) -> AdaptiveMutexGuard<'_, T> {
+        AdaptiveMutex::lock(self)
+    }
+}
+
+impl<T> Lock<T> for MicroMutex<T> {
+    type Guard<'a>
+        = MicroMutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        MicroMutex::new(value)
+    }
+
+    fn lock(&self) -> MicroMutexGuard<'_, T> {
+        MicroMutex::lock(self)
+    }
+}
+
+impl<T> Lock<T> for FairMutex<T> {
+    type Guard<'a>
+        = FairMutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        FairMutex::new(value)
+    }
+
+    fn lock(&self) -> FairMutexGuard<'_, T> {
+        FairMutex::lock(self)
+    }
+}
+
+impl<T> Lock<T> for SpinLock<T> {
+    type Guard<'a>
+        = SpinLockGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        SpinLock::new(value)
+    }
+
+    fn lock(&self) -> SpinLockGuard<'_, T> {
+        SpinLock::lock(self)
+    }
+}
+
+/// Locked by the write lock.
+impl<T, const MAX_READERS: u32> Lock<T> for RwLock<T, MAX_READERS> {
+    type Guard<'a>
+        = WriteGuard<'a, T, MAX_READERS>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        RwLock::with_reader_cap(value)
+    }
+
+    fn lock(&self) -> WriteGuard<'_, T, MAX_READERS> {
+        RwLock::write(self)
+    }
+}
+
+impl<T, const MAX_READERS: u32> RwLockLike<T> for RwLock<T, MAX_READERS> {
+    type ReadGuard<'a>
+        = ReadGuard<'a, T, MAX_READERS>
+    where
+        T: 'a;
+    type WriteGuard<'a>
+        = WriteGuard<'a, T, MAX_READERS>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        RwLock::with_reader_cap(value)
+    }
+
+    fn read(&self) -> ReadGuard<'_, T, MAX_READERS> {
+        RwLock::read(self)
+    }
+
+    fn write(&self) -> WriteGuard<'_, T, MAX_READERS> {
+        RwLock::write(self)
+    }
+}
+
+impl<T> RwLockLike<T> for WideRwLock<T> {
+    type ReadGuard<'a>
+        = WideReadGuard<'a, T>
+    where
+        T: 'a;
+    type WriteGuard<'a>
+        = WideWriteGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        WideRwLock::new(value)
+    }
+
+    fn read(&self) -> WideReadGuard<'_, T> {
+        WideRwLock::read(self)
+    }
+
+    fn write(&self) -> WideWriteGuard<'_, T> {
+        WideRwLock::write(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{Lock, RwLockLike};
+    use crate::{
+        mutex::{AdaptiveMutex, FairMutex, MicroMutex, Mutex},
+        rwlock::{RwLock, WideRwLock},
+        spin::SpinLock,
+    };
+
+    fn count<L: Lock<u64> + Sync>() -> u64 {
+        let lock = L::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| (0..1000).for_each(|_| *lock.lock() += 1));
+            }
+        });
+        let total = *lock.lock();
+        total
+    }
+
+    fn read_write<L: RwLockLike<Vec<u32>>>() -> usize {
+        let lock = L::new(Vec::new());
+        lock.write().push(1);
+        let (a, b) = (lock.read(), lock.read());
+        a.len() + b.len()
+    }
+
+    #[test]
+    fn test_lock() {
+        assert_eq!(count::<Mutex<u64>>(), 4000);
+        assert_eq!(count::<AdaptiveMutex<u64>>(), 4000);
+        assert_eq!(count::<MicroMutex<u64>>(), 4000);
+        assert_eq!(count::<FairMutex<u64>>(), 4000);
+        assert_eq!(count::<SpinLock<u64>>(), 4000);
+        assert_eq!(count::<RwLock<u64>>(), 4000);
+        assert_eq!(read_write::<RwLock<_>>(), 2);
+        assert_eq!(read_write::<WideRwLock<_>>(), 2);
+    }
+}
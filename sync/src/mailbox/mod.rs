@@ -0,0 +1,253 @@
+//! A tiny actor: state owned by a thread, changed only by the messages of
+//! its bounded mailbox, so it needs no lock.
+//!
+//! ```
+//! use sync::mailbox::spawn_actor;
+//!
+//! let actor = spawn_actor(0u64, |total, n: u64| *total += n);
+//! let addr = actor.addr();
+//! (1..=10).for_each(|n| addr.send(n).unwrap());
+//! addr.stop();
+//! assert_eq!(actor.join().unwrap(), 55);
+//! ```
+
+use std::thread::{self, JoinHandle};
+
+use crate::{
+    cancel::CancellationToken,
+    channel::bounded::{self, SendError},
+    thread_ext::{catch, Panicked},
+};
+
+/// Mailbox capacity of `spawn_actor`.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// What an actor does if its handler panics. The message is lost either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestartPolicy {
+    /// Stop the actor, `Actor::join` gives the panic back.
+    #[default]
+    Stop,
+    /// Rebuild the state and go on with the next message, at most
+    /// max_restarts times, then stop.
+    Restart { max_restarts: u32 },
+}
+
+enum Envelope<M> {
+    Message(M),
+    Stop,
+}
+
+/// The address of an actor, a cloneable handle sending to its mailbox.
+pub struct Addr<M> {
+    tx: bounded::Sender<Envelope<M>>,
+}
+
+impl<M> Addr<M> {
+    /// Send a message, block while the mailbox is full.
+    /// Fail if the actor is stopped.
+    pub fn send(&self, message: M) -> Result<(), SendError<M>> {
+        self.tx
+            .send(Envelope::Message(message))
+            .map_err(|e| match e.0 {
+                Envelope::Message(message) => SendError(message),
+                Envelope::Stop => unreachable!("sent a message"),
+            })
+    }
+
+    /// Stop the actor gracefully once the messages sent before are handled,
+    /// the messages sent after are dropped.
+    pub fn stop(&self) {
+        // The actor is stopped already if the mailbox is closed.
+        let _ = self.tx.send(Envelope::Stop);
+    }
+}
+
+impl<M> Clone for Addr<M> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+/// A running actor, joined for its final state.
+pub struct Actor<S, M> {
+    addr: Addr<M>,
+    stopped: CancellationToken,
+    thread: JoinHandle<Result<S, Panicked>>,
+}
+
+impl<S, M> Actor<S, M> {
+    /// The address of the actor.
+    pub fn addr(&self) -> Addr<M> {
+        self.addr.clone()
+    }
+
+    /// A token cancelled once the actor stops, for any reason.
+    pub fn stopped(&self) -> &CancellationToken {
+        &self.stopped
+    }
+
+    /// Wait until the actor stops, by `Addr::stop` or once all addresses
+    /// are dropped, and return its state, or the panic that stopped it.
+    pub fn join(self) -> Result<S, Panicked> {
+        drop(self.addr);
+        self.thread.join().expect("actor thread catches panics")
+    }
+}
+
+/// Options of spawning an actor.
+#[derive(Debug, Clone)]
+pub struct ActorBuilder {
+    capacity: usize,
+    restart: RestartPolicy,
+}
+
+impl Default for ActorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActorBuilder {
+    /// Options of a mailbox of `DEFAULT_CAPACITY`, stopping on a panic.
+    pub fn new() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            restart: RestartPolicy::Stop,
+        }
+    }
+
+    /// Messages the mailbox buffers before senders block.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// What the actor does once its handler panics.
+    pub fn restart(mut self, restart: RestartPolicy) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    /// Spawn an actor on its own thread, with the state built by init,
+    /// which builds it again on every restart.
+    pub fn spawn<S, M, I, F>(self, mut init: I, mut handler: F) -> Actor<S, M>
+    where
+        S: Send + 'static,
+        M: Send + 'static,
+        I: FnMut() -> S + Send + 'static,
+        F: FnMut(&mut S, M) + Send + 'static,
+    {
+        let (tx, rx) = bounded::channel(self.capacity);
+        let stopped = CancellationToken::new();
+        let token = stopped.clone();
+        let max_restarts = match self.restart {
+            RestartPolicy::Stop => 0,
+            RestartPolicy::Restart { max_restarts } => max_restarts,
+        };
+        let thread = thread::spawn(move || {
+            // Tell the actor is stopped on every exit, after the mailbox is
+            // closed by dropping rx, which is declared later.
+            let _stop = CancelOnDrop(token);
+            let rx = rx;
+            let mut restarts = 0;
+            loop {
+                let mut state = None;
+                // A panic of init counts like one of the handler.
+                let handled = catch(|| {
+                    let state = state.insert(init());
+                    while let Ok(Envelope::Message(message)) = rx.recv() {
+                        handler(state, message);
+                    }
+                });
+                match handled {
+                    Ok(()) => break Ok(state.unwrap()),
+                    Err(panicked) if restarts == max_restarts => break Err(panicked),
+                    Err(_) => restarts += 1,
+                }
+            }
+        });
+        Actor {
+            addr: Addr { tx },
+            stopped,
+            thread,
+        }
+    }
+}
+
+struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Spawn an actor owning state on its own thread, handling every message
+/// of its mailbox by handler, with the default capacity.
+/// A panic of the handler stops it.
+pub fn spawn_actor<S, M, F>(state: S, handler: F) -> Actor<S, M>
+where
+    S: Send + 'static,
+    M: Send + 'static,
+    F: FnMut(&mut S, M) + Send + 'static,
+{
+    let mut state = Some(state);
+    ActorBuilder::new().spawn(
+        move || state.take().expect("never restarted by the Stop policy"),
+        handler,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{spawn_actor, ActorBuilder, RestartPolicy};
+    use crate::channel::bounded::SendError;
+
+    #[test]
+    fn test_actor() {
+        let actor = spawn_actor(Vec::new(), |seen: &mut Vec<u32>, n| seen.push(n));
+        let addr = actor.addr();
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let addr = addr.clone();
+                s.spawn(move || (0..100).for_each(|i| addr.send(t * 100 + i).unwrap()));
+            }
+        });
+        addr.stop();
+        actor.stopped().wait();
+        assert_eq!(addr.send(1), Err(SendError(1)));
+        let mut seen = actor.join().unwrap();
+        seen.sort();
+        assert_eq!(seen, (0..400).collect::<Vec<_>>());
+
+        // Restarted with a fresh state twice, then stopped by the panic.
+        let actor = ActorBuilder::new()
+            .capacity(1)
+            .restart(RestartPolicy::Restart { max_restarts: 2 })
+            .spawn(
+                || 0,
+                |count: &mut u32, n: u32| {
+                    *count += 1;
+                    assert!(n != 0, "zero");
+                },
+            );
+        let addr = actor.addr();
+        for n in [1, 0, 1, 1, 0, 1] {
+            addr.send(n).unwrap();
+        }
+        drop(addr);
+        assert_eq!(actor.join().unwrap(), 1);
+
+        let actor = spawn_actor((), |_, _: ()| panic!("boom"));
+        actor.addr().send(()).unwrap();
+        assert_eq!(actor.join().unwrap_err().message(), Some("boom"));
+
+        // A panic of init stops the actor too.
+        let actor = ActorBuilder::new().spawn(|| -> u32 { panic!("init") }, |_, _: ()| {});
+        actor.stopped().wait();
+        assert_eq!(actor.join().unwrap_err().message(), Some("init"));
+    }
+}
@@ -0,0 +1,135 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::{
+    condvar::Condvar,
+    mutex::{Mutex, MutexGuard},
+};
+
+/// A mutex bundled with named conditions of the value it protects.
+///
+/// The conditions can only be waited with the guard of this monitor,
+/// so a condition is never used with the wrong mutex.
+pub struct Monitor<T> {
+    mutex: Mutex<T>,
+    conditions: Box<[(&'static str, Condvar)]>,
+}
+
+impl<T> Monitor<T> {
+    /// Create a monitor with given condition names.
+    pub fn new(value: T, conditions: &[&'static str]) -> Self {
+        Self {
+            mutex: Mutex::new(value),
+            conditions: conditions.iter().map(|&n| (n, Condvar::new())).collect(),
+        }
+    }
+
+    pub fn lock(&self) -> MonitorGuard<'_, T> {
+        MonitorGuard {
+            monitor: self,
+            guard: self.mutex.lock(),
+        }
+    }
+
+    /// Lock and block until pred returns true on the value.
+    pub fn wait_until(
+        &self,
+        condition: &str,
+        pred: impl FnMut(&mut T) -> bool,
+    ) -> MonitorGuard<'_, T> {
+        self.lock().wait_until(condition, pred)
+    }
+
+    /// Wake one thread waiting on the condition.
+    pub fn signal(&self, condition: &str) {
+        self.condition(condition).notify_one();
+    }
+
+    /// Wake all threads waiting on the condition.
+    pub fn broadcast(&self, condition: &str) {
+        self.condition(condition).notify_all();
+    }
+
+    /// Panic if there's no condition of the name.
+    fn condition(&self, name: &str) -> &Condvar {
+        self.conditions
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, c)| c)
+            .unwrap_or_else(|| panic!("no condition named {:?}", name))
+    }
+}
+
+/// A guard type acquired by Monitor lock method.
+pub struct MonitorGuard<'a, T> {
+    monitor: &'a Monitor<T>,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<T> MonitorGuard<'_, T> {
+    /// Release the lock and block until the condition is signaled.
+    /// May wake up spuriously.
+    pub fn wait(self, condition: &str) -> Self {
+        let monitor = self.monitor;
+        let guard = monitor.condition(condition).wait(self.guard);
+        Self { monitor, guard }
+    }
+
+    /// Block until pred returns true on the value, waiting on the condition.
+    pub fn wait_until(mut self, condition: &str, mut pred: impl FnMut(&mut T) -> bool) -> Self {
+        while !pred(&mut self.guard) {
+            self = self.wait(condition);
+        }
+        self
+    }
+
+    /// Wake one thread waiting on the condition.
+    pub fn signal(&self, condition: &str) {
+        self.monitor.signal(condition);
+    }
+
+    /// Wake all threads waiting on the condition.
+    pub fn broadcast(&self, condition: &str) {
+        self.monitor.broadcast(condition);
+    }
+}
+
+impl<T> Deref for MonitorGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for MonitorGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, thread};
+
+    use super::Monitor;
+
+    #[test]
+    fn test_monitor() {
+        const CAPACITY: usize = 4;
+        let queue = Monitor::new(VecDeque::new(), &["not_empty", "not_full"]);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..1000 {
+                    let mut q = queue.wait_until("not_full", |q| q.len() < CAPACITY);
+                    q.push_back(i);
+                    q.signal("not_empty");
+                }
+            });
+            for i in 0..1000 {
+                let mut q = queue.wait_until("not_empty", |q| !q.is_empty());
+                assert_eq!(q.pop_front(), Some(i));
+                q.signal("not_full");
+            }
+        });
+        assert!(queue.lock().is_empty());
+    }
+}
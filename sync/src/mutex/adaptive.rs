@@ -0,0 +1,290 @@
+use std::{
+    cell::UnsafeCell,
+    hint,
+    ops::{Deref, DerefMut},
+    sync::atomic::{
+        AtomicU32,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use super::{MUTEX_CONTENTION, MUTEX_LOCKED, MUTEX_UNLOCKED};
+use crate::futex::{wait, wake_one, yield_point};
+
+// Contention is an EWMA of contended acquisitions scaled to 0..=CONTENDED,
+// each acquisition moves it 1/2^EWMA_SHIFT towards its sample.
+const CONTENDED: u32 = 1 << 16;
+const EWMA_SHIFT: u32 = 3;
+// Below SPIN_BELOW acquisitions spin, above PARK_ABOVE they park at once.
+const SPIN_BELOW: u32 = CONTENDED / 8;
+const PARK_ABOVE: u32 = CONTENDED / 2;
+
+/// How an AdaptiveMutex acquisition waits for the lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcquireStrategy {
+    /// Spin until the lock is released, yielding the CPU now and then,
+    /// for rare and short contention.
+    Spin,
+    /// Spin the budget of `Mutex::lock` then park, like Mutex.
+    SpinThenPark,
+    /// Park at once, for heavy contention where spinning burns CPU.
+    Park,
+}
+
+/// An experimental mutex which tracks recent contention by an EWMA, and
+/// picks how to wait by it for each acquisition.
+pub struct AdaptiveMutex<T> {
+    // Same as Mutex: unlocked, locked, locked with waiters.
+    state: AtomicU32,
+    // Updated racily, a lost update only delays adapting.
+    contention: AtomicU32,
+    #[cfg(feature = "metrics")]
+    stats: Counters,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for AdaptiveMutex<T> where T: Send {}
+
+impl<T> AdaptiveMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(MUTEX_UNLOCKED),
+            contention: AtomicU32::new(0),
+            #[cfg(feature = "metrics")]
+            stats: Counters::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// The strategy the next contended acquisition takes.
+    pub fn strategy(&self) -> AcquireStrategy {
+        match self.contention.load(Relaxed) {
+            c if c < SPIN_BELOW => AcquireStrategy::Spin,
+            c if c > PARK_ABOVE => AcquireStrategy::Park,
+            _ => AcquireStrategy::SpinThenPark,
+        }
+    }
+
+    /// Recent contention, the fraction of contended acquisitions.
+    pub fn contention(&self) -> f64 {
+        self.contention.load(Relaxed) as f64 / CONTENDED as f64
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> AdaptiveMutexStats {
+        self.stats.snapshot()
+    }
+
+    pub fn lock(&self) -> AdaptiveMutexGuard<'_, T> {
+        yield_point();
+        let contended = self
+            .state
+            .compare_exchange(MUTEX_UNLOCKED, MUTEX_LOCKED, Acquire, Relaxed)
+            .is_err();
+        if contended {
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(self, "AdaptiveMutex");
+            let strategy = self.strategy();
+            #[cfg(feature = "metrics")]
+            self.stats.record_contended(strategy);
+            match strategy {
+                AcquireStrategy::Spin => self.spin(),
+                AcquireStrategy::SpinThenPark => self.park(crate::config::get().mutex_spin_iters),
+                AcquireStrategy::Park => self.park(0),
+            }
+        }
+        self.sample(contended);
+        #[cfg(feature = "metrics")]
+        self.stats.record_acquire();
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "AdaptiveMutex");
+        AdaptiveMutexGuard { mutex: self }
+    }
+
+    fn sample(&self, contended: bool) {
+        let c = self.contention.load(Relaxed);
+        let c = if contended {
+            c + ((CONTENDED - c) >> EWMA_SHIFT)
+        } else {
+            c - (c >> EWMA_SHIFT)
+        };
+        self.contention.store(c, Relaxed);
+    }
+
+    #[cold]
+    fn spin(&self) {
+        let mut spins = 0u32;
+        loop {
+            // Keep the waiters bit if there're parked waiters.
+            let x = self.state.load(Relaxed);
+            if x == MUTEX_UNLOCKED
+                && self
+                    .state
+                    .compare_exchange_weak(x, MUTEX_LOCKED, Acquire, Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+            // Let a preempted owner run if the CPUs are oversubscribed.
+            spins = spins.wrapping_add(1);
+            if spins.is_multiple_of(64) {
+                std::thread::yield_now();
+            } else {
+                hint::spin_loop();
+            }
+            yield_point();
+        }
+    }
+
+    #[cold]
+    fn park(&self, mut spin_count: u32) {
+        let state = &self.state;
+        while state.load(Relaxed) == MUTEX_LOCKED && spin_count > 0 {
+            spin_count -= 1;
+            hint::spin_loop();
+        }
+        if state
+            .compare_exchange(MUTEX_UNLOCKED, MUTEX_LOCKED, Acquire, Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+        while state.swap(MUTEX_CONTENTION, Acquire) != MUTEX_UNLOCKED {
+            wait(state, MUTEX_CONTENTION);
+        }
+    }
+}
+
+/// A guard of AdaptiveMutex.
+pub struct AdaptiveMutexGuard<'a, T> {
+    mutex: &'a AdaptiveMutex<T>,
+}
+
+impl<T> Deref for AdaptiveMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Safety: the guard is the only accessor until it's dropped.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for AdaptiveMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: the guard is the only accessor until it's dropped.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for AdaptiveMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.mutex, "AdaptiveMutex");
+        if self.mutex.state.swap(MUTEX_UNLOCKED, Release) == MUTEX_CONTENTION {
+            wake_one(&self.mutex.state);
+        }
+        yield_point();
+    }
+}
+
+#[cfg(feature = "metrics")]
+struct Counters {
+    acquisitions: std::sync::atomic::AtomicU64,
+    // Contended acquisitions by AcquireStrategy.
+    contended: [std::sync::atomic::AtomicU64; 3],
+}
+
+#[cfg(feature = "metrics")]
+impl Counters {
+    const fn new() -> Self {
+        use std::sync::atomic::AtomicU64;
+        Self {
+            acquisitions: AtomicU64::new(0),
+            contended: [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)],
+        }
+    }
+
+    fn record_acquire(&self) {
+        self.acquisitions.fetch_add(1, Relaxed);
+    }
+
+    fn record_contended(&self, strategy: AcquireStrategy) {
+        self.contended[strategy as usize].fetch_add(1, Relaxed);
+    }
+
+    fn snapshot(&self) -> AdaptiveMutexStats {
+        AdaptiveMutexStats {
+            acquisitions: self.acquisitions.load(Relaxed),
+            spun: self.contended[AcquireStrategy::Spin as usize].load(Relaxed),
+            spun_then_parked: self.contended[AcquireStrategy::SpinThenPark as usize].load(Relaxed),
+            parked: self.contended[AcquireStrategy::Park as usize].load(Relaxed),
+        }
+    }
+}
+
+/// A snapshot of AdaptiveMutex statistics.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveMutexStats {
+    /// Count of finished acquisitions.
+    pub acquisitions: u64,
+    /// Contended acquisitions by `AcquireStrategy::Spin`.
+    pub spun: u64,
+    /// Contended acquisitions by `AcquireStrategy::SpinThenPark`.
+    pub spun_then_parked: u64,
+    /// Contended acquisitions by `AcquireStrategy::Park`.
+    pub parked: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use std::sync::atomic::Ordering::Relaxed;
+
+    use super::{AcquireStrategy, AdaptiveMutex, MUTEX_CONTENTION};
+
+    #[test]
+    fn test_adaptive_mutex() {
+        let x = AdaptiveMutex::new(0);
+        for _ in 0..100 {
+            *x.lock() += 1;
+        }
+        assert_eq!(x.strategy(), AcquireStrategy::Spin);
+        assert_eq!(x.contention(), 0.0);
+
+        // Contended acquisitions raise the contention, uncontended ones decay it.
+        for _ in 0..32 {
+            x.sample(true);
+        }
+        assert_eq!(x.strategy(), AcquireStrategy::Park);
+        for _ in 0..8 {
+            x.sample(false);
+        }
+        assert_eq!(x.strategy(), AcquireStrategy::SpinThenPark);
+        let g = x.lock();
+        thread::scope(|s| {
+            s.spawn(|| *x.lock() += 1);
+            while x.state.load(Relaxed) != MUTEX_CONTENTION {
+                thread::yield_now();
+            }
+            drop(g);
+        });
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        *x.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*x.lock(), 40_101);
+
+        #[cfg(feature = "metrics")]
+        {
+            let stats = x.stats();
+            assert_eq!(stats.acquisitions, 40_103);
+            assert!(stats.spun + stats.spun_then_parked + stats.parked >= 1);
+        }
+    }
+}
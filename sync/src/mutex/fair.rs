@@ -0,0 +1,152 @@
+use std::{
+    cell::UnsafeCell,
+    hint,
+    ops::{Deref, DerefMut},
+    sync::atomic::{
+        AtomicU32,
+        Ordering::{Relaxed, SeqCst},
+    },
+};
+
+use crate::futex::{wait, wake_all, yield_point};
+
+/// A ticket mutex granting the lock in the order threads ask for it, so
+/// no thread barges ahead of waiters, e.g. for latency-sensitive users who
+/// prefer fairness over the throughput of Mutex.
+///
+/// Every waiter waits on the same futex word for its turn and an unlock
+/// wakes them all, so it suits a handful of waiters rather than hundreds.
+pub struct FairMutex<T> {
+    // The ticket of the next thread asking for the lock.
+    next: AtomicU32,
+    // The ticket holding the lock, or next once unlocked.
+    serving: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+/// Implement Sync if and only if T is Send.
+/// Only one thread access the &T at a time,
+/// so T is not required to be Sync.
+unsafe impl<T> Sync for FairMutex<T> where T: Send {}
+
+impl<T> FairMutex<T> {
+    /// Create a new mutex for given value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            next: AtomicU32::new(0),
+            serving: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Whether the mutex is locked now, the answer may be stale at once.
+    pub fn is_locked(&self) -> bool {
+        self.next.load(Relaxed) != self.serving.load(Relaxed)
+    }
+
+    /// Acquire lock guard once the threads asked before have unlocked.
+    pub fn lock(&self) -> FairMutexGuard<'_, T> {
+        yield_point();
+        let ticket = self.next.fetch_add(1, SeqCst);
+        let mut serving = self.serving.load(SeqCst);
+        if serving != ticket {
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(self, "FairMutex");
+            let mut spin_count = crate::config::get().mutex_spin_iters;
+            while serving != ticket {
+                // Spin only if the lock is next, others wait longer.
+                if serving.wrapping_add(1) == ticket && spin_count > 0 {
+                    spin_count -= 1;
+                    hint::spin_loop();
+                } else {
+                    wait(&self.serving, serving);
+                }
+                serving = self.serving.load(SeqCst);
+            }
+        }
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "FairMutex");
+        FairMutexGuard { mutex: self }
+    }
+
+    /// Acquire lock guard if nobody holds or waits for the mutex.
+    pub fn try_lock(&self) -> Option<FairMutexGuard<'_, T>> {
+        let serving = self.serving.load(SeqCst);
+        self.next
+            .compare_exchange(serving, serving.wrapping_add(1), SeqCst, Relaxed)
+            .ok()?;
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "FairMutex");
+        Some(FairMutexGuard { mutex: self })
+    }
+}
+
+/// A guard type can be acquired from FairMutex lock method.
+pub struct FairMutexGuard<'a, T> {
+    mutex: &'a FairMutex<T>,
+}
+
+impl<T> Deref for FairMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: There's only one guard of same mutex can be accessed at a time,
+        // it's safe to access the inner value by any shared reference.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for FairMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: There's only one guard of same mutex can be accessed at a time,
+        // it's safe to access the inner value with mutable reference by mutable reference.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for FairMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.mutex, "FairMutex");
+        let serving = self.mutex.serving.fetch_add(1, SeqCst).wrapping_add(1);
+        // Wake the waiters if any, the one with the next ticket goes on.
+        if self.mutex.next.load(SeqCst) != serving {
+            wake_all(&self.mutex.serving);
+        }
+        yield_point();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::FairMutex;
+
+    #[test]
+    fn test_fair_mutex() {
+        let x = FairMutex::new(Vec::new());
+        let g = x.try_lock().unwrap();
+        assert!(x.try_lock().is_none());
+        thread::scope(|s| {
+            // Waiters are served in the order they asked.
+            for i in 0..4 {
+                let x = &x;
+                s.spawn(move || x.lock().push(i));
+                while x.next.load(std::sync::atomic::Ordering::SeqCst) != i + 2 {
+                    thread::yield_now();
+                }
+            }
+            drop(g);
+        });
+        assert_eq!(*x.lock(), [0, 1, 2, 3]);
+        assert!(!x.is_locked());
+
+        let x = FairMutex::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| (0..10_000).for_each(|_| *x.lock() += 1));
+            }
+        });
+        assert_eq!(*x.lock(), 40_000);
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    cell::UnsafeCell,
+    hint,
+    ops::{Deref, DerefMut},
+    sync::atomic::{
+        AtomicU8,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use crate::futex::yield_point;
+use crate::parking::{self, ParkResult, UnparkResult, DEFAULT_UNPARK_TOKEN};
+
+const LOCKED: u8 = 1; // bit of the lock
+const PARKED: u8 = 2; // bit of threads parked on the mutex
+
+// Token of a thread the lock is handed off to, it owns the lock already.
+const HANDOFF: usize = 1;
+
+/// A mutex of a single byte besides the value, its waiters park in the
+/// global table of `parking`, e.g. for one lock per row or node of a large
+/// data structure: `MicroMutex<u8>` takes 2 bytes where `Mutex<u8>` takes 8
+/// with default features.
+///
+/// It parks like Mutex, but has no timeout, handoff mode or diagnostics.
+///
+/// `MicroMutexGuard::unlock_fair` hands the lock directly to the first
+/// waiter, so a thread locking in a loop can't barge ahead of it.
+pub struct MicroMutex<T> {
+    state: AtomicU8,
+    value: UnsafeCell<T>,
+}
+
+/// Implement Sync if and only if T is Send.
+/// Only one thread access the &T at a time,
+/// so T is not required to be Sync.
+unsafe impl<T> Sync for MicroMutex<T> where T: Send {}
+
+impl<T> MicroMutex<T> {
+    /// Create a new mutex for given value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU8::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Whether the mutex is locked now, the answer may be stale at once.
+    pub fn is_locked(&self) -> bool {
+        self.state.load(Relaxed) & LOCKED != 0
+    }
+
+    /// Acquire lock guard if mutex is not locked,
+    /// otherwise block until the lock is released.
+    pub fn lock(&self) -> MicroMutexGuard<'_, T> {
+        yield_point();
+        if self
+            .state
+            .compare_exchange(0, LOCKED, Acquire, Relaxed)
+            .is_err()
+        {
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(self, "MicroMutex");
+            self.lock_contended();
+        }
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "MicroMutex");
+        MicroMutexGuard { mutex: self }
+    }
+
+    #[cold]
+    fn lock_contended(&self) {
+        let mut spin_count = crate::config::get().mutex_spin_iters;
+        let mut x = self.state.load(Relaxed);
+        loop {
+            // Take the lock if it's free, even if others are parked.
+            if x & LOCKED == 0 {
+                match self
+                    .state
+                    .compare_exchange_weak(x, x | LOCKED, Acquire, Relaxed)
+                {
+                    Ok(_) => return,
+                    Err(e) => x = e,
+                }
+                continue;
+            }
+
+            // Spin while nobody is parked.
+            if x & PARKED == 0 && spin_count > 0 {
+                spin_count -= 1;
+                hint::spin_loop();
+                x = self.state.load(Relaxed);
+                continue;
+            }
+
+            if x & PARKED == 0 {
+                if let Err(e) = self
+                    .state
+                    .compare_exchange_weak(x, x | PARKED, Relaxed, Relaxed)
+                {
+                    x = e;
+                    continue;
+                }
+            }
+
+            // Park only if the state is still locked with parked threads.
+            let validate = || self.state.load(Relaxed) == LOCKED | PARKED;
+            if let ParkResult::Unparked(HANDOFF) = parking::park(self.addr(), validate, None) {
+                return;
+            }
+            x = self.state.load(Relaxed);
+        }
+    }
+
+    // Waiters park on the state byte, which no other lock shares,
+    // unlike the address of the mutex which its value may start at.
+    fn addr(&self) -> usize {
+        &self.state as *const AtomicU8 as usize
+    }
+}
+
+/// A guard type can be acquired from MicroMutex lock method.
+pub struct MicroMutexGuard<'a, T> {
+    mutex: &'a MicroMutex<T>,
+}
+
+impl<T> Deref for MicroMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: There's only one guard of same mutex can be accessed at a time,
+        // it's safe to access the inner value by any shared reference.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MicroMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: There's only one guard of same mutex can be accessed at a time,
+        // it's safe to access the inner value with mutable reference by mutable reference.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> MicroMutexGuard<'_, T> {
+    /// Unlock by handing the lock to the first parked thread if any,
+    /// it holds the lock when it wakes up.
+    pub fn unlock_fair(this: Self) {
+        this.unlock(true);
+        std::mem::forget(this);
+    }
+
+    fn unlock(&self, fair: bool) {
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.mutex, "MicroMutex");
+        let state = &self.mutex.state;
+        if state.compare_exchange(LOCKED, 0, Release, Relaxed).is_err() {
+            // Threads are parked, update the state with the bucket locked
+            // so a thread about to park sees it.
+            parking::unpark_one(self.mutex.addr(), |result: UnparkResult| {
+                if result.unparked && fair {
+                    // Stay locked for the unparked thread.
+                    if !result.have_more {
+                        state.store(LOCKED, Relaxed);
+                    }
+                    return HANDOFF;
+                }
+                let parked = if result.have_more { PARKED } else { 0 };
+                state.store(parked, Release);
+                DEFAULT_UNPARK_TOKEN
+            });
+        }
+        yield_point();
+    }
+}
+
+impl<T> Drop for MicroMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.unlock(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem::size_of, thread};
+
+    use super::{MicroMutex, MicroMutexGuard};
+
+    #[test]
+    fn test_micro_mutex() {
+        assert_eq!(size_of::<MicroMutex<u8>>(), 2);
+        let x = MicroMutex::new(0);
+        thread::scope(|s| {
+            for i in 0..4 {
+                let x = &x;
+                s.spawn(move || {
+                    for _ in 0..10_000 {
+                        let mut g = x.lock();
+                        *g += 1;
+                        if i % 2 == 0 {
+                            MicroMutexGuard::unlock_fair(g);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock(), 40_000);
+    }
+}
@@ -1,21 +1,66 @@
 use std::{
     cell::UnsafeCell,
-    hint,
+    error::Error,
+    fmt, hint, mem,
     ops::{Deref, DerefMut},
     sync::atomic::AtomicU32,
     sync::atomic::Ordering::{Acquire, Relaxed, Release},
+    time::{Duration, Instant},
 };
 
-use atomic_wait::{wait, wake_one};
+use crate::futex::{wait, wait_timeout, wake_one, yield_point};
+use crate::parking::{self, ParkResult, UnparkResult, DEFAULT_UNPARK_TOKEN};
+#[cfg(feature = "registry")]
+use crate::registry::Name;
+use crate::time;
+
+mod adaptive;
+mod fair;
+mod micro;
+#[cfg(feature = "metrics")]
+pub use adaptive::AdaptiveMutexStats;
+pub use adaptive::{AcquireStrategy, AdaptiveMutex, AdaptiveMutexGuard};
+pub use fair::{FairMutex, FairMutexGuard};
+pub use micro::{MicroMutex, MicroMutexGuard};
+#[cfg(all(feature = "linux-pi", target_os = "linux"))]
+mod pi;
+#[cfg(all(feature = "linux-pi", target_os = "linux"))]
+pub use pi::{PiFutexMutex, PiFutexMutexGuard};
+#[cfg(feature = "poison")]
+mod poison;
+#[cfg(feature = "poison")]
+pub use poison::{LockResult, PoisonError, PoisonMutex, PoisonMutexGuard};
+#[cfg(feature = "tracing")]
+mod tracing;
+#[cfg(feature = "tracing")]
+pub use tracing::{set_stall_handler, set_stall_threshold, Stall};
 
 const MUTEX_UNLOCKED: u32 = 0; // unlocked
 const MUTEX_LOCKED: u32 = 1; // locked, no contention
 const MUTEX_CONTENTION: u32 = 2; // locked, other threads waiting
 
+// Token of a parked thread which is handed the lock on unlock.
+const HANDOFF: usize = 1;
+
+/// Spin budget of `Mutex::lock` before parking, unless tuned otherwise
+/// by `config::SyncConfig`.
+pub const DEFAULT_SPIN_ITERS: u32 = 100;
+
 /// A mutual-exclusive lock implementation.
+///
+/// Waiters park in the `parking` table keyed by the state word, so the
+/// mutex is the word and the value, and a condvar can requeue its waiters
+/// onto it. With the `ffi` feature they wait on the futex word instead,
+/// see `as_futex_word`.
 pub struct Mutex<T> {
-    // 0 if unlocked, 1 if locked.
+    // 0 if unlocked, 1 if locked, 2 if locked with waiters.
     state: AtomicU32,
+    // Whether unlock hands the lock to the longest waiter, see with_handoff.
+    handoff: bool,
+    #[cfg(feature = "registry")]
+    name: Name,
+    #[cfg(feature = "tracing")]
+    owner: tracing::Owner,
     value: UnsafeCell<T>,
 }
 
@@ -26,16 +71,99 @@ unsafe impl<T> Sync for Mutex<T> where T: Send {}
 
 impl<T> Mutex<T> {
     /// Create a new mutex for given value.
-    pub fn new(value: T) -> Self {
+    pub const fn new(value: T) -> Self {
+        Self::build(None, false, value)
+    }
+
+    /// Create a new mutex for given value, named in the registry
+    /// and diagnostics.
+    #[cfg(feature = "registry")]
+    pub const fn named(name: &'static str, value: T) -> Self {
+        Self::build(Some(name), false, value)
+    }
+
+    /// Create a new mutex for given value, whose contended unlock hands
+    /// the lock directly to the thread waiting longest instead of letting
+    /// everyone race for it.
+    ///
+    /// A plain Mutex lets a running thread barge in ahead of the woken
+    /// waiter, which keeps the lock busy and maximizes throughput, but a
+    /// waiter may lose the race over and over. With handoff no waiter
+    /// starves and the worst-case latency is bounded by the queue ahead of
+    /// it, at the cost of a context switch per contended unlock, during
+    /// which nobody runs the critical section (see `bench_handoff`).
+    ///
+    /// Waiters park in the `parking` table even with the `ffi` feature,
+    /// so foreign code locking the futex word of a handoff mutex isn't
+    /// supported.
+    pub const fn with_handoff(value: T) -> Self {
+        Self::build(None, true, value)
+    }
+
+    const fn build(name: Option<&'static str>, handoff: bool, value: T) -> Self {
+        #[cfg(not(feature = "registry"))]
+        let _ = name;
         Self {
             state: AtomicU32::new(0),
+            handoff,
+            #[cfg(feature = "registry")]
+            name: Name::new(name),
+            #[cfg(feature = "tracing")]
+            owner: tracing::Owner::new(),
             value: UnsafeCell::new(value),
         }
     }
 
+    /// The thread holding the mutex.
+    #[cfg(feature = "tracing")]
+    pub fn owner(&self) -> Option<std::thread::Thread> {
+        self.owner.get()
+    }
+
+    /// Name of the mutex given by `named`.
+    #[cfg(feature = "registry")]
+    pub fn name(&self) -> Option<&'static str> {
+        self.name.get()
+    }
+
+    /// Consume the mutex and return the value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// The futex word of the mutex, for foreign code locking the same mutex:
+    /// 0 if unlocked, 1 if locked, 2 if locked with waiters.
+    /// Lock by CAS 0 to 1, otherwise swap in 2 and wait on the word while
+    /// it's 2, until the swap returns 0. Unlock by swapping in 0, and wake
+    /// one waiter if it was 2. Waiting and waking are process private.
+    ///
+    /// Condvars wake all their waiters on notify_all with the feature,
+    /// instead of requeueing them onto the mutex.
+    ///
+    /// # Safety
+    ///
+    /// Foreign code must follow the protocol, and must not touch the value
+    /// while it's not holding the lock.
+    #[cfg(feature = "ffi")]
+    pub unsafe fn as_futex_word(&self) -> &AtomicU32 {
+        &self.state
+    }
+
+    /// Whether the mutex is locked now, the answer may be stale at once.
+    pub fn is_locked(&self) -> bool {
+        self.state.load(Relaxed) != MUTEX_UNLOCKED
+    }
+
     /// Acquire lock guard if mutex is not locked,
     /// otherwise block until the lock is released.
-    pub fn lock(&self) -> MutexGuard<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.lock_spin_then_park(crate::config::get().mutex_spin_iters)
+    }
+
+    /// Acquire lock guard like `lock`, but spin at most `spin_iters` times
+    /// on contention before parking, so call sites can tune the budget.
+    pub fn lock_spin_then_park(&self, spin_iters: u32) -> MutexGuard<'_, T> {
+        yield_point();
         // Skip atomic-wait if there is no contention.
         if self
             .state
@@ -44,33 +172,235 @@ impl<T> Mutex<T> {
         {
             // Slow path if lock-contention happens,
             // Spin lock or wait for waking.
-            Self::lock_contented(&self.state);
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(self, "Mutex");
+            #[cfg(all(feature = "metrics", feature = "registry"))]
+            let start = std::time::Instant::now();
+            self.lock_contented(spin_iters);
+            let guard = self.acquired();
+            #[cfg(all(feature = "metrics", feature = "registry"))]
+            self.name.contended(start);
+            return guard;
+        }
+        self.acquired()
+    }
+
+    /// Acquire lock guard like `lock`, but give up once the timeout elapses.
+    pub fn try_lock_for(&self, timeout: Duration) -> Result<MutexGuard<'_, T>, LockTimeoutError> {
+        match time::now().checked_add(timeout) {
+            Some(deadline) => self.try_lock_until(deadline),
+            None => Ok(self.lock()),
+        }
+    }
+
+    /// Acquire lock guard like `lock`, but give up once the deadline passes.
+    pub fn try_lock_until(&self, deadline: Instant) -> Result<MutexGuard<'_, T>, LockTimeoutError> {
+        yield_point();
+        if self
+            .state
+            .compare_exchange(MUTEX_UNLOCKED, MUTEX_LOCKED, Acquire, Relaxed)
+            .is_err()
+        {
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(self, "Mutex");
+            #[cfg(all(feature = "metrics", feature = "registry"))]
+            let start = std::time::Instant::now();
+            if !self.lock_contented_until(deadline) {
+                #[cfg(feature = "deadlock-detection")]
+                crate::deadlock::gave_up();
+                return Err(LockTimeoutError);
+            }
+            let guard = self.acquired();
+            #[cfg(all(feature = "metrics", feature = "registry"))]
+            self.name.contended(start);
+            return Ok(guard);
         }
+        Ok(self.acquired())
+    }
+
+    fn acquired(&self) -> MutexGuard<'_, T> {
+        #[cfg(feature = "registry")]
+        self.name.register(self);
+        #[cfg(feature = "tracing")]
+        self.owner.acquired();
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "Mutex");
         MutexGuard { mutex: self }
     }
 
-    #[cold]
-    fn lock_contented(state: &AtomicU32) {
-        let mut spin_count = 100;
-        while state.load(Relaxed) == MUTEX_LOCKED && spin_count > 0 {
-            spin_count -= 1;
-            hint::spin_loop();
+    /// Run f on the value with the lock elided by a hardware transaction:
+    /// f runs without writing the lock word, so elided sections touching
+    /// different data run in parallel. If the transaction aborts, e.g. on
+    /// a conflict or a thread really taking the lock, f runs again with
+    /// the lock taken, so it may run partly more than once, and its side
+    /// effects outside memory must be idempotent.
+    #[cfg(feature = "htm")]
+    pub fn elide<R>(&self, mut f: impl FnMut(&mut T) -> R) -> R {
+        let elided = crate::elision::elide(
+            || self.state.load(Relaxed) == MUTEX_UNLOCKED,
+            // Safety: the lock is free and stays so until the commit,
+            // a thread taking it aborts the transaction.
+            || f(unsafe { &mut *self.value.get() }),
+        );
+        match elided {
+            Some(r) => r,
+            None => f(&mut self.lock()),
         }
+    }
 
-        if state
+    // Spin while locked without waiters, then try to lock once. The state
+    // is checked with exponential backoff, so spinners leave the cache
+    // line to the holder, spin_count counts the spin hints.
+    fn spin_then_try_lock(&self, mut spin_count: u32) -> bool {
+        let state = &self.state;
+        let max_step = crate::config::get().backoff_spin_limit;
+        let mut step = 0;
+        while state.load(Relaxed) == MUTEX_LOCKED && spin_count > 0 {
+            let spins = (1u32 << step).min(spin_count);
+            (0..spins).for_each(|_| hint::spin_loop());
+            spin_count -= spins;
+            step = (step + 1).min(max_step);
+        }
+        state
             .compare_exchange(MUTEX_UNLOCKED, MUTEX_LOCKED, Acquire, Relaxed)
             .is_ok()
-        {
-            return;
+    }
+
+    #[cold]
+    fn lock_contented_until(&self, deadline: Instant) -> bool {
+        if self.spin_then_try_lock(crate::config::get().mutex_spin_iters) {
+            return true;
         }
+        self.lock_parked(Some(deadline))
+    }
+
+    #[cold]
+    fn lock_contented(&self, spin_count: u32) {
+        if !self.spin_then_try_lock(spin_count) {
+            self.lock_parked(None);
+        }
+    }
+
+    // Wait in FIFO order until the lock is found unlocked or handed over
+    // by unlock, return false if the deadline passes first. The swap comes
+    // before giving up, so a wake taken by this thread is never lost: the
+    // swap either locks or leaves 2 for the holder to wake another.
+    fn lock_parked(&self, deadline: Option<Instant>) -> bool {
+        #[cfg(feature = "tracing")]
+        let (start, mut reported) = (Instant::now(), false);
+        while self.state.swap(MUTEX_CONTENTION, Acquire) != MUTEX_UNLOCKED {
+            let timeout = match deadline {
+                Some(deadline) => match deadline.saturating_duration_since(time::now()) {
+                    timeout if timeout.is_zero() => return false,
+                    timeout => Some(timeout),
+                },
+                #[cfg(feature = "tracing")]
+                None => self.owner.stall_timeout(
+                    start,
+                    &mut reported,
+                    self as *const Self as usize,
+                    self.name.get(),
+                ),
+                #[cfg(not(feature = "tracing"))]
+                None => None,
+            };
+            if self.wait_contended(timeout) {
+                return true;
+            }
+        }
+        true
+    }
+
+    // Block while the mutex is locked with waiters for at most timeout,
+    // return true if the unlocking thread handed the lock over.
+    fn wait_contended(&self, timeout: Option<Duration>) -> bool {
+        if cfg!(feature = "ffi") && !self.handoff {
+            match timeout {
+                Some(timeout) => wait_timeout(&self.state, MUTEX_CONTENTION, timeout),
+                None => wait(&self.state, MUTEX_CONTENTION),
+            }
+            return false;
+        }
+        // Park only if the state is still locked with waiters, unlock
+        // updates it before unparking with the bucket locked.
+        let validate = || self.state.load(Relaxed) == MUTEX_CONTENTION;
+        let result = parking::park(self.key(), validate, timeout);
+        result == ParkResult::Unparked(HANDOFF)
+    }
+
+    // Wake a waiter of the mutex unlocked with waiters.
+    fn wake_one(&self) {
+        if cfg!(feature = "ffi") {
+            wake_one(&self.state);
+        } else {
+            parking::unpark_one(self.key(), |_| DEFAULT_UNPARK_TOKEN);
+        }
+    }
 
-        while state.swap(MUTEX_CONTENTION, Acquire) != MUTEX_UNLOCKED {
-            // Wait until lock state is no longer MUTEX_CONTENTION.
-            wait(state, MUTEX_CONTENTION);
+    // Threads are parked, hand the lock to the first one if any.
+    fn unlock_handoff(&self) {
+        let state = &self.state;
+        parking::unpark_one(self.key(), |result: UnparkResult| {
+            if result.unparked {
+                // Stay locked for the unparked thread.
+                if !result.have_more {
+                    state.store(MUTEX_LOCKED, Relaxed);
+                }
+                return HANDOFF;
+            }
+            state.store(MUTEX_UNLOCKED, Release);
+            DEFAULT_UNPARK_TOKEN
+        });
+    }
+
+    // Waiters park on the state word, which no other lock shares,
+    // unlike the address of the mutex which its value may start at.
+    fn key(&self) -> usize {
+        &self.state as *const AtomicU32 as usize
+    }
+
+    /// The state word of the mutex, the key its waiters park on.
+    pub(crate) fn word(&self) -> &AtomicU32 {
+        &self.state
+    }
+
+    /// Lock again after a condvar wait ending with result. The waiter may
+    /// have been requeued onto the mutex and handed the lock, otherwise
+    /// it locks as contended, since others may be requeued behind it.
+    pub(crate) fn relock(&self, result: ParkResult) -> MutexGuard<'_, T> {
+        if result == ParkResult::Unparked(HANDOFF) {
+            return self.acquired();
         }
+        if self.state.swap(MUTEX_CONTENTION, Acquire) != MUTEX_UNLOCKED {
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(self, "Mutex");
+            self.lock_parked(None);
+        }
+        self.acquired()
     }
 }
 
+/// Mark the mutex of the state word contended if it's locked, so its
+/// unlock unparks the waiters a condvar requeues onto it.
+pub(crate) fn mark_contended(word: &AtomicU32) {
+    let _ = word.fetch_update(Relaxed, Relaxed, |state| {
+        (state != MUTEX_UNLOCKED).then_some(MUTEX_CONTENTION)
+    });
+}
+
+/// Error returned by `Mutex::try_lock_for` and `Mutex::try_lock_until`
+/// if the lock isn't acquired in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockTimeoutError;
+
+impl fmt::Display for LockTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out locking the mutex")
+    }
+}
+
+impl Error for LockTimeoutError {}
+
 /// A guard type can be acquired from Mutex lock method.
 pub struct MutexGuard<'a, T> {
     pub(crate) mutex: &'a Mutex<T>,
@@ -93,20 +423,52 @@ impl<T> DerefMut for MutexGuard<'_, T> {
     }
 }
 
-impl<T> Drop for MutexGuard<'_, T> {
-    fn drop(&mut self) {
-        // Release the lock
-        if self.mutex.state.swap(MUTEX_UNLOCKED, Release) == MUTEX_CONTENTION {
+impl<T> MutexGuard<'_, T> {
+    /// Unlock and lock again if other threads are waiting for the mutex,
+    /// a cheap point for a long-running holder to let them in.
+    pub fn bump(this: &mut Self) {
+        if this.mutex.state.load(Relaxed) != MUTEX_CONTENTION {
+            return;
+        }
+        this.unlock();
+        // Let the woken waiter take the lock before locking again.
+        std::thread::yield_now();
+        // The old guard is unlocked already, don't unlock it again.
+        let mutex = this.mutex;
+        mem::forget(mem::replace(this, mutex.lock()));
+    }
+
+    fn unlock(&self) {
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.mutex, "Mutex");
+        #[cfg(feature = "tracing")]
+        self.mutex.owner.released();
+        if self.mutex.handoff {
+            let state = &self.mutex.state;
+            if state
+                .compare_exchange(MUTEX_LOCKED, MUTEX_UNLOCKED, Release, Relaxed)
+                .is_err()
+            {
+                self.mutex.unlock_handoff();
+            }
+        } else if self.mutex.state.swap(MUTEX_UNLOCKED, Release) == MUTEX_CONTENTION {
             // wake any one blocked thread if lock-contention.
-            wake_one(&self.mutex.state);
+            self.mutex.wake_one();
         }
+        yield_point();
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.unlock();
     }
 }
 
 #[cfg(test)]
 mod tests {
     #[allow(unused_imports)]
-    use super::Mutex;
+    use super::{Mutex, MutexGuard};
     #[allow(unused_imports)]
     use std::thread;
 
@@ -126,4 +488,201 @@ mod tests {
             assert!(g.as_slice() == [1, 2, 2] || g.as_slice() == [2, 2, 1]);
         }
     }
+
+    #[test]
+    fn test_mutex_bump() {
+        let x = Mutex::new(Vec::new());
+        let mut g = x.lock();
+        // Nobody waits, so it keeps the lock.
+        MutexGuard::bump(&mut g);
+        g.push(0);
+        thread::scope(|s| {
+            s.spawn(|| x.lock().push(1));
+            while x.state.load(std::sync::atomic::Ordering::Relaxed) != super::MUTEX_CONTENTION {
+                thread::yield_now();
+            }
+            while g.len() == 1 {
+                MutexGuard::bump(&mut g);
+            }
+            g.push(2);
+        });
+        drop(g);
+        assert_eq!(*x.lock(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_spin_then_park() {
+        let x = Mutex::new(0);
+        assert!(!x.is_locked());
+        let g = x.lock();
+        assert!(x.is_locked());
+        drop(g);
+        thread::scope(|s| {
+            let x = &x;
+            for spin_iters in [0, 10, 10_000] {
+                s.spawn(move || {
+                    for _ in 0..10_000 {
+                        *x.lock_spin_then_park(spin_iters) += 1;
+                    }
+                });
+            }
+        });
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock(), 30_000);
+    }
+
+    #[test]
+    fn test_try_lock_for() {
+        use super::LockTimeoutError;
+        use std::time::{Duration, Instant};
+
+        let x = Mutex::new(0);
+        *x.try_lock_for(Duration::ZERO).unwrap() += 1;
+        let g = x.lock();
+        let start = Instant::now();
+        assert!(matches!(
+            x.try_lock_for(Duration::from_millis(20)),
+            Err(LockTimeoutError)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        thread::scope(|s| {
+            // Timed waiters giving up never swallow the wake of a waiter.
+            for _ in 0..4 {
+                s.spawn(|| while x.try_lock_for(Duration::from_micros(50)).is_err() {});
+            }
+            s.spawn(|| *x.lock() += 1);
+            thread::sleep(Duration::from_millis(10));
+            drop(g);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while *x.try_lock_until(deadline).unwrap() != 2 {
+                thread::yield_now();
+            }
+        });
+        assert!(!x.is_locked());
+    }
+
+    #[test]
+    fn test_mutex_handoff() {
+        use std::sync::atomic::Ordering::Relaxed;
+        use std::time::Duration;
+
+        let x = Mutex::with_handoff(Vec::new());
+        let g = x.lock();
+        thread::scope(|s| {
+            s.spawn(|| x.lock().push(1));
+            while x.state.load(Relaxed) != super::MUTEX_CONTENTION {
+                thread::yield_now();
+            }
+            thread::sleep(Duration::from_millis(20));
+            // The parked waiter owns the lock once unlocked, no barging.
+            drop(g);
+            x.lock().push(2);
+        });
+        assert_eq!(*x.lock(), [1, 2]);
+
+        let x = Mutex::with_handoff(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| (0..10_000).for_each(|_| *x.lock() += 1));
+            }
+            s.spawn(|| {
+                for _ in 0..1_000 {
+                    if let Ok(mut g) = x.try_lock_for(Duration::from_micros(10)) {
+                        *g += 1;
+                        break;
+                    }
+                }
+            });
+        });
+        assert!(!x.is_locked());
+        assert!((40_000..=40_001).contains(&*x.lock()));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_stall_report() {
+        use super::{set_stall_handler, set_stall_threshold, Stall};
+        use std::time::Duration;
+
+        static STALLS: std::sync::Mutex<Vec<Stall>> = std::sync::Mutex::new(Vec::new());
+        set_stall_handler(|stall| STALLS.lock().unwrap().push(stall.clone()));
+        set_stall_threshold(Duration::from_millis(20));
+
+        let x = Mutex::named("stalled", 0);
+        let addr = &x as *const _ as usize;
+        let g = x.lock();
+        assert_eq!(x.owner().unwrap().id(), thread::current().id());
+        thread::scope(|s| {
+            let waiter = s.spawn(|| *x.lock() += 1);
+            thread::sleep(Duration::from_millis(100));
+            drop(g);
+            waiter.join().unwrap();
+        });
+        set_stall_threshold(Duration::from_secs(1));
+        assert!(x.owner().is_none());
+
+        let stalls = STALLS.lock().unwrap();
+        let stall = stalls.iter().find(|s| s.lock == addr).unwrap();
+        assert_eq!(stall.name, Some("stalled"));
+        assert!(stall.waited >= Duration::from_millis(20));
+        assert_eq!(stall.owner.as_ref().unwrap().id(), thread::current().id());
+        assert!(stall.to_string().contains("\"stalled\""));
+        // Reported once per lock.
+        assert_eq!(stalls.iter().filter(|s| s.lock == addr).count(), 1);
+    }
+
+    #[cfg(all(feature = "ffi", target_os = "linux"))]
+    #[test]
+    fn test_mutex_futex_word() {
+        use std::sync::atomic::{
+            AtomicU32,
+            Ordering::{Acquire, Relaxed, Release},
+        };
+
+        // Lock and unlock like foreign code by raw futex syscalls.
+        fn futex(word: &AtomicU32, op: i32, value: u32) {
+            unsafe {
+                libc::syscall(
+                    libc::SYS_futex,
+                    word as *const AtomicU32,
+                    op | libc::FUTEX_PRIVATE_FLAG,
+                    value,
+                    std::ptr::null::<libc::timespec>(),
+                );
+            }
+        }
+        fn foreign_lock(word: &AtomicU32) {
+            if word.compare_exchange(0, 1, Acquire, Relaxed).is_ok() {
+                return;
+            }
+            while word.swap(2, Acquire) != 0 {
+                futex(word, libc::FUTEX_WAIT, 2);
+            }
+        }
+        fn foreign_unlock(word: &AtomicU32) {
+            if word.swap(0, Release) == 2 {
+                futex(word, libc::FUTEX_WAKE, 1);
+            }
+        }
+
+        let x = Mutex::new(0);
+        let word = unsafe { x.as_futex_word() };
+        thread::scope(|s| {
+            s.spawn(|| {
+                let x = &x;
+                for _ in 0..1000 {
+                    foreign_lock(word);
+                    unsafe { *x.value.get() += 1 };
+                    foreign_unlock(word);
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    *x.lock() += 1;
+                }
+            });
+        });
+        assert_eq!(*x.lock(), 2000);
+        assert_eq!(word.load(Relaxed), 0);
+    }
 }
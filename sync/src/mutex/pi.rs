@@ -0,0 +1,175 @@
+use std::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{
+        AtomicU32,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+thread_local! {
+    static TID: u32 = unsafe { libc::syscall(libc::SYS_gettid) as u32 };
+}
+
+/// A mutex on the kernel priority-inheritance futex: a thread blocked on it
+/// lends its priority to the owner, so real-time threads don't suffer
+/// priority inversion.
+///
+/// The kernel owns the wait queue, so it's not driven by the `testutil`
+/// scheduler, and the guard must be dropped by the locking thread.
+pub struct PiFutexMutex<T> {
+    // TID of the owner, 0 if unlocked, with FUTEX_WAITERS set by the kernel.
+    state: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for PiFutexMutex<T> where T: Send {}
+
+impl<T> PiFutexMutex<T> {
+    /// Create a new mutex for given value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Acquire lock guard if mutex is not locked, otherwise block until
+    /// the kernel hands the lock over, lending our priority to the owner.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mutex is relocked by its owner.
+    pub fn lock(&self) -> PiFutexMutexGuard<'_, T> {
+        let tid = TID.with(|tid| *tid);
+        if self
+            .state
+            .compare_exchange(0, tid, Acquire, Relaxed)
+            .is_err()
+        {
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(self, "PiFutexMutex");
+            // The kernel sets the state to our TID once it hands the lock over.
+            while self.futex(libc::FUTEX_LOCK_PI) != 0 {
+                let error = std::io::Error::last_os_error();
+                match error.raw_os_error() {
+                    // EAGAIN: the owner is exiting, retry.
+                    Some(libc::EINTR | libc::EAGAIN) => continue,
+                    Some(libc::EDEADLK) => panic!("PiFutexMutex relocked by its owner"),
+                    _ => panic!("FUTEX_LOCK_PI failed: {}", error),
+                }
+            }
+        }
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "PiFutexMutex");
+        PiFutexMutexGuard {
+            lock: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Acquire lock guard if mutex is not locked, otherwise return None.
+    pub fn try_lock(&self) -> Option<PiFutexMutexGuard<'_, T>> {
+        let tid = TID.with(|tid| *tid);
+        self.state.compare_exchange(0, tid, Acquire, Relaxed).ok()?;
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "PiFutexMutex");
+        Some(PiFutexMutexGuard {
+            lock: self,
+            _not_send: PhantomData,
+        })
+    }
+
+    fn unlock(&self) {
+        let tid = TID.with(|tid| *tid);
+        // There're waiters if the kernel set FUTEX_WAITERS, let it pick the next owner.
+        if self
+            .state
+            .compare_exchange(tid, 0, Release, Relaxed)
+            .is_err()
+        {
+            self.futex(libc::FUTEX_UNLOCK_PI);
+        }
+    }
+
+    fn futex(&self, op: i32) -> libc::c_long {
+        // Safety: the futex word outlives the syscall, PI operations
+        // take no value and a null timeout blocks forever.
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                &self.state as *const AtomicU32,
+                op | libc::FUTEX_PRIVATE_FLAG,
+                0,
+                std::ptr::null::<libc::timespec>(),
+            )
+        }
+    }
+}
+
+/// A guard of PiFutexMutex, it's not Send since the kernel
+/// requires the owner to unlock.
+pub struct PiFutexMutexGuard<'a, T> {
+    lock: &'a PiFutexMutex<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T> Sync for PiFutexMutexGuard<'_, T> where T: Sync {}
+
+impl<T> Deref for PiFutexMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Safety: the guard is the only accessor until it's dropped.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for PiFutexMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: the guard is the only accessor until it's dropped.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for PiFutexMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.lock, "PiFutexMutex");
+        self.lock.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::PiFutexMutex;
+
+    #[test]
+    fn test_pi_futex_mutex() {
+        let x = PiFutexMutex::new(0);
+        let g = x.lock();
+        thread::scope(|s| {
+            s.spawn(|| assert!(x.try_lock().is_none())).join().unwrap();
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock() += 1;
+                    }
+                });
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+            drop(g);
+        });
+        assert_eq!(*x.lock(), 4000);
+    }
+
+    #[test]
+    #[should_panic(expected = "relocked by its owner")]
+    fn test_pi_futex_mutex_relock() {
+        let x = PiFutexMutex::new(0);
+        let _g = x.lock();
+        let _ = x.lock();
+    }
+}
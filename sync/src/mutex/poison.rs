@@ -0,0 +1,160 @@
+use std::{
+    error::Error,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{
+        AtomicBool,
+        Ordering::{Acquire, Release},
+    },
+};
+
+use super::{Mutex, MutexGuard};
+
+/// A Mutex poisoned once a thread panics holding it, like std's, so later
+/// lockers learn the value may be left half updated.
+///
+/// Mutex stays lean without the check, this wraps it for code which wants
+/// to notice broken invariants instead of repairing them (see
+/// `SpinLock::with_repair`).
+pub struct PoisonMutex<T> {
+    mutex: Mutex<T>,
+    poisoned: AtomicBool,
+}
+
+/// Error returned by `PoisonMutex::lock` if the mutex is poisoned,
+/// carrying the guard, as the lock is acquired anyway.
+pub struct PoisonError<G>(pub G);
+
+impl<G> PoisonError<G> {
+    /// The guard, to use the value regardless.
+    pub fn into_inner(self) -> G {
+        self.0
+    }
+}
+
+impl<G> fmt::Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<G> fmt::Display for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mutex poisoned by a panic of its holder")
+    }
+}
+
+impl<G> Error for PoisonError<G> {}
+
+/// The result of locking a PoisonMutex.
+pub type LockResult<'a, T> = Result<PoisonMutexGuard<'a, T>, PoisonError<PoisonMutexGuard<'a, T>>>;
+
+impl<T> PoisonMutex<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            mutex: Mutex::new(value),
+            poisoned: AtomicBool::new(false),
+        }
+    }
+
+    /// Acquire lock guard like `Mutex::lock`, failing with the guard if a
+    /// holder panicked before.
+    pub fn lock(&self) -> LockResult<'_, T> {
+        let guard = PoisonMutexGuard {
+            lock: self,
+            panicking: std::thread::panicking(),
+            guard: self.mutex.lock(),
+        };
+        if self.is_poisoned() {
+            return Err(PoisonError(guard));
+        }
+        Ok(guard)
+    }
+
+    /// Whether a holder panicked, the answer may be stale at once.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Acquire)
+    }
+
+    /// Mark the value consistent again, e.g. after fixing it up through
+    /// the guard of the PoisonError.
+    pub fn clear_poison(&self) {
+        self.poisoned.store(false, Release);
+    }
+
+    /// Take the value, failing with it if the mutex is poisoned.
+    pub fn into_inner(self) -> Result<T, PoisonError<T>> {
+        let poisoned = self.is_poisoned();
+        let value = self.mutex.into_inner();
+        if poisoned {
+            return Err(PoisonError(value));
+        }
+        Ok(value)
+    }
+}
+
+/// A guard of PoisonMutex, poisoning it if dropped by a panic.
+pub struct PoisonMutexGuard<'a, T> {
+    lock: &'a PoisonMutex<T>,
+    // Whether the thread was already panicking when the lock was acquired.
+    panicking: bool,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<T> Deref for PoisonMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for PoisonMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PoisonMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for PoisonMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Poisoned before the mutex is unlocked by the inner guard.
+        if !self.panicking && std::thread::panicking() {
+            self.lock.poisoned.store(true, Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::PoisonMutex;
+
+    #[test]
+    fn test_poison_mutex() {
+        let x = PoisonMutex::new((0, 0));
+        *x.lock().unwrap() = (1, 1);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut g = x.lock().unwrap();
+            g.0 += 1;
+            panic!("broken invariant");
+        }));
+        assert!(result.is_err());
+        assert!(x.is_poisoned());
+
+        // Still locked and unlocked, the error gives the guard.
+        let mut g = x.lock().unwrap_err().into_inner();
+        assert_eq!(*g, (2, 1));
+        g.1 += 1;
+        drop(g);
+        assert!(x.lock().is_err());
+        x.clear_poison();
+        assert_eq!(*x.lock().unwrap(), (2, 2));
+        assert_eq!(x.into_inner().unwrap(), (2, 2));
+    }
+}
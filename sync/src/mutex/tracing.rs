@@ -0,0 +1,130 @@
+use std::{
+    backtrace::Backtrace,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc, Mutex, RwLock,
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+// Nanoseconds a lock() blocks before it's reported, 1 second by default.
+static STALL_THRESHOLD: AtomicU64 = AtomicU64::new(1_000_000_000);
+static STALL_HANDLER: RwLock<fn(&Stall)> = RwLock::new(print_stall);
+
+/// Set how long a blocked `Mutex::lock` waits before it's reported.
+pub fn set_stall_threshold(threshold: Duration) {
+    STALL_THRESHOLD.store(threshold.as_nanos() as u64, Relaxed);
+}
+
+/// Set the handler of stalls, which prints them to stderr by default.
+pub fn set_stall_handler(handler: fn(&Stall)) {
+    *STALL_HANDLER.write().unwrap_or_else(|e| e.into_inner()) = handler;
+}
+
+fn print_stall(stall: &Stall) {
+    eprintln!("{}", stall);
+}
+
+/// A `Mutex::lock` blocked longer than the stall threshold.
+#[derive(Debug, Clone)]
+pub struct Stall {
+    /// Address of the mutex.
+    pub lock: usize,
+    /// Name of the mutex given by `Mutex::named`.
+    pub name: Option<&'static str>,
+    /// The blocked thread.
+    pub waiter: Thread,
+    /// How long the thread has been blocked.
+    pub waited: Duration,
+    /// The thread holding the mutex, if it's still held.
+    pub owner: Option<Thread>,
+    /// Where the owner acquired the mutex, captured if enabled by
+    /// `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`.
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl fmt::Display for Stall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "thread {:?} blocked on mutex {:#x}",
+            self.waiter.id(),
+            self.lock
+        )?;
+        if let Some(name) = self.name {
+            write!(f, " {:?}", name)?;
+        }
+        write!(f, " for {:?}", self.waited)?;
+        match &self.owner {
+            Some(owner) => write!(f, ", held by thread {:?}", owner.id())?,
+            None => write!(f, ", owner unknown")?,
+        }
+        if let Some(backtrace) = &self.backtrace {
+            write!(f, ", acquired at:\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// The owner of a mutex.
+pub(super) struct Owner {
+    owner: Mutex<Option<(Thread, Arc<Backtrace>)>>,
+}
+
+impl Owner {
+    pub(super) const fn new() -> Self {
+        Self {
+            owner: Mutex::new(None),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<(Thread, Arc<Backtrace>)>> {
+        self.owner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(super) fn acquired(&self) {
+        *self.lock() = Some((thread::current(), Arc::new(Backtrace::capture())));
+    }
+
+    pub(super) fn released(&self) {
+        *self.lock() = None;
+    }
+
+    pub(super) fn get(&self) -> Option<Thread> {
+        self.lock().as_ref().map(|(thread, _)| thread.clone())
+    }
+
+    /// The timeout of the next wait of a lock blocked since start, up to
+    /// the stall threshold, and report the stall once it's exceeded.
+    pub(super) fn stall_timeout(
+        &self,
+        start: Instant,
+        reported: &mut bool,
+        lock: usize,
+        name: Option<&'static str>,
+    ) -> Option<Duration> {
+        let threshold = Duration::from_nanos(STALL_THRESHOLD.load(Relaxed));
+        let waited = start.elapsed();
+        if *reported {
+            return None;
+        }
+        if waited < threshold {
+            return Some(threshold - waited);
+        }
+        *reported = true;
+        let owner = self.lock().clone();
+        let stall = Stall {
+            lock,
+            name,
+            waiter: thread::current(),
+            waited,
+            owner: owner.as_ref().map(|(thread, _)| thread.clone()),
+            backtrace: owner.map(|(_, backtrace)| backtrace),
+        };
+        let handler = *STALL_HANDLER.read().unwrap_or_else(|e| e.into_inner());
+        handler(&stall);
+        None
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::mutex::Mutex;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// An event which callbacks are registered on, each runs exactly once when
+/// the event fires, e.g. shutdown hooks or cache invalidation fan-out.
+/// Clones share the same event.
+#[derive(Clone)]
+pub struct Notifier {
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    fired: bool,
+    // Callbacks by the order they're registered.
+    callbacks: BTreeMap<u64, Callback>,
+    next_id: u64,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Register a callback run by the firing thread, or at once by the
+    /// current thread if the event has fired.
+    pub fn register(&self, f: impl FnOnce() + Send + 'static) -> CallbackHandle {
+        let mut state = self.inner.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        if state.fired {
+            drop(state);
+            f();
+        } else {
+            state.callbacks.insert(id, Box::new(f));
+        }
+        CallbackHandle {
+            inner: Arc::clone(&self.inner),
+            id,
+        }
+    }
+
+    /// Fire the event and run the callbacks by the order they're registered,
+    /// return false if it has fired.
+    pub fn fire(&self) -> bool {
+        let mut state = self.inner.lock();
+        if state.fired {
+            return false;
+        }
+        state.fired = true;
+        let callbacks = std::mem::take(&mut state.callbacks);
+        // Callbacks may register more callbacks, which run at once.
+        drop(state);
+        callbacks.into_values().for_each(|f| f());
+        true
+    }
+
+    pub fn is_fired(&self) -> bool {
+        self.inner.lock().fired
+    }
+}
+
+/// A handle of a registered callback.
+pub struct CallbackHandle {
+    inner: Arc<Mutex<State>>,
+    id: u64,
+}
+
+impl CallbackHandle {
+    /// Unregister the callback, return false if it has run or been cancelled.
+    pub fn cancel(&self) -> bool {
+        self.inner.lock().callbacks.remove(&self.id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::Notifier;
+
+    #[test]
+    fn test_notifier() {
+        let notifier = Notifier::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        thread::scope(|s| {
+            for i in 0..4 {
+                let (notifier, log) = (notifier.clone(), Arc::clone(&log));
+                s.spawn(move || notifier.register(move || log.lock().unwrap().push(i)));
+            }
+        });
+        let log2 = Arc::clone(&log);
+        let cancelled = notifier.register(move || log2.lock().unwrap().push(100));
+        assert!(cancelled.cancel());
+        assert!(!notifier.is_fired());
+
+        assert!(notifier.fire());
+        assert!(!notifier.fire());
+        let mut fired = log.lock().unwrap().clone();
+        fired.sort();
+        assert_eq!(fired, [0, 1, 2, 3]);
+
+        // Registered after firing, it runs at once.
+        let log2 = Arc::clone(&log);
+        let handle = notifier.register(move || log2.lock().unwrap().push(4));
+        assert_eq!(log.lock().unwrap().last(), Some(&4));
+        assert!(!handle.cancel());
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    cell::UnsafeCell,
+    error::Error,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{
+        AtomicU32,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use crate::futex::{wait, wake_all, wake_one};
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+const CONTENDED: u32 = 2; // locked, other writers waiting
+const FROZEN: u32 = 3;
+
+/// A cell mutable through a guard until it's frozen, then its reads are
+/// lock-free and writes are rejected, e.g. for state built at startup then
+/// shared by every thread.
+pub struct OnceRefCell<T> {
+    state: AtomicU32, // UNLOCKED, LOCKED or CONTENDED by a writer, or FROZEN.
+    value: UnsafeCell<T>,
+}
+
+/// Writers access the value one at a time, readers share it after freezing.
+unsafe impl<T> Sync for OnceRefCell<T> where T: Send + Sync {}
+
+/// Error returned by writing a frozen cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frozen;
+
+impl fmt::Display for Frozen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cell is frozen")
+    }
+}
+
+impl Error for Frozen {}
+
+impl<T> OnceRefCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Lock the value for writing, blocking other writers,
+    /// return Frozen if the cell is frozen.
+    pub fn write(&self) -> Result<OnceRefGuard<'_, T>, Frozen> {
+        // Acquire on failure too, the value frozen is read once FROZEN is seen.
+        let state = &self.state;
+        let Err(mut x) = state.compare_exchange(UNLOCKED, LOCKED, Acquire, Acquire) else {
+            return Ok(OnceRefGuard { cell: self });
+        };
+        loop {
+            match x {
+                FROZEN => return Err(Frozen),
+                // Lock as contended, others may still be waiting.
+                UNLOCKED => match state.compare_exchange(UNLOCKED, CONTENDED, Acquire, Acquire) {
+                    Ok(_) => return Ok(OnceRefGuard { cell: self }),
+                    Err(e) => x = e,
+                },
+                LOCKED => match state.compare_exchange(LOCKED, CONTENDED, Relaxed, Acquire) {
+                    Ok(_) => x = CONTENDED,
+                    Err(e) => x = e,
+                },
+                _ => {
+                    wait(state, CONTENDED);
+                    x = state.load(Acquire);
+                }
+            }
+        }
+    }
+
+    /// Freeze the cell and return the value, waiting for the writer if any.
+    pub fn freeze(&self) -> &T {
+        if let Ok(guard) = self.write() {
+            std::mem::forget(guard);
+            // Waiting writers all fail now.
+            if self.state.swap(FROZEN, Release) == CONTENDED {
+                wake_all(&self.state);
+            }
+        }
+        // Safety: the cell is frozen, nobody mutates it anymore.
+        unsafe { &*self.value.get() }
+    }
+
+    /// Get the value if the cell is frozen.
+    pub fn get(&self) -> Option<&T> {
+        if self.is_frozen() {
+            // Safety: the cell is frozen, nobody mutates it anymore.
+            Some(unsafe { &*self.value.get() })
+        } else {
+            None
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.state.load(Acquire) == FROZEN
+    }
+
+    /// Get the mutable value, frozen or not, it's unique by &mut self.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T> Deref for OnceRefCell<T> {
+    type Target = T;
+
+    /// Read the frozen value, panic if the cell is not frozen.
+    fn deref(&self) -> &T {
+        self.get().expect("read an OnceRefCell before it's frozen")
+    }
+}
+
+/// A guard writing the value of an unfrozen OnceRefCell.
+pub struct OnceRefGuard<'a, T> {
+    cell: &'a OnceRefCell<T>,
+}
+
+impl<T> Deref for OnceRefGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Safety: the guard is the only accessor until it's dropped.
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T> DerefMut for OnceRefGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: the guard is the only accessor until it's dropped.
+        unsafe { &mut *self.cell.value.get() }
+    }
+}
+
+impl<T> Drop for OnceRefGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.cell.state.swap(UNLOCKED, Release) == CONTENDED {
+            wake_one(&self.cell.state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, thread};
+
+    use super::{Frozen, OnceRefCell};
+
+    #[test]
+    fn test_once_ref_cell() {
+        let cell = OnceRefCell::new(HashMap::new());
+        assert!(cell.get().is_none());
+        thread::scope(|s| {
+            for i in 0..4 {
+                let cell = &cell;
+                s.spawn(move || {
+                    cell.write().unwrap().insert(i, i * 2);
+                });
+            }
+        });
+        let frozen = cell.freeze();
+        assert_eq!(frozen.len(), 4);
+        assert_eq!(cell.write().err(), Some(Frozen));
+        thread::scope(|s| {
+            for i in 0..4 {
+                let cell = &cell;
+                s.spawn(move || assert_eq!(cell[&i], i * 2));
+            }
+        });
+        assert_eq!(cell.freeze().len(), 4);
+
+        let cell = OnceRefCell::new(0);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *cell));
+        assert!(result.is_err());
+    }
+}
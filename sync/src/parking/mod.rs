@@ -0,0 +1,340 @@
+//! A global table of wait queues keyed by address, so a lock needs no
+//! futex word of its own, e.g. a lock of one byte, and waiters can be
+//! handed the lock directly or moved between queues without waking.
+//!
+//! Each address hashes to one of a fixed number of buckets, a bucket
+//! holds the threads parked on all of its addresses in FIFO order.
+//! The callbacks run while the bucket is locked, so they must not park
+//! or unpark themselves.
+//!
+//! The futex metrics of a thread parking or unparking are counted for the
+//! primitive calling in, e.g. `mutex`. Each waiter sleeps on a word of its
+//! own, so the wakes of `coalesce_wakes` never merge here.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{
+            AtomicU32, AtomicUsize,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use crate::futex::{wait, wait_timeout, wake_one};
+use crate::time;
+
+const BUCKET_BITS: u32 = 6;
+const BUCKETS: usize = 1 << BUCKET_BITS;
+
+const PARKED: u32 = 0;
+const UNPARKED: u32 = 1;
+
+/// Token given by `unpark_one` and `unpark_all` to the unparked thread,
+/// e.g. to tell it the lock is handed off to it.
+pub type UnparkToken = usize;
+
+/// Default token of an unparked thread.
+pub const DEFAULT_UNPARK_TOKEN: UnparkToken = 0;
+
+/// Result of `park`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParkResult {
+    /// Unparked with the token of the unparking thread.
+    Unparked(UnparkToken),
+    /// The validate callback returned false, the thread didn't park.
+    Invalid,
+    /// The timeout passed before the thread is unparked.
+    TimedOut,
+}
+
+/// Result of `unpark_one`, also given to its callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UnparkResult {
+    /// Whether a thread is unparked.
+    pub unparked: bool,
+    /// Whether threads are still parked on the address.
+    pub have_more: bool,
+}
+
+/// Result of `unpark_requeue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RequeueResult {
+    /// Whether a thread is unparked.
+    pub unparked: bool,
+    /// Count of threads moved to the other address.
+    pub requeued: usize,
+}
+
+struct Waiter {
+    // Address the thread is parked on, changed by requeueing.
+    key: AtomicUsize,
+    state: AtomicU32,
+    token: AtomicUsize,
+}
+
+type Bucket = Mutex<VecDeque<Arc<Waiter>>>;
+
+static TABLE: [Bucket; BUCKETS] = [const { Mutex::new(VecDeque::new()) }; BUCKETS];
+
+thread_local! {
+    // Reused by every park of the thread.
+    static WAITER: Arc<Waiter> = Arc::new(Waiter::new());
+}
+
+impl Waiter {
+    fn new() -> Self {
+        Self {
+            key: AtomicUsize::new(0),
+            state: AtomicU32::new(UNPARKED),
+            token: AtomicUsize::new(DEFAULT_UNPARK_TOKEN),
+        }
+    }
+}
+
+fn index(addr: usize) -> usize {
+    // Fibonacci hashing, neighbouring addresses spread over buckets.
+    addr.wrapping_mul(0x9E37_79B9_7F4A_7C15_u64 as usize) >> (usize::BITS - BUCKET_BITS)
+}
+
+// Keep the table usable even if a callback panicked while holding a bucket.
+fn lock(index: usize) -> MutexGuard<'static, VecDeque<Arc<Waiter>>> {
+    TABLE[index].lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Park the thread on addr if validate returns true, until another
+/// thread unparks it from addr or the timeout passes.
+///
+/// validate runs with the bucket locked, so an unpark of addr can't slip
+/// in between it and parking, just like the value check of a futex wait.
+#[cfg_attr(feature = "metrics", track_caller)]
+pub fn park(addr: usize, validate: impl FnOnce() -> bool, timeout: Option<Duration>) -> ParkResult {
+    let waiter = WAITER
+        .try_with(Arc::clone)
+        .unwrap_or_else(|_| Arc::new(Waiter::new()));
+    {
+        let mut queue = lock(index(addr));
+        if !validate() {
+            return ParkResult::Invalid;
+        }
+        waiter.key.store(addr, Relaxed);
+        waiter.state.store(PARKED, Relaxed);
+        queue.push_back(Arc::clone(&waiter));
+    }
+
+    let deadline = timeout.map(|timeout| time::now() + timeout);
+    while waiter.state.load(Acquire) == PARKED {
+        let Some(at) = deadline else {
+            wait(&waiter.state, PARKED);
+            continue;
+        };
+        let remaining = at.saturating_duration_since(time::now());
+        if !remaining.is_zero() {
+            wait_timeout(&waiter.state, PARKED, remaining);
+            continue;
+        }
+        // Leave the queue, unless an unpark took us out meanwhile.
+        if remove(&waiter) {
+            return ParkResult::TimedOut;
+        }
+    }
+    ParkResult::Unparked(waiter.token.load(Relaxed))
+}
+
+// Remove a parked waiter from its queue, false if it's unparked already.
+fn remove(waiter: &Arc<Waiter>) -> bool {
+    loop {
+        let key = waiter.key.load(Relaxed);
+        let mut queue = lock(index(key));
+        // Requeued to another bucket before we got the lock.
+        if waiter.key.load(Relaxed) != key {
+            continue;
+        }
+        // The state is set with the bucket locked.
+        if waiter.state.load(Relaxed) == UNPARKED {
+            return false;
+        }
+        queue.retain(|w| !Arc::ptr_eq(w, waiter));
+        return true;
+    }
+}
+
+fn unpark(waiter: Arc<Waiter>, token: UnparkToken) -> Arc<Waiter> {
+    waiter.token.store(token, Relaxed);
+    waiter.state.store(UNPARKED, Release);
+    waiter
+}
+
+/// Unpark the first thread parked on addr. callback runs with the bucket
+/// locked before the thread wakes, and returns the token for it,
+/// e.g. to update the lock state for the threads still parked.
+#[cfg_attr(feature = "metrics", track_caller)]
+pub fn unpark_one(addr: usize, callback: impl FnOnce(UnparkResult) -> UnparkToken) -> UnparkResult {
+    let mut queue = lock(index(addr));
+    let mut result = UnparkResult::default();
+    let mut position = None;
+    for (i, w) in queue.iter().enumerate() {
+        if w.key.load(Relaxed) != addr {
+            continue;
+        }
+        if position.is_some() {
+            result.have_more = true;
+            break;
+        }
+        position = Some(i);
+    }
+    result.unparked = position.is_some();
+    let token = callback(result);
+    let woken = position
+        .and_then(|i| queue.remove(i))
+        .map(|w| unpark(w, token));
+    drop(queue);
+    if let Some(waiter) = woken {
+        wake_one(&waiter.state);
+    }
+    result
+}
+
+/// Unpark all threads parked on addr with the token,
+/// return the count of them.
+#[cfg_attr(feature = "metrics", track_caller)]
+pub fn unpark_all(addr: usize, token: UnparkToken) -> usize {
+    let mut queue = lock(index(addr));
+    let mut woken = Vec::new();
+    queue.retain(|w| {
+        if w.key.load(Relaxed) != addr {
+            return true;
+        }
+        woken.push(unpark(Arc::clone(w), token));
+        false
+    });
+    drop(queue);
+    for waiter in &woken {
+        wake_one(&waiter.state);
+    }
+    woken.len()
+}
+
+/// Unpark the first thread parked on from, and move the rest to park on
+/// to without waking them, if validate returns true with the buckets
+/// locked, e.g. a condvar notifying all requeues its waiters onto the
+/// mutex instead of waking them all to fight for it.
+///
+/// validate only runs if threads are parked on from, so it may touch
+/// memory they keep alive, e.g. the mutex they wait with.
+#[cfg_attr(feature = "metrics", track_caller)]
+pub fn unpark_requeue(from: usize, to: usize, validate: impl FnOnce() -> bool) -> RequeueResult {
+    let (i, j) = (index(from), index(to));
+    // Lock both buckets in the order of the table against deadlocks.
+    let (mut first, second) = match i.cmp(&j) {
+        std::cmp::Ordering::Equal => (lock(i), None),
+        std::cmp::Ordering::Less => {
+            let a = lock(i);
+            (a, Some(lock(j)))
+        }
+        std::cmp::Ordering::Greater => {
+            let b = lock(j);
+            (lock(i), Some(b))
+        }
+    };
+    let mut result = RequeueResult::default();
+    if !first.iter().any(|w| w.key.load(Relaxed) == from) || !validate() {
+        return result;
+    }
+    let mut woken = None;
+    let mut moved = Vec::new();
+    first.retain(|w| {
+        if w.key.load(Relaxed) != from {
+            return true;
+        }
+        if woken.is_none() {
+            woken = Some(unpark(Arc::clone(w), DEFAULT_UNPARK_TOKEN));
+            return false;
+        }
+        w.key.store(to, Relaxed);
+        result.requeued += 1;
+        // Moved waiters stay in place if both addresses share the bucket.
+        if second.is_some() {
+            moved.push(Arc::clone(w));
+            return false;
+        }
+        true
+    });
+    result.unparked = woken.is_some();
+    if let Some(mut second) = second {
+        second.extend(moved);
+    }
+    drop(first);
+    if let Some(waiter) = woken {
+        wake_one(&waiter.state);
+    }
+    result
+}
+
+/// Yield until at least n threads are parked on addr, for tests racing
+/// the unparking with the parking.
+#[cfg(test)]
+pub(crate) fn wait_parked(addr: usize, n: usize) {
+    let parked = || {
+        let queue = lock(index(addr));
+        queue.iter().filter(|w| w.key.load(Relaxed) == addr).count()
+    };
+    while parked() < n {
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+        thread,
+        time::Duration,
+    };
+
+    use super::{
+        park, unpark_all, unpark_one, unpark_requeue, wait_parked, ParkResult, UnparkResult,
+    };
+
+    #[test]
+    fn test_parking() {
+        let word = AtomicBool::new(true);
+        let addr = &word as *const _ as usize;
+        assert_eq!(park(addr, || false, None), ParkResult::Invalid);
+        assert_eq!(
+            park(addr, || true, Some(Duration::from_millis(10))),
+            ParkResult::TimedOut
+        );
+        assert_eq!(unpark_one(addr, |_| 0), UnparkResult::default());
+
+        let other = AtomicBool::new(true);
+        let to = &other as *const _ as usize;
+        let unparked = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    if let ParkResult::Unparked(token) = park(addr, || word.load(SeqCst), None) {
+                        unparked.fetch_add(token, SeqCst);
+                    }
+                });
+            }
+            wait_parked(addr, 4);
+            // The token reaches the unparked thread.
+            let result = unpark_one(addr, |result| {
+                assert!(result.unparked && result.have_more);
+                10
+            });
+            assert!(result.have_more);
+            // One is woken, the rest move to the other address.
+            let result = unpark_requeue(addr, to, || true);
+            assert!(result.unparked);
+            assert_eq!(result.requeued, 2);
+            wait_parked(to, 2);
+            assert_eq!(unpark_all(addr, 0), 0);
+            assert_eq!(unpark_all(to, 100), 2);
+        });
+        assert_eq!(unparked.into_inner(), 210);
+    }
+}
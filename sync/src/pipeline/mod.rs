@@ -0,0 +1,109 @@
+//! Scoped threads wired by bounded channels.
+//!
+//! Every stage runs on its own scoped thread. Shutdown propagates both ways:
+//! a finished or failed upstream closes the channel and downstream drains it,
+//! a dropped downstream makes the sends upstream fail and stops the stages.
+
+use std::thread::Scope;
+
+use crate::channel::bounded::{channel, Receiver};
+
+/// Capacity of the channels between stages if not given.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Spawn a thread sending all items of the source.
+pub fn source<'scope, I>(
+    s: &'scope Scope<'scope, '_>,
+    source: I,
+    capacity: usize,
+) -> Receiver<I::Item>
+where
+    I: IntoIterator + Send + 'scope,
+    I::Item: Send + 'scope,
+{
+    let (tx, rx) = channel(capacity);
+    s.spawn(move || {
+        for item in source {
+            if tx.send(item).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Spawn a thread mapping every item received from upstream.
+pub fn stage<'scope, A, B, F>(
+    s: &'scope Scope<'scope, '_>,
+    upstream: Receiver<A>,
+    mut f: F,
+    capacity: usize,
+) -> Receiver<B>
+where
+    A: Send + 'scope,
+    B: Send + 'scope,
+    F: FnMut(A) -> B + Send + 'scope,
+{
+    let (tx, rx) = channel(capacity);
+    s.spawn(move || {
+        for item in &upstream {
+            if tx.send(f(item)).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Run a pipeline in a thread scope: `pipeline!(src => stage1 => stage2 => sink, cap = 64)`.
+///
+/// `src` is an `IntoIterator`, stages are `FnMut(A) -> B` closures, each on its own thread.
+/// The sink is a `FnOnce(Receiver<T>) -> R` run on the calling thread,
+/// it may drop the receiver early to shut the pipeline down.
+/// Return what the sink returns after all stages finished.
+#[macro_export]
+macro_rules! pipeline {
+    ($src:expr => $($rest:expr)=>+ , cap = $cap:expr $(,)?) => {
+        ::std::thread::scope(|s| {
+            let cap = $cap;
+            let rx = $crate::pipeline::source(s, $src, cap);
+            $crate::pipeline!(@stages s, rx, cap; $($rest),+)
+        })
+    };
+    ($src:expr => $($rest:expr)=>+ $(,)?) => {
+        $crate::pipeline!($src => $($rest)=>+, cap = $crate::pipeline::DEFAULT_CAPACITY)
+    };
+    (@stages $s:ident, $rx:ident, $cap:ident; $sink:expr) => {
+        ($sink)($rx)
+    };
+    (@stages $s:ident, $rx:ident, $cap:ident; $stage:expr, $($rest:expr),+) => {{
+        let $rx = $crate::pipeline::stage($s, $rx, $stage, $cap);
+        $crate::pipeline!(@stages $s, $rx, $cap; $($rest),+)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    use crate::channel::bounded::Receiver;
+
+    #[test]
+    fn test_pipeline() {
+        let sum = pipeline!(
+            1..=100 => |x: i32| x * 2 => |x: i32| x + 1 => |rx: Receiver<i32>| rx.iter().sum::<i32>(),
+            cap = 4
+        );
+        assert_eq!(sum, 10200);
+
+        // The sink stops early, the endless source is shut down.
+        let produced = AtomicUsize::new(0);
+        let first: Vec<_> = pipeline!(
+            (0..).inspect(|_| {
+                produced.fetch_add(1, Relaxed);
+            }) => |x: u64| x * x => |rx: Receiver<u64>| rx.iter().take(5).collect()
+        );
+        assert_eq!(first, [0, 1, 4, 9, 16]);
+        assert!(produced.load(Relaxed) < 1000);
+    }
+}
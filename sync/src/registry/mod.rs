@@ -0,0 +1,227 @@
+//! A global registry of named locks, so diagnostics refer to locks by
+//! human-readable names instead of addresses.
+//!
+//! A lock created by `Mutex::named` or `RwLock::named` is registered
+//! under its address when it's acquired, and the address is refreshed on
+//! the next acquisition if the lock was moved. Locks carry their name only
+//! with the `registry` feature, so the core lock types stay lean without.
+
+#[cfg(feature = "metrics")]
+use std::time::{Duration, Instant};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Mutex, MutexGuard,
+    },
+};
+
+struct Entry {
+    // Id of the Name registered, locks may share the name literal.
+    id: usize,
+    name: &'static str,
+    #[cfg(feature = "metrics")]
+    stats: LockStats,
+}
+
+// Every named lock acquired, by address.
+static NAMES: Mutex<BTreeMap<usize, Entry>> = Mutex::new(BTreeMap::new());
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+// Keep the registry usable even if a thread panicked while holding it.
+fn names() -> MutexGuard<'static, BTreeMap<usize, Entry>> {
+    NAMES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Contention of a named lock, by `lock_stats`.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockStats {
+    /// Acquisitions which found the lock held and waited.
+    pub contended: u64,
+    /// Time spent waiting by the contended acquisitions.
+    pub waited: Duration,
+}
+
+/// The name of a lock of the crate in the registry.
+pub(crate) struct Name {
+    name: Option<&'static str>,
+    // Address the lock is registered at, 0 if not registered.
+    addr: AtomicUsize,
+    // Unique id given on the first registration, 0 before.
+    id: AtomicUsize,
+}
+
+impl Name {
+    pub(crate) const fn new(name: Option<&'static str>) -> Self {
+        Self {
+            name,
+            addr: AtomicUsize::new(0),
+            id: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Register the lock under the name if it's named,
+    /// called with the lock held so it can't move meanwhile.
+    #[inline]
+    pub(crate) fn register<L>(&self, lock: &L) {
+        if let Some(name) = self.name {
+            let addr = lock as *const L as usize;
+            if self.addr.load(Relaxed) != addr {
+                self.register_slow(addr, name);
+            }
+        }
+    }
+
+    #[cold]
+    fn register_slow(&self, addr: usize, name: &'static str) {
+        let mut names = names();
+        let old = self.addr.swap(addr, Relaxed);
+        let id = match self.id.load(Relaxed) {
+            0 => {
+                let id = NEXT_ID.fetch_add(1, Relaxed);
+                self.id.store(id, Relaxed);
+                id
+            }
+            id => id,
+        };
+        // The stats move along with the lock.
+        let entry = remove_own(&mut names, old, id).unwrap_or(Entry {
+            id,
+            name,
+            #[cfg(feature = "metrics")]
+            stats: LockStats::default(),
+        });
+        names.insert(addr, entry);
+    }
+
+    /// Count an acquisition which waited since start, called with the
+    /// lock held after `register`.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn contended(&self, start: Instant) {
+        if self.name.is_none() {
+            return;
+        }
+        let waited = start.elapsed();
+        if let Some(entry) = names().get_mut(&self.addr.load(Relaxed)) {
+            entry.stats.contended += 1;
+            entry.stats.waited += waited;
+        }
+    }
+}
+
+impl Drop for Name {
+    fn drop(&mut self) {
+        if self.name.is_some() {
+            remove_own(&mut names(), *self.addr.get_mut(), *self.id.get_mut());
+        }
+    }
+}
+
+// Remove the entry of the lock at addr, unless another lock registered
+// there since it moved away.
+fn remove_own(names: &mut BTreeMap<usize, Entry>, addr: usize, id: usize) -> Option<Entry> {
+    if addr == 0 || names.get(&addr).is_none_or(|e| e.id != id) {
+        return None;
+    }
+    names.remove(&addr)
+}
+
+/// The name of the lock at the address, if it's a named lock.
+pub fn name_of(addr: usize) -> Option<&'static str> {
+    names().get(&addr).map(|e| e.name)
+}
+
+/// Addresses and names of all named locks alive, ordered by address.
+/// A lock is listed once it has been acquired.
+pub fn named_locks() -> Vec<(usize, &'static str)> {
+    names().iter().map(|(&addr, e)| (addr, e.name)).collect()
+}
+
+/// Names and contention of all named locks alive, ordered by address.
+#[cfg(feature = "metrics")]
+pub fn lock_stats() -> Vec<(&'static str, LockStats)> {
+    names().values().map(|e| (e.name, e.stats)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{name_of, named_locks};
+    use crate::{mutex::Mutex, rwlock::RwLock};
+
+    #[test]
+    fn test_registry() {
+        let m = Mutex::named("shard-3 index", 0);
+        let l = RwLock::named("config", 0);
+        let unnamed = Mutex::new(0);
+        assert_eq!(m.name(), Some("shard-3 index"));
+        assert_eq!(unnamed.name(), None);
+        *m.lock() += 1;
+        *unnamed.lock() += 1;
+        assert_eq!(*l.read(), 0);
+        let m_addr = &m as *const _ as usize;
+        assert_eq!(name_of(m_addr), Some("shard-3 index"));
+        assert_eq!(name_of(&l as *const _ as usize), Some("config"));
+        assert_eq!(name_of(&unnamed as *const _ as usize), None);
+
+        // The registry follows a moved lock.
+        let moved = Box::new(m);
+        *moved.lock() += 1;
+        let moved_addr = &*moved as *const _ as usize;
+        assert_eq!(name_of(moved_addr), Some("shard-3 index"));
+        assert!(moved_addr == m_addr || name_of(m_addr).is_none());
+        drop(moved);
+        assert_eq!(name_of(moved_addr), None);
+        assert!(named_locks().iter().any(|&(_, name)| name == "config"));
+
+        // A lock moved away leaves the entry of the lock now at its old
+        // address alone.
+        let mut slot = Mutex::named("old", 0);
+        *slot.lock() += 1;
+        let slot_addr = &slot as *const _ as usize;
+        let old = Box::new(std::mem::replace(&mut slot, Mutex::named("new", 0)));
+        *slot.lock() += 1;
+        assert_eq!(name_of(slot_addr), Some("new"));
+        *old.lock() += 1;
+        assert_eq!(name_of(slot_addr), Some("new"));
+        drop(old);
+        assert_eq!(name_of(slot_addr), Some("new"));
+
+        // Even if both locks share the name literal.
+        let mut slot = Mutex::named("shard", 0);
+        *slot.lock() += 1;
+        let slot_addr = &slot as *const _ as usize;
+        let old = Box::new(std::mem::replace(&mut slot, Mutex::named("shard", 0)));
+        *slot.lock() += 1;
+        *old.lock() += 1;
+        let old_addr = &*old as *const _ as usize;
+        assert_eq!(name_of(slot_addr), Some("shard"));
+        assert_eq!(name_of(old_addr), Some("shard"));
+        drop(old);
+        assert_eq!(name_of(slot_addr), Some("shard"));
+
+        #[cfg(feature = "metrics")]
+        {
+            let x = Mutex::named("contended", 0);
+            let g = x.lock();
+            std::thread::scope(|s| {
+                s.spawn(|| *x.lock() += 1);
+                // The waiter started timing once it marked the contention.
+                while x.word().load(std::sync::atomic::Ordering::Relaxed) != 2 {
+                    std::thread::yield_now();
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                drop(g);
+            });
+            let stats = super::lock_stats();
+            let (_, stats) = stats.iter().find(|(name, _)| *name == "contended").unwrap();
+            assert_eq!(stats.contended, 1);
+            assert!(stats.waited >= std::time::Duration::from_millis(10));
+        }
+    }
+}
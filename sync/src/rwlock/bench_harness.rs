@@ -0,0 +1,180 @@
+use std::{
+    hint::black_box,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{RwLock, WideRwLock};
+use crate::xorshift::XorShift;
+
+/// A reader-writer lock exercised by the harness.
+pub trait HarnessLock: Sync {
+    /// Take a read lock and touch the protected value.
+    fn read_op(&self);
+    /// Take a write lock and update the protected value.
+    fn write_op(&self);
+}
+
+impl HarnessLock for RwLock<u64> {
+    fn read_op(&self) {
+        black_box(*self.read());
+    }
+
+    fn write_op(&self) {
+        *self.write() += 1;
+    }
+}
+
+impl HarnessLock for WideRwLock<u64> {
+    fn read_op(&self) {
+        black_box(*self.read());
+    }
+
+    fn write_op(&self) {
+        *self.write() += 1;
+    }
+}
+
+impl HarnessLock for std::sync::RwLock<u64> {
+    fn read_op(&self) {
+        black_box(*self.read().unwrap());
+    }
+
+    fn write_op(&self) {
+        *self.write().unwrap() += 1;
+    }
+}
+
+/// A mix of read and write operations run by the harness.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    /// Count of threads running concurrently.
+    pub threads: usize,
+    /// Count of operations run by each thread.
+    pub ops_per_thread: usize,
+    /// Probability of an operation to be a write, in `[0, 1]`.
+    pub write_ratio: f64,
+    /// Seed choosing the operations, same seed runs the same mix.
+    pub seed: u64,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Self {
+            threads: 4,
+            ops_per_thread: 10_000,
+            write_ratio: 0.1,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// Latency percentiles of one kind of operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Percentiles {
+    fn from_samples(samples: &mut [Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let at = |p: usize| samples[(samples.len() - 1) * p / 100];
+        Self {
+            p50: at(50),
+            p90: at(90),
+            p99: at(99),
+            max: at(100),
+        }
+    }
+}
+
+/// Result of a harness run.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Wall time of the whole run.
+    pub elapsed: Duration,
+    pub reads: usize,
+    pub writes: usize,
+    pub read_latency: Percentiles,
+    pub write_latency: Percentiles,
+}
+
+impl Report {
+    /// Operations finished per second.
+    pub fn throughput(&self) -> f64 {
+        (self.reads + self.writes) as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Run the workload against the lock, measuring every operation.
+pub fn run<L: HarnessLock>(lock: &L, workload: &Workload) -> Report {
+    let threshold = (workload.write_ratio.clamp(0.0, 1.0) * u32::MAX as f64) as u32;
+    let start = Instant::now();
+    let samples: Vec<(Vec<_>, Vec<_>)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..workload.threads)
+            .map(|i| {
+                let mut rng = XorShift::new(workload.seed ^ i as u64);
+                s.spawn(move || {
+                    let mut reads = Vec::with_capacity(workload.ops_per_thread);
+                    let mut writes = Vec::new();
+                    for _ in 0..workload.ops_per_thread {
+                        let is_write = ((rng.next() >> 32) as u32) < threshold;
+                        let begin = Instant::now();
+                        if is_write {
+                            lock.write_op();
+                            writes.push(begin.elapsed());
+                        } else {
+                            lock.read_op();
+                            reads.push(begin.elapsed());
+                        }
+                    }
+                    (reads, writes)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let elapsed = start.elapsed();
+
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    for (r, w) in samples {
+        reads.extend(r);
+        writes.extend(w);
+    }
+    Report {
+        elapsed,
+        reads: reads.len(),
+        writes: writes.len(),
+        read_latency: Percentiles::from_samples(&mut reads),
+        write_latency: Percentiles::from_samples(&mut writes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, RwLock, Workload};
+
+    #[test]
+    fn test_bench_harness() {
+        let lock = RwLock::new(0);
+        let workload = Workload {
+            threads: 2,
+            ops_per_thread: 1000,
+            write_ratio: 0.25,
+            ..Default::default()
+        };
+        let report = run(&lock, &workload);
+        assert_eq!(report.reads + report.writes, 2000);
+        assert_eq!(*lock.read(), report.writes as u64);
+        assert!(report.writes > 0 && report.reads > report.writes);
+        assert!(report.read_latency.p50 <= report.read_latency.p99);
+        assert!(report.throughput() > 0.0);
+    }
+}
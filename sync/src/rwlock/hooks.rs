@@ -0,0 +1,245 @@
+use std::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    panic,
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc,
+    },
+};
+
+use super::{ReadGuard, RwLock, UpgradableReadGuard, UpgradeError, WriteGuard};
+use crate::{spin::SpinLock, thread_ext::catch};
+
+type Hook = Arc<dyn Fn() + Send + Sync>;
+
+/// A RwLock running hooks after every write unlock, e.g. to invalidate
+/// caches derived from the value without polling. Plain RwLocks don't
+/// carry the hooks, so they pay nothing for them.
+///
+/// Hooks run on the unlocking thread outside the critical section, so they
+/// may lock the rwlock for read, but a hook writing it runs again and
+/// again. A panic of a hook is raised once all hooks ran, or dropped if the
+/// write guard is dropped by a panic already.
+pub struct HookedRwLock<T> {
+    lock: RwLock<T>,
+    hooks: Hooks,
+}
+
+/// Id of a hook registered by `HookedRwLock::on_write_unlock`,
+/// for `HookedRwLock::remove_write_hook`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WriteHookId(u64);
+
+// The list is copied on write, so the unlocking thread takes a snapshot
+// and runs it without any lock held.
+struct Hooks {
+    // Whether there's any hook, checked before taking the list.
+    any: AtomicBool,
+    inner: SpinLock<HookList>,
+}
+
+struct HookList {
+    next_id: u64,
+    hooks: Arc<Vec<(u64, Hook)>>,
+}
+
+impl<T> HookedRwLock<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            lock: RwLock::new(value),
+            hooks: Hooks {
+                any: AtomicBool::new(false),
+                inner: SpinLock::new(HookList {
+                    next_id: 0,
+                    hooks: Arc::new(Vec::new()),
+                }),
+            },
+        }
+    }
+
+    /// Register f to run after every write unlock.
+    pub fn on_write_unlock(&self, f: impl Fn() + Send + Sync + 'static) -> WriteHookId {
+        let mut list = self.hooks.inner.lock();
+        let id = list.next_id;
+        list.next_id += 1;
+        let mut hooks = Vec::clone(&list.hooks);
+        hooks.push((id, Arc::new(f)));
+        list.hooks = Arc::new(hooks);
+        self.hooks.any.store(true, Relaxed);
+        WriteHookId(id)
+    }
+
+    /// Unregister a hook, false if it's not registered.
+    pub fn remove_write_hook(&self, id: WriteHookId) -> bool {
+        let mut list = self.hooks.inner.lock();
+        if !list.hooks.iter().any(|(i, _)| *i == id.0) {
+            return false;
+        }
+        let hooks: Vec<_> = list
+            .hooks
+            .iter()
+            .filter(|(i, _)| *i != id.0)
+            .cloned()
+            .collect();
+        self.hooks.any.store(!hooks.is_empty(), Relaxed);
+        list.hooks = Arc::new(hooks);
+        true
+    }
+
+    /// Read lock for value, see `RwLock::read`.
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        self.lock.read()
+    }
+
+    /// Write lock for value, the hooks run once the guard is dropped.
+    pub fn write(&self) -> HookedWriteGuard<'_, T> {
+        HookedWriteGuard {
+            guard: ManuallyDrop::new(self.lock.write()),
+            hooks: &self.hooks,
+        }
+    }
+
+    /// Read lock for value, which may be upgraded to a write lock later,
+    /// see `RwLock::upgradable_read`.
+    pub fn upgradable_read(&self) -> HookedUpgradableReadGuard<'_, T> {
+        HookedUpgradableReadGuard {
+            guard: self.lock.upgradable_read(),
+            hooks: &self.hooks,
+        }
+    }
+}
+
+impl Hooks {
+    fn run(&self) {
+        if !self.any.load(Relaxed) {
+            return;
+        }
+        let hooks = Arc::clone(&self.inner.lock().hooks);
+        let mut panicked = None;
+        for (_, hook) in hooks.iter() {
+            if let Err(e) = catch(|| hook()) {
+                panicked.get_or_insert(e);
+            }
+        }
+        // Raising a panic while unwinding aborts, drop it then.
+        if let Some(e) = panicked {
+            if !std::thread::panicking() {
+                panic::resume_unwind(e.into_payload());
+            }
+        }
+    }
+}
+
+/// A guard of HookedRwLock write lock, running the hooks after unlocking.
+pub struct HookedWriteGuard<'a, T> {
+    guard: ManuallyDrop<WriteGuard<'a, T>>,
+    hooks: &'a Hooks,
+}
+
+impl<T> Deref for HookedWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for HookedWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for HookedWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        // Safety: the guard is dropped once, here.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        self.hooks.run();
+    }
+}
+
+/// A guard of HookedRwLock upgradable read lock.
+pub struct HookedUpgradableReadGuard<'a, T> {
+    guard: UpgradableReadGuard<'a, T>,
+    hooks: &'a Hooks,
+}
+
+impl<'a, T> HookedUpgradableReadGuard<'a, T> {
+    /// Upgrade to a write lock, see `UpgradableReadGuard::upgrade`.
+    pub fn upgrade(this: Self) -> Result<HookedWriteGuard<'a, T>, (Self, UpgradeError)> {
+        let hooks = this.hooks;
+        match UpgradableReadGuard::upgrade(this.guard) {
+            Ok(guard) => Ok(HookedWriteGuard {
+                guard: ManuallyDrop::new(guard),
+                hooks,
+            }),
+            Err((guard, e)) => Err((Self { guard, hooks }, e)),
+        }
+    }
+}
+
+impl<T> Deref for HookedUpgradableReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        sync::{
+            atomic::{AtomicUsize, Ordering::Relaxed},
+            Arc,
+        },
+    };
+
+    use super::{HookedRwLock, HookedUpgradableReadGuard};
+
+    #[test]
+    fn test_write_unlock_hooks() {
+        let x = Arc::new(HookedRwLock::new(0));
+        let seen = Arc::new(AtomicUsize::new(0));
+        let id = {
+            let (x, seen) = (Arc::downgrade(&x), Arc::clone(&seen));
+            // Runs outside the critical section, so it may read the rwlock.
+            x.upgrade().unwrap().on_write_unlock(move || {
+                let value = *x.upgrade().unwrap().read();
+                seen.store(value, Relaxed);
+            })
+        };
+        drop(x.read());
+        assert_eq!(seen.load(Relaxed), 0);
+        *x.write() = 1;
+        assert_eq!(seen.load(Relaxed), 1);
+        let mut w = HookedUpgradableReadGuard::upgrade(x.upgradable_read())
+            .ok()
+            .unwrap();
+        *w = 2;
+        drop(w);
+        assert_eq!(seen.load(Relaxed), 2);
+
+        assert!(x.remove_write_hook(id));
+        assert!(!x.remove_write_hook(id));
+        *x.write() = 3;
+        assert_eq!(seen.load(Relaxed), 2);
+
+        // A panicking hook doesn't stop the others, nor abort a panicking
+        // writer.
+        let id = x.on_write_unlock(|| panic!("hook"));
+        let seen2 = Arc::clone(&seen);
+        x.on_write_unlock(move || seen2.store(4, Relaxed));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| *x.write() = 4));
+        assert!(result.is_err());
+        assert_eq!(seen.load(Relaxed), 4);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _w = x.write();
+            panic!("writer");
+        }));
+        assert!(result.is_err());
+        assert!(x.remove_write_hook(id));
+        *x.write() = 5;
+        assert_eq!(*x.read(), 5);
+    }
+}
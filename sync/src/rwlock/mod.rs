@@ -1,69 +1,273 @@
 use std::{
     cell::UnsafeCell,
+    error::Error,
+    fmt, hint, mem,
     ops::{Deref, DerefMut},
     sync::atomic::{
         AtomicU32,
-        Ordering::{Acquire, Relaxed, Release},
+        Ordering::{AcqRel, Acquire, Relaxed, Release},
     },
 };
 
-use atomic_wait::{wait, wake_all, wake_one};
+use crate::futex::{wait, wake_all, yield_point};
+use crate::parking::{self, ParkResult, UnparkResult, DEFAULT_UNPARK_TOKEN};
+#[cfg(feature = "registry")]
+use crate::registry::Name;
 
-const RWLOCK_WLOCKED: u32 = u32::MAX;
+pub mod bench_harness;
+mod hooks;
+mod wide;
 
-pub struct RwLock<T> {
-    state: AtomicU32,               // Counter of reader, RWLOCK_WLOCKED for write lock.
-    writer_wake_counter: AtomicU32, // Counter of wake up writer. Just like a Condvar.
+pub use hooks::{HookedRwLock, HookedUpgradableReadGuard, HookedWriteGuard, WriteHookId};
+pub use wide::{WideReadGuard, WideRwLock, WideWriteGuard};
+
+const WRITER_WAITING: u32 = 1; // a writer waits, new readers block behind it
+const READERS_PARKED: u32 = 2; // readers are parked
+const WRITERS_PARKED: u32 = 4; // writers are parked
+const UPGRADING: u32 = 8; // an upgradable reader is upgrading
+const WRITE_LOCKED: u32 = 16; // write locked
+const ONE_READER: u32 = 32; // readers are counted above the flags
+
+// Offsets of the keys readers, writers and the upgrading reader park on,
+// all within the state word, so no other lock shares them.
+const READERS_KEY: usize = 0;
+const WRITERS_KEY: usize = 1;
+const UPGRADER_KEY: usize = 2;
+
+// Token of a parked writer the last reader handed the lock to.
+const HANDOFF: usize = 1;
+
+/// The most readers a RwLock admits at a time by default, as many as the
+/// state counts. Readers saturate at the cap instead of overflowing the
+/// state: new readers park until the readers drain below it.
+pub const DEFAULT_MAX_READERS: u32 = u32::MAX / ONE_READER;
+
+/// A reader-writer lock admitting at most `MAX_READERS` readers at a time,
+/// more readers block until some of the readers leave.
+///
+/// The lock is a single state word besides the value, e.g. for arrays of
+/// millions of fine-grained locks: `RwLock<u32>` takes 8 bytes without the
+/// `registry` feature, where `WideRwLock<u32>` takes 24. Its waiters park
+/// in the `parking` table keyed by addresses within the word, which costs
+/// a bucket lock per contended wait and wake over the futex words of
+/// WideRwLock (see `bench_rwlock_array`).
+pub struct RwLock<T, const MAX_READERS: u32 = DEFAULT_MAX_READERS> {
+    state: AtomicU32, // Counter of readers by ONE_READER, and the flags below it.
+    #[cfg(feature = "registry")]
+    name: Name,
     value: UnsafeCell<T>,
 }
 
 /// Implement Sync if and only if T is Send.
 /// Only one thread access the &mut T at a time,
 /// so T is not required to be Sync.
-unsafe impl<T> Sync for RwLock<T> where T: Send + Sync {}
+unsafe impl<T, const MAX_READERS: u32> Sync for RwLock<T, MAX_READERS> where T: Send + Sync {}
 
 impl<T> RwLock<T> {
     /// Create a new rwlock for given value.
     pub const fn new(value: T) -> Self {
+        Self::with_reader_cap(value)
+    }
+
+    /// Create a new rwlock for given value, named in the registry
+    /// and diagnostics.
+    #[cfg(feature = "registry")]
+    pub const fn named(name: &'static str, value: T) -> Self {
+        Self::build(Some(name), value)
+    }
+}
+
+impl<T, const MAX_READERS: u32> RwLock<T, MAX_READERS> {
+    /// Create a new rwlock for given value with the reader cap of the type,
+    /// e.g. `RwLock::<_, 64>::with_reader_cap(value)`.
+    pub const fn with_reader_cap(value: T) -> Self {
+        Self::build(None, value)
+    }
+
+    const fn build(name: Option<&'static str>, value: T) -> Self {
+        #[cfg(not(feature = "registry"))]
+        let _ = name;
+        const {
+            assert!(
+                MAX_READERS > 0 && MAX_READERS <= DEFAULT_MAX_READERS,
+                "reader cap out of range"
+            )
+        };
         Self {
             state: AtomicU32::new(0),
-            writer_wake_counter: AtomicU32::new(0),
+            #[cfg(feature = "registry")]
+            name: Name::new(name),
             value: UnsafeCell::new(value),
         }
     }
 
+    /// Name of the rwlock given by `named`.
+    #[cfg(feature = "registry")]
+    pub fn name(&self) -> Option<&'static str> {
+        self.name.get()
+    }
+
+    /// The futex word of the rwlock, for foreign code locking the same
+    /// rwlock. It counts readers by 32 above five flags: bit 4 if write
+    /// locked, bit 3 if a reader is upgrading, bit 0 if a writer waits,
+    /// and bits 1 and 2 if readers and writers wait on the word.
+    ///
+    /// Read lock by adding 32 while bits 0 and 4 are clear, write lock by
+    /// setting bit 4 while there's no reader nor bit 4. A blocked writer
+    /// sets bit 0, a blocked thread sets its waiting bit and waits on the
+    /// word while it's unchanged. Write unlock by clearing bits 0, 1 and 4,
+    /// read unlock by subtracting 32; and if the waiting bit of a thread
+    /// the unlock may let in was set, or bit 3 with one reader left, clear
+    /// the waiting bit and wake all waiters. Waiting and waking are process
+    /// private, waiters of the rwlock wait on the word instead of parking
+    /// with the `ffi` feature.
+    ///
+    /// # Safety
+    ///
+    /// Foreign code must follow the protocol, and must not touch the value
+    /// while it's not holding the lock.
+    #[cfg(feature = "ffi")]
+    pub unsafe fn as_futex_word(&self) -> &AtomicU32 {
+        &self.state
+    }
+
+    /// Whether the rwlock is locked by any reader or writer now,
+    /// the answer may be stale at once.
+    pub fn is_locked(&self) -> bool {
+        let x = self.state.load(Relaxed);
+        x & WRITE_LOCKED != 0 || x >= ONE_READER
+    }
+
     /// Read lock for value.
-    pub fn read(&self) -> ReadGuard<T> {
+    pub fn read(&self) -> ReadGuard<'_, T, MAX_READERS> {
+        self.read_spin_then_park(crate::config::get().rwlock_spin_iters)
+    }
+
+    /// Read lock like `read`, but spin at most `spin_iters` times
+    /// before parking, so call sites can tune the budget.
+    pub fn read_spin_then_park(&self, spin_iters: u32) -> ReadGuard<'_, T, MAX_READERS> {
+        self.lock_shared(spin_iters);
+        ReadGuard { lock: self }
+    }
+
+    /// Run f on the value with the read lock elided by a hardware
+    /// transaction, if there's no writer locked or waiting: f runs without
+    /// writing the lock word, so readers don't bounce its cache line.
+    /// If the transaction aborts f runs again with the read lock taken,
+    /// see `Mutex::elide`.
+    #[cfg(feature = "htm")]
+    pub fn elide_read<R>(&self, mut f: impl FnMut(&T) -> R) -> R {
+        let elided = crate::elision::elide(
+            || self.state.load(Relaxed) & (WRITE_LOCKED | WRITER_WAITING) == 0,
+            // Safety: no writer holds the lock until the commit, a writer
+            // taking it aborts the transaction.
+            || f(unsafe { &*self.value.get() }),
+        );
+        match elided {
+            Some(r) => r,
+            None => f(&self.read()),
+        }
+    }
+
+    /// Read lock like `read`, but never block behind a waiting writer, only
+    /// behind a writer holding the lock, e.g. for a call stack which may
+    /// re-enter a read while holding one. `read` blocks behind a waiting
+    /// writer, which waits for the outer read, and both deadlock.
+    ///
+    /// A stream of recursive readers may starve writers.
+    pub fn read_recursive(&self) -> ReadGuard<'_, T, MAX_READERS> {
+        self.lock_shared_with(0, true);
+        ReadGuard { lock: self }
+    }
+
+    /// Read lock for value, which may be upgraded to a write lock later.
+    /// Upgradable readers share the lock with each other and plain readers.
+    pub fn upgradable_read(&self) -> UpgradableReadGuard<'_, T, MAX_READERS> {
+        self.lock_shared(0);
+        UpgradableReadGuard {
+            guard: ReadGuard { lock: self },
+        }
+    }
+
+    fn lock_shared(&self, spin_iters: u32) {
+        self.lock_shared_with(spin_iters, false)
+    }
+
+    fn lock_shared_with(&self, mut spin_iters: u32, recursive: bool) {
+        yield_point();
+        #[cfg(all(feature = "metrics", feature = "registry"))]
+        let mut start = None;
+        // Block until no pending writer and the readers are below the cap,
+        // a recursive reader only waits for a writer holding the lock.
+        let writer = if recursive {
+            WRITE_LOCKED
+        } else {
+            WRITE_LOCKED | WRITER_WAITING
+        };
+        let blocked = |x: u32| x & writer != 0 || x / ONE_READER >= MAX_READERS;
         let mut x = self.state.load(Relaxed);
         loop {
-            // Block until no pending writer.
-            if x % 2 == 1 {
-                wait(&self.state, x);
+            if !blocked(x) {
+                match self
+                    .state
+                    .compare_exchange_weak(x, x + ONE_READER, Acquire, Relaxed)
+                {
+                    Ok(_) => break,
+                    Err(e) => x = e,
+                }
+                continue;
+            }
+            if spin_iters > 0 {
+                spin_iters -= 1;
+                hint::spin_loop();
                 x = self.state.load(Relaxed);
+                continue;
             }
-            // There's no writer waiting.
-            if x % 2 == 0 {
-                assert!(x != u32::MAX - 2, "too many readers");
-                match self.state.compare_exchange_weak(x, x + 2, Acquire, Relaxed) {
-                    Ok(_) => {
-                        break;
-                    }
-                    Err(e) => x = e,
+            if x & READERS_PARKED == 0 {
+                if let Err(e) =
+                    self.state
+                        .compare_exchange_weak(x, x | READERS_PARKED, Relaxed, Relaxed)
+                {
+                    x = e;
+                    continue;
                 }
             }
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(self, "RwLock read");
+            #[cfg(all(feature = "metrics", feature = "registry"))]
+            start.get_or_insert_with(std::time::Instant::now);
+            // The unlock letting readers in clears the flag before unparking.
+            self.park(READERS_KEY, |x| x & READERS_PARKED != 0 && blocked(x));
+            x = self.state.load(Relaxed);
         }
-        ReadGuard { lock: self }
+        #[cfg(feature = "registry")]
+        self.name.register(self);
+        #[cfg(all(feature = "metrics", feature = "registry"))]
+        if let Some(start) = start {
+            self.name.contended(start);
+        }
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "RwLock read");
     }
 
     /// Write lock fro value
-    pub fn write(&self) -> WriteGuard<T> {
+    pub fn write(&self) -> WriteGuard<'_, T, MAX_READERS> {
+        self.write_spin_then_park(crate::config::get().rwlock_spin_iters)
+    }
+
+    /// Write lock like `write`, but spin at most `spin_iters` times
+    /// before parking, so call sites can tune the budget.
+    pub fn write_spin_then_park(&self, mut spin_iters: u32) -> WriteGuard<'_, T, MAX_READERS> {
+        yield_point();
+        #[cfg(all(feature = "metrics", feature = "registry"))]
+        let mut start = None;
         let mut x = self.state.load(Relaxed);
         loop {
-            // Try to lock if there's no locking.
-            if x <= 1 {
+            // Try to lock if there's no locking, even if others are parked.
+            if x & WRITE_LOCKED == 0 && x < ONE_READER {
                 match self
                     .state
-                    .compare_exchange(x, RWLOCK_WLOCKED, Acquire, Relaxed)
+                    .compare_exchange(x, x | WRITE_LOCKED, Acquire, Relaxed)
                 {
                     Ok(_) => break,
                     Err(e) => {
@@ -74,9 +278,12 @@ impl<T> RwLock<T> {
             }
 
             // Block new incoming reader.
-            if x % 2 == 0 {
-                match self.state.compare_exchange(x, x + 1, Relaxed, Relaxed) {
-                    Ok(_) => {}
+            if x & WRITER_WAITING == 0 {
+                match self
+                    .state
+                    .compare_exchange(x, x | WRITER_WAITING, Relaxed, Relaxed)
+                {
+                    Ok(_) => x |= WRITER_WAITING,
                     Err(e) => {
                         x = e;
                         continue;
@@ -84,24 +291,149 @@ impl<T> RwLock<T> {
                 }
             }
 
-            // Wait if there're readers.
-            let w = self.writer_wake_counter.load(Acquire);
-            if self.state.load(Relaxed) >= 2 {
-                wait(&self.writer_wake_counter, w);
+            // Spin a while before parking.
+            if spin_iters > 0 {
+                spin_iters -= 1;
+                hint::spin_loop();
                 x = self.state.load(Relaxed);
+                continue;
+            }
+
+            if x & WRITERS_PARKED == 0 {
+                if let Err(e) = self
+                    .state
+                    .compare_exchange(x, x | WRITERS_PARKED, Relaxed, Relaxed)
+                {
+                    x = e;
+                    continue;
+                }
+            }
+
+            // Park while locked, the last reader may hand the lock over,
+            // or wake this to race for it once the lock is free.
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(self, "RwLock write");
+            #[cfg(all(feature = "metrics", feature = "registry"))]
+            start.get_or_insert_with(std::time::Instant::now);
+            let locked = |x: u32| x & WRITE_LOCKED != 0 || x >= ONE_READER;
+            if self.park(WRITERS_KEY, |x| x & WRITERS_PARKED != 0 && locked(x)) {
+                break;
             }
+            x = self.state.load(Relaxed);
         }
 
+        #[cfg(feature = "registry")]
+        self.name.register(self);
+        #[cfg(all(feature = "metrics", feature = "registry"))]
+        if let Some(start) = start {
+            self.name.contended(start);
+        }
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "RwLock write");
         WriteGuard { lock: self }
     }
+
+    fn key(&self, offset: usize) -> usize {
+        &self.state as *const AtomicU32 as usize + offset
+    }
+
+    // Park on the key while validate holds for the state, return true if
+    // the lock is handed over. Waiters wait on the state word itself with
+    // ffi, so foreign code can wake them.
+    fn park(&self, offset: usize, validate: impl Fn(u32) -> bool) -> bool {
+        if cfg!(feature = "ffi") {
+            let x = self.state.load(Relaxed);
+            if validate(x) {
+                wait(&self.state, x);
+            }
+            return false;
+        }
+        let validate = || validate(self.state.load(Relaxed));
+        parking::park(self.key(offset), validate, None) == ParkResult::Unparked(HANDOFF)
+    }
+
+    fn unpark_readers(&self) {
+        if cfg!(feature = "ffi") {
+            wake_all(&self.state);
+        } else {
+            parking::unpark_all(self.key(READERS_KEY), DEFAULT_UNPARK_TOKEN);
+        }
+    }
+
+    // Wake a parked writer, the flag is cleared once none is left.
+    fn unpark_writer(&self) {
+        if cfg!(feature = "ffi") {
+            self.state.fetch_and(!WRITERS_PARKED, Relaxed);
+            wake_all(&self.state);
+            return;
+        }
+        parking::unpark_one(self.key(WRITERS_KEY), |result: UnparkResult| {
+            if !result.have_more {
+                self.state.fetch_and(!WRITERS_PARKED, Relaxed);
+            }
+            DEFAULT_UNPARK_TOKEN
+        });
+    }
+
+    fn unpark_upgrader(&self) {
+        if cfg!(feature = "ffi") {
+            wake_all(&self.state);
+        } else {
+            parking::unpark_one(self.key(UPGRADER_KEY), |_| DEFAULT_UNPARK_TOKEN);
+        }
+    }
+
+    /// Write lock the state x of the last reader on behalf of a parked
+    /// writer and unpark it holding the lock, false if there's none to
+    /// hand to. Foreign waiters can't take a handoff, so not with ffi.
+    fn handoff(&self, x: u32) -> bool {
+        if cfg!(feature = "ffi") {
+            return false;
+        }
+        let mut handed = false;
+        parking::unpark_one(self.key(WRITERS_KEY), |result: UnparkResult| {
+            let parked = if result.have_more { WRITERS_PARKED } else { 0 };
+            if result.unparked {
+                let locked = (x - ONE_READER) & !WRITERS_PARKED | parked | WRITE_LOCKED;
+                handed = self
+                    .state
+                    .compare_exchange(x, locked, AcqRel, Relaxed)
+                    .is_ok();
+            }
+            if !handed && parked == 0 {
+                self.state.fetch_and(!WRITERS_PARKED, Relaxed);
+            }
+            if handed {
+                HANDOFF
+            } else {
+                DEFAULT_UNPARK_TOKEN
+            }
+        });
+        handed
+    }
+
+    // Release the write lock with threads waiting, let all readers and a
+    // writer race for it.
+    #[cold]
+    fn write_unlock_slow(&self) {
+        let x = self
+            .state
+            .fetch_and(!(WRITE_LOCKED | WRITER_WAITING | READERS_PARKED), Release);
+        if x & READERS_PARKED != 0 {
+            self.unpark_readers();
+        }
+        if x & WRITERS_PARKED != 0 {
+            self.unpark_writer();
+        }
+    }
 }
 
 /// A guard type for read operation of RwLock.
-pub struct ReadGuard<'a, T> {
-    pub(crate) lock: &'a RwLock<T>,
+pub struct ReadGuard<'a, T, const MAX_READERS: u32 = DEFAULT_MAX_READERS> {
+    pub(crate) lock: &'a RwLock<T, MAX_READERS>,
 }
 
-impl<T> Deref for ReadGuard<'_, T> {
+impl<T, const MAX_READERS: u32> Deref for ReadGuard<'_, T, MAX_READERS> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         // Safety: multi-thread get the immutable reference of inner value is safe.
@@ -109,23 +441,142 @@ impl<T> Deref for ReadGuard<'_, T> {
     }
 }
 
-impl<T> Drop for ReadGuard<'_, T> {
-    fn drop(&mut self) {
+impl<T, const MAX_READERS: u32> ReadGuard<'_, T, MAX_READERS> {
+    /// Unlock and lock again if a writer is waiting, a cheap point for a
+    /// long-running reader to let it in.
+    pub fn bump(this: &mut Self) {
+        if this.lock.state.load(Relaxed) & WRITER_WAITING == 0 {
+            return;
+        }
+        this.unlock();
+        // Blocks behind the waiting writer.
+        this.lock.lock_shared(0);
+    }
+
+    fn unlock(&self) {
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.lock, "RwLock read");
+        let lock = self.lock;
+        // The last reader hands the lock to a parked writer directly,
+        // so a new writer can't take it in between.
+        let x = lock.state.load(Relaxed);
+        if x & !(READERS_PARKED | WRITER_WAITING) == ONE_READER | WRITERS_PARKED && lock.handoff(x)
+        {
+            yield_point();
+            return;
+        }
         // Release the lock
-        if self.lock.state.fetch_sub(2, Release) == 3 {
+        let x = lock.state.fetch_sub(ONE_READER, Release);
+        let readers = x / ONE_READER - 1;
+        if readers == 0 && x & WRITERS_PARKED != 0 {
             // Notifying for writers.
-            self.lock.writer_wake_counter.fetch_add(1, Release);
-            wake_one(&self.lock.writer_wake_counter);
+            lock.unpark_writer();
+        } else if readers == 1 && x & UPGRADING != 0 {
+            // The reader left is the upgrading one, it waits for the others.
+            lock.unpark_upgrader();
+        }
+        if x / ONE_READER == MAX_READERS && x & READERS_PARKED != 0 {
+            // Readers may be blocked by the cap, wake them all since
+            // a woken reader may lose the freed slot to a new reader.
+            lock.state.fetch_and(!READERS_PARKED, Relaxed);
+            lock.unpark_readers();
         }
+        yield_point();
     }
 }
 
+impl<T, const MAX_READERS: u32> Drop for ReadGuard<'_, T, MAX_READERS> {
+    fn drop(&mut self) {
+        self.unlock();
+    }
+}
+
+/// A guard type for upgradable read operation of RwLock.
+pub struct UpgradableReadGuard<'a, T, const MAX_READERS: u32 = DEFAULT_MAX_READERS> {
+    guard: ReadGuard<'a, T, MAX_READERS>,
+}
+
+impl<'a, T, const MAX_READERS: u32> UpgradableReadGuard<'a, T, MAX_READERS> {
+    /// Upgrade to a write lock, blocking until all other readers are gone.
+    ///
+    /// Only one upgrade runs at a time: if another upgradable reader is
+    /// upgrading, this fails immediately and gives back the read guard.
+    /// The upgrading reader waits for every other reader, so the caller
+    /// must drop the returned guard soon, or the upgrade never completes.
+    /// The read lock is held all the way, no writer comes in between.
+    pub fn upgrade(this: Self) -> Result<WriteGuard<'a, T, MAX_READERS>, (Self, UpgradeError)> {
+        let lock = this.guard.lock;
+        if lock.state.fetch_or(UPGRADING, Acquire) & UPGRADING != 0 {
+            return Err((this, UpgradeError));
+        }
+        let mut x = lock.state.load(Relaxed);
+        loop {
+            // Upgrade if this is the only reader, a writer may be waiting.
+            if x / ONE_READER == 1 {
+                let locked = (x - ONE_READER) & !UPGRADING | WRITE_LOCKED;
+                match lock.state.compare_exchange(x, locked, Acquire, Relaxed) {
+                    Ok(_) => break,
+                    Err(e) => {
+                        x = e;
+                        continue;
+                    }
+                }
+            }
+
+            // Block new incoming reader.
+            if x & WRITER_WAITING == 0 {
+                if let Err(e) = lock
+                    .state
+                    .compare_exchange(x, x | WRITER_WAITING, Relaxed, Relaxed)
+                {
+                    x = e;
+                    continue;
+                }
+            }
+
+            // Park while there're other readers, the second last of them
+            // unparks this.
+            #[cfg(feature = "deadlock-detection")]
+            crate::deadlock::waiting(lock, "RwLock write");
+            lock.park(UPGRADER_KEY, |x| x / ONE_READER > 1);
+            x = lock.state.load(Relaxed);
+        }
+        #[cfg(feature = "deadlock-detection")]
+        {
+            crate::deadlock::released(lock, "RwLock read");
+            crate::deadlock::acquired(lock, "RwLock write");
+        }
+        // The read lock is turned into the write lock.
+        mem::forget(this);
+        Ok(WriteGuard { lock })
+    }
+}
+
+impl<T, const MAX_READERS: u32> Deref for UpgradableReadGuard<'_, T, MAX_READERS> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+/// Error returned by an upgrade racing with another upgrade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpgradeError;
+
+impl fmt::Display for UpgradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "another reader is upgrading")
+    }
+}
+
+impl Error for UpgradeError {}
+
 /// A guard type for write operation of RwLock.
-pub struct WriteGuard<'a, T> {
-    pub(crate) lock: &'a RwLock<T>,
+pub struct WriteGuard<'a, T, const MAX_READERS: u32 = DEFAULT_MAX_READERS> {
+    pub(crate) lock: &'a RwLock<T, MAX_READERS>,
 }
 
-impl<T> Deref for WriteGuard<'_, T> {
+impl<T, const MAX_READERS: u32> Deref for WriteGuard<'_, T, MAX_READERS> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         // Safety: There's only one guard of same mutex can be accessed at a time,
@@ -134,7 +585,7 @@ impl<T> Deref for WriteGuard<'_, T> {
     }
 }
 
-impl<T> DerefMut for WriteGuard<'_, T> {
+impl<T, const MAX_READERS: u32> DerefMut for WriteGuard<'_, T, MAX_READERS> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         // Safety: There's only one guard of same mutex can be accessed at a time,
         // it's safe to access the inner value with mutable reference by mutable reference.
@@ -142,14 +593,37 @@ impl<T> DerefMut for WriteGuard<'_, T> {
     }
 }
 
-impl<T> Drop for WriteGuard<'_, T> {
+impl<T, const MAX_READERS: u32> WriteGuard<'_, T, MAX_READERS> {
+    /// Unlock, let waiting readers and writers in, and lock again,
+    /// a cooperative yield point for a long-running writer.
+    /// It always unlocks, unlike the bump of ReadGuard, since a spinning
+    /// waiter doesn't show in the state.
+    pub fn bump(this: &mut Self) {
+        this.unlock();
+        std::thread::yield_now();
+        // The old guard is unlocked already, don't unlock it again.
+        let lock = this.lock;
+        mem::forget(mem::replace(this, lock.write()));
+    }
+
+    fn unlock(&self) {
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.lock, "RwLock write");
+        // Release the lock, wake up one writer and all readers if parked.
+        let state = &self.lock.state;
+        if state
+            .compare_exchange(WRITE_LOCKED, 0, Release, Relaxed)
+            .is_err()
+        {
+            self.lock.write_unlock_slow();
+        }
+        yield_point();
+    }
+}
+
+impl<T, const MAX_READERS: u32> Drop for WriteGuard<'_, T, MAX_READERS> {
     fn drop(&mut self) {
-        // Release the lock
-        self.lock.state.store(0, Release);
-        self.lock.writer_wake_counter.fetch_add(1, Release);
-        // Wake up one writer and wake up all reader.
-        wake_one(&self.lock.writer_wake_counter);
-        wake_all(&self.lock.state)
+        self.unlock();
     }
 }
 
@@ -157,6 +631,8 @@ impl<T> Drop for WriteGuard<'_, T> {
 mod tests {
     #[allow(unused_imports)]
     use super::RwLock;
+    use super::{ReadGuard, UpgradableReadGuard, UpgradeError, WriteGuard};
+    use crate::barrier::Barrier;
     #[allow(unused_imports)]
     use std::thread;
 
@@ -186,4 +662,212 @@ mod tests {
             assert!(g.as_slice() == [1, 2, 2] || g.as_slice() == [2, 2, 1]);
         }
     }
+
+    #[test]
+    fn test_size() {
+        use super::WideRwLock;
+        use std::mem::size_of;
+
+        assert_eq!(size_of::<WideRwLock<u32>>(), 24);
+        #[cfg(not(feature = "registry"))]
+        {
+            assert_eq!(size_of::<RwLock<()>>(), 4);
+            assert_eq!(size_of::<RwLock<u32>>(), 8);
+        }
+    }
+
+    #[test]
+    fn test_spin_then_park() {
+        let x = RwLock::new(0);
+        assert!(!x.is_locked());
+        let g = x.read();
+        assert!(x.is_locked());
+        drop(g);
+        thread::scope(|s| {
+            let x = &x;
+            for spin_iters in [0, 10, 10_000] {
+                s.spawn(move || {
+                    for _ in 0..10_000 {
+                        *x.write_spin_then_park(spin_iters) += 1;
+                        assert!(*x.read_spin_then_park(spin_iters) > 0);
+                    }
+                });
+            }
+        });
+        assert!(!x.is_locked());
+        assert_eq!(*x.read(), 30_000);
+    }
+
+    #[test]
+    fn test_reader_cap() {
+        let x = RwLock::<_, 2>::with_reader_cap(0);
+        let readers = std::sync::atomic::AtomicU32::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        let _guard = x.read();
+                        let n = readers.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        assert!(n < 2, "reader cap exceeded");
+                        readers.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..100 {
+                    *x.write() += 1;
+                }
+            });
+        });
+        assert_eq!(*x.read(), 100);
+    }
+
+    #[test]
+    fn test_reader_saturation() {
+        const CAP: u32 = 1 << 15;
+        let x = RwLock::<_, CAP>::with_reader_cap(0);
+        let entered = std::sync::atomic::AtomicU32::new(0);
+        let held: Vec<_> = (0..CAP).map(|_| x.read()).collect();
+        thread::scope(|s| {
+            for _ in 0..64 {
+                s.spawn(|| {
+                    let _guard = x.read();
+                    entered.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                });
+            }
+            thread::sleep(std::time::Duration::from_millis(100));
+            // All the readers over the cap are parked.
+            assert_eq!(entered.load(std::sync::atomic::Ordering::SeqCst), 0);
+            drop(held);
+        });
+        assert_eq!(entered.into_inner(), 64);
+        assert_eq!(*x.write(), 0);
+    }
+
+    // Foreign waiters can't take a handoff, so there's none with ffi.
+    #[cfg(not(feature = "ffi"))]
+    #[test]
+    fn test_writer_handoff() {
+        use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+
+        let x = RwLock::new(0);
+        let release = AtomicBool::new(false);
+        let r = x.read();
+        thread::scope(|s| {
+            s.spawn(|| {
+                let mut g = x.write();
+                *g += 1;
+                while !release.load(SeqCst) {
+                    thread::yield_now();
+                }
+            });
+            crate::parking::wait_parked(x.key(super::WRITERS_KEY), 1);
+            drop(r);
+            // Write locked for the writer by the last reader at once.
+            assert_ne!(x.state.load(SeqCst) & super::WRITE_LOCKED, 0);
+            release.store(true, SeqCst);
+        });
+        assert_eq!(*x.read(), 1);
+        assert_eq!(x.state.load(SeqCst), 0);
+    }
+
+    #[test]
+    fn test_read_recursive() {
+        let x = RwLock::new(0);
+        thread::scope(|s| {
+            let outer = x.read();
+            s.spawn(|| *x.write() += 1);
+            // Wait until the writer is pending.
+            while x.state.load(std::sync::atomic::Ordering::Relaxed) & super::WRITER_WAITING == 0 {
+                thread::yield_now();
+            }
+            assert_eq!(*x.read_recursive(), 0);
+            drop(outer);
+        });
+        assert_eq!(*x.read_recursive(), 1);
+    }
+
+    #[test]
+    fn test_bump() {
+        let x = RwLock::new(0);
+        let mut r = x.read();
+        // No writer waits, so it keeps the lock.
+        ReadGuard::bump(&mut r);
+        thread::scope(|s| {
+            s.spawn(|| *x.write() += 1);
+            while *r == 0 {
+                ReadGuard::bump(&mut r);
+            }
+        });
+        drop(r);
+
+        let mut w = x.write();
+        thread::scope(|s| {
+            // Seen either while bumping or after unlocking.
+            s.spawn(|| assert!([2, 3].contains(&*x.read())));
+            *w += 1;
+            WriteGuard::bump(&mut w);
+            *w += 1;
+            drop(w);
+        });
+        assert_eq!(*x.read(), 3);
+    }
+
+    #[test]
+    fn test_upgrade_race() {
+        let x = RwLock::new(0);
+        let barrier = Barrier::new(2);
+        for _ in 0..100 {
+            let results: Vec<_> = thread::scope(|s| {
+                let handles: Vec<_> = (0..2)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let guard = x.upgradable_read();
+                            barrier.wait();
+                            match UpgradableReadGuard::upgrade(guard) {
+                                Ok(mut w) => {
+                                    *w += 1;
+                                    Ok(())
+                                }
+                                Err((_, e)) => Err(e),
+                            }
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            });
+            assert!(results.contains(&Ok(())));
+            assert!(results.contains(&Err(UpgradeError)));
+        }
+        assert_eq!(*x.read(), 100);
+    }
+
+    #[test]
+    fn test_upgrade_stress() {
+        let x = RwLock::new(0);
+        let upgraded = std::sync::atomic::AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        let guard = x.upgradable_read();
+                        let seen = *guard;
+                        if let Ok(mut w) = UpgradableReadGuard::upgrade(guard) {
+                            // No writer comes in between.
+                            assert_eq!(*w, seen);
+                            *w += 1;
+                            upgraded.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..10_000 {
+                    *x.write() += 1;
+                    assert!(*x.read() > 0);
+                }
+            });
+        });
+        assert_eq!(*x.read(), upgraded.into_inner() + 10_000);
+    }
 }
@@ -0,0 +1,216 @@
+use std::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{
+        AtomicU32, AtomicU64,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use crate::futex::{wait, wake_all, wake_one, yield_point};
+
+const RWLOCK_WLOCKED: u64 = u64::MAX;
+
+/// A reader-writer lock with 64-bit state, the count of readers is never
+/// capped in practice, e.g. for massively threaded green-thread runtimes.
+///
+/// The futex only waits on 32-bit words, so readers and writers wait on
+/// their own wake counters instead of the state, which costs an extra
+/// atomic operation on unlock compared with RwLock.
+pub struct WideRwLock<T> {
+    state: AtomicU64,               // Counter of reader, RWLOCK_WLOCKED for write lock.
+    reader_wake_counter: AtomicU32, // Counter of wake up reader. Just like a Condvar.
+    writer_wake_counter: AtomicU32, // Counter of wake up writer. Just like a Condvar.
+    value: UnsafeCell<T>,
+}
+
+/// Implement Sync if and only if T is Send.
+/// Only one thread access the &mut T at a time,
+/// so T is not required to be Sync.
+unsafe impl<T> Sync for WideRwLock<T> where T: Send + Sync {}
+
+impl<T> WideRwLock<T> {
+    /// Create a new rwlock for given value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU64::new(0),
+            reader_wake_counter: AtomicU32::new(0),
+            writer_wake_counter: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Read lock for value.
+    pub fn read(&self) -> WideReadGuard<'_, T> {
+        yield_point();
+        let mut x = self.state.load(Relaxed);
+        loop {
+            // Block until no pending writer.
+            if x % 2 == 1 {
+                let r = self.reader_wake_counter.load(Acquire);
+                if self.state.load(Relaxed) % 2 == 1 {
+                    #[cfg(feature = "deadlock-detection")]
+                    crate::deadlock::waiting(self, "WideRwLock read");
+                    wait(&self.reader_wake_counter, r);
+                }
+                x = self.state.load(Relaxed);
+                continue;
+            }
+            match self.state.compare_exchange_weak(x, x + 2, Acquire, Relaxed) {
+                Ok(_) => break,
+                Err(e) => x = e,
+            }
+        }
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "WideRwLock read");
+        WideReadGuard { lock: self }
+    }
+
+    /// Write lock for value.
+    pub fn write(&self) -> WideWriteGuard<'_, T> {
+        yield_point();
+        let mut x = self.state.load(Relaxed);
+        loop {
+            // Try to lock if there's no locking.
+            if x <= 1 {
+                match self
+                    .state
+                    .compare_exchange(x, RWLOCK_WLOCKED, Acquire, Relaxed)
+                {
+                    Ok(_) => break,
+                    Err(e) => {
+                        x = e;
+                        continue;
+                    }
+                }
+            }
+
+            // Block new incoming reader.
+            if x.is_multiple_of(2) {
+                match self.state.compare_exchange(x, x + 1, Relaxed, Relaxed) {
+                    Ok(_) => {}
+                    Err(e) => {
+                        x = e;
+                        continue;
+                    }
+                }
+            }
+
+            // Wait if there're readers or another writer.
+            let w = self.writer_wake_counter.load(Acquire);
+            if self.state.load(Relaxed) >= 2 {
+                #[cfg(feature = "deadlock-detection")]
+                crate::deadlock::waiting(self, "WideRwLock write");
+                wait(&self.writer_wake_counter, w);
+            }
+            x = self.state.load(Relaxed);
+        }
+
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "WideRwLock write");
+        WideWriteGuard { lock: self }
+    }
+}
+
+/// A guard type for read operation of WideRwLock.
+pub struct WideReadGuard<'a, T> {
+    lock: &'a WideRwLock<T>,
+}
+
+impl<T> Deref for WideReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: multi-thread get the immutable reference of inner value is safe.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for WideReadGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.lock, "WideRwLock read");
+        // Release the lock
+        if self.lock.state.fetch_sub(2, Release) == 3 {
+            // Notifying for writers.
+            self.lock.writer_wake_counter.fetch_add(1, Release);
+            wake_one(&self.lock.writer_wake_counter);
+        }
+        yield_point();
+    }
+}
+
+/// A guard type for write operation of WideRwLock.
+pub struct WideWriteGuard<'a, T> {
+    lock: &'a WideRwLock<T>,
+}
+
+impl<T> Deref for WideWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: There's only one guard of same lock can be accessed at a time,
+        // it's safe to access the inner value by any shared reference.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for WideWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: There's only one guard of same lock can be accessed at a time,
+        // it's safe to access the inner value with mutable reference by mutable reference.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for WideWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.lock, "WideRwLock write");
+        // Release the lock
+        self.lock.state.store(0, Release);
+        self.lock.writer_wake_counter.fetch_add(1, Release);
+        self.lock.reader_wake_counter.fetch_add(1, Release);
+        // Wake up one writer and wake up all reader.
+        wake_one(&self.lock.writer_wake_counter);
+        wake_all(&self.lock.reader_wake_counter);
+        yield_point();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WideRwLock;
+    use std::sync::atomic::Ordering::Relaxed;
+    use std::thread;
+
+    #[test]
+    fn test_wide_rwlock() {
+        let x = WideRwLock::new(0);
+        // Pretend more readers than a 32-bit state could count are inside.
+        let readers = u32::MAX as u64 + 1;
+        x.state.store(readers * 2, Relaxed);
+        thread::scope(|s| {
+            let g = x.read();
+            assert_eq!(x.state.load(Relaxed), (readers + 1) * 2);
+            s.spawn(|| *x.write() += 1);
+            // A waiting writer blocks new readers, let the readers leave.
+            while x.state.load(Relaxed).is_multiple_of(2) {
+                thread::yield_now();
+            }
+            x.state.fetch_sub(readers * 2, Relaxed);
+            drop(g);
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        assert!(*x.read() <= 1001);
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    *x.write() += 1;
+                }
+            });
+        });
+        assert_eq!(*x.read(), 1001);
+    }
+}
@@ -0,0 +1,159 @@
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::{fence, AtomicU64};
+
+use crate::spin::SpinLock;
+
+mod stat;
+pub use stat::{Pod, StatCell, StatWriter};
+
+/// Versions kept by `SeqLock::new`.
+pub const DEFAULT_HISTORY: usize = 4;
+
+struct Slot<T> {
+    // 2 * (version + 1) while the slot holds the version,
+    // odd while it's being written, 0 if never written.
+    seq: AtomicU64,
+    value: UnsafeCell<T>,
+}
+
+/// A sequence lock keeping a ring of recent versions, e.g. for sampling
+/// high-frequency telemetry: writers never wait for readers, and each write
+/// goes to the slot of the oldest version, so a reader of the latest version
+/// retries only if `history` more versions are written meanwhile, rather
+/// than on every racing write.
+pub struct SeqLock<T> {
+    slots: Box<[Slot<T>]>,
+    // Latest version published.
+    head: AtomicU64,
+    writer: SpinLock<()>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+unsafe impl<T: Copy + Send> Send for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    /// Create a seqlock keeping the latest `DEFAULT_HISTORY` versions.
+    pub fn new(value: T) -> Self {
+        Self::with_history(value, DEFAULT_HISTORY)
+    }
+
+    /// Create a seqlock keeping the latest `history` versions.
+    pub fn with_history(value: T, history: usize) -> Self {
+        assert!(history > 0, "history must be positive");
+        let slots = (0..history)
+            .map(|i| Slot {
+                seq: AtomicU64::new(if i == 0 { 2 } else { 0 }),
+                value: UnsafeCell::new(value),
+            })
+            .collect();
+        Self {
+            slots,
+            head: AtomicU64::new(0),
+            writer: SpinLock::new(()),
+        }
+    }
+
+    /// Publish a new version, return its number. Writers are serialized,
+    /// but never wait for readers.
+    pub fn write(&self, value: T) -> u64 {
+        let _writer = self.writer.lock();
+        let version = self.head.load(Relaxed) + 1;
+        let slot = &self.slots[version as usize % self.slots.len()];
+        slot.seq.store(2 * version + 1, Relaxed);
+        fence(Release);
+        // Safety: writers are serialized, readers validate what they copied
+        // by the sequence and discard torn copies.
+        unsafe { ptr::write_volatile(slot.value.get(), value) };
+        slot.seq.store(2 * version + 2, Release);
+        self.head.store(version, Release);
+        version
+    }
+
+    /// Read the latest consistent value.
+    pub fn read(&self) -> T {
+        self.read_versioned().1
+    }
+
+    /// Read the latest consistent value with its version.
+    pub fn read_versioned(&self) -> (u64, T) {
+        loop {
+            // Torn only if the slot is reused by a later version, then the
+            // head has moved on.
+            if let Some(read) = self.read_slot(self.head.load(Acquire)) {
+                return read;
+            }
+        }
+    }
+
+    /// Consistent recent versions, the latest first.
+    /// Versions racing writes are skipped.
+    pub fn history(&self) -> Vec<(u64, T)> {
+        let head = self.head.load(Acquire);
+        let kept = (self.slots.len() as u64).min(head + 1);
+        (0..kept)
+            .filter_map(|back| self.read_slot(head - back))
+            .collect()
+    }
+
+    /// Latest version published, 0 for the initial value.
+    pub fn version(&self) -> u64 {
+        self.head.load(Acquire)
+    }
+
+    fn read_slot(&self, version: u64) -> Option<(u64, T)> {
+        let slot = &self.slots[version as usize % self.slots.len()];
+        let seq = slot.seq.load(Acquire);
+        if seq != 2 * version + 2 {
+            return None;
+        }
+        // Safety: T is Copy, a copy torn by a racing write is discarded.
+        let value = unsafe { ptr::read_volatile(slot.value.get()) };
+        fence(Acquire);
+        (slot.seq.load(Relaxed) == seq).then_some((version, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering::Relaxed},
+        thread,
+    };
+
+    use super::SeqLock;
+
+    #[test]
+    fn test_seqlock() {
+        let lock = SeqLock::with_history((0u64, 0u64), 3);
+        assert_eq!(lock.history(), [(0, (0, 0))]);
+        for i in 1..=4 {
+            assert_eq!(lock.write((i, i)), i);
+        }
+        assert_eq!(lock.history(), [(4, (4, 4)), (3, (3, 3)), (2, (2, 2))]);
+
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 5..100000 {
+                    lock.write((i, i));
+                }
+                done.store(true, Relaxed);
+            });
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    while !done.load(Relaxed) {
+                        // Never torn, and never goes backwards.
+                        let (version, (a, b)) = lock.read_versioned();
+                        assert!(a == b && a == version && version >= last);
+                        last = version;
+                        thread::yield_now();
+                    }
+                });
+            }
+        });
+        assert_eq!(lock.read(), (99999, 99999));
+    }
+}
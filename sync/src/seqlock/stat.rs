@@ -0,0 +1,237 @@
+use std::{
+    hint,
+    marker::PhantomData,
+    mem::{size_of, MaybeUninit},
+    ptr,
+    sync::atomic::{
+        fence, AtomicBool, AtomicU64,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+/// Plain old data: Copy, without padding, and valid for any bit pattern,
+/// so it can be copied word by word through atomics.
+/// Implement it for structs by `pod_struct!`.
+///
+/// # Safety
+///
+/// The type must have no padding bytes and no invalid bit patterns.
+pub unsafe trait Pod: Copy + Send + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Declare a `#[repr(C)]` struct of Pod fields implementing Pod,
+/// failing to compile if the fields leave padding.
+///
+/// ```
+/// sync::pod_struct! {
+///     pub struct Stats {
+///         pub requests: u64,
+///         pub errors: u32,
+///         pub max_latency_us: u32,
+///     }
+/// }
+/// let cell = sync::seqlock::StatCell::new(Stats::default());
+/// cell.writer().unwrap().update(|s| s.requests += 1);
+/// assert_eq!(cell.read().requests, 1);
+/// ```
+#[macro_export]
+macro_rules! pod_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_meta:meta])* $field_vis:vis $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq)]
+        #[repr(C)]
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $ty),*
+        }
+
+        // Safety: the fields are Pod, and the size check rules out padding.
+        unsafe impl $crate::seqlock::Pod for $name {}
+
+        const _: fn() = || {
+            fn assert_pod<T: $crate::seqlock::Pod>() {}
+            $(assert_pod::<$ty>();)*
+        };
+        const _: () = assert!(
+            ::std::mem::size_of::<$name>() == 0 $(+ ::std::mem::size_of::<$ty>())*,
+            "pod struct must not have padding"
+        );
+    };
+}
+
+/// A cell of a small stats struct updated by a single writer and sampled
+/// by many readers, e.g. for metrics hot paths where even an uncontended
+/// Mutex shows up in profiles.
+///
+/// Writing is wait-free: the value is stored word by word into atomics
+/// under a sequence number, readers retry if a write raced their copy.
+pub struct StatCell<T> {
+    // Odd while the writer is storing the words.
+    seq: AtomicU64,
+    words: Box<[AtomicU64]>,
+    writer: AtomicBool,
+    _value: PhantomData<T>,
+}
+
+impl<T: Pod> StatCell<T> {
+    pub fn new(value: T) -> Self {
+        let words = (0..size_of::<T>().div_ceil(8))
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        let cell = Self {
+            seq: AtomicU64::new(0),
+            words,
+            writer: AtomicBool::new(false),
+            _value: PhantomData,
+        };
+        cell.store(&value);
+        cell
+    }
+
+    /// The writer of the cell, None if it's taken by another writer.
+    pub fn writer(&self) -> Option<StatWriter<'_, T>> {
+        if self.writer.swap(true, Acquire) {
+            return None;
+        }
+        Some(StatWriter {
+            cell: self,
+            value: self.read(),
+        })
+    }
+
+    /// Sample the latest value, never torn.
+    pub fn read(&self) -> T {
+        loop {
+            let seq = self.seq.load(Acquire);
+            if seq % 2 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+            let mut value = MaybeUninit::<T>::uninit();
+            let bytes = value.as_mut_ptr() as *mut u8;
+            for (i, word) in self.words.iter().enumerate() {
+                let word = word.load(Relaxed).to_ne_bytes();
+                let len = (size_of::<T>() - i * 8).min(8);
+                // Safety: the bytes are within value.
+                unsafe { ptr::copy_nonoverlapping(word.as_ptr(), bytes.add(i * 8), len) };
+            }
+            fence(Acquire);
+            if self.seq.load(Relaxed) == seq {
+                // Safety: every byte is written, and T is valid for any bits.
+                return unsafe { value.assume_init() };
+            }
+        }
+    }
+
+    // Only called by the single writer.
+    fn store(&self, value: &T) {
+        let seq = self.seq.load(Relaxed);
+        self.seq.store(seq + 1, Relaxed);
+        fence(Release);
+        let bytes = value as *const T as *const u8;
+        for (i, word) in self.words.iter().enumerate() {
+            let mut buf = [0u8; 8];
+            let len = (size_of::<T>() - i * 8).min(8);
+            // Safety: the bytes are within value, and T has no padding.
+            unsafe { ptr::copy_nonoverlapping(bytes.add(i * 8), buf.as_mut_ptr(), len) };
+            word.store(u64::from_ne_bytes(buf), Relaxed);
+        }
+        self.seq.store(seq + 2, Release);
+    }
+}
+
+/// The single writer of a StatCell, keeping a copy of the value.
+pub struct StatWriter<'a, T: Pod> {
+    cell: &'a StatCell<T>,
+    value: T,
+}
+
+impl<T: Pod> StatWriter<'_, T> {
+    /// Update the value by f and publish it.
+    pub fn update(&mut self, f: impl FnOnce(&mut T)) {
+        f(&mut self.value);
+        self.cell.store(&self.value);
+    }
+
+    /// Publish a new value.
+    pub fn set(&mut self, value: T) {
+        self.value = value;
+        self.cell.store(&self.value);
+    }
+
+    /// The value last published.
+    pub fn get(&self) -> T {
+        self.value
+    }
+}
+
+impl<T: Pod> Drop for StatWriter<'_, T> {
+    fn drop(&mut self) {
+        self.cell.writer.store(false, Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering::Relaxed},
+        thread,
+    };
+
+    use super::StatCell;
+
+    crate::pod_struct! {
+        struct Stats {
+            requests: u64,
+            errors: u32,
+            buckets: [u16; 2],
+            total: u64,
+        }
+    }
+
+    #[test]
+    fn test_stat_cell() {
+        let cell = StatCell::new(Stats::default());
+        let mut writer = cell.writer().unwrap();
+        assert!(cell.writer().is_none());
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    while !done.load(Relaxed) {
+                        // Never torn.
+                        let stats = cell.read();
+                        assert_eq!(stats.requests, stats.total);
+                        assert_eq!(stats.errors as u64, stats.requests / 2);
+                        thread::yield_now();
+                    }
+                });
+            }
+            for i in 1..=100_000u64 {
+                writer.update(|s| {
+                    s.requests = i;
+                    s.errors = (i / 2) as u32;
+                    s.buckets[(i % 2) as usize] += 1;
+                    s.total = i;
+                });
+            }
+            done.store(true, Relaxed);
+        });
+        assert_eq!(cell.read().buckets, [50_000, 50_000]);
+        drop(writer);
+        assert_eq!(cell.writer().unwrap().get().total, 100_000);
+    }
+}
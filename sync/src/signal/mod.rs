@@ -0,0 +1,177 @@
+//! Fan-out of SIGINT and SIGTERM into the crate's channels, so services get
+//! shutdown notifications without another crate.
+//!
+//! The handlers only record the signal and wake a forwarding thread,
+//! which publishes it to the channels outside of the signal context.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU32, Ordering::SeqCst},
+        OnceLock,
+    },
+    thread,
+};
+
+use crate::{
+    cancel::CancellationToken,
+    channel::{
+        broadcast::{self, Policy},
+        watch,
+    },
+    futex::wait,
+};
+
+/// A signal forwarded by the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// SIGINT, e.g. Ctrl-C.
+    Interrupt,
+    /// SIGTERM, e.g. stopped by a service manager.
+    Terminate,
+}
+
+impl Signal {
+    const ALL: [Signal; 2] = [Signal::Interrupt, Signal::Terminate];
+
+    fn number(self) -> libc::c_int {
+        match self {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+        }
+    }
+
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+// Bits of the signals received but not forwarded yet.
+static PENDING: AtomicU32 = AtomicU32::new(0);
+// The bus, or the kind of error installing it failed with.
+static BUS: OnceLock<Result<SignalBus, io::ErrorKind>> = OnceLock::new();
+
+/// The process-wide bus of SIGINT and SIGTERM.
+pub struct SignalBus {
+    last: watch::Receiver<Option<Signal>>,
+    signals: broadcast::Sender<Signal>,
+    shutdown: CancellationToken,
+}
+
+impl SignalBus {
+    /// Install the handlers once and get the bus. The handlers replace the
+    /// default ones, so the process no longer exits on the signals by itself.
+    ///
+    /// A failure is final, later calls fail with the same kind of error.
+    pub fn install() -> io::Result<&'static SignalBus> {
+        let mut error = None;
+        let bus = BUS.get_or_init(|| {
+            let (tx, last) = watch::channel(None);
+            let bus = SignalBus {
+                last,
+                signals: broadcast::channel(16),
+                shutdown: CancellationToken::new(),
+            };
+            let (signals, shutdown) = (bus.signals.clone(), bus.shutdown.clone());
+            let forwarder = thread::Builder::new()
+                .name("sync-signal".to_string())
+                .spawn(move || forward(tx, signals, shutdown));
+            match forwarder.and_then(|_| install_handlers()) {
+                Ok(()) => Ok(bus),
+                Err(e) => Err(error.insert(e).kind()),
+            }
+        });
+        match (bus, error) {
+            (Ok(bus), _) => Ok(bus),
+            // The first caller gets the error itself.
+            (Err(_), Some(e)) => Err(e),
+            (Err(kind), None) => Err((*kind).into()),
+        }
+    }
+
+    /// Watch the last signal received, None before any.
+    pub fn watch(&self) -> watch::Receiver<Option<Signal>> {
+        self.last.clone()
+    }
+
+    /// Receive every signal from now on.
+    pub fn subscribe(&self, policy: Policy) -> broadcast::Receiver<Signal> {
+        self.signals.subscribe(policy)
+    }
+
+    /// A token cancelled by the first signal.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+}
+
+/// Publish the pending signals, run by the forwarding thread.
+fn forward(
+    last: watch::Sender<Option<Signal>>,
+    signals: broadcast::Sender<Signal>,
+    shutdown: CancellationToken,
+) {
+    loop {
+        let pending = PENDING.swap(0, SeqCst);
+        if pending == 0 {
+            wait(&PENDING, 0);
+            continue;
+        }
+        for signal in Signal::ALL {
+            if pending & signal.bit() != 0 {
+                last.send(Some(signal));
+                // Nobody subscribing is fine.
+                let _ = signals.send(signal);
+                shutdown.cancel();
+            }
+        }
+    }
+}
+
+fn install_handlers() -> io::Result<()> {
+    for signal in Signal::ALL {
+        // Safety: the handler only touches an atomic and wakes the futex,
+        // both async-signal-safe.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal.number(), &action, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+extern "C" fn handle(number: libc::c_int) {
+    for signal in Signal::ALL {
+        if signal.number() == number {
+            PENDING.fetch_or(signal.bit(), SeqCst);
+        }
+    }
+    // Skip the futex layer, its hooks are not async-signal-safe.
+    atomic_wait::wake_one(&PENDING);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Signal, SignalBus};
+    use crate::channel::broadcast::Policy;
+
+    #[test]
+    fn test_signal_bus() {
+        let bus = SignalBus::install().unwrap();
+        let mut signals = bus.subscribe(Policy::Block);
+        let watch = bus.watch();
+        let token = bus.shutdown_token();
+        unsafe { libc::raise(libc::SIGINT) };
+        assert_eq!(signals.recv().unwrap(), Signal::Interrupt);
+        token.wait();
+        assert_eq!(*watch.borrow(), Some(Signal::Interrupt));
+        unsafe { libc::raise(libc::SIGTERM) };
+        assert_eq!(signals.recv().unwrap(), Signal::Terminate);
+        assert!(std::ptr::eq(bus, SignalBus::install().unwrap()));
+    }
+}
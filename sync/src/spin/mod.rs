@@ -1,12 +1,34 @@
 use std::cell::UnsafeCell;
 use std::ops::{Deref, DerefMut};
 use std::sync::atomic::AtomicBool;
+#[cfg(feature = "deadlock-detection")]
+use std::sync::atomic::Ordering::Relaxed;
 use std::sync::atomic::Ordering::{Acquire, Release};
 
+use crate::config::YieldStrategy;
+
+#[cfg(feature = "metrics")]
+mod stats;
+#[cfg(feature = "metrics")]
+pub use stats::{SpinHistogram, SpinLockStats, SPIN_BUCKETS};
+
+/// Spins of an acquisition before it's counted as starved by default.
+#[cfg(feature = "metrics")]
+pub const DEFAULT_STARVATION_SPINS: u64 = 1 << 20;
+
 /// A raw spin lock implementation
 pub struct SpinLock<T> {
     locked: AtomicBool,
     value: UnsafeCell<T>,
+    // Called on the value if a panic unwinds through a held guard.
+    repair: Option<fn(&mut T)>,
+    #[cfg(feature = "metrics")]
+    stats: stats::Counters,
+    // Spins of an acquisition to be counted as starved, and the alarm called then.
+    #[cfg(feature = "metrics")]
+    starvation_spins: u64,
+    #[cfg(feature = "metrics")]
+    on_starvation: Option<fn(u64)>,
 }
 
 /// Implement Sync if and only if T is Send
@@ -19,17 +41,96 @@ impl<T> SpinLock<T> {
         Self {
             locked: AtomicBool::new(false),
             value: UnsafeCell::new(value),
+            repair: None,
+            #[cfg(feature = "metrics")]
+            stats: stats::Counters::new(),
+            #[cfg(feature = "metrics")]
+            starvation_spins: DEFAULT_STARVATION_SPINS,
+            #[cfg(feature = "metrics")]
+            on_starvation: None,
         }
     }
 
+    /// Create a spin lock which repairs the value instead of poisoning:
+    /// if a panic unwinds through a held guard,
+    /// the value is passed to `repair` before unlock.
+    pub const fn with_repair(value: T, repair: fn(&mut T)) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+            repair: Some(repair),
+            #[cfg(feature = "metrics")]
+            stats: stats::Counters::new(),
+            #[cfg(feature = "metrics")]
+            starvation_spins: DEFAULT_STARVATION_SPINS,
+            #[cfg(feature = "metrics")]
+            on_starvation: None,
+        }
+    }
+
+    /// Count an acquisition as starved once it spins `spins` times,
+    /// and call `alarm` with the spins from the starved thread then,
+    /// since a test-and-set spin lock can starve unlucky threads silently.
+    #[cfg(feature = "metrics")]
+    pub const fn with_starvation_alarm(mut self, spins: u64, alarm: fn(u64)) -> Self {
+        self.starvation_spins = spins;
+        self.on_starvation = Some(alarm);
+        self
+    }
+
+    /// Statistics collected since the SpinLock is created.
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> SpinLockStats {
+        self.stats.snapshot()
+    }
+
+    /// Histogram of spins before acquire since the SpinLock is created,
+    /// to tell whether critical sections are short enough for spinning
+    /// or the lock should move to the futex Mutex.
+    #[cfg(feature = "metrics")]
+    pub fn spin_histogram(&self) -> SpinHistogram {
+        self.stats.histogram()
+    }
+
     /// Acquire the spin lock and access the unique mutable reference of inner T
-    pub fn lock(&self) -> SpinLockGuard<T> {
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
         // Must use acquire-release memory order to sync in multithread.
+        #[cfg(feature = "deadlock-detection")]
+        if self.locked.load(Relaxed) {
+            crate::deadlock::waiting(self, "SpinLock");
+        }
+        let yield_after = match crate::config::get().spin_yield {
+            YieldStrategy::Spin => u64::MAX,
+            YieldStrategy::YieldAfter(n) => n as u64,
+        };
+        let mut spins = 0;
         while self.locked.swap(true, Acquire) {
-            // Enter a spin loop
-            std::hint::spin_loop();
+            // Enter a spin loop, yield the thread once spun long enough.
+            if spins < yield_after {
+                std::hint::spin_loop();
+            } else {
+                std::thread::yield_now();
+            }
+            crate::futex::yield_point();
+            spins += 1;
+            #[cfg(feature = "metrics")]
+            {
+                if spins == self.starvation_spins {
+                    self.stats.record_starvation();
+                    if let Some(alarm) = self.on_starvation {
+                        alarm(spins);
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "metrics")]
+        self.stats.record_acquire(spins);
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::acquired(self, "SpinLock");
+        SpinLockGuard {
+            lock: self,
+            panicking: std::thread::panicking(),
         }
-        SpinLockGuard { lock: self }
     }
 
     #[inline]
@@ -41,6 +142,8 @@ impl<T> SpinLock<T> {
 /// A guard type acquired by SpinLock lock method
 pub struct SpinLockGuard<'a, T> {
     lock: &'a SpinLock<T>,
+    // Whether the thread was already panicking when the lock was acquired.
+    panicking: bool,
 }
 
 impl<T> Deref for SpinLockGuard<'_, T> {
@@ -63,6 +166,14 @@ impl<T> DerefMut for SpinLockGuard<'_, T> {
 
 impl<T> Drop for SpinLockGuard<'_, T> {
     fn drop(&mut self) {
+        // Repair the value if a panic started while the guard was held.
+        if let Some(repair) = self.lock.repair {
+            if !self.panicking && std::thread::panicking() {
+                repair(&mut **self);
+            }
+        }
+        #[cfg(feature = "deadlock-detection")]
+        crate::deadlock::released(self.lock, "SpinLock");
         // Unlock the corresponding spin lock when guard is dropped
         self.lock.unlock();
     }
@@ -91,4 +202,78 @@ mod tests {
             assert!(g.as_slice() == [1, 2, 2] || g.as_slice() == [2, 2, 1]);
         }
     }
+
+    #[test]
+    fn test_spin_lock_repair() {
+        // The invariant: the pair always sums to zero.
+        let x = SpinLock::with_repair((0i32, 0i32), |v| *v = (0, 0));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut g = x.lock();
+            g.0 += 1;
+            panic!("broken invariant");
+        }));
+        assert!(result.is_err());
+        assert_eq!(*x.lock(), (0, 0));
+        let mut g = x.lock();
+        g.0 += 1;
+        g.1 -= 1;
+        drop(g);
+        assert_eq!(*x.lock(), (1, -1));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_spin_lock_starvation_alarm() {
+        use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+        static ALARMS: AtomicU64 = AtomicU64::new(0);
+
+        let x = SpinLock::new(0).with_starvation_alarm(1000, |spins| {
+            assert_eq!(spins, 1000);
+            ALARMS.fetch_add(1, Relaxed);
+        });
+        let g = x.lock();
+        thread::scope(|s| {
+            s.spawn(|| *x.lock() += 1);
+            while ALARMS.load(Relaxed) == 0 {
+                thread::yield_now();
+            }
+            drop(g);
+        });
+        let stats = x.stats();
+        assert_eq!(ALARMS.load(Relaxed), 1);
+        assert_eq!(stats.acquisitions, 2);
+        assert_eq!(stats.starvations, 1);
+        assert!(stats.max_spins >= 1000);
+        assert!(stats.average_spins() >= 500);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_spin_histogram() {
+        use super::SpinHistogram;
+        use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+
+        assert_eq!(SpinHistogram::bucket(0), 0);
+        assert_eq!(SpinHistogram::bucket(1), 1);
+        assert_eq!(SpinHistogram::bucket(1000), 10);
+        assert_eq!(SpinHistogram::bucket_range(10), 512..=1023);
+        assert_eq!(SpinHistogram::bucket_range(64), 1 << 63..=u64::MAX);
+
+        static STARVED: AtomicBool = AtomicBool::new(false);
+        let x = SpinLock::new(0).with_starvation_alarm(1000, |_| STARVED.store(true, Relaxed));
+        *x.lock() += 1;
+        let g = x.lock();
+        thread::scope(|s| {
+            s.spawn(|| *x.lock() += 1);
+            while !STARVED.load(Relaxed) {
+                thread::yield_now();
+            }
+            drop(g);
+        });
+        let histogram = x.spin_histogram();
+        assert_eq!(histogram.count(), 3);
+        assert_eq!(histogram.buckets[0], 2);
+        assert_eq!(histogram.quantile(0.5), 0);
+        assert!(histogram.quantile(1.0) >= 1000);
+    }
 }
@@ -0,0 +1,132 @@
+use std::{
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+};
+
+/// Count of buckets of a SpinHistogram, one for no spin
+/// and one per power of two of spins.
+pub const SPIN_BUCKETS: usize = 65;
+
+/// Counters collected by a SpinLock.
+pub(super) struct Counters {
+    acquisitions: AtomicU64,
+    spins: AtomicU64,
+    max_spins: AtomicU64,
+    starvations: AtomicU64,
+    histogram: [AtomicU64; SPIN_BUCKETS],
+}
+
+impl Counters {
+    pub(super) const fn new() -> Self {
+        Self {
+            acquisitions: AtomicU64::new(0),
+            spins: AtomicU64::new(0),
+            max_spins: AtomicU64::new(0),
+            starvations: AtomicU64::new(0),
+            histogram: [const { AtomicU64::new(0) }; SPIN_BUCKETS],
+        }
+    }
+
+    pub(super) fn record_acquire(&self, spins: u64) {
+        self.acquisitions.fetch_add(1, Relaxed);
+        self.histogram[SpinHistogram::bucket(spins)].fetch_add(1, Relaxed);
+        if spins > 0 {
+            self.spins.fetch_add(spins, Relaxed);
+            self.max_spins.fetch_max(spins, Relaxed);
+        }
+    }
+
+    pub(super) fn record_starvation(&self) {
+        self.starvations.fetch_add(1, Relaxed);
+    }
+
+    pub(super) fn snapshot(&self) -> SpinLockStats {
+        SpinLockStats {
+            acquisitions: self.acquisitions.load(Relaxed),
+            spins: self.spins.load(Relaxed),
+            max_spins: self.max_spins.load(Relaxed),
+            starvations: self.starvations.load(Relaxed),
+        }
+    }
+
+    pub(super) fn histogram(&self) -> SpinHistogram {
+        SpinHistogram {
+            buckets: std::array::from_fn(|i| self.histogram[i].load(Relaxed)),
+        }
+    }
+}
+
+/// A snapshot of SpinLock statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpinLockStats {
+    /// Count of finished acquisitions.
+    pub acquisitions: u64,
+    /// Total spins of all acquisitions.
+    pub spins: u64,
+    /// Most spins taken by a single acquisition.
+    pub max_spins: u64,
+    /// Count of acquisitions spinning past the starvation threshold.
+    pub starvations: u64,
+}
+
+impl SpinLockStats {
+    /// Average spins taken by an acquisition.
+    pub fn average_spins(&self) -> u64 {
+        if self.acquisitions == 0 {
+            return 0;
+        }
+        self.spins / self.acquisitions
+    }
+}
+
+/// A snapshot of spins-before-acquire of SpinLock acquisitions.
+///
+/// Bucket 0 counts acquisitions without spinning,
+/// bucket i counts the ones spinning `2^(i-1)..2^i` times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpinHistogram {
+    pub buckets: [u64; SPIN_BUCKETS],
+}
+
+impl SpinHistogram {
+    /// Index of the bucket counting an acquisition of `spins`.
+    pub fn bucket(spins: u64) -> usize {
+        (u64::BITS - spins.leading_zeros()) as usize
+    }
+
+    /// Spins counted by the bucket at index i.
+    pub fn bucket_range(i: usize) -> RangeInclusive<u64> {
+        match i {
+            0 => 0..=0,
+            _ => 1 << (i - 1)..=u64::MAX >> (SPIN_BUCKETS - 1 - i),
+        }
+    }
+
+    /// Count of acquisitions recorded.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Non-empty buckets by spins, with their counts.
+    pub fn iter(&self) -> impl Iterator<Item = (RangeInclusive<u64>, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(i, &n)| (Self::bucket_range(i), n))
+    }
+
+    /// Upper bound of spins taken by the fraction q of acquisitions,
+    /// e.g. `quantile(0.99)`, 0 if nothing is recorded.
+    pub fn quantile(&self, q: f64) -> u64 {
+        let target = (self.count() as f64 * q.clamp(0.0, 1.0)).ceil() as u64;
+        let mut seen = 0;
+        for (range, n) in self.iter() {
+            seen += n;
+            if seen >= target {
+                return *range.end();
+            }
+        }
+        0
+    }
+}
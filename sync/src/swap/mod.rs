@@ -0,0 +1,215 @@
+//! A rendezvous swapping values between pairs of threads, e.g. a producer
+//! handing a full buffer to a consumer for an empty one.
+
+use std::cell::UnsafeCell;
+use std::error::Error;
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicPtr, AtomicU32};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::futex::{wait, wait_timeout, wake_one};
+use crate::time;
+
+const WAITING: u32 = 0;
+const EXCHANGED: u32 = 1;
+
+// An offer parked in the slot, the waiting thread parks on its state.
+struct Node<T> {
+    offer: UnsafeCell<Option<T>>,
+    reply: UnsafeCell<Option<T>>,
+    state: AtomicU32,
+}
+
+// Safety: the offer and reply are accessed only by the thread owning the
+// slot's reference of the node, or by the waiter once the state is EXCHANGED.
+unsafe impl<T: Send> Sync for Node<T> {}
+
+/// A rendezvous point where two threads swap values: each calls
+/// `exchange` and receives the value of the other.
+pub struct Exchanger<T> {
+    // The offer waiting for a partner, holding a reference of the node,
+    // null if nobody waits.
+    slot: AtomicPtr<Node<T>>,
+}
+
+unsafe impl<T: Send> Sync for Exchanger<T> {}
+unsafe impl<T: Send> Send for Exchanger<T> {}
+
+impl<T> Default for Exchanger<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Exchanger<T> {
+    /// Create an exchanger nobody waits on.
+    pub const fn new() -> Self {
+        Self {
+            slot: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Block until another thread exchanges, return its value.
+    pub fn exchange(&self, value: T) -> T {
+        match self.exchange_until(value, None) {
+            Ok(value) => value,
+            Err(_) => unreachable!("exchange without deadline never times out"),
+        }
+    }
+
+    /// Like `exchange`, but give the value back if no thread exchanges
+    /// within timeout.
+    pub fn exchange_timeout(
+        &self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<T, ExchangeTimeoutError<T>> {
+        self.exchange_until(value, Some(time::now() + timeout))
+    }
+
+    fn exchange_until(
+        &self,
+        value: T,
+        deadline: Option<Instant>,
+    ) -> Result<T, ExchangeTimeoutError<T>> {
+        let node = Arc::new(Node {
+            offer: UnsafeCell::new(Some(value)),
+            reply: UnsafeCell::new(None),
+            state: AtomicU32::new(WAITING),
+        });
+        loop {
+            let current = self.slot.load(Acquire);
+            if current.is_null() {
+                let raw = Arc::into_raw(Arc::clone(&node)) as *mut Node<T>;
+                match self
+                    .slot
+                    .compare_exchange(ptr::null_mut(), raw, AcqRel, Relaxed)
+                {
+                    Ok(_) => return self.wait_partner(&node, deadline),
+                    Err(_) => drop(unsafe { Arc::from_raw(raw) }),
+                }
+                continue;
+            }
+            // Claim the waiting offer, the slot's reference is ours then.
+            if self
+                .slot
+                .compare_exchange(current, ptr::null_mut(), AcqRel, Relaxed)
+                .is_err()
+            {
+                continue;
+            }
+            let partner = unsafe { Arc::from_raw(current) };
+            // Safety: the offer is unused until we claim it, and the waiter
+            // reads the reply only after the state is EXCHANGED.
+            unsafe {
+                let theirs = (*partner.offer.get()).take();
+                *partner.reply.get() = (*node.offer.get()).take();
+                partner.state.store(EXCHANGED, Release);
+                wake_one(&partner.state);
+                return Ok(theirs.expect("offer of a waiting node"));
+            }
+        }
+    }
+
+    // Wait until the offer in the slot is exchanged or the deadline passes.
+    fn wait_partner(
+        &self,
+        node: &Arc<Node<T>>,
+        deadline: Option<Instant>,
+    ) -> Result<T, ExchangeTimeoutError<T>> {
+        let mut deadline = deadline;
+        while node.state.load(Acquire) == WAITING {
+            let Some(at) = deadline else {
+                wait(&node.state, WAITING);
+                continue;
+            };
+            let remaining = at.saturating_duration_since(time::now());
+            if !remaining.is_zero() {
+                wait_timeout(&node.state, WAITING, remaining);
+                continue;
+            }
+            // Take the offer back, unless a partner claimed it meanwhile,
+            // then the exchange is finishing.
+            let raw = Arc::as_ptr(node) as *mut Node<T>;
+            if self
+                .slot
+                .compare_exchange(raw, ptr::null_mut(), AcqRel, Relaxed)
+                .is_ok()
+            {
+                drop(unsafe { Arc::from_raw(raw) });
+                let value = unsafe { (*node.offer.get()).take() };
+                return Err(ExchangeTimeoutError(value.expect("offer not claimed")));
+            }
+            deadline = None;
+        }
+        // Safety: the partner finished writing the reply before EXCHANGED.
+        let value = unsafe { (*node.reply.get()).take() };
+        Ok(value.expect("reply of an exchanged node"))
+    }
+}
+
+/// Error returned by `exchange_timeout` if no thread exchanges in time,
+/// carrying the value back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExchangeTimeoutError<T>(pub T);
+
+impl<T> fmt::Display for ExchangeTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no thread exchanged in time")
+    }
+}
+
+impl<T: fmt::Debug> Error for ExchangeTimeoutError<T> {}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::{ExchangeTimeoutError, Exchanger};
+
+    #[test]
+    fn test_exchanger() {
+        let lonely = Exchanger::new();
+        assert_eq!(
+            lonely.exchange_timeout(String::from("alone"), Duration::from_millis(10)),
+            Err(ExchangeTimeoutError(String::from("alone")))
+        );
+
+        // Two threads pair up on every round.
+        let exchanger = Exchanger::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..1000 {
+                    assert_eq!(exchanger.exchange(i), i + 1000);
+                }
+            });
+            for i in 0..1000 {
+                assert_eq!(exchanger.exchange(i + 1000), i);
+            }
+        });
+    }
+
+    #[cfg(feature = "testutil")]
+    #[test]
+    fn test_exchanger_fake_clock() {
+        use crate::testutil::FakeClock;
+
+        // The timeout runs on the fake clock, an hour passes at once.
+        let clock = FakeClock::new();
+        let lonely = Exchanger::new();
+        thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let _clock = clock.enter();
+                lonely.exchange_timeout(1, Duration::from_secs(3600))
+            });
+            while !waiter.is_finished() {
+                clock.advance(Duration::from_secs(60));
+                thread::yield_now();
+            }
+            assert_eq!(waiter.join().unwrap(), Err(ExchangeTimeoutError(1)));
+        });
+    }
+}
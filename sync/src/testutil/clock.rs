@@ -0,0 +1,172 @@
+//! A manually advanced clock, so tests of timeouts run instantly and
+//! deterministically.
+
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering::SeqCst},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// How long a timed futex wait sleeps in real time while a fake clock is
+/// in effect, before the waiter checks the fake time again.
+const POLL: Duration = Duration::from_millis(1);
+
+thread_local! {
+    static CURRENT: RefCell<Option<FakeClock>> = const { RefCell::new(None) };
+}
+
+/// A clock standing still until it's advanced by `advance`, read by the
+/// timeouts of the crate's primitives in the threads which `enter` it,
+/// see `time::now`.
+///
+/// ```
+/// use std::time::Duration;
+/// use sync::{channel::bounded, testutil::FakeClock};
+///
+/// let clock = FakeClock::new();
+/// let (_tx, rx) = bounded::channel::<u32>(1);
+/// std::thread::scope(|s| {
+///     let waiter = s.spawn(|| {
+///         let _clock = clock.enter();
+///         rx.recv_timeout(Duration::from_secs(3600))
+///     });
+///     // An hour passes at once.
+///     while !waiter.is_finished() {
+///         clock.advance(Duration::from_secs(60));
+///         std::thread::yield_now();
+///     }
+///     assert!(waiter.join().unwrap().is_err());
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct FakeClock {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    start: Instant,
+    elapsed_nanos: AtomicU64,
+}
+
+impl Default for FakeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeClock {
+    /// Create a clock starting at the real time now.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                start: Instant::now(),
+                elapsed_nanos: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// The fake time now.
+    pub fn now(&self) -> Instant {
+        self.inner.start + Duration::from_nanos(self.inner.elapsed_nanos.load(SeqCst))
+    }
+
+    /// Move the time forward, waiters whose deadline passes time out
+    /// shortly after.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        self.inner.elapsed_nanos.fetch_add(nanos, SeqCst);
+    }
+
+    /// Make this the clock of the current thread until the guard is
+    /// dropped. Other threads, e.g. spawned by the test, enter it on their
+    /// own, except the thread of a `time::Timer` created meanwhile.
+    pub fn enter(&self) -> ClockGuard {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        ClockGuard {
+            previous,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+/// The guard of `FakeClock::enter`, restoring the clock entered before.
+pub struct ClockGuard {
+    previous: Option<FakeClock>,
+    // Restored on the thread which entered.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ClockGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        let _ = CURRENT.try_with(|current| current.replace(previous));
+    }
+}
+
+/// The clock entered by the current thread.
+pub(crate) fn current() -> Option<FakeClock> {
+    CURRENT.try_with(|current| current.borrow().clone()).ok()?
+}
+
+/// The fake time of the current thread, None if it entered no clock.
+pub(crate) fn now() -> Option<Instant> {
+    CURRENT
+        .try_with(|current| current.borrow().as_ref().map(FakeClock::now))
+        .ok()?
+}
+
+/// The real timeout of a timed futex wait: a short poll under a fake
+/// clock, as the waiter is not woken when the clock is advanced.
+pub(crate) fn wait_timeout(timeout: Duration) -> Duration {
+    match now() {
+        Some(_) => timeout.min(POLL),
+        None => timeout,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::FakeClock;
+    use crate::{mutex::Mutex, time};
+
+    #[test]
+    fn test_fake_clock() {
+        let clock = FakeClock::new();
+        let start = time::now();
+        {
+            let _guard = clock.enter();
+            let now = time::now();
+            clock.advance(Duration::from_secs(10));
+            assert_eq!(time::now() - now, Duration::from_secs(10));
+        }
+        // The real clock again.
+        assert!(time::now() - start < Duration::from_secs(10));
+
+        // A minute-long timeout passes as fast as the clock is advanced.
+        let x = Mutex::new(());
+        let guard = x.lock();
+        thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let _guard = clock.enter();
+                x.try_lock_for(Duration::from_secs(60)).is_err()
+            });
+            thread::sleep(Duration::from_millis(5));
+            assert!(!waiter.is_finished());
+            let now = clock.now();
+            while !waiter.is_finished() {
+                clock.advance(Duration::from_secs(1));
+                thread::yield_now();
+            }
+            assert!(waiter.join().unwrap());
+            assert!(clock.now() - now >= Duration::from_secs(59));
+        });
+        drop(guard);
+    }
+}
@@ -0,0 +1,41 @@
+//! Hooks of the futex layer, taking effect on scheduled threads only.
+
+use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+use super::scheduler::{current, Status};
+
+/// Block the scheduled thread while the atomic equals value.
+/// Return false if the thread is not scheduled.
+pub(crate) fn wait(atomic: &AtomicU32, value: u32) -> bool {
+    let Some((execution, me)) = current() else {
+        return false;
+    };
+    if atomic.load(SeqCst) == value {
+        execution.switch(me, Status::Blocked(atomic as *const _ as usize));
+    }
+    true
+}
+
+/// A scheduled thread never sleeps on a timeout, it yields as if
+/// woken up spuriously. Return false if the thread is not scheduled.
+pub(crate) fn wait_timeout() -> bool {
+    if current().is_none() {
+        return false;
+    }
+    super::yield_now();
+    true
+}
+
+/// Wake the scheduled threads waiting on the atomic.
+/// Return false if the thread is not scheduled.
+pub(crate) fn wake(atomic: *const AtomicU32, all: bool) -> bool {
+    let Some((execution, _)) = current() else {
+        return false;
+    };
+    execution.wake(atomic as usize, all);
+    true
+}
+
+pub(crate) fn yield_point() {
+    super::yield_now();
+}
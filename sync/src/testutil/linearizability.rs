@@ -0,0 +1,256 @@
+//! A linearizability checker: record a concurrent history of operations,
+//! then search for an order of them which respects real time and matches
+//! a sequential model.
+//!
+//! ```
+//! use std::{sync::Mutex, thread};
+//! use sync::testutil::linearizability::{check, History, Model};
+//!
+//! #[derive(Clone, Hash, PartialEq, Eq)]
+//! struct Register(u32);
+//!
+//! #[derive(Debug, Clone)]
+//! enum Op {
+//!     Write(u32),
+//!     Read,
+//! }
+//!
+//! impl Model for Register {
+//!     type Op = Op;
+//!     type Ret = Option<u32>;
+//!     fn apply(&mut self, op: &Op) -> Option<u32> {
+//!         match *op {
+//!             Op::Write(v) => {
+//!                 self.0 = v;
+//!                 None
+//!             }
+//!             Op::Read => Some(self.0),
+//!         }
+//!     }
+//! }
+//!
+//! let register = Mutex::new(0);
+//! let history = History::new();
+//! thread::scope(|s| {
+//!     for i in 1..3 {
+//!         let (register, history) = (&register, &history);
+//!         s.spawn(move || {
+//!             history.record(Op::Write(i), || {
+//!                 *register.lock().unwrap() = i;
+//!                 None
+//!             });
+//!             history.record(Op::Read, || Some(*register.lock().unwrap()));
+//!         });
+//!     }
+//! });
+//! check(Register(0), &history).unwrap();
+//! ```
+
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering::SeqCst},
+        Mutex,
+    },
+};
+
+/// A sequential specification of a concurrent object.
+pub trait Model: Clone + Hash + Eq {
+    type Op: fmt::Debug;
+    type Ret: fmt::Debug + PartialEq;
+
+    /// Run the operation on the model, return what it returns.
+    fn apply(&mut self, op: &Self::Op) -> Self::Ret;
+}
+
+struct Event<Op, Ret> {
+    op: Op,
+    ret: Ret,
+    // Logical time of invocation and response.
+    call: u64,
+    done: u64,
+}
+
+/// A history of operations recorded by concurrent threads.
+pub struct History<Op, Ret> {
+    clock: AtomicU64,
+    events: Mutex<Vec<Event<Op, Ret>>>,
+}
+
+impl<Op, Ret> Default for History<Op, Ret> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Op, Ret> History<Op, Ret> {
+    pub fn new() -> Self {
+        Self {
+            clock: AtomicU64::new(0),
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Run the operation by f and record it with what it returns.
+    pub fn record(&self, op: Op, f: impl FnOnce() -> Ret) -> Ret
+    where
+        Ret: Clone,
+    {
+        let call = self.clock.fetch_add(1, SeqCst);
+        let ret = f();
+        let done = self.clock.fetch_add(1, SeqCst);
+        self.events.lock().unwrap().push(Event {
+            op,
+            ret: ret.clone(),
+            call,
+            done,
+        });
+        ret
+    }
+
+    /// Count of operations recorded.
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Error returned by `check` if a history is not linearizable.
+#[derive(Debug, Clone)]
+pub struct NotLinearizable {
+    history: String,
+}
+
+impl fmt::Display for NotLinearizable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "history is not linearizable:\n{}", self.history)
+    }
+}
+
+impl Error for NotLinearizable {}
+
+/// Check whether the history is linearizable against the model
+/// in its initial state.
+///
+/// The search backtracks over the operations which may take effect first,
+/// memoizing the visited states, so keep histories short, e.g. under a
+/// hundred operations.
+pub fn check<M: Model>(model: M, history: &History<M::Op, M::Ret>) -> Result<(), NotLinearizable> {
+    let mut events = history.events.lock().unwrap();
+    events.sort_by_key(|e| e.call);
+    let mut done = vec![false; events.len()];
+    let mut visited = HashSet::new();
+    if search(&events, &mut done, model, &mut visited) {
+        return Ok(());
+    }
+    let history = events
+        .iter()
+        .map(|e| format!("[{}, {}] {:?} -> {:?}\n", e.call, e.done, e.op, e.ret))
+        .collect();
+    Err(NotLinearizable { history })
+}
+
+fn search<M: Model>(
+    events: &[Event<M::Op, M::Ret>],
+    done: &mut [bool],
+    model: M,
+    visited: &mut HashSet<(Vec<bool>, M)>,
+) -> bool {
+    // An operation may take effect next only if it's invoked before
+    // every pending operation responded.
+    let Some(deadline) = events
+        .iter()
+        .zip(done.iter())
+        .filter(|(_, &d)| !d)
+        .map(|(e, _)| e.done)
+        .min()
+    else {
+        return true;
+    };
+    if !visited.insert((done.to_vec(), model.clone())) {
+        return false;
+    }
+    for i in 0..events.len() {
+        if done[i] || events[i].call > deadline {
+            continue;
+        }
+        let mut next = model.clone();
+        if next.apply(&events[i].op) != events[i].ret {
+            continue;
+        }
+        done[i] = true;
+        if search(events, done, next, visited) {
+            return true;
+        }
+        done[i] = false;
+    }
+    false
+}
+
+/// A FIFO queue model, e.g. of channels.
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
+pub struct QueueModel<T>(pub std::collections::VecDeque<T>);
+
+/// An operation of `QueueModel`.
+#[derive(Debug, Clone)]
+pub enum QueueOp<T> {
+    Push(T),
+    Pop,
+}
+
+impl<T: fmt::Debug + Clone + Hash + Eq> Model for QueueModel<T> {
+    type Op = QueueOp<T>;
+    type Ret = Option<T>;
+
+    /// Push returns None, pop returns None if the queue is empty.
+    fn apply(&mut self, op: &QueueOp<T>) -> Option<T> {
+        match op {
+            QueueOp::Push(v) => {
+                self.0.push_back(v.clone());
+                None
+            }
+            QueueOp::Pop => self.0.pop_front(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicU32, sync::atomic::Ordering::SeqCst, thread};
+
+    use super::{check, History, QueueModel, QueueOp};
+
+    #[test]
+    fn test_linearizability() {
+        // A queue losing the order of pushes is caught.
+        let history = History::new();
+        history.record(QueueOp::Push(1), || None);
+        history.record(QueueOp::Push(2), || None);
+        history.record(QueueOp::Pop, || Some(2));
+        let err = check(QueueModel::default(), &history).unwrap_err();
+        assert!(err.to_string().contains("Pop -> Some(2)"));
+
+        // Overlapping operations may take effect in any order.
+        let x = AtomicU32::new(0);
+        let history = History::new();
+        thread::scope(|s| {
+            for i in 1..=4 {
+                let (x, history) = (&x, &history);
+                s.spawn(move || {
+                    history.record(QueueOp::Push(i), || {
+                        x.fetch_add(1, SeqCst);
+                        None
+                    })
+                });
+            }
+        });
+        assert_eq!(history.len(), 4);
+        check(QueueModel::default(), &history).unwrap();
+    }
+}
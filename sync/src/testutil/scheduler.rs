@@ -0,0 +1,345 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
+
+use crate::xorshift::XorShift;
+
+// Count of executions running, the hooks are skipped if none.
+static EXECUTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// How the scheduler chooses the next thread at a yield point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Choose a runnable thread uniformly at random.
+    Random,
+    /// Probabilistic concurrency testing: run the runnable thread with the
+    /// highest random priority, and lower the running thread's priority at
+    /// `depth - 1` random steps among the first `max_steps` ones.
+    /// Finds every bug of given depth with a known probability.
+    Pct { depth: usize, max_steps: usize },
+}
+
+/// Configure and run a concurrency test.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    seed: u64,
+    iterations: usize,
+    strategy: Strategy,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            seed: 0,
+            iterations: 100,
+            strategy: Strategy::Random,
+        }
+    }
+
+    /// Seed of the first iteration, iteration i runs with `seed + i`.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Count of interleavings explored.
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Run f once per iteration under the scheduler.
+    /// Panic with the failing seed if f or a thread it spawned panics,
+    /// or if all threads are blocked.
+    pub fn check<F>(&self, f: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        for i in 0..self.iterations {
+            let seed = self.seed.wrapping_add(i as u64);
+            let f = Arc::clone(&f);
+            if let Err(payload) = Execution::run(seed, self.strategy, move || f()) {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("Box<dyn Any>");
+                panic!("failed with seed {}: {}", seed, message);
+            }
+        }
+    }
+}
+
+/// Spawn a thread scheduled with the current thread.
+///
+/// Panic if the current thread is not running under `Builder::check`.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (execution, _) = current().expect("spawn outside of Builder::check");
+    let result = Arc::new(Mutex::new(None));
+    let id = {
+        let result = Arc::clone(&result);
+        execution.spawn(move || {
+            let value = f();
+            *result.lock().unwrap() = Some(value);
+        })
+    };
+    // Let the scheduler decide whether the new thread runs first.
+    yield_now();
+    JoinHandle { id, result }
+}
+
+/// Give the scheduler a chance to switch threads.
+pub fn yield_now() {
+    if let Some((execution, me)) = current() {
+        execution.switch(me, Status::Runnable);
+    }
+}
+
+/// Handle to join a thread spawned by `spawn`.
+pub struct JoinHandle<T> {
+    id: usize,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Block until the thread finishes, return its result.
+    pub fn join(self) -> T {
+        let (execution, me) = current().expect("join outside of Builder::check");
+        execution.switch(me, Status::Joining(self.id));
+        self.result.lock().unwrap().take().unwrap()
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<(Arc<Execution>, usize)>> = const { RefCell::new(None) };
+}
+
+/// The execution and id of the current thread if it's scheduled.
+pub(super) fn current() -> Option<(Arc<Execution>, usize)> {
+    if EXECUTIONS.load(Relaxed) == 0 {
+        return None;
+    }
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Status {
+    Runnable,
+    // Waiting on the address of an atomic.
+    Blocked(usize),
+    // Waiting for the thread to finish.
+    Joining(usize),
+    Finished,
+}
+
+/// Payload unwinding the threads of an aborted execution.
+struct Aborted;
+
+pub(super) struct Execution {
+    state: Mutex<State>,
+    turn: Condvar,
+}
+
+struct State {
+    threads: Vec<Status>,
+    priorities: Vec<u64>,
+    current: usize,
+    rng: XorShift,
+    strategy: Strategy,
+    steps: usize,
+    change_points: Vec<usize>,
+    // The first failure, set to abort the execution.
+    failure: Option<Box<dyn Any + Send>>,
+}
+
+impl Execution {
+    fn run(
+        seed: u64,
+        strategy: Strategy,
+        f: impl FnOnce() + Send + 'static,
+    ) -> Result<(), Box<dyn Any + Send>> {
+        let mut rng = XorShift::new(seed);
+        let change_points = match strategy {
+            Strategy::Random => Vec::new(),
+            Strategy::Pct { depth, max_steps } => (1..depth)
+                .map(|_| rng.next() as usize % max_steps.max(1))
+                .collect(),
+        };
+        let execution = Arc::new(Execution {
+            state: Mutex::new(State {
+                threads: Vec::new(),
+                priorities: Vec::new(),
+                current: 0,
+                rng,
+                strategy,
+                steps: 0,
+                change_points,
+                failure: None,
+            }),
+            turn: Condvar::new(),
+        });
+        EXECUTIONS.fetch_add(1, Relaxed);
+        execution.spawn(f);
+
+        let mut state = execution.state.lock().unwrap();
+        while !state.threads.iter().all(|s| *s == Status::Finished) {
+            state = execution.turn.wait(state).unwrap();
+        }
+        EXECUTIONS.fetch_sub(1, Relaxed);
+        match state.failure.take() {
+            Some(payload) => Err(payload),
+            None => Ok(()),
+        }
+    }
+
+    /// Register a thread and start it, it runs once scheduled.
+    fn spawn(self: &Arc<Self>, f: impl FnOnce() + Send + 'static) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let id = state.threads.len();
+        state.threads.push(Status::Runnable);
+        // Priorities of PCT are above all change points.
+        let priority = state.change_points.len() as u64 + 1 + (state.rng.next() >> 1);
+        state.priorities.push(priority);
+        drop(state);
+
+        let execution = Arc::clone(self);
+        thread::spawn(move || {
+            CURRENT.with(|c| *c.borrow_mut() = Some((Arc::clone(&execution), id)));
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                execution.wait_turn(id);
+                f();
+            }));
+            CURRENT.with(|c| *c.borrow_mut() = None);
+            execution.finish(id, result.err());
+        });
+        id
+    }
+
+    /// Set the status of the current thread, switch to the thread chosen.
+    /// Return once the current thread is scheduled again.
+    pub(super) fn switch(&self, me: usize, status: Status) {
+        let mut state = self.state.lock().unwrap();
+        if state.failure.is_some() {
+            drop(state);
+            abort_unwind();
+            return;
+        }
+        if let Status::Joining(id) = status {
+            if state.threads[id] == Status::Finished {
+                return;
+            }
+        }
+        state.threads[me] = status;
+        self.schedule(&mut state);
+        drop(state);
+        self.wait_turn(me);
+    }
+
+    /// Make the threads blocked on the address runnable.
+    pub(super) fn wake(&self, addr: usize, all: bool) {
+        let mut state = self.state.lock().unwrap();
+        for status in state.threads.iter_mut() {
+            if *status == Status::Blocked(addr) {
+                *status = Status::Runnable;
+                if !all {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn finish(&self, me: usize, panic: Option<Box<dyn Any + Send>>) {
+        let mut state = self.state.lock().unwrap();
+        state.threads[me] = Status::Finished;
+        if let Some(payload) = panic {
+            if !payload.is::<Aborted>() && state.failure.is_none() {
+                state.failure = Some(payload);
+            }
+        }
+        for status in state.threads.iter_mut() {
+            if *status == Status::Joining(me) {
+                *status = Status::Runnable;
+            }
+        }
+        if state.failure.is_none() {
+            self.schedule(&mut state);
+        }
+        self.turn.notify_all();
+    }
+
+    /// Choose the next thread to run, abort if all threads are blocked.
+    fn schedule(&self, state: &mut State) {
+        state.steps += 1;
+        let runnable: Vec<usize> = (0..state.threads.len())
+            .filter(|&i| state.threads[i] == Status::Runnable)
+            .collect();
+        if runnable.is_empty() {
+            if !state.threads.iter().all(|s| *s == Status::Finished) {
+                state.failure = Some(Box::new(format!(
+                    "deadlock, all threads are blocked: {:?}",
+                    state.threads
+                )));
+            }
+            self.turn.notify_all();
+            return;
+        }
+        state.current = match state.strategy {
+            Strategy::Random => runnable[state.rng.next() as usize % runnable.len()],
+            Strategy::Pct { .. } => {
+                let steps = state.steps;
+                if let Some(i) = state.change_points.iter().position(|&p| p == steps) {
+                    let current = state.current;
+                    state.priorities[current] = i as u64;
+                }
+                *runnable
+                    .iter()
+                    .max_by_key(|&&i| state.priorities[i])
+                    .unwrap()
+            }
+        };
+        self.turn.notify_all();
+    }
+
+    fn wait_turn(&self, me: usize) {
+        let mut state = self.state.lock().unwrap();
+        while state.current != me || state.threads[me] != Status::Runnable {
+            if state.failure.is_some() {
+                drop(state);
+                abort_unwind();
+                return;
+            }
+            state = self.turn.wait(state).unwrap();
+        }
+    }
+}
+
+/// Unwind the current thread of an aborted execution,
+/// unless it's already unwinding.
+fn abort_unwind() {
+    if !thread::panicking() {
+        panic::resume_unwind(Box::new(Aborted));
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    any::Any,
+    error::Error,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    thread::{self, JoinHandle, Scope, ScopedJoinHandle},
+};
+
+use crate::channel::oneshot::{self, Receiver};
+
+/// Error delivered instead of the result if the thread panics.
+pub struct Panicked(Box<dyn Any + Send + 'static>);
+
+impl Panicked {
+    /// The panic message, if the payload is a string.
+    pub fn message(&self) -> Option<&str> {
+        if let Some(s) = self.0.downcast_ref::<&'static str>() {
+            return Some(s);
+        }
+        self.0.downcast_ref::<String>().map(String::as_str)
+    }
+
+    /// Take the panic payload, e.g. to resume the panic on another thread.
+    pub fn into_payload(self) -> Box<dyn Any + Send + 'static> {
+        self.0
+    }
+}
+
+impl fmt::Debug for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Panicked").field(&self.message()).finish()
+    }
+}
+
+impl fmt::Display for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(message) => write!(f, "thread panicked: {}", message),
+            None => write!(f, "thread panicked"),
+        }
+    }
+}
+
+impl Error for Panicked {}
+
+/// Spawn a thread and deliver its return value through a oneshot channel.
+/// A panic of the thread is delivered as `Err(Panicked)`.
+pub fn spawn_with_result<F, R>(f: F) -> (JoinHandle<()>, Receiver<Result<R, Panicked>>)
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    let handle = thread::spawn(move || tx.send(catch(f)));
+    (handle, rx)
+}
+
+/// Same as `spawn_with_result`, but spawn a scoped thread.
+pub fn spawn_scoped_with_result<'scope, F, R>(
+    scope: &'scope Scope<'scope, '_>,
+    f: F,
+) -> (ScopedJoinHandle<'scope, ()>, Receiver<Result<R, Panicked>>)
+where
+    F: FnOnce() -> R + Send + 'scope,
+    R: Send + 'scope,
+{
+    let (tx, rx) = oneshot::channel();
+    let handle = scope.spawn(move || tx.send(catch(f)));
+    (handle, rx)
+}
+
+pub(crate) fn catch<F, R>(f: F) -> Result<R, Panicked>
+where
+    F: FnOnce() -> R,
+{
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(Panicked)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{spawn_scoped_with_result, spawn_with_result};
+
+    #[test]
+    fn test_spawn_with_result() {
+        let (handle, rx) = spawn_with_result(|| 40 + 2);
+        assert_eq!(rx.recv().unwrap(), 42);
+        handle.join().unwrap();
+
+        let (handle, rx) = spawn_with_result(|| -> i32 { panic!("boom") });
+        assert_eq!(rx.recv().unwrap_err().message(), Some("boom"));
+        handle.join().unwrap();
+
+        let v = [1, 2, 3];
+        thread::scope(|s| {
+            let (_, rx) = spawn_scoped_with_result(s, || v.iter().sum::<i32>());
+            assert_eq!(rx.recv().unwrap(), 6);
+        });
+    }
+}
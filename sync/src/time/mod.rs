@@ -0,0 +1,259 @@
+//! A shared timer service running callbacks at deadlines on a dedicated
+//! thread, so timeouts are not reimplemented per primitive.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    sync::{
+        atomic::{
+            AtomicU32,
+            Ordering::{Acquire, Release},
+        },
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use crate::futex::{wait, wait_timeout, wake_all, wake_one};
+use crate::mutex::Mutex;
+use crate::thread_ext::catch;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// The current time by the clock in effect, consulted by the timeouts of
+/// the crate's primitives: the `testutil::FakeClock` the thread entered,
+/// or the real clock.
+pub fn now() -> Instant {
+    #[cfg(feature = "testutil")]
+    if let Some(now) = crate::testutil::clock::now() {
+        return now;
+    }
+    Instant::now()
+}
+
+/// A timer thread running callbacks at their deadlines.
+///
+/// Scheduling takes O(log n) and cancelling amortized O(log n), as the
+/// deadlines cancelled are dropped together once they outnumber the live
+/// ones. Callbacks run on the timer thread one by one, so they should be
+/// short, e.g. waking a thread. A callback panicking is skipped, the later
+/// ones still run.
+pub struct Timer {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // Bumped to wake the timer thread up when an earlier deadline is
+    // scheduled or the timer is dropped.
+    changed: AtomicU32,
+}
+
+struct State {
+    // Deadlines by the order they're due, cancelled ones are skipped.
+    deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+    callbacks: HashMap<u64, Callback>,
+    next_id: u64,
+    shutdown: bool,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timer {
+    /// Create a timer with its own thread.
+    pub fn new() -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                deadlines: BinaryHeap::new(),
+                callbacks: HashMap::new(),
+                next_id: 0,
+                shutdown: false,
+            }),
+            changed: AtomicU32::new(0),
+        });
+        let thread = thread::Builder::new()
+            .name("sync-timer".to_string())
+            .spawn({
+                let shared = Arc::clone(&shared);
+                // The timer keeps the time of the thread creating it.
+                #[cfg(feature = "testutil")]
+                let clock = crate::testutil::clock::current();
+                move || {
+                    #[cfg(feature = "testutil")]
+                    let _clock = clock.as_ref().map(|clock| clock.enter());
+                    shared.run()
+                }
+            })
+            .expect("failed to spawn timer thread");
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Run f on the timer thread at the deadline, or at once if it's passed.
+    pub fn schedule(&self, deadline: Instant, f: impl FnOnce() + Send + 'static) -> TimerHandle {
+        let mut state = self.shared.state.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        let earliest = state
+            .deadlines
+            .peek()
+            .is_none_or(|Reverse((d, _))| deadline < *d);
+        state.deadlines.push(Reverse((deadline, id)));
+        state.callbacks.insert(id, Box::new(f));
+        drop(state);
+        if earliest {
+            self.shared.changed.fetch_add(1, Release);
+            wake_one(&self.shared.changed);
+        }
+        TimerHandle {
+            shared: Arc::clone(&self.shared),
+            id,
+        }
+    }
+
+    /// Bump the futex word and wake all its waiters at the deadline.
+    pub fn wake_at(&self, deadline: Instant, word: Arc<AtomicU32>) -> TimerHandle {
+        self.schedule(deadline, move || {
+            word.fetch_add(1, Release);
+            wake_all(&*word);
+        })
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.shared.state.lock().shutdown = true;
+        self.shared.changed.fetch_add(1, Release);
+        wake_one(&self.shared.changed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    fn run(&self) {
+        loop {
+            let changed = self.changed.load(Acquire);
+            let mut state = self.state.lock();
+            if state.shutdown {
+                return;
+            }
+            let now = now();
+            let mut due = Vec::new();
+            let mut next = None;
+            while let Some(&Reverse((deadline, id))) = state.deadlines.peek() {
+                if deadline > now {
+                    next = Some(deadline);
+                    break;
+                }
+                state.deadlines.pop();
+                // Cancelled if the callback is gone.
+                if let Some(f) = state.callbacks.remove(&id) {
+                    due.push(f);
+                }
+            }
+            drop(state);
+            if !due.is_empty() {
+                for f in due {
+                    let _ = catch(f);
+                }
+                continue;
+            }
+            match next {
+                Some(deadline) => wait_timeout(&self.changed, changed, deadline - now),
+                None => wait(&self.changed, changed),
+            }
+        }
+    }
+}
+
+/// A handle of a scheduled callback.
+pub struct TimerHandle {
+    shared: Arc<Shared>,
+    id: u64,
+}
+
+impl TimerHandle {
+    /// Cancel the callback, return false if it has run or been cancelled.
+    pub fn cancel(&self) -> bool {
+        // The deadline is left in the heap, and skipped when it's due,
+        // unless the heap is mostly cancelled deadlines.
+        let mut state = self.shared.state.lock();
+        if state.callbacks.remove(&self.id).is_none() {
+            return false;
+        }
+        if state.deadlines.len() > 2 * state.callbacks.len() {
+            let State {
+                deadlines,
+                callbacks,
+                ..
+            } = &mut *state;
+            deadlines.retain(|Reverse((_, id))| callbacks.contains_key(id));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicU32, atomic::Ordering::Relaxed, mpsc, Arc},
+        time::{Duration, Instant},
+    };
+
+    use super::Timer;
+    use crate::futex::wait;
+
+    #[test]
+    fn test_timer() {
+        let timer = Timer::new();
+        let (tx, rx) = mpsc::channel();
+        let now = Instant::now();
+        for i in [3, 1, 2] {
+            let tx = tx.clone();
+            timer.schedule(now + Duration::from_millis(i * 10), move || {
+                tx.send(i).unwrap()
+            });
+        }
+        let cancelled =
+            timer.schedule(now + Duration::from_millis(15), move || tx.send(0).unwrap());
+        assert!(cancelled.cancel());
+        assert!(!cancelled.cancel());
+        assert_eq!(rx.iter().take(3).collect::<Vec<_>>(), [1, 2, 3]);
+        assert!(now.elapsed() >= Duration::from_millis(30));
+
+        let word = Arc::new(AtomicU32::new(0));
+        let fired = timer.wake_at(
+            Instant::now() + Duration::from_millis(10),
+            Arc::clone(&word),
+        );
+        while word.load(Relaxed) == 0 {
+            wait(&word, 0);
+        }
+        assert!(!fired.cancel());
+
+        // Cancelled far deadlines don't pile up.
+        let far = Instant::now() + Duration::from_secs(3600);
+        for _ in 0..100 {
+            assert!(timer.schedule(far, || {}).cancel());
+        }
+        assert!(timer.shared.state.lock().deadlines.len() <= 2);
+
+        // The timer survives a panicking callback.
+        timer.schedule(Instant::now(), || panic!("callback"));
+        let (tx2, rx2) = mpsc::channel();
+        timer.schedule(Instant::now(), move || tx2.send(()).unwrap());
+        rx2.recv().unwrap();
+        drop(timer);
+        assert!(rx.try_recv().is_err());
+    }
+}
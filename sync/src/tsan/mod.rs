@@ -0,0 +1,50 @@
+//! Annotations for ThreadSanitizer, no-ops unless built with `--cfg tsan`.
+//!
+//! TSan models atomic operations but not fences nor inline assembly, so it
+//! reports false races on the Arc drop path, which pairs a release
+//! decrement with an acquire fence, and on the `cmpxchg16b` of AtomicPair.
+//! Build downstream TSan runs with
+//! `RUSTFLAGS="-Zsanitizer=thread --cfg tsan" cargo +nightly test -Zbuild-std --target <triple>`,
+//! then the fences are replaced with acquire loads and the assembly is
+//! annotated with happens-before edges.
+
+/// An acquire fence pairing with the release operations on the atomic,
+/// an acquire load of it under TSan.
+macro_rules! acquire_fence {
+    ($atomic:expr) => {{
+        #[cfg(tsan)]
+        let _ = $atomic.load(::std::sync::atomic::Ordering::Acquire);
+        #[cfg(not(tsan))]
+        ::std::sync::atomic::fence(::std::sync::atomic::Ordering::Acquire);
+    }};
+}
+pub(crate) use acquire_fence;
+
+#[cfg(tsan)]
+extern "C" {
+    fn __tsan_acquire(addr: *mut std::ffi::c_void);
+    fn __tsan_release(addr: *mut std::ffi::c_void);
+}
+
+/// Annotate that what the thread did before happens before the
+/// `happens_after` on the same address by other threads.
+#[inline]
+#[allow(unused_variables)]
+pub(crate) fn happens_before<T>(addr: *const T) {
+    // Safety: TSan only records the address, never dereferences it.
+    #[cfg(tsan)]
+    unsafe {
+        __tsan_release(addr as *mut _)
+    }
+}
+
+/// See `happens_before`.
+#[inline]
+#[allow(unused_variables)]
+pub(crate) fn happens_after<T>(addr: *const T) {
+    // Safety: TSan only records the address, never dereferences it.
+    #[cfg(tsan)]
+    unsafe {
+        __tsan_acquire(addr as *mut _)
+    }
+}
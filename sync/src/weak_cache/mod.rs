@@ -0,0 +1,155 @@
+use std::{
+    borrow::Borrow,
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hash},
+};
+
+use crate::{
+    arc::{Arc, Weak},
+    rwlock::RwLock,
+};
+
+const WEAK_CACHE_SHARDS: usize = 16;
+const WEAK_CACHE_MIN_PURGE: usize = 16;
+
+/// A map of weak references, handing out shared values by key.
+/// A value is created at most once while any Arc of it is alive,
+/// entries of dropped values are purged automatically.
+pub struct WeakCache<K, T, S = RandomState> {
+    shards: Box<[RwLock<Shard<K, T>>]>,
+    hasher: S,
+}
+
+struct Shard<K, T> {
+    entries: HashMap<K, Weak<T>>,
+    // Purge dead entries when the shard grows to this size.
+    purge_at: usize,
+}
+
+impl<K, T> Shard<K, T> {
+    fn purge(&mut self) {
+        self.entries.retain(|_, weak| weak.strong_count() > 0);
+        self.purge_at = WEAK_CACHE_MIN_PURGE.max(self.entries.len() * 2);
+    }
+}
+
+impl<K: Hash + Eq, T> Default for WeakCache<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, T> WeakCache<K, T> {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq, T, S: BuildHasher> WeakCache<K, T, S> {
+    /// Create an empty cache sharding keys by given hasher.
+    pub fn with_hasher(hasher: S) -> Self {
+        let shards = (0..WEAK_CACHE_SHARDS)
+            .map(|_| {
+                RwLock::new(Shard {
+                    entries: HashMap::new(),
+                    purge_at: WEAK_CACHE_MIN_PURGE,
+                })
+            })
+            .collect();
+        Self { shards, hasher }
+    }
+
+    /// Get the value of key if it's still alive.
+    pub fn get<Q>(&self, key: &Q) -> Option<Arc<T>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).read().entries.get(key)?.upgrade()
+    }
+
+    /// Get the value of key, create it by init if it's not alive.
+    /// init is called under the shard lock, so it must not access the cache.
+    pub fn get_or_create<F>(&self, key: K, init: F) -> Arc<T>
+    where
+        F: FnOnce() -> T,
+    {
+        let shard = self.shard(&key);
+        if let Some(value) = shard.read().entries.get(&key).and_then(Weak::upgrade) {
+            return value;
+        }
+
+        let mut shard = shard.write();
+        // Check again, the value may be created by others before write lock.
+        if let Some(value) = shard.entries.get(&key).and_then(Weak::upgrade) {
+            return value;
+        }
+        if shard.entries.len() >= shard.purge_at {
+            shard.purge();
+        }
+        let value = Arc::new(init());
+        shard.entries.insert(key, Arc::downgrade(&value));
+        value
+    }
+
+    /// Remove entries of dropped values.
+    pub fn purge(&self) {
+        for shard in self.shards.iter() {
+            shard.write().purge();
+        }
+    }
+
+    /// Count of entries, including the dropped ones not purged yet.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().entries.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn shard<Q>(&self, key: &Q) -> &RwLock<Shard<K, T>>
+    where
+        Q: Hash + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key) as usize;
+        &self.shards[hash % self.shards.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::thread;
+
+    use super::WeakCache;
+
+    #[test]
+    fn test_weak_cache() {
+        let cache = WeakCache::new();
+        let inits = AtomicUsize::new(0);
+        let init = || {
+            inits.fetch_add(1, Relaxed);
+            String::from("value")
+        };
+
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| s.spawn(|| cache.get_or_create("key", init)))
+                .collect();
+            let values: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+            assert_eq!(inits.load(Relaxed), 1);
+            assert!(values.iter().all(|v| v.as_str() == "value"));
+            assert!(cache.get("key").is_some());
+        });
+
+        // All values are dropped, so the entry is dead.
+        assert!(cache.get("key").is_none());
+        cache.get_or_create("key", init);
+        assert_eq!(inits.load(Relaxed), 2);
+
+        cache.purge();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+//! Independent reads of independent writes: two writers store to different
+//! locations, two readers load both by opposite orders, and they must agree
+//! on the order of the writes.
+
+use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+use sync::rwlock::RwLock;
+
+use super::litmus;
+
+// Readers return what they read first and second as bits 1 and 0.
+fn disagree(o: &[u32]) -> bool {
+    // Reader of x then y saw x only, reader of y then x saw y only.
+    o[2] == 0b10 && o[3] == 0b10
+}
+
+#[derive(Default)]
+struct Atomics {
+    x: AtomicU32,
+    y: AtomicU32,
+}
+
+#[test]
+fn test_iriw_seq_cst() {
+    litmus(
+        "iriw seq_cst",
+        Atomics::default,
+        &[
+            |s| {
+                s.x.store(1, SeqCst);
+                0
+            },
+            |s| {
+                s.y.store(1, SeqCst);
+                0
+            },
+            |s| (s.x.load(SeqCst) << 1) | s.y.load(SeqCst),
+            |s| (s.y.load(SeqCst) << 1) | s.x.load(SeqCst),
+        ],
+        disagree,
+    );
+}
+
+struct Locks {
+    x: RwLock<u32>,
+    y: RwLock<u32>,
+}
+
+#[test]
+fn test_iriw_rwlock() {
+    litmus::<Locks>(
+        "iriw rwlock",
+        || Locks {
+            x: RwLock::new(0),
+            y: RwLock::new(0),
+        },
+        &[
+            |s| {
+                *s.x.write() = 1;
+                0
+            },
+            |s| {
+                *s.y.write() = 1;
+                0
+            },
+            // Guards are dropped at once, holding both may deadlock
+            // behind the pending writers.
+            |s| {
+                let x = *s.x.read();
+                (x << 1) | *s.y.read()
+            },
+            |s| {
+                let y = *s.y.read();
+                (y << 1) | *s.x.read()
+            },
+        ],
+        disagree,
+    );
+}
@@ -0,0 +1,69 @@
+//! Litmus tests of the memory orderings chosen by the primitives: each
+//! test runs a few threads racing on fresh state many times, and fails
+//! if an outcome forbidden by the model is ever observed.
+//!
+//! Iterations default to 2000, override them by `ORDERING_ITERATIONS`.
+
+use std::{sync::Barrier, thread};
+
+mod iriw;
+mod message_passing;
+mod store_buffering;
+
+/// A thread of a litmus test, returning what it observed.
+type Thread<S> = fn(&S) -> u32;
+
+fn iterations() -> usize {
+    std::env::var("ORDERING_ITERATIONS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(2000)
+}
+
+/// Pin the current thread to a CPU, so threads of a test run in parallel
+/// when there are enough CPUs.
+#[cfg(target_os = "linux")]
+fn pin(cpu: usize) {
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu % cpus, &mut set);
+        libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin(_cpu: usize) {}
+
+/// Run the threads on a fresh state created by new for every iteration,
+/// panic if forbidden returns true for the observations of an iteration.
+fn litmus<S: Sync>(
+    name: &str,
+    new: impl Fn() -> S,
+    threads: &[Thread<S>],
+    forbidden: impl Fn(&[u32]) -> bool,
+) {
+    for i in 0..iterations() {
+        let state = new();
+        let barrier = Barrier::new(threads.len());
+        let observed: Vec<u32> = thread::scope(|s| {
+            let handles: Vec<_> = threads
+                .iter()
+                .enumerate()
+                .map(|(cpu, f)| {
+                    let (state, barrier) = (&state, &barrier);
+                    s.spawn(move || {
+                        pin(cpu);
+                        barrier.wait();
+                        f(state)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(
+            !forbidden(&observed),
+            "{name}: forbidden outcome {observed:?} at iteration {i}"
+        );
+    }
+}
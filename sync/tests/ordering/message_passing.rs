@@ -0,0 +1,130 @@
+//! Message passing: a writer publishes data then a flag, a reader seeing
+//! the flag must see the data.
+
+use std::sync::atomic::{
+    AtomicBool, AtomicU32,
+    Ordering::{Acquire, Relaxed, Release},
+};
+
+use sync::{arc::Arc, mutex::Mutex, rwlock::RwLock};
+
+use super::litmus;
+
+// Observed by readers: the data if the flag is seen, NOT_SEEN otherwise.
+const NOT_SEEN: u32 = u32::MAX;
+
+#[derive(Default)]
+struct Atomics {
+    data: AtomicU32,
+    flag: AtomicBool,
+}
+
+#[test]
+fn test_mp_atomics() {
+    litmus(
+        "mp atomics",
+        Atomics::default,
+        &[
+            |s| {
+                s.data.store(42, Relaxed);
+                s.flag.store(true, Release);
+                0
+            },
+            |s| match s.flag.load(Acquire) {
+                true => s.data.load(Relaxed),
+                false => NOT_SEEN,
+            },
+        ],
+        |o| o[1] != 42 && o[1] != NOT_SEEN,
+    );
+}
+
+// Data is published by a Relaxed store, ordered by the lock only.
+struct Locked<L> {
+    data: AtomicU32,
+    lock: L,
+}
+
+#[test]
+fn test_mp_mutex() {
+    litmus::<Locked<Mutex<bool>>>(
+        "mp mutex",
+        || Locked {
+            data: AtomicU32::new(0),
+            lock: Mutex::new(false),
+        },
+        &[
+            |s| {
+                s.data.store(42, Relaxed);
+                *s.lock.lock() = true;
+                0
+            },
+            |s| match *s.lock.lock() {
+                true => s.data.load(Relaxed),
+                false => NOT_SEEN,
+            },
+        ],
+        |o| o[1] != 42 && o[1] != NOT_SEEN,
+    );
+}
+
+#[test]
+fn test_mp_rwlock() {
+    litmus::<Locked<RwLock<bool>>>(
+        "mp rwlock",
+        || Locked {
+            data: AtomicU32::new(0),
+            lock: RwLock::new(false),
+        },
+        &[
+            |s| {
+                s.data.store(42, Relaxed);
+                *s.lock.write() = true;
+                0
+            },
+            |s| match *s.lock.read() {
+                true => s.data.load(Relaxed),
+                false => NOT_SEEN,
+            },
+        ],
+        |o| o[1] != 42 && o[1] != NOT_SEEN,
+    );
+}
+
+// Written by both threads, the drop run by the last Arc checks the writes.
+#[derive(Default)]
+struct Witness([AtomicU32; 2]);
+
+impl Drop for Witness {
+    fn drop(&mut self) {
+        let seen: u32 = self.0.iter().map(|w| w.load(Relaxed)).sum();
+        assert_eq!(seen, 2, "mp arc drop: the last drop missed a write");
+    }
+}
+
+type Handoff = [std::sync::Mutex<Option<Arc<Witness>>>; 2];
+
+// Each thread writes by Relaxed then drops its Arc, the drop of the value
+// must see both writes whichever thread drops last.
+fn write_then_drop<const I: usize>(arcs: &Handoff) -> u32 {
+    let arc = arcs[I].lock().unwrap().take().unwrap();
+    arc.0[I].store(1, Relaxed);
+    drop(arc);
+    0
+}
+
+#[test]
+fn test_mp_arc_drop() {
+    litmus(
+        "mp arc drop",
+        || {
+            let arc = Arc::new(Witness::default());
+            [
+                std::sync::Mutex::new(Some(arc.clone())),
+                std::sync::Mutex::new(Some(arc)),
+            ]
+        },
+        &[write_then_drop::<0>, write_then_drop::<1>],
+        |_| false,
+    );
+}
@@ -0,0 +1,78 @@
+//! Store buffering: each thread stores to its own location then loads the
+//! other's, at least one of them must see the other's store.
+
+use std::sync::atomic::{
+    fence, AtomicU32,
+    Ordering::{Relaxed, SeqCst},
+};
+
+use sync::mutex::Mutex;
+
+use super::litmus;
+
+#[derive(Default)]
+struct Atomics {
+    x: AtomicU32,
+    y: AtomicU32,
+}
+
+#[test]
+fn test_sb_seq_cst() {
+    litmus(
+        "sb seq_cst",
+        Atomics::default,
+        &[
+            |s| {
+                s.x.store(1, SeqCst);
+                s.y.load(SeqCst)
+            },
+            |s| {
+                s.y.store(1, SeqCst);
+                s.x.load(SeqCst)
+            },
+        ],
+        |o| o == [0, 0],
+    );
+}
+
+#[test]
+fn test_sb_fences() {
+    litmus(
+        "sb fences",
+        Atomics::default,
+        &[
+            |s| {
+                s.x.store(1, Relaxed);
+                fence(SeqCst);
+                s.y.load(Relaxed)
+            },
+            |s| {
+                s.y.store(1, Relaxed);
+                fence(SeqCst);
+                s.x.load(Relaxed)
+            },
+        ],
+        |o| o == [0, 0],
+    );
+}
+
+#[test]
+fn test_sb_mutex() {
+    litmus::<Mutex<(u32, u32)>>(
+        "sb mutex",
+        || Mutex::new((0, 0)),
+        &[
+            |s| {
+                let mut g = s.lock();
+                g.0 = 1;
+                g.1
+            },
+            |s| {
+                let mut g = s.lock();
+                g.1 = 1;
+                g.0
+            },
+        ],
+        |o| o == [0, 0],
+    );
+}